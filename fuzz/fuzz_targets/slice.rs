@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use npch_slicer::pages::PageRanges;
+
+// The first byte picks the pages to keep (as a bitmask over pages 1-8), the
+// rest is the PDF itself.
+fuzz_target!(|data: &[u8]| {
+    let Some((mask, pdf)) = data.split_first() else {
+        return;
+    };
+    let pages = (0..8)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .map(|bit| bit + 1)
+        .collect::<PageRanges>();
+    let _ = npch_slicer::slice_bytes(pdf, &pages);
+});
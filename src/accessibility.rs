@@ -0,0 +1,181 @@
+use crate::schema::SCHEMA_VERSION;
+use crate::variant::OutputTarget;
+use lopdf::Document;
+use npch_slicer::limits::Limits;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::SliceRequest;
+use npch_slicer::{pdf, structure};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// For every page of the source, whether each of the figures on it has
+/// alternate text.
+pub struct SourceFigures {
+    pub tagged: bool,
+    by_page: BTreeMap<u32, Vec<bool>>,
+}
+
+impl SourceFigures {
+    pub fn new(document: &Document) -> SourceFigures {
+        let page_numbers = document
+            .get_pages()
+            .into_iter()
+            .map(|(number, id)| (id, number))
+            .collect::<BTreeMap<_, _>>();
+        let mut by_page = BTreeMap::<u32, Vec<bool>>::new();
+        for figure in structure::figures(document) {
+            if let Some(number) = figure.page.and_then(|page| page_numbers.get(&page)) {
+                by_page
+                    .entry(*number)
+                    .or_default()
+                    .push(figure.alt.is_some());
+            }
+        }
+        SourceFigures {
+            tagged: structure::is_tagged(document),
+            by_page,
+        }
+    }
+}
+
+/// How accessible one slice is: its figures' alternate text, and whether its
+/// structure tree made it through cutting and optimizing.
+#[derive(Debug, Serialize)]
+pub struct SliceAccessibility {
+    pub description: String,
+    pub pages: PageRanges,
+    /// Figures the source's structure tree puts on the slice's pages.
+    pub figures: usize,
+    /// The page of every one of them without alternate text.
+    pub missing_alt_text: Vec<u32>,
+    /// Whether the cut slice has a structure tree; absent if it wasn't cut.
+    pub structure_tree: Option<bool>,
+    /// Whether each optimized output has one, by path.
+    pub optimized: BTreeMap<String, bool>,
+}
+
+impl SliceAccessibility {
+    pub fn new(
+        slice_request: &SliceRequest,
+        source: &SourceFigures,
+        tagged: Option<bool>,
+        targets: &[OutputTarget],
+        output_dir: &Path,
+        limits: &Limits,
+    ) -> SliceAccessibility {
+        let figures = slice_request
+            .pages
+            .iter()
+            .flat_map(|page| {
+                let figures = source.by_page.get(&page).into_iter().flatten();
+                figures.map(move |has_alt| (page, *has_alt))
+            })
+            .collect::<Vec<(u32, bool)>>();
+        let optimized = targets
+            .iter()
+            .filter_map(|target| {
+                let path = output_dir
+                    .join(target.directory())
+                    .join(format!("{}.pdf", slice_request.output_name()));
+                let document = pdf::load(&path, limits).ok()?;
+                Some((path.display().to_string(), structure::is_tagged(&document)))
+            })
+            .collect();
+
+        SliceAccessibility {
+            description: slice_request.description.clone(),
+            pages: slice_request.pages.clone(),
+            figures: figures.len(),
+            missing_alt_text: figures
+                .iter()
+                .filter(|(_, has_alt)| !has_alt)
+                .map(|(page, _)| *page)
+                .collect(),
+            structure_tree: tagged,
+            optimized,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AccessibilityFile<'a> {
+    schema_version: u32,
+    source_tagged: bool,
+    slices: &'a [SliceAccessibility],
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn yes_no(value: Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "yes",
+        Some(false) => "<strong>no</strong>",
+        None => "not cut",
+    }
+}
+
+/// Writes the audit as JSON when `path` ends in `.json`, and as an HTML page
+/// otherwise.
+pub fn emit(path: &Path, source_tagged: bool, slices: &[SliceAccessibility]) -> io::Result<()> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let file = AccessibilityFile {
+            schema_version: SCHEMA_VERSION,
+            source_tagged,
+            slices,
+        };
+        return std::fs::write(path, serde_json::to_string_pretty(&file).unwrap());
+    }
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\">\
+         <title>Accessibility audit</title></head>\n<body>\n<h1>Accessibility audit</h1>\n",
+    );
+    html.push_str(&format!(
+        "<p>The source PDF {} a structure tree.</p>\n",
+        if source_tagged {
+            "has"
+        } else {
+            "<strong>does not have</strong>"
+        }
+    ));
+    html.push_str(
+        "<table>\n<tr><th>Slice</th><th>Pages</th><th>Figures</th>\
+         <th>Missing alt text (pages)</th><th>Structure tree</th>\
+         <th>After optimizing</th></tr>\n",
+    );
+    for slice in slices {
+        let missing = slice
+            .missing_alt_text
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<String>>()
+            .join(", ");
+        let optimized = slice
+            .optimized
+            .iter()
+            .map(|(path, tagged)| format!("{}: {}", escape(path), yes_no(Some(*tagged))))
+            .collect::<Vec<String>>()
+            .join("<br>");
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(&slice.description),
+            slice.pages,
+            slice.figures,
+            missing,
+            yes_no(slice.structure_tree),
+            optimized
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    std::fs::write(path, html)
+}
@@ -0,0 +1,148 @@
+use crate::MAX_NESTING;
+use lopdf::{Document, Object, ObjectId};
+use std::collections::BTreeSet;
+
+fn resolved_array(document: &Document, object: &Object) -> Vec<Object> {
+    document
+        .dereference(object)
+        .and_then(|(_, object)| object.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn references(objects: &[Object]) -> Vec<ObjectId> {
+    objects
+        .iter()
+        .filter_map(|object| object.as_reference().ok())
+        .collect()
+}
+
+fn retained_widgets(document: &Document) -> BTreeSet<ObjectId> {
+    document
+        .page_iter()
+        .filter_map(|page_id| document.get_dictionary(page_id).ok())
+        .filter_map(|page| page.get(b"Annots").ok())
+        .flat_map(|annots| references(&resolved_array(document, annots)))
+        .collect()
+}
+
+fn is_field(document: &Document, id: ObjectId) -> bool {
+    document
+        .get_dictionary(id)
+        .map(|dict| dict.has(b"T") || dict.has(b"FT"))
+        .unwrap_or(false)
+}
+
+/// Returns whether the field survives; non-terminal fields have their `Kids`
+/// trimmed down to the surviving children.
+fn retain_field(
+    document: &mut Document,
+    field_id: ObjectId,
+    widgets: &BTreeSet<ObjectId>,
+    visited: &mut BTreeSet<ObjectId>,
+    kept: &mut BTreeSet<ObjectId>,
+    depth: usize,
+) -> bool {
+    if depth > MAX_NESTING || !visited.insert(field_id) {
+        return false;
+    }
+    let kids = match document.get_dictionary(field_id) {
+        Ok(field) => match field.get(b"Kids") {
+            Ok(kids) => references(&resolved_array(document, kids)),
+            Err(_) => Vec::new(),
+        },
+        Err(_) => return false,
+    };
+
+    let retained = if kids.is_empty() {
+        widgets.contains(&field_id)
+    } else if !kids.iter().any(|kid| is_field(document, *kid)) {
+        kids.iter().all(|kid| widgets.contains(kid))
+    } else {
+        retain_kids(document, field_id, kids, widgets, visited, kept, depth)
+    };
+    if retained {
+        kept.insert(field_id);
+    }
+    retained
+}
+
+fn retain_kids(
+    document: &mut Document,
+    field_id: ObjectId,
+    kids: Vec<ObjectId>,
+    widgets: &BTreeSet<ObjectId>,
+    visited: &mut BTreeSet<ObjectId>,
+    kept: &mut BTreeSet<ObjectId>,
+    depth: usize,
+) -> bool {
+    let surviving = kids
+        .into_iter()
+        .filter(|kid| retain_field(document, *kid, widgets, visited, kept, depth + 1))
+        .map(Object::Reference)
+        .collect::<Vec<Object>>();
+    if surviving.is_empty() {
+        return false;
+    }
+    if let Ok(field) = document.get_dictionary_mut(field_id) {
+        field.set("Kids", surviving);
+    }
+    true
+}
+
+/// Keeps the form fields whose widgets all sit on pages still in `document`,
+/// dropping the rest (and the whole `AcroForm` if nothing survives).
+pub fn retain_slice_fields(document: &mut Document) {
+    let Some(mut acroform) = document
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|acroform| document.dereference(acroform).ok())
+        .and_then(|(_, acroform)| acroform.as_dict().ok())
+        .cloned()
+    else {
+        return;
+    };
+
+    let widgets = retained_widgets(document);
+    let fields = acroform
+        .get(b"Fields")
+        .map(|fields| references(&resolved_array(document, fields)))
+        .unwrap_or_default();
+    let mut visited = BTreeSet::new();
+    let mut kept = BTreeSet::new();
+    let surviving = fields
+        .into_iter()
+        .filter(|field| retain_field(document, *field, &widgets, &mut visited, &mut kept, 0))
+        .collect::<Vec<ObjectId>>();
+
+    let Ok(catalog) = document.catalog_mut() else {
+        return;
+    };
+    if surviving.is_empty() {
+        catalog.remove(b"AcroForm");
+        return;
+    }
+
+    if let Some(order) = acroform.get(b"CO").ok().and_then(|co| co.as_array().ok()) {
+        let order = order
+            .iter()
+            .filter(|field| {
+                field
+                    .as_reference()
+                    .map(|id| kept.contains(&id))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect::<Vec<Object>>();
+        acroform.set("CO", order);
+    }
+    acroform.set(
+        "Fields",
+        surviving
+            .into_iter()
+            .map(Object::Reference)
+            .collect::<Vec<Object>>(),
+    );
+    catalog.set("AcroForm", acroform);
+}
@@ -0,0 +1,261 @@
+use crate::config::Config;
+use crate::file_names;
+use crate::Row;
+use lopdf::{Document, ObjectId};
+use npch_slicer::pages::{self, PageRanges};
+use npch_slicer::{page, pdf, text, SliceRequests};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub notes: Vec<String>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn failed(&self, strict: bool) -> bool {
+        !self.errors.is_empty() || (strict && !self.warnings.is_empty())
+    }
+
+    pub fn print(&self) {
+        for note in &self.notes {
+            println!("{note}");
+        }
+        for error in &self.errors {
+            println!("error: {error}");
+        }
+        for warning in &self.warnings {
+            println!("warning: {warning}");
+        }
+        println!(
+            "{} error(s), {} warning(s)",
+            self.errors.len(),
+            self.warnings.len()
+        );
+    }
+}
+
+/// Pages of about the same displayed size, that of the first of them.
+struct SizeGroup {
+    width: f32,
+    height: f32,
+    pages: PageRanges,
+}
+
+const PAPER_SIZES: [(&str, f32, f32); 5] = [
+    ("A3", 842.0, 1191.0),
+    ("A4", 595.0, 842.0),
+    ("A5", 420.0, 595.0),
+    ("Letter", 612.0, 792.0),
+    ("Legal", 612.0, 1008.0),
+];
+
+impl SizeGroup {
+    fn describe(&self, tolerance: f32) -> String {
+        let (short, long) = (self.width.min(self.height), self.width.max(self.height));
+        let paper = PAPER_SIZES
+            .iter()
+            .find(|(_, width, height)| {
+                (short - width).abs() <= tolerance && (long - height).abs() <= tolerance
+            })
+            .map(|(name, _, _)| format!("{name} "))
+            .unwrap_or_default();
+        let orientation = match self.width.total_cmp(&self.height) {
+            std::cmp::Ordering::Less => "portrait",
+            std::cmp::Ordering::Equal => "square",
+            std::cmp::Ordering::Greater => "landscape",
+        };
+        format!(
+            "{:.0}x{:.0} pt ({paper}{orientation})",
+            self.width, self.height
+        )
+    }
+}
+
+/// Groups `pages` by displayed size, largest group first.
+fn size_groups(
+    document: &Document,
+    page_ids: &BTreeMap<u32, ObjectId>,
+    pages: &PageRanges,
+    tolerance: f32,
+) -> Vec<SizeGroup> {
+    let mut groups: Vec<SizeGroup> = Vec::new();
+    for page in pages.iter() {
+        let Some((width, height)) = page_ids
+            .get(&page)
+            .and_then(|&page_id| page::displayed_size(document, page_id))
+        else {
+            continue;
+        };
+        let group = groups.iter_mut().find(|group| {
+            (group.width - width).abs() <= tolerance && (group.height - height).abs() <= tolerance
+        });
+        match group {
+            Some(group) => {
+                group.pages.insert(page);
+            }
+            None => groups.push(SizeGroup {
+                width,
+                height,
+                pages: PageRanges::from(page..=page),
+            }),
+        }
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.pages.len()));
+    groups
+}
+
+/// Validates the slice requests against the document; with `page_sizes`, also
+/// lists the page sizes of every slice.
+pub fn check(rows: &[Row], pdf: &Path, config: &Config, page_sizes: bool) -> Report {
+    let mut report = Report::default();
+
+    let document = match pdf::load_with_password(pdf, &config.limits(), config.password.as_deref())
+    {
+        Ok(document) => Some(document),
+        Err(error) => {
+            report
+                .errors
+                .push(format!("cannot load {}: {error}", pdf.display()));
+            None
+        }
+    };
+    let page_ids = document
+        .as_ref()
+        .map(Document::get_pages)
+        .unwrap_or_default();
+    let page_count = document.as_ref().map(|_| page_ids.len() as u32);
+    let tolerance = config.page_size_tolerance();
+
+    let mut requested = Vec::new();
+    let mut file_names = BTreeMap::<String, Vec<u64>>::new();
+    for row in rows {
+        let request = match &row.request {
+            Ok(request) => request,
+            Err(error) => {
+                report.errors.push(format!("row {}: {error}", row.line));
+                continue;
+            }
+        };
+
+        if let Some(page_count) = page_count {
+            let outside = pages::outside(&request.pages, page_count);
+            if !outside.is_empty() {
+                report.errors.push(format!(
+                    "row {}: {:?} requests pages {} outside the document's {page_count} pages",
+                    row.line, request.description, outside
+                ));
+            }
+        }
+
+        if let Some(document) = &document {
+            let groups = size_groups(document, &page_ids, &request.pages, tolerance);
+            if page_sizes {
+                let sizes = groups
+                    .iter()
+                    .map(|group| {
+                        format!(
+                            "{} page(s) {}",
+                            group.pages.len(),
+                            group.describe(tolerance)
+                        )
+                    })
+                    .collect::<Vec<String>>();
+                report
+                    .notes
+                    .push(format!("{:?}: {}", request.description, sizes.join(", ")));
+            }
+            if let [usual, outliers @ ..] = groups.as_slice() {
+                for outlier in outliers {
+                    report.warnings.push(format!(
+                        "row {}: {:?} has pages {} at {} among {} page(s) at {}",
+                        row.line,
+                        request.description,
+                        outlier.pages,
+                        outlier.describe(tolerance),
+                        usual.pages.len(),
+                        usual.describe(tolerance)
+                    ));
+                }
+            }
+        }
+
+        let touched = config.blocked(&request.pages);
+        if !touched.is_empty() {
+            report.errors.push(format!(
+                "row {}: {:?} requests pages {}, which are in blocked_pages",
+                row.line, request.description, touched
+            ));
+        }
+
+        if let Some(expected_pages) = request.expected_pages {
+            if request.pages.len() != expected_pages {
+                report.errors.push(format!(
+                    "row {}: {:?} covers {} page(s), but expected_pages is {expected_pages}",
+                    row.line,
+                    request.description,
+                    request.pages.len()
+                ));
+            }
+        }
+
+        if let (Some(document), Some(phrase)) = (&document, &request.must_contain) {
+            let pages = request
+                .pages
+                .iter()
+                .filter(|page| page_ids.contains_key(page))
+                .collect::<Vec<u32>>();
+            let search = text::contains(document, &pages, phrase, &config.limits());
+            if !search.unreadable.is_empty() {
+                report.warnings.push(format!(
+                    "row {}: cannot read all of the text of pages {} of {:?}; must_contain \
+                     was looked for in the rest",
+                    row.line,
+                    search.unreadable.keys().copied().collect::<PageRanges>(),
+                    request.description
+                ));
+            }
+            if !search.found {
+                report.errors.push(format!(
+                    "row {}: {:?} doesn't contain must_contain {phrase:?}",
+                    row.line, request.description
+                ));
+            }
+        }
+
+        requested.push(request.clone());
+        // Numbered like the run numbers them, counting the valid rows only.
+        let index = requested.len();
+        match file_names::file_name(config.file_name_template(), index, request) {
+            // Compared case-insensitively, since that's how Windows resolves them.
+            Ok(file_name) => file_names
+                .entry(format!("{file_name}.pdf").to_lowercase())
+                .or_default()
+                .push(row.line),
+            Err(error) => report.errors.push(format!("row {}: {error}", row.line)),
+        }
+    }
+
+    for (file_name, lines) in &file_names {
+        if lines.len() > 1 {
+            let lines = lines
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<String>>()
+                .join(", ");
+            report.errors.push(format!(
+                "rows {lines} would all be written to {file_name:?}"
+            ));
+        }
+    }
+
+    // Pages outside the document are reported with their rows above.
+    let issues = SliceRequests::new(requested).coverage_issues(page_count);
+    for issue in issues.iter().filter(|issue| !issue.is_error()) {
+        report.warnings.push(issue.to_string());
+    }
+
+    report
+}
@@ -0,0 +1,188 @@
+//! Listings of the output set for standard verification tools: a `SHA256SUMS`
+//! file for `sha256sum -c`, and a CycloneDX bill of materials.
+
+use crate::slice_log::utc_now;
+use crate::slice_set::{SetSlice, SliceSet};
+use clap::ValueEnum;
+use npch_slicer::digest::sha256_hex;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumFormat {
+    /// SHA256SUMS, as written by sha256sum
+    Sha256sums,
+    /// bom.cdx.json, a CycloneDX 1.5 listing of the outputs as files
+    Cyclonedx,
+}
+
+impl ChecksumFormat {
+    pub fn path(self, output_dir: &Path) -> PathBuf {
+        output_dir.join(match self {
+            ChecksumFormat::Sha256sums => "SHA256SUMS",
+            ChecksumFormat::Cyclonedx => "bom.cdx.json",
+        })
+    }
+}
+
+/// One output of the set, by its path within the output directory.
+pub struct Artifact<'a> {
+    pub path: &'a str,
+    pub slice: &'a SetSlice,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Hashes every output of `set` in `output_dir`.
+pub fn artifacts<'a>(set: &'a SliceSet, output_dir: &Path) -> io::Result<Vec<Artifact<'a>>> {
+    set.slices()
+        .iter()
+        .flat_map(|slice| {
+            slice
+                .outputs
+                .iter()
+                .map(move |(path, &size)| (slice, path, size))
+        })
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|(slice, path, size)| {
+            Ok(Artifact {
+                path,
+                slice,
+                size,
+                sha256: sha256_hex(&std::fs::read(output_dir.join(path))?),
+            })
+        })
+        .collect()
+}
+
+/// Writes the listing of `set` in each of `formats` to the output directory,
+/// returning the paths written.
+pub fn write(
+    set: &SliceSet,
+    output_dir: &Path,
+    formats: &[ChecksumFormat],
+) -> Result<Vec<PathBuf>, (PathBuf, io::Error)> {
+    let Some(&first) = formats.first() else {
+        return Ok(Vec::new());
+    };
+    let artifacts = artifacts(set, output_dir).map_err(|error| (first.path(output_dir), error))?;
+    let mut written = Vec::new();
+    for &format in formats {
+        let path = format.path(output_dir);
+        let contents = match format {
+            ChecksumFormat::Sha256sums => sha256sums(&artifacts),
+            ChecksumFormat::Cyclonedx => cyclonedx(set.version, &artifacts),
+        };
+        std::fs::write(&path, contents).map_err(|error| (path.clone(), error))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+pub fn sha256sums<'a: 'b, 'b>(artifacts: impl IntoIterator<Item = &'b Artifact<'a>>) -> String {
+    artifacts
+        .into_iter()
+        .map(|artifact| format!("{}  {}\n", artifact.sha256, artifact.path))
+        .collect()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bom<'a> {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<Component<'a>>,
+}
+
+#[derive(Serialize)]
+struct Metadata {
+    timestamp: String,
+    tools: Tools,
+}
+
+#[derive(Serialize)]
+struct Tools {
+    components: [Tool; 1],
+}
+
+#[derive(Serialize)]
+struct Tool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct Component<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: &'a str,
+    name: &'a str,
+    description: &'a str,
+    hashes: [Hash<'a>; 1],
+    properties: [Property; 2],
+}
+
+#[derive(Serialize)]
+struct Hash<'a> {
+    alg: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct Property {
+    name: &'static str,
+    value: String,
+}
+
+/// The outputs as `file` components of a CycloneDX BOM, numbered after the
+/// slice set's version.
+fn cyclonedx(version: u32, artifacts: &[Artifact]) -> String {
+    let bom = Bom {
+        bom_format: "CycloneDX",
+        spec_version: "1.5",
+        version,
+        metadata: Metadata {
+            timestamp: utc_now(),
+            tools: Tools {
+                components: [Tool {
+                    kind: "application",
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        components: artifacts
+            .iter()
+            .map(|artifact| Component {
+                kind: "file",
+                bom_ref: artifact.path,
+                name: artifact.path,
+                description: &artifact.slice.description,
+                hashes: [Hash {
+                    alg: "SHA-256",
+                    content: &artifact.sha256,
+                }],
+                properties: [
+                    Property {
+                        name: "npch_slicer:pages",
+                        value: artifact.slice.pages.to_string(),
+                    },
+                    Property {
+                        name: "npch_slicer:size",
+                        value: artifact.size.to_string(),
+                    },
+                ],
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&bom).unwrap()
+}
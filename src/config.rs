@@ -0,0 +1,456 @@
+use crate::cover::Cover;
+use crate::file_names;
+use crate::schema::{self, SchemaError, SCHEMA_VERSION};
+use crate::sink::Sink;
+use crate::template::CommandTemplate;
+use crate::variant::Variant;
+use npch_slicer::encryption::{Permission, UnknownPermission};
+use npch_slicer::limits::Limits;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::stamp::{Corner, StampPages};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./npch_slicer.toml";
+pub const DEFAULT_PDF_PATH: &str = "./inputs/npch_guide.pdf";
+pub const DEFAULT_CSV_PATH: &str = "./inputs/npch_slicer.csv";
+pub const DEFAULT_OUTPUT_DIR: &str = "./outputs";
+pub const PDF_ENV: &str = "NPCH_SLICER_PDF";
+pub const CSV_ENV: &str = "NPCH_SLICER_CSV";
+pub const OUTPUT_DIR_ENV: &str = "NPCH_SLICER_OUT_DIR";
+pub const DEFAULT_PAGE_SIZE_TOLERANCE: f32 = 2.0;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Schema version the project file was written for; 1 when absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// The guide to slice.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf: Option<PathBuf>,
+    /// The slice requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csv: Option<PathBuf>,
+    /// Where slices, logs and reports are written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_dir: Option<PathBuf>,
+    /// Values for slice request columns that are missing or left empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub slice_defaults: BTreeMap<String, toml::Value>,
+    /// Named page numbers that page cells can count from.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub anchors: BTreeMap<String, Anchor>,
+    /// The version of the guide, for slice requests limited to some; read
+    /// from the guide when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_version: Option<String>,
+    /// Names the output files, with `{description}`, `{index:02}` and other
+    /// placeholders; the description alone when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variants: BTreeMap<Variant, VariantConfig>,
+    /// Extra Ghostscript arguments, after the built-in ones; placeholders
+    /// like `{description}` are filled in per slice.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gs_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Pages that must never end up in a slice, like answer keys.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_pages: Vec<PageRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qr_code: Option<QrCodeConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover: Option<Cover>,
+    #[serde(default, skip_serializing_if = "EncryptionConfig::is_empty")]
+    pub encryption: EncryptionConfig,
+    /// How far apart, in points, page sizes may be and still count as the
+    /// same size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_size_tolerance: Option<f32>,
+    /// Resource limits for loading PDFs; unset ones keep their defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limits: Option<Limits>,
+    /// The guide's password, from `--password` or asked for on the terminal;
+    /// never read from or written to the project file.
+    #[serde(skip)]
+    pub password: Option<String>,
+    /// Directory of the project file, which relative paths in it (and the
+    /// default inputs and outputs) are resolved against; empty for the
+    /// working directory.
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// A page anchor: a page number, or the page a bookmark of the guide leads to.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Anchor {
+    Page(u32),
+    Bookmark { bookmark: String },
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sink: Option<Sink>,
+}
+
+/// A QR code stamped on each slice, leading readers of printed copies back to
+/// the living document.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QrCodeConfig {
+    /// Link encoded in the code; `{description}` is filled in per slice.
+    pub url: String,
+    #[serde(default)]
+    pub corner: Corner,
+    #[serde(default)]
+    pub pages: StampPages,
+}
+
+/// Encryption of the optimized outputs, restricting what readers may do with
+/// them. Outputs are only encrypted when they have an owner password.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// Lifts the restrictions in readers that ask for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_password: Option<String>,
+    /// What readers may do without the owner password; nothing but read when
+    /// unset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<Permission>,
+}
+
+/// How one slice's outputs are encrypted.
+#[derive(Debug, Clone)]
+pub struct SliceEncryption {
+    #[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+    pub owner_password: String,
+    pub permissions: Vec<Permission>,
+}
+
+/// Permissions written as a list like `print, copy`, or `none`.
+pub fn parse_permissions(text: &str) -> Result<Vec<Permission>, UnknownPermission> {
+    if text.trim().eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    text.split([',', ';', ' '])
+        .filter(|name| !name.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+impl EncryptionConfig {
+    pub fn is_empty(&self) -> bool {
+        self.owner_password.is_none() && self.permissions.is_empty()
+    }
+
+    /// The encryption of a slice's outputs: these settings, with its
+    /// `owner_password` and `permissions` cells in place of them; empty cells
+    /// keep the run's. `None` when it has no owner password.
+    pub fn for_slice(
+        &self,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<Option<SliceEncryption>, UnknownPermission> {
+        let cell = |column: &str| {
+            fields
+                .get(column)
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+        };
+        let permissions = match cell("permissions") {
+            Some(permissions) => parse_permissions(permissions)?,
+            None => self.permissions.clone(),
+        };
+        let owner_password = cell("owner_password")
+            .map(String::from)
+            .or_else(|| self.owner_password.clone());
+        Ok(owner_password.map(|owner_password| SliceEncryption {
+            owner_password,
+            permissions,
+        }))
+    }
+}
+
+/// Pages `first` to `last`, inclusive, written as `12` or `40-45`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PageRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+#[derive(Debug, Error)]
+#[error("{0:?} is not a page or an inclusive page range like \"40-45\"")]
+pub struct PageRangeError(String);
+
+impl TryFrom<String> for PageRange {
+    type Error = PageRangeError;
+
+    fn try_from(text: String) -> Result<PageRange, PageRangeError> {
+        let page = |page: &str| page.trim().parse::<u32>().ok().filter(|page| *page > 0);
+        let range = match text.split_once('-') {
+            Some((first, last)) => page(first).zip(page(last)),
+            None => page(&text).map(|page| (page, page)),
+        };
+        match range {
+            Some((first, last)) if first <= last => Ok(PageRange { first, last }),
+            _ => Err(PageRangeError(text)),
+        }
+    }
+}
+
+impl From<PageRange> for String {
+    fn from(range: PageRange) -> String {
+        match range.first == range.last {
+            true => range.first.to_string(),
+            false => format!("{}-{}", range.first, range.last),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InterpolationError {
+    #[error("environment variable {0} is not set")]
+    Unset(String),
+    #[error("unclosed ${{ in {0:?}")]
+    Unclosed(String),
+}
+
+/// Replaces every `${VAR}` in `text` with the environment variable `VAR`;
+/// `$$` stands for a literal `$`.
+fn interpolate(text: &str) -> Result<String, InterpolationError> {
+    let mut interpolated = String::new();
+    let mut rest = text;
+    while let Some(dollar) = rest.find('$') {
+        interpolated.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            interpolated.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| InterpolationError::Unclosed(text.to_string()))?;
+            let name = &after[..end];
+            let value =
+                std::env::var(name).map_err(|_| InterpolationError::Unset(name.to_string()))?;
+            interpolated.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            interpolated.push('$');
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+fn interpolate_value(value: &mut toml::Value) -> Result<(), InterpolationError> {
+    match value {
+        toml::Value::String(text) => *text = interpolate(text)?,
+        toml::Value::Array(values) => values.iter_mut().try_for_each(interpolate_value)?,
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .try_for_each(|(_, value)| interpolate_value(value))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    Read(#[from] io::Error),
+    #[error("not valid TOML: {0}")]
+    Syntax(toml::de::Error),
+    #[error(transparent)]
+    Interpolation(#[from] InterpolationError),
+    #[error("{0}")]
+    Invalid(toml::de::Error),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+}
+
+/// Commands run at points of the slicing run.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Run on each optimized slice once verified, before it is delivered,
+    /// to sign it in place or write a signed copy to `{signed}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sign: Option<CommandTemplate>,
+    /// Run after each optimized slice is verified and delivered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_slice: Option<CommandTemplate>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.sign.is_none() && self.post_slice.is_none()
+    }
+}
+
+impl Config {
+    /// Loads the project file, expanding `${VAR}` references in its strings.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let mut value = toml::Value::Table(Config::read_table(path)?);
+        interpolate_value(&mut value)?;
+        Config::from_value(path, value)
+    }
+
+    /// Loads the project file with `${VAR}` references left as written, for
+    /// rewriting it.
+    pub fn load_verbatim(path: &Path) -> Result<Config, ConfigError> {
+        Config::from_value(path, toml::Value::Table(Config::read_table(path)?))
+    }
+
+    fn read_table(path: &Path) -> Result<toml::Table, ConfigError> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(ConfigError::Syntax)
+    }
+
+    fn from_value(path: &Path, value: toml::Value) -> Result<Config, ConfigError> {
+        let config: Config = value.try_into().map_err(ConfigError::Invalid)?;
+        schema::check_version(
+            &path.display().to_string(),
+            config.schema_version.unwrap_or(1),
+        )?;
+        Ok(Config {
+            root: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            ..config
+        })
+    }
+
+    /// Brings an older project file, or the implicit `./inputs/` layout of
+    /// releases without one, up to the current schema. Paths already in the
+    /// project file are kept; missing ones are filled in from `root/inputs/`
+    /// when the files are there.
+    pub fn migrate(existing: Option<Config>, root: &Path) -> Config {
+        let mut config = existing.unwrap_or_default();
+        config.schema_version = Some(SCHEMA_VERSION);
+
+        let legacy = |default: &str| {
+            let relative = Path::new(default).strip_prefix("./").unwrap();
+            root.join(relative).exists().then(|| relative.to_path_buf())
+        };
+        if config.pdf.is_none() {
+            config.pdf = legacy(DEFAULT_PDF_PATH);
+        }
+        if config.csv.is_none() {
+            config.csv = legacy(DEFAULT_CSV_PATH);
+        }
+
+        config
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap()
+    }
+
+    /// Lets `NPCH_SLICER_PDF`, `NPCH_SLICER_CSV` and `NPCH_SLICER_OUT_DIR`
+    /// override the project file. Like command line paths, they are relative
+    /// to the working directory.
+    pub fn apply_env(&mut self) {
+        self.apply_paths(
+            std::env::var_os(PDF_ENV).map(PathBuf::from),
+            std::env::var_os(CSV_ENV).map(PathBuf::from),
+            std::env::var_os(OUTPUT_DIR_ENV).map(PathBuf::from),
+        );
+    }
+
+    /// Overrides the project file's paths with ones relative to the working
+    /// directory, from the command line or the environment.
+    pub fn apply_paths(
+        &mut self,
+        pdf: Option<PathBuf>,
+        csv: Option<PathBuf>,
+        output_dir: Option<PathBuf>,
+    ) {
+        if let Some(pdf) = pdf {
+            self.pdf = Some(std::path::absolute(pdf).unwrap());
+        }
+        if let Some(csv) = csv {
+            self.csv = Some(std::path::absolute(csv).unwrap());
+        }
+        if let Some(output_dir) = output_dir {
+            self.output_dir = Some(std::path::absolute(output_dir).unwrap());
+        }
+    }
+
+    /// Resolves the paths that are still relative against the working
+    /// directory, so that every path derived from here on is absolute.
+    pub fn make_absolute(&mut self) {
+        self.root = std::path::absolute(&self.root).unwrap_or_else(|_| {
+            // Only the empty root, of a run without a project file, fails.
+            std::env::current_dir().unwrap()
+        });
+    }
+
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        if self.root.as_os_str().is_empty() {
+            return path.to_path_buf();
+        }
+        self.root.join(path.strip_prefix("./").unwrap_or(path))
+    }
+
+    pub fn pdf_path(&self) -> PathBuf {
+        self.resolve(self.pdf.as_deref().unwrap_or(Path::new(DEFAULT_PDF_PATH)))
+    }
+
+    pub fn csv_path(&self) -> PathBuf {
+        self.resolve(self.csv.as_deref().unwrap_or(Path::new(DEFAULT_CSV_PATH)))
+    }
+
+    pub fn output_dir(&self) -> PathBuf {
+        self.resolve(
+            self.output_dir
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_OUTPUT_DIR)),
+        )
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits.unwrap_or_default()
+    }
+
+    pub fn file_name_template(&self) -> &str {
+        self.file_name
+            .as_deref()
+            .unwrap_or(file_names::DEFAULT_TEMPLATE)
+    }
+
+    pub fn page_size_tolerance(&self) -> f32 {
+        self.page_size_tolerance
+            .unwrap_or(DEFAULT_PAGE_SIZE_TOLERANCE)
+    }
+
+    /// The ones of `pages` that are in `blocked_pages`.
+    pub fn blocked(&self, pages: &PageRanges) -> PageRanges {
+        let mut blocked = PageRanges::new();
+        for range in &self.blocked_pages {
+            blocked.insert_range(range.first, range.last);
+        }
+        pages.intersection(&blocked)
+    }
+
+    pub fn slice_default_cells(&self) -> Vec<(&str, String)> {
+        self.slice_defaults
+            .iter()
+            .map(|(column, value)| {
+                let cell = match value {
+                    toml::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                (column.as_str(), cell)
+            })
+            .collect()
+    }
+}
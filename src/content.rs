@@ -0,0 +1,454 @@
+//! Pages' content streams read operation by operation, so that an operation
+//! that doesn't parse is skipped on its own rather than, as lopdf's parser
+//! does, ending the page's content there.
+
+use crate::limits::{self, DecodeError, Limits};
+use crate::MAX_NESTING;
+use lopdf::content::{Content, Operation};
+use lopdf::{Document, Object, ObjectId};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContentError {
+    #[error("content stream {} {} R is missing or not a stream", .0.0, .0.1)]
+    Missing(ObjectId),
+    #[error("content stream {} {} R cannot be decompressed: {source}", .id.0, .id.1)]
+    Decompress {
+        id: ObjectId,
+        #[source]
+        source: DecodeError,
+    },
+}
+
+/// Why a page's content couldn't all be read.
+#[derive(Error, Debug)]
+pub enum Unreadable {
+    #[error("{0} operation(s) of its content don't parse")]
+    Skipped(usize),
+    #[error(transparent)]
+    Content(#[from] ContentError),
+    #[error("there is no such page")]
+    NoPage,
+}
+
+/// The 1-based pages of `document` whose content couldn't all be read, with
+/// why.
+pub fn unreadable_pages(document: &Document, limits: &Limits) -> BTreeMap<u32, Unreadable> {
+    document
+        .get_pages()
+        .into_iter()
+        .filter_map(|(page, page_id)| {
+            let readable = match PageContent::new(document, page_id, limits) {
+                Ok(content) => content.readable(),
+                Err(error) => Err(error.into()),
+            };
+            readable.err().map(|unreadable| (page, unreadable))
+        })
+        .collect()
+}
+
+/// The operations of a page's content that parse, in order, and how many
+/// were skipped for not parsing. Inline images are passed over.
+#[derive(Debug, Default)]
+pub struct PageContent {
+    pub operations: Vec<Operation>,
+    pub skipped: usize,
+}
+
+impl PageContent {
+    /// The content of the page `page_id`.
+    pub fn new(
+        document: &Document,
+        page_id: ObjectId,
+        limits: &Limits,
+    ) -> Result<PageContent, ContentError> {
+        Ok(PageContent::decode(&page_data(document, page_id, limits)?))
+    }
+
+    /// Whether every operation parsed.
+    pub fn readable(&self) -> Result<(), Unreadable> {
+        match self.skipped {
+            0 => Ok(()),
+            skipped => Err(Unreadable::Skipped(skipped)),
+        }
+    }
+
+    /// Reads `data` operation by operation: one with a malformed operand, or an
+    /// operand lopdf cannot parse, is skipped and the next is read from the
+    /// token after its operator.
+    pub fn decode(data: &[u8]) -> PageContent {
+        let tokens = Tokens { data, at: 0 }.collect::<Vec<Token>>();
+        let clean = tokens
+            .iter()
+            .all(|token| matches!(token, Token::Operand(_) | Token::Operator(_)))
+            && matches!(tokens.last(), None | Some(Token::Operator(_)));
+        if clean {
+            // Most content parses whole, which is far quicker than operation
+            // by operation.
+            let operators = tokens.iter().filter_map(|token| match token {
+                Token::Operator(operator) => Some(*operator),
+                _ => None,
+            });
+            if let Ok(content) = Content::decode(data) {
+                if content
+                    .operations
+                    .iter()
+                    .map(|operation| operation.operator.as_bytes())
+                    .eq(operators)
+                {
+                    return PageContent {
+                        operations: content.operations,
+                        skipped: 0,
+                    };
+                }
+            }
+        }
+
+        let mut content = PageContent::default();
+        let mut operands = Vec::new();
+        let mut broken = false;
+        for token in tokens {
+            match token {
+                Token::Operand(operand) => operands.push(operand),
+                Token::Broken => broken = true,
+                Token::InlineImage | Token::Operator(_) => {
+                    let operation = match token {
+                        Token::Operator(operator) if !broken => operation(&operands, operator),
+                        _ => None,
+                    };
+                    match operation {
+                        Some(operation) => content.operations.push(operation),
+                        None if broken || !operands.is_empty() => content.skipped += 1,
+                        None => {}
+                    }
+                    operands.clear();
+                    broken = false;
+                }
+            }
+        }
+        if broken || !operands.is_empty() {
+            content.skipped += 1;
+        }
+        content
+    }
+}
+
+/// What ends the text object and restores the graphics states the page's
+/// content leaves open, so that what is drawn after it starts from the state
+/// the page started in. Only the operators are read, so it is far quicker
+/// than decoding the content.
+pub fn closing(
+    document: &Document,
+    page_id: ObjectId,
+    limits: &Limits,
+) -> Result<Vec<Operation>, ContentError> {
+    let data = page_data(document, page_id, limits)?;
+    let mut in_text = false;
+    let mut saved = 0usize;
+    for token in (Tokens { data: &data, at: 0 }) {
+        match token {
+            Token::Operator(b"BT") => in_text = true,
+            Token::Operator(b"ET") => in_text = false,
+            Token::Operator(b"q") => saved += 1,
+            // Readers ignore restoring more states than were saved.
+            Token::Operator(b"Q") => saved = saved.saturating_sub(1),
+            _ => {}
+        }
+    }
+    let end_text = in_text.then(|| Operation::new("ET", vec![]));
+    let restore = std::iter::repeat_with(|| Operation::new("Q", vec![])).take(saved);
+    Ok(end_text.into_iter().chain(restore).collect())
+}
+
+/// The page's content streams, decompressed, one after the other.
+fn page_data(
+    document: &Document,
+    page_id: ObjectId,
+    limits: &Limits,
+) -> Result<Vec<u8>, ContentError> {
+    let mut data = Vec::new();
+    for id in document.get_page_contents(page_id) {
+        let stream = document
+            .get_object(id)
+            .and_then(Object::as_stream)
+            .map_err(|_| ContentError::Missing(id))?;
+        let content = limits::decompressed(stream, limits)
+            .map_err(|source| ContentError::Decompress { id, source })?;
+        data.extend_from_slice(&content);
+        // Streams split the content between tokens, never within one.
+        data.push(b'\n');
+    }
+    Ok(data)
+}
+
+/// The operation `operator` with `operands`, if lopdf can parse them.
+fn operation(operands: &[&[u8]], operator: &[u8]) -> Option<Operation> {
+    let operator = String::from_utf8(operator.to_vec()).ok()?;
+    // lopdf's parser only knows operators made of letters, so the operands
+    // are parsed with one that is.
+    let mut data = operands.join(&b' ');
+    data.extend_from_slice(b" x");
+    let mut content = Content::decode(&data).ok()?;
+    match content.operations.as_mut_slice() {
+        [parsed] if parsed.operator == "x" && parsed.operands.len() == operands.len() => Some(
+            Operation::new(&operator, std::mem::take(&mut parsed.operands)),
+        ),
+        _ => None,
+    }
+}
+
+enum Token<'a> {
+    Operand(&'a [u8]),
+    Operator(&'a [u8]),
+    InlineImage,
+    /// Bytes that aren't a token, or one left unterminated.
+    Broken,
+}
+
+struct Tokens<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let data = self.data;
+        let start = skip_space(data, self.at);
+        if start >= data.len() {
+            self.at = start;
+            return None;
+        }
+        if matches!(data[start], b')' | b'>' | b']' | b'{' | b'}') {
+            self.at = start + 1;
+            return Some(Token::Broken);
+        }
+        let Some(end) = object_end(data, start, 0) else {
+            // An unterminated string runs to the end of the content; anything
+            // else malformed is read again from the byte after its first.
+            self.at = match data[start] {
+                b'(' => data.len(),
+                _ => start + 1,
+            };
+            return Some(Token::Broken);
+        };
+        self.at = end;
+        let token = &data[start..end];
+        if !is_regular(data[start]) || is_number(token) {
+            return Some(Token::Operand(token));
+        }
+        match token {
+            b"true" | b"false" | b"null" => Some(Token::Operand(token)),
+            b"BI" => Some(self.inline_image()),
+            _ => Some(Token::Operator(token)),
+        }
+    }
+}
+
+impl Tokens<'_> {
+    /// Passes over an inline image, from after its `BI` to after its `EI`.
+    fn inline_image(&mut self) -> Token<'static> {
+        let data = self.data;
+        loop {
+            let start = skip_space(data, self.at);
+            let Some(end) = (start < data.len())
+                .then(|| object_end(data, start, 0))
+                .flatten()
+            else {
+                self.at = (start + 1).min(data.len());
+                return Token::Broken;
+            };
+            self.at = end;
+            if &data[start..end] == b"ID" {
+                break;
+            }
+        }
+        // The image data starts after a single white-space byte and ends at
+        // an `EI` standing on its own.
+        let from = self.at + 1;
+        let end = (from..data.len().saturating_sub(1)).find(|&at| {
+            &data[at..at + 2] == b"EI"
+                && is_space(data[at - 1])
+                && data.get(at + 2).map_or(true, |&byte| !is_regular(byte))
+        });
+        match end {
+            Some(end) => {
+                self.at = end + 2;
+                Token::InlineImage
+            }
+            None => {
+                self.at = data.len();
+                Token::Broken
+            }
+        }
+    }
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_regular(byte: u8) -> bool {
+    !is_space(byte) && !b"()<>[]{}/%".contains(&byte)
+}
+
+fn is_number(token: &[u8]) -> bool {
+    token.iter().any(u8::is_ascii_digit)
+        && token
+            .iter()
+            .all(|byte| byte.is_ascii_digit() || b"+-.".contains(byte))
+}
+
+/// Where the white space and comments from `at` end.
+fn skip_space(data: &[u8], mut at: usize) -> usize {
+    while let Some(&byte) = data.get(at) {
+        match byte {
+            b'%' => {
+                while data
+                    .get(at)
+                    .is_some_and(|&byte| !matches!(byte, b'\r' | b'\n'))
+                {
+                    at += 1;
+                }
+            }
+            byte if is_space(byte) => at += 1,
+            _ => break,
+        }
+    }
+    at
+}
+
+/// Where the object starting at `at` ends, if it is one and is terminated.
+fn object_end(data: &[u8], at: usize, depth: usize) -> Option<usize> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    match data[at] {
+        b'(' => {
+            let mut open = 0usize;
+            let mut index = at;
+            while index < data.len() {
+                match data[index] {
+                    b'\\' => index += 1,
+                    b'(' => open += 1,
+                    b')' => {
+                        open -= 1;
+                        if open == 0 {
+                            return Some(index + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                index += 1;
+            }
+            None
+        }
+        b'<' if data.get(at + 1) == Some(&b'<') => composite_end(data, at + 2, b">>", depth),
+        b'<' => {
+            let end = at + 1 + data[at + 1..].iter().position(|&byte| byte == b'>')?;
+            data[at + 1..end]
+                .iter()
+                .all(|&byte| byte.is_ascii_hexdigit() || is_space(byte))
+                .then_some(end + 1)
+        }
+        b'[' => composite_end(data, at + 1, b"]", depth),
+        b'/' => Some(regular_end(data, at + 1)),
+        byte if is_regular(byte) => Some(regular_end(data, at)),
+        _ => None,
+    }
+}
+
+/// Where the array or dictionary whose contents start at `at` ends, after
+/// `close`.
+fn composite_end(data: &[u8], mut at: usize, close: &[u8], depth: usize) -> Option<usize> {
+    loop {
+        at = skip_space(data, at);
+        if at >= data.len() {
+            return None;
+        }
+        if data[at..].starts_with(close) {
+            return Some(at + close.len());
+        }
+        at = object_end(data, at, depth + 1)?;
+    }
+}
+
+fn regular_end(data: &[u8], at: usize) -> usize {
+    at + data[at..]
+        .iter()
+        .position(|&byte| !is_regular(byte))
+        .unwrap_or(data.len() - at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text;
+    use lopdf::{dictionary, Stream};
+
+    /// Pages whose content is whole, has an operation with a stray `]`, and
+    /// is missing.
+    fn document() -> Document {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let contents = [
+            Object::Reference(document.add_object(Stream::new(
+                dictionary! {},
+                b"BT /F1 12 Tf 72 720 Td (Whole) Tj ET".to_vec(),
+            ))),
+            Object::Reference(document.add_object(Stream::new(
+                dictionary! {},
+                b"BT /F1 12 Tf 72 ] 720 Td (Broken) Tj ET".to_vec(),
+            ))),
+            Object::Reference((999, 0)),
+        ];
+        let kids = contents
+            .into_iter()
+            .map(|contents| {
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                        "Contents" => contents,
+                    })
+                    .into()
+            })
+            .collect::<Vec<Object>>();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => 3 }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        document
+    }
+
+    #[test]
+    fn unreadable_pages_say_why() {
+        let unreadable = unreadable_pages(&document(), &Limits::default());
+        assert_eq!(unreadable.keys().copied().collect::<Vec<u32>>(), [2, 3]);
+        assert!(matches!(unreadable[&2], Unreadable::Skipped(1)));
+        assert!(matches!(
+            unreadable[&3],
+            Unreadable::Content(ContentError::Missing((999, 0)))
+        ));
+    }
+
+    #[test]
+    fn searches_report_the_same_pages() {
+        let search = text::contains(&document(), &[1, 2, 3, 4], "broken", &Limits::default());
+        assert!(search.found);
+        assert_eq!(
+            search.unreadable.keys().copied().collect::<Vec<u32>>(),
+            [2, 3, 4]
+        );
+        assert!(matches!(search.unreadable[&2], Unreadable::Skipped(1)));
+        assert!(matches!(search.unreadable[&4], Unreadable::NoPage));
+    }
+}
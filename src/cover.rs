@@ -0,0 +1,176 @@
+use crate::template::{CommandTemplate, TemplateError, Variables};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object};
+use npch_slicer::limits::Limits;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::pdf::{self, PdfError};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+use std::process::ExitStatus;
+use thiserror::Error;
+
+/// A cover page for every slice, rendered by an external tool such as
+/// `typst compile` or an HTML to PDF converter, so its design lives in a
+/// template rather than here.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cover {
+    /// Writes the cover to `{output}`. Every column of the slice's row is a
+    /// placeholder, as are `{pages}` and `{page_count}`.
+    pub command: CommandTemplate,
+}
+
+#[derive(Debug, Error)]
+pub enum CoverError {
+    #[error("cover command: {0}")]
+    Template(#[from] TemplateError),
+    #[error("cover command could not be started: {0}")]
+    Spawn(io::Error),
+    #[error("cover command failed ({status}): {stderr}")]
+    Failed { status: ExitStatus, stderr: String },
+    #[error("cover is not a readable PDF: {0}")]
+    Unreadable(PdfError),
+    #[error("cover could not be put in front of the slice: {0}")]
+    Merge(lopdf::Error),
+}
+
+impl Cover {
+    /// Renders the cover to `output` and loads it, removing the file again.
+    pub fn render(
+        &self,
+        variables: &Variables,
+        output: &Path,
+        use_shell: bool,
+        limits: &Limits,
+    ) -> Result<Document, CoverError> {
+        let output_result = self
+            .command
+            .command(variables, use_shell)?
+            .output()
+            .map_err(CoverError::Spawn)?;
+        if !output_result.status.success() {
+            let _ = std::fs::remove_file(output);
+            return Err(CoverError::Failed {
+                status: output_result.status,
+                stderr: String::from_utf8_lossy(&output_result.stderr)
+                    .trim_end()
+                    .to_string(),
+            });
+        }
+        let cover = pdf::load(output, limits).map_err(CoverError::Unreadable);
+        let _ = std::fs::remove_file(output);
+        cover
+    }
+}
+
+const TITLE_SIZE: f32 = 24.0;
+const DETAIL_SIZE: f32 = 12.0;
+/// Helvetica's letters are about half as wide as the text is high; wider
+/// ones are rare enough that lines broken at this width fit.
+const AVERAGE_WIDTH: f32 = 0.55;
+
+/// What a cover generated with `--cover-page` shows: the slice's
+/// description, and which pages of which guide it holds.
+pub struct CoverPage<'a> {
+    pub description: &'a str,
+    pub pages: &'a PageRanges,
+    /// The guide's file name.
+    pub source: &'a str,
+}
+
+impl CoverPage<'_> {
+    /// A document of the one cover page, `width` by `height` points like the
+    /// slice's first page.
+    pub fn generate(&self, width: f32, height: f32) -> lopdf::Result<Document> {
+        let margin = width.min(height) / 10.0;
+        let mut operations = Vec::new();
+        let mut y = height * 0.65;
+        for line in wrap(self.description, width - 2.0 * margin, TITLE_SIZE) {
+            text_at(&mut operations, TITLE_SIZE, margin, y, &line);
+            y -= TITLE_SIZE * 1.25;
+        }
+        y -= DETAIL_SIZE;
+        let details = [
+            format!("Pages {} of {}", self.pages, self.source),
+            format!("{} page(s)", self.pages.len()),
+        ];
+        for detail in details {
+            for line in wrap(&detail, width - 2.0 * margin, DETAIL_SIZE) {
+                text_at(&mut operations, DETAIL_SIZE, margin, y, &line);
+                y -= DETAIL_SIZE * 1.5;
+            }
+        }
+
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let content = Content { operations }.encode()?;
+        let content_id = document.add_object(lopdf::Stream::new(dictionary! {}, content));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        Ok(document)
+    }
+}
+
+fn text_at(operations: &mut Vec<Operation>, size: f32, x: f32, y: f32, text: &str) {
+    let text = Object::string_literal(Document::encode_text(Some("WinAnsiEncoding"), text));
+    operations.extend([
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec!["F1".into(), size.into()]),
+        Operation::new("Td", vec![x.into(), y.into()]),
+        Operation::new("Tj", vec![text]),
+        Operation::new("ET", vec![]),
+    ]);
+}
+
+/// `text` broken into lines about `width` points wide at `size`, between
+/// words where it can be.
+fn wrap(text: &str, width: f32, size: f32) -> Vec<String> {
+    let max_chars = ((width / (size * AVERAGE_WIDTH)) as usize).max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<char>>();
+        if !line.is_empty() && line.chars().count() + 1 + word.len() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        // Words longer than a line are broken where the line ends.
+        while word.len() > max_chars {
+            let rest = word.split_off(max_chars);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,76 @@
+//! `diff-source`: which slices two revisions of the guide differ in, to tell
+//! which of them must be issued again.
+
+use npch_slicer::fingerprint;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::SliceRequests;
+use std::path::Path;
+
+/// How a slice's pages fare in the new revision.
+#[derive(Debug)]
+pub enum SliceDiff {
+    Unchanged,
+    /// The same pages, by as many pages later (or, negative, earlier).
+    Moved(i64),
+    /// These pages differ, or are missing from either revision.
+    Changed(PageRanges),
+}
+
+impl SliceDiff {
+    pub fn new(old: &[String], new: &[String], pages: &PageRanges) -> SliceDiff {
+        let changed = pages
+            .iter()
+            .filter(|&page| {
+                let index = page as usize - 1;
+                old.get(index).is_none() || old.get(index) != new.get(index)
+            })
+            .collect::<PageRanges>();
+        if changed.is_empty() {
+            return SliceDiff::Unchanged;
+        }
+        match fingerprint::page_shift(old, new, pages) {
+            Some(offset) => SliceDiff::Moved(offset),
+            None => SliceDiff::Changed(changed),
+        }
+    }
+}
+
+/// Prints how every slice fares going from the `old` to the `new` page
+/// fingerprints.
+pub fn report(
+    slice_requests: &SliceRequests,
+    (old_path, old): (&Path, &[String]),
+    (new_path, new): (&Path, &[String]),
+) {
+    println!("{}: {} page(s)", old_path.display(), old.len());
+    println!(
+        "{}: {} page(s) ({:+})",
+        new_path.display(),
+        new.len(),
+        new.len() as i64 - old.len() as i64
+    );
+    let (mut changed, mut moved) = (0, 0);
+    for slice_request in slice_requests.iter() {
+        let pages = &slice_request.pages;
+        let status = match SliceDiff::new(old, new, pages) {
+            SliceDiff::Unchanged => "unchanged".to_string(),
+            SliceDiff::Moved(offset) => {
+                moved += 1;
+                let now = pages
+                    .iter()
+                    .map(|page| (i64::from(page) + offset) as u32)
+                    .collect::<PageRanges>();
+                format!("moved by {offset:+} page(s), to pages {now}")
+            }
+            SliceDiff::Changed(pages) => {
+                changed += 1;
+                format!("changed (pages {pages})")
+            }
+        };
+        println!("{:?} (pages {pages}): {status}", slice_request.description);
+    }
+    println!(
+        "{changed} changed, {moved} moved, {} unchanged slice(s)",
+        slice_requests.len() - changed - moved
+    );
+}
@@ -0,0 +1,26 @@
+//! SHA-256 digests written out as text, for content hashes that stay
+//! comparable between runs, builds and other tools.
+
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 digest of `data`, in lowercase hexadecimal.
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_hex_known_answer() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::checksums::ChecksumFormat;
+use crate::config::{Anchor, Config, PageRange, QrCodeConfig};
+use crate::sink::Sink;
+use crate::variant::{OutputTarget, Variant};
+use crate::{request_source, scratch_dir, Args, EndPage, Optimizer};
+use npch_slicer::encryption::Permission;
+use npch_slicer::limits::Limits;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The settings a run actually uses: command line flags first, then their
+/// `NPCH_SLICER_*` environment variables, then the project file, then the
+/// built-in defaults.
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_file: Option<PathBuf>,
+    pdf: PathBuf,
+    requests: String,
+    output_dir: PathBuf,
+    scratch_dir: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outline_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
+    master: bool,
+    min_size_ratio: f64,
+    resize_threshold: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<ChecksumFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package_max_size: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spot_check: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slice_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<u32>,
+    limits: Limits,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    blocked_pages: Vec<PageRange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qr_code: Option<QrCodeConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    gs_args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolution: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdf_settings: Option<Variant>,
+    compatibility_level: String,
+    file_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<u64>,
+    end_page: EndPage,
+    zero_based: bool,
+    use_page_labels: bool,
+    strict: bool,
+    cover_page: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimizer: Option<Optimizer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gs_path: Option<PathBuf>,
+    slice_defaults: BTreeMap<String, toml::Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    anchors: BTreeMap<String, Anchor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_version: Option<String>,
+    /// Whether the guide's password is given; never the password itself.
+    password: bool,
+    /// Whether the outputs' owner password is given; never the password
+    /// itself.
+    owner_password: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    permissions: Vec<Permission>,
+    targets: Vec<EffectiveTarget>,
+}
+
+#[derive(Debug, Serialize)]
+struct EffectiveTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<Variant>,
+    directory: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sink: Option<Sink>,
+}
+
+impl EffectiveConfig {
+    pub fn new(args: &Args, config: &Config, project_file: Option<&Path>) -> EffectiveConfig {
+        let output_dir = config.output_dir();
+        let targets = OutputTarget::all(&args.variants, config)
+            .into_iter()
+            .map(|target| EffectiveTarget {
+                variant: target.variant,
+                directory: output_dir.join(target.directory()),
+                sink: target.sink.as_ref().map(Sink::redacted),
+            })
+            .collect();
+
+        EffectiveConfig {
+            project_file: project_file.map(Path::to_path_buf),
+            pdf: config.pdf_path(),
+            requests: request_source(args, config).describe(),
+            output_dir,
+            scratch_dir: scratch_dir(args),
+            outline_depth: args.outline_depth,
+            lang: args.lang.clone(),
+            master: args.master,
+            min_size_ratio: args.min_size_ratio,
+            resize_threshold: args.resize_threshold,
+            checksums: args.checksums.clone(),
+            package: args.package.clone(),
+            package_max_size: args.package_max_size,
+            report: args.report.clone(),
+            spot_check: args.spot_check,
+            slice_timeout: args.slice_timeout,
+            jobs: args.jobs,
+            limits: config.limits(),
+            blocked_pages: config.blocked_pages.clone(),
+            qr_code: config.qr_code.clone(),
+            gs_args: config.gs_args.clone(),
+            resolution: args.resolution,
+            pdf_settings: args.pdf_settings,
+            compatibility_level: args
+                .compatibility_level
+                .clone()
+                .unwrap_or_else(|| String::from("1.7")),
+            max_size: args.max_size,
+            file_name: config.file_name_template().to_string(),
+            end_page: args.end_page,
+            zero_based: args.zero_based,
+            use_page_labels: args.use_page_labels,
+            strict: args.strict,
+            cover_page: args.cover_page,
+            optimizer: args.optimizer,
+            gs_path: args.gs_path.clone(),
+            slice_defaults: config.slice_defaults.clone(),
+            anchors: config.anchors.clone(),
+            source_version: config.source_version.clone(),
+            password: config.password.is_some(),
+            owner_password: config.encryption.owner_password.is_some(),
+            permissions: config.encryption.permissions.clone(),
+            targets,
+        }
+    }
+}
@@ -0,0 +1,875 @@
+//! The PDF standard security handler: opening a document encrypted with a
+//! user or owner password, from 40-bit RC4 up to the 256-bit AES of PDF 2.0,
+//! and encrypting one with the latter to restrict what readers may do.
+
+use crate::limits::Limits;
+use crate::updates::read_object_stream;
+use aes::cipher::block_padding::NoPadding;
+#[cfg(feature = "encryption")]
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+#[cfg(feature = "encryption")]
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes256};
+#[cfg(feature = "encryption")]
+use lopdf::dictionary;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use md5::Md5;
+use rc4::{Rc4, StreamCipher};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// What the user password is padded or made up to 32 bytes with.
+const PADDING: [u8; 32] = [
+    0x28, 0xbf, 0x4e, 0x5e, 0x4e, 0x75, 0x8a, 0x41, 0x64, 0x00, 0x4e, 0x56, 0xff, 0xfa, 0x01, 0x08,
+    0x2e, 0x2e, 0x00, 0xb6, 0xd0, 0x68, 0x3e, 0x80, 0x2f, 0x0c, 0xa9, 0xfe, 0x64, 0x53, 0x69, 0x7a,
+];
+
+const OBJECT_STREAM: &[u8] = b"/ObjStm";
+/// The name object streams are hidden behind while loading; as long as the
+/// original, so that every offset in the file still holds.
+const MASKED_OBJECT_STREAM: &[u8] = b"/ObjStX";
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("it is encrypted, and opening it takes a password")]
+    PasswordRequired,
+    #[error("the password is neither its user nor its owner password")]
+    WrongPassword,
+    #[error("it is encrypted with {0}, which cannot be decrypted")]
+    Unsupported(String),
+    #[error("its encryption dictionary has no valid {0}")]
+    Malformed(&'static str),
+    #[error("cannot get random bytes from the operating system: {0}")]
+    Random(#[from] getrandom::Error),
+    #[error(transparent)]
+    Pdf(#[from] lopdf::Error),
+}
+
+/// What readers of a document `encrypt` encrypted may do without its owner
+/// password, besides reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Printing, at full quality.
+    Print,
+    /// Copying text and images out.
+    Copy,
+    /// Changing the content, annotating, filling in forms, and inserting,
+    /// removing and rotating pages.
+    Modify,
+}
+
+#[derive(Debug, Error)]
+#[error("{0:?} is none of print, copy and modify")]
+pub struct UnknownPermission(pub String);
+
+impl Permission {
+    pub const ALL: [Permission; 3] = [Permission::Print, Permission::Copy, Permission::Modify];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Print => "print",
+            Permission::Copy => "copy",
+            Permission::Modify => "modify",
+        }
+    }
+
+    /// The bits of the `P` entry it grants, by Table 22 of ISO 32000-1.
+    #[cfg(feature = "encryption")]
+    fn bits(self) -> u32 {
+        match self {
+            Permission::Print => 1 << 2 | 1 << 11,
+            Permission::Copy => 1 << 4,
+            Permission::Modify => 1 << 3 | 1 << 5 | 1 << 8 | 1 << 10,
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = UnknownPermission;
+
+    fn from_str(name: &str) -> Result<Permission, UnknownPermission> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| UnknownPermission(name.to_string()))
+    }
+}
+
+/// How strings or streams are encrypted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Method {
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+/// The encryption dictionary's parameters, with the trailer's file
+/// identifier.
+struct Parameters {
+    revision: i64,
+    /// Bytes in the file key.
+    length: usize,
+    owner: Vec<u8>,
+    user: Vec<u8>,
+    owner_key: Vec<u8>,
+    user_key: Vec<u8>,
+    permissions: i32,
+    id: Vec<u8>,
+    encrypt_metadata: bool,
+}
+
+/// What decrypts the document: its file key and how it is applied.
+struct Handler {
+    key: Vec<u8>,
+    strings: Method,
+    streams: Method,
+    encrypt_metadata: bool,
+}
+
+/// Whether `document`, as lopdf loaded it, is encrypted.
+pub fn is_encrypted(document: &Document) -> bool {
+    document.trailer.has(b"Encrypt")
+}
+
+/// Decrypts `document`, loaded by lopdf from `data`, with `password`, or with
+/// the empty user password that documents only restricting what readers may
+/// do are encrypted with.
+///
+/// lopdf cannot read the objects in encrypted object streams and drops them,
+/// so a document with any is loaded again with its object streams hidden
+/// from lopdf, and they are read once decrypted.
+pub fn decrypt(
+    data: &[u8],
+    mut document: Document,
+    password: Option<&str>,
+    limits: &Limits,
+) -> Result<Document, EncryptionError> {
+    let (encrypt_id, encrypt) = match document.trailer.get(b"Encrypt") {
+        Ok(Object::Reference(id)) => (Some(*id), document.get_dictionary(*id)),
+        Ok(object) => (None, object.as_dict()),
+        Err(error) => (None, Err(error)),
+    };
+    let encrypt = encrypt.map_err(|_| EncryptionError::Malformed("dictionary"))?;
+    let handler = Handler::new(encrypt, &document.trailer, password)?;
+
+    let compressed = data
+        .windows(OBJECT_STREAM.len())
+        .any(|window| window == OBJECT_STREAM);
+    if compressed {
+        let trailer = std::mem::take(&mut document.trailer);
+        document = Document::load_mem(&mask_object_streams(data))?;
+        document.trailer = trailer;
+    }
+    for (&id, object) in document.objects.iter_mut() {
+        if Some(id) == encrypt_id {
+            continue;
+        }
+        handler.decrypt_object(id, object)?;
+    }
+    if let Some(id) = encrypt_id {
+        document.objects.remove(&id);
+    }
+    document.trailer.remove(b"Encrypt");
+
+    let mut unpacked = BTreeMap::new();
+    for object in document.objects.values_mut() {
+        if let Object::Stream(stream) = object {
+            if stream.dict.type_is(&MASKED_OBJECT_STREAM[1..]) {
+                stream
+                    .dict
+                    .set("Type", Object::Name(OBJECT_STREAM[1..].to_vec()));
+                // Unreadable ones are left out, as lopdf does.
+                unpacked.extend(read_object_stream(stream, limits).unwrap_or_default());
+            }
+        }
+    }
+    for (id, object) in unpacked {
+        document.objects.entry(id).or_insert(object);
+    }
+    Ok(document)
+}
+
+/// `data` with the type of every object stream renamed.
+fn mask_object_streams(data: &[u8]) -> Vec<u8> {
+    let mut masked = data.to_vec();
+    let mut at = 0;
+    while let Some(found) = masked[at..]
+        .windows(OBJECT_STREAM.len())
+        .position(|window| window == OBJECT_STREAM)
+    {
+        at += found;
+        masked[at..at + OBJECT_STREAM.len()].copy_from_slice(MASKED_OBJECT_STREAM);
+        at += OBJECT_STREAM.len();
+    }
+    masked
+}
+
+fn bytes<'a>(encrypt: &'a Dictionary, key: &'static str) -> Result<&'a [u8], EncryptionError> {
+    encrypt
+        .get(key.as_bytes())
+        .and_then(Object::as_str)
+        .map_err(|_| EncryptionError::Malformed(key))
+}
+
+/// `password` as the bytes revisions 2 to 4 hash: PDFDocEncoding, which is
+/// Latin-1 for the characters passwords are made of.
+fn legacy_bytes(password: &str) -> Vec<u8> {
+    match password.chars().all(|character| u32::from(character) < 256) {
+        true => password.chars().map(|character| character as u8).collect(),
+        false => password.as_bytes().to_vec(),
+    }
+}
+
+fn padded(password: &[u8]) -> [u8; 32] {
+    let length = password.len().min(32);
+    let mut padded = PADDING;
+    padded[..length].copy_from_slice(&password[..length]);
+    padded[length..].copy_from_slice(&PADDING[..32 - length]);
+    padded
+}
+
+fn xored(key: &[u8], value: u8) -> Vec<u8> {
+    key.iter().map(|byte| byte ^ value).collect()
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    Md5::digest(data).into()
+}
+
+/// RC4 of `data` under `key`; the same call encrypts and decrypts. Empty
+/// for a key outside the 5 to 16 bytes of the standard's keys.
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    use rc4::consts::{U10, U11, U12, U13, U14, U15, U16, U5, U6, U7, U8, U9};
+    use rc4::KeyInit;
+    fn with<Cipher: KeyInit + StreamCipher>(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        if let Ok(mut cipher) = Cipher::new_from_slice(key) {
+            cipher.apply_keystream(&mut data);
+        }
+        data
+    }
+    match key.len() {
+        5 => with::<Rc4<U5>>(key, data),
+        6 => with::<Rc4<U6>>(key, data),
+        7 => with::<Rc4<U7>>(key, data),
+        8 => with::<Rc4<U8>>(key, data),
+        9 => with::<Rc4<U9>>(key, data),
+        10 => with::<Rc4<U10>>(key, data),
+        11 => with::<Rc4<U11>>(key, data),
+        12 => with::<Rc4<U12>>(key, data),
+        13 => with::<Rc4<U13>>(key, data),
+        14 => with::<Rc4<U14>>(key, data),
+        15 => with::<Rc4<U15>>(key, data),
+        16 => with::<Rc4<U16>>(key, data),
+        _ => Vec::new(),
+    }
+}
+
+/// `data`, a whole number of blocks, decrypted in CBC mode with AES-128 or
+/// AES-256 by the length of `key`; any padding is left on. Empty for a key
+/// of any other length.
+fn decrypt_cbc(key: &[u8], iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let decrypted = match key.len() {
+        16 => cbc::Decryptor::<Aes128>::new_from_slices(key, iv)
+            .map(|cipher| cipher.decrypt_padded_vec_mut::<NoPadding>(data)),
+        32 => cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+            .map(|cipher| cipher.decrypt_padded_vec_mut::<NoPadding>(data)),
+        _ => return Vec::new(),
+    };
+    decrypted.ok().and_then(Result::ok).unwrap_or_default()
+}
+
+/// Algorithm 2.B of ISO 32000-2: the hash of `password` with `salt`, and the
+/// user key `user` when checking or making the owner key.
+fn hash_2b(password: &[u8], salt: &[u8], user: &[u8]) -> [u8; 32] {
+    let mut hash = Sha256::digest([password, salt, user].concat()).to_vec();
+    let mut round = 0;
+    loop {
+        let block = [password, &hash, user].concat().repeat(64);
+        let encrypted = cbc::Encryptor::<Aes128>::new_from_slices(&hash[..16], &hash[16..32])
+            .unwrap()
+            .encrypt_padded_vec_mut::<NoPadding>(&block);
+        // The first 16 bytes as a number modulo 3, 256 being 1 modulo 3.
+        hash = match encrypted[..16]
+            .iter()
+            .map(|&byte| u32::from(byte))
+            .sum::<u32>()
+            % 3
+        {
+            0 => Sha256::digest(&encrypted).to_vec(),
+            1 => Sha384::digest(&encrypted).to_vec(),
+            _ => Sha512::digest(&encrypted).to_vec(),
+        };
+        round += 1;
+        if round >= 64 && u32::from(encrypted[encrypted.len() - 1]) <= round - 32 {
+            break;
+        }
+    }
+    hash[..32].try_into().unwrap()
+}
+
+/// A digest of `password` that takes as long to check a guess against as
+/// the owner key of revision 6 does: Algorithm 2.B with `salt`. For
+/// recording that a password changed without recording it.
+pub fn password_digest(password: &str, salt: &[u8]) -> [u8; 32] {
+    hash_2b(&password.as_bytes()[..password.len().min(127)], salt, &[])
+}
+
+/// Bytes for keys, salts and initialization vectors, or anything else that
+/// must not be guessed, from the operating system's generator.
+pub fn random<const N: usize>() -> Result<[u8; N], getrandom::Error> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Encrypts `document` for saving, with the 256-bit AES of revision 6 and an
+/// empty user password: it opens for anyone, but readers only allow what
+/// `permissions` grant without `owner_password`. Those restrictions are up to
+/// the reader to honour; the encryption only keeps them from being edited
+/// out of the file. It fails only when the operating system has no random
+/// bytes to give, leaving `document` partly encrypted.
+#[cfg(feature = "encryption")]
+pub fn encrypt(
+    document: &mut Document,
+    owner_password: &str,
+    permissions: &[Permission],
+) -> Result<(), EncryptionError> {
+    let key = random::<32>()?;
+    for object in document.objects.values_mut() {
+        encrypt_object(&key, object)?;
+    }
+
+    // Each key is a hash, a salt to check the password with, and a salt to
+    // make the key that wraps the file key with.
+    let owner_password = &owner_password.as_bytes()[..owner_password.len().min(127)];
+    let (user_salts, owner_salts) = (random::<16>()?, random::<16>()?);
+    let wrapped = |wrapping: [u8; 32]| {
+        cbc::Encryptor::<Aes256>::new(&wrapping.into(), &[0; 16].into())
+            .encrypt_padded_vec_mut::<NoPadding>(&key)
+    };
+    let mut user = hash_2b(b"", &user_salts[..8], &[]).to_vec();
+    user.extend_from_slice(&user_salts);
+    let user_key = wrapped(hash_2b(b"", &user_salts[8..], &[]));
+    let mut owner = hash_2b(owner_password, &owner_salts[..8], &user).to_vec();
+    owner.extend_from_slice(&owner_salts);
+    let owner_key = wrapped(hash_2b(owner_password, &owner_salts[8..], &user));
+    let granted = Permission::ALL
+        .into_iter()
+        .filter(|permission| !permissions.contains(permission))
+        .fold(!0b11u32, |bits, permission| bits & !permission.bits());
+    let mut perms = [0; 16];
+    perms[..4].copy_from_slice(&granted.to_le_bytes());
+    perms[4..8].copy_from_slice(&[0xff; 4]);
+    perms[8..12].copy_from_slice(b"Tadb");
+    perms[12..].copy_from_slice(&random::<4>()?);
+    Aes256::new(&key.into()).encrypt_block((&mut perms).into());
+
+    let string = |bytes: &[u8]| Object::String(bytes.to_vec(), lopdf::StringFormat::Hexadecimal);
+    let encrypt_id = document.add_object(dictionary! {
+        "Filter" => "Standard",
+        "V" => 5,
+        "R" => 6,
+        "Length" => 256,
+        "CF" => dictionary! {
+            "StdCF" => dictionary! {
+                "CFM" => "AESV3",
+                "AuthEvent" => "DocOpen",
+                "Length" => 32,
+            },
+        },
+        "StmF" => "StdCF",
+        "StrF" => "StdCF",
+        "O" => string(&owner),
+        "U" => string(&user),
+        "OE" => string(&owner_key),
+        "UE" => string(&user_key),
+        "P" => granted as i32,
+        "Perms" => string(&perms),
+        "EncryptMetadata" => true,
+    });
+    document.trailer.set("Encrypt", encrypt_id);
+    if !document.trailer.has(b"ID") {
+        let id = random::<16>()?;
+        document.trailer.set("ID", vec![string(&id), string(&id)]);
+    }
+    // Revision 6 came with PDF 2.0, and before it with Adobe's extension
+    // level 8 of PDF 1.7.
+    if document.version.as_str() < "2.0" {
+        document.version = String::from("1.7");
+        if let Ok(catalog) = document.catalog_mut() {
+            catalog.set(
+                "Extensions",
+                dictionary! {
+                    "ADBE" => dictionary! {
+                        "BaseVersion" => Object::Name(b"1.7".to_vec()),
+                        "ExtensionLevel" => 8,
+                    },
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The strings in `object` encrypted with `key`, and its content if it is a
+/// stream, each with an initialization vector of its own before it and
+/// padding.
+#[cfg(feature = "encryption")]
+fn encrypt_object(key: &[u8; 32], object: &mut Object) -> Result<(), getrandom::Error> {
+    let encrypt = |data: &[u8]| -> Result<Vec<u8>, getrandom::Error> {
+        let iv = random::<16>()?;
+        let encrypted = cbc::Encryptor::<Aes256>::new(key.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(data);
+        Ok([&iv[..], &encrypted].concat())
+    };
+    match object {
+        Object::String(bytes, _) => *bytes = encrypt(bytes)?,
+        Object::Array(items) => {
+            for item in items {
+                encrypt_object(key, item)?;
+            }
+        }
+        Object::Dictionary(dictionary) => {
+            for (_, value) in dictionary.iter_mut() {
+                encrypt_object(key, value)?;
+            }
+        }
+        Object::Stream(stream) => {
+            if stream.dict.type_is(b"XRef") {
+                return Ok(());
+            }
+            for (_, value) in stream.dict.iter_mut() {
+                encrypt_object(key, value)?;
+            }
+            let content = encrypt(&stream.content)?;
+            stream.set_content(content);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl Parameters {
+    /// Algorithms 2 and 6 of ISO 32000-1: the file key, if `password` is the
+    /// user password.
+    fn user_password_key(&self, password: &[u8]) -> Option<Vec<u8>> {
+        let mut input = padded(password).to_vec();
+        input.extend_from_slice(self.owner.get(..32)?);
+        input.extend_from_slice(&self.permissions.to_le_bytes());
+        input.extend_from_slice(&self.id);
+        if self.revision >= 4 && !self.encrypt_metadata {
+            input.extend_from_slice(&[0xff; 4]);
+        }
+        let mut hash = md5(&input);
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                hash = md5(&hash[..self.length]);
+            }
+        }
+        let key = hash[..self.length].to_vec();
+        let valid = match self.revision {
+            2 => self.user.get(..32) == Some(&rc4(&key, &PADDING)[..]),
+            _ => {
+                let mut check = rc4(&key, &md5(&[&PADDING[..], &self.id].concat()));
+                for round in 1..=19 {
+                    check = rc4(&xored(&key, round), &check);
+                }
+                self.user.get(..16) == Some(&check[..])
+            }
+        };
+        valid.then_some(key)
+    }
+
+    /// Algorithm 7 of ISO 32000-1: the user password, padded, that
+    /// `password` unlocks if it is the owner password.
+    fn owner_password_user(&self, password: &[u8]) -> Vec<u8> {
+        let mut hash = md5(&padded(password));
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                hash = md5(&hash);
+            }
+        }
+        let key = &hash[..self.length];
+        let owner = &self.owner[..self.owner.len().min(32)];
+        match self.revision {
+            2 => rc4(key, owner),
+            _ => (0..=19)
+                .rev()
+                .fold(owner.to_vec(), |user, round| rc4(&xored(key, round), &user)),
+        }
+    }
+
+    /// Algorithm 2.B, or plain SHA-256 in revision 5.
+    fn hash(&self, password: &[u8], salt: &[u8], user: &[u8]) -> [u8; 32] {
+        match self.revision {
+            5 => Sha256::digest([password, salt, user].concat()).into(),
+            _ => hash_2b(password, salt, user),
+        }
+    }
+
+    /// Algorithm 2.A of ISO 32000-2: the file key of revisions 5 and 6, if
+    /// `password` is the owner or user password.
+    fn aes256_key(&self, password: &str) -> Option<Vec<u8>> {
+        let password = &password.as_bytes()[..password.len().min(127)];
+        let user = self.user.get(..48)?;
+        let owner = self.owner.get(..48)?;
+        // Each is a hash, a salt to check the password with, and a salt to
+        // make the key that unwraps the file key with.
+        let (key_salt, user_data, wrapped) =
+            if self.hash(password, &owner[32..40], user) == owner[..32] {
+                (&owner[40..48], user, &self.owner_key)
+            } else if self.hash(password, &user[32..40], &[]) == user[..32] {
+                (&user[40..48], &[][..], &self.user_key)
+            } else {
+                return None;
+            };
+        let key = self.hash(password, key_salt, user_data);
+        Some(decrypt_cbc(&key, &[0; 16], wrapped.get(..32)?))
+    }
+
+    fn key(&self, password: &str) -> Option<Vec<u8>> {
+        if self.revision >= 5 {
+            return self.aes256_key(password);
+        }
+        let password = legacy_bytes(password);
+        self.user_password_key(&password)
+            .or_else(|| self.user_password_key(&self.owner_password_user(&password)))
+    }
+}
+
+impl Handler {
+    fn new(
+        encrypt: &Dictionary,
+        trailer: &Dictionary,
+        password: Option<&str>,
+    ) -> Result<Handler, EncryptionError> {
+        let filter = encrypt
+            .get(b"Filter")
+            .and_then(Object::as_name)
+            .map_err(|_| EncryptionError::Malformed("Filter"))?;
+        if filter != b"Standard" {
+            return Err(EncryptionError::Unsupported(format!(
+                "the {} security handler",
+                String::from_utf8_lossy(filter)
+            )));
+        }
+        let integer = |key: &'static str| {
+            encrypt
+                .get(key.as_bytes())
+                .and_then(Object::as_i64)
+                .map_err(|_| EncryptionError::Malformed(key))
+        };
+        let version = integer("V").unwrap_or(0);
+        let revision = integer("R")?;
+        let (length, strings, streams) = match version {
+            1 => (5, Method::Rc4, Method::Rc4),
+            2 => {
+                let bits = integer("Length").unwrap_or(40);
+                if !(40..=128).contains(&bits) || bits % 8 != 0 {
+                    return Err(EncryptionError::Malformed("Length"));
+                }
+                (bits as usize / 8, Method::Rc4, Method::Rc4)
+            }
+            4 | 5 => (
+                if version == 4 { 16 } else { 32 },
+                crypt_filter(encrypt, b"StrF")?,
+                crypt_filter(encrypt, b"StmF")?,
+            ),
+            _ => {
+                return Err(EncryptionError::Unsupported(format!(
+                    "version {version} of the standard security handler"
+                )))
+            }
+        };
+        if !(2..=6).contains(&revision) {
+            return Err(EncryptionError::Unsupported(format!(
+                "revision {revision} of the standard security handler"
+            )));
+        }
+        let string = |key: &'static str| bytes(encrypt, key).map(<[u8]>::to_vec);
+        let parameters = Parameters {
+            revision,
+            length: if revision == 2 { 5 } else { length },
+            owner: string("O")?,
+            user: string("U")?,
+            owner_key: string("OE").unwrap_or_default(),
+            user_key: string("UE").unwrap_or_default(),
+            permissions: integer("P")? as i32,
+            id: trailer
+                .get(b"ID")
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|id| id.first()?.as_str().ok())
+                .unwrap_or_default()
+                .to_vec(),
+            encrypt_metadata: encrypt
+                .get(b"EncryptMetadata")
+                .and_then(Object::as_bool)
+                .unwrap_or(true),
+        };
+        let key = match password {
+            Some(password) => parameters
+                .key(password)
+                .ok_or(EncryptionError::WrongPassword)?,
+            None => parameters
+                .key("")
+                .ok_or(EncryptionError::PasswordRequired)?,
+        };
+        Ok(Handler {
+            key,
+            strings,
+            streams,
+            encrypt_metadata: parameters.encrypt_metadata,
+        })
+    }
+
+    /// Algorithm 1 of ISO 32000-1: the key of object `id`. Revisions 5 and 6
+    /// use the file key throughout.
+    fn object_key(&self, method: Method, (number, generation): ObjectId) -> Vec<u8> {
+        if method == Method::Aes256 {
+            return self.key.clone();
+        }
+        let mut input = self.key.clone();
+        input.extend_from_slice(&number.to_le_bytes()[..3]);
+        input.extend_from_slice(&generation.to_le_bytes()[..2]);
+        if method == Method::Aes128 {
+            input.extend_from_slice(b"sAlT");
+        }
+        md5(&input)[..(self.key.len() + 5).min(16)].to_vec()
+    }
+
+    fn decrypt(&self, method: Method, id: ObjectId, data: &[u8]) -> Vec<u8> {
+        if method == Method::Identity {
+            return data.to_vec();
+        }
+        let key = self.object_key(method, id);
+        if method == Method::Rc4 {
+            return rc4(&key, data);
+        }
+        // The initialization vector, then whole blocks ending in padding.
+        let Some((iv, encrypted)) = data.split_first_chunk::<16>() else {
+            return Vec::new();
+        };
+        let whole = encrypted.len() / 16 * 16;
+        let mut decrypted = decrypt_cbc(&key, iv, &encrypted[..whole]);
+        let padding = decrypted.last().copied().unwrap_or(0) as usize;
+        if (1..=16).contains(&padding)
+            && decrypted.len() >= padding
+            && decrypted[decrypted.len() - padding..]
+                .iter()
+                .all(|&byte| byte as usize == padding)
+        {
+            decrypted.truncate(decrypted.len() - padding);
+        }
+        decrypted
+    }
+
+    /// Decrypts the strings in `object`, and its content if it is a stream.
+    /// Cross-reference streams are never encrypted, nor metadata streams if
+    /// the dictionary says so.
+    fn decrypt_object(&self, id: ObjectId, object: &mut Object) -> Result<(), EncryptionError> {
+        match object {
+            Object::String(bytes, _) => *bytes = self.decrypt(self.strings, id, bytes),
+            Object::Array(items) => {
+                for item in items {
+                    self.decrypt_object(id, item)?;
+                }
+            }
+            Object::Dictionary(dictionary) => {
+                for (_, value) in dictionary.iter_mut() {
+                    self.decrypt_object(id, value)?;
+                }
+            }
+            Object::Stream(stream) => {
+                if stream.dict.type_is(b"XRef") {
+                    return Ok(());
+                }
+                for (_, value) in stream.dict.iter_mut() {
+                    self.decrypt_object(id, value)?;
+                }
+                if stream
+                    .filters()
+                    .is_ok_and(|filters| filters.iter().any(|filter| filter == "Crypt"))
+                {
+                    return Err(EncryptionError::Unsupported(
+                        "crypt filters on single streams".to_string(),
+                    ));
+                }
+                if self.encrypt_metadata || !stream.dict.type_is(b"Metadata") {
+                    let content = self.decrypt(self.streams, id, &stream.content);
+                    stream.set_content(content);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The method of the crypt filter named by `key` of the encryption
+/// dictionary of a version 4 or 5 handler.
+fn crypt_filter(encrypt: &Dictionary, key: &[u8]) -> Result<Method, EncryptionError> {
+    let name = encrypt
+        .get(key)
+        .and_then(Object::as_name)
+        .unwrap_or(b"Identity");
+    if name == b"Identity" {
+        return Ok(Method::Identity);
+    }
+    let method = encrypt
+        .get(b"CF")
+        .and_then(Object::as_dict)
+        .and_then(|filters| filters.get(name))
+        .and_then(Object::as_dict)
+        .map_err(|_| EncryptionError::Malformed("CF"))?
+        .get(b"CFM")
+        .and_then(Object::as_name)
+        .unwrap_or(b"None");
+    match method {
+        b"V2" => Ok(Method::Rc4),
+        b"AESV2" => Ok(Method::Aes128),
+        b"AESV3" => Ok(Method::Aes256),
+        _ => Err(EncryptionError::Unsupported(format!(
+            "the {} crypt filter method",
+            String::from_utf8_lossy(method)
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::hex;
+    use crate::limits::Limits;
+    use crate::pdf::{self, PdfError};
+
+    /// Written by `tests/fixtures/make_encrypted.py`, with the user password
+    /// "user" and the owner password "owner".
+    const FIXTURES: [(&str, &[u8]); 4] = [
+        ("R2", include_bytes!("../tests/fixtures/encrypted_r2.pdf")),
+        ("R3", include_bytes!("../tests/fixtures/encrypted_r3.pdf")),
+        ("R4", include_bytes!("../tests/fixtures/encrypted_r4.pdf")),
+        ("R6", include_bytes!("../tests/fixtures/encrypted_r6.pdf")),
+    ];
+
+    fn open(data: &[u8], password: Option<&str>) -> Result<Document, PdfError> {
+        pdf::load_mem_with_password(data, &Limits::default(), password)
+    }
+
+    #[test]
+    fn hash_2b_known_answers() {
+        // From an implementation of ISO 32000-2 on Python's hashlib.
+        assert_eq!(
+            hex(&hash_2b(b"", &[0, 1, 2, 3, 4, 5, 6, 7], &[])),
+            "1403c04eb647d2e60452dfc4eb0a5e0cf322e8a83a759eabbd17d498a93ba041"
+        );
+        assert_eq!(
+            hex(&hash_2b(
+                b"owner",
+                &[0, 1, 2, 3, 4, 5, 6, 7],
+                &(0..48).collect::<Vec<u8>>()
+            )),
+            "f77bbaa82ba46eca0e3997ad8c91b9fa4a3f83750e13a950f901abef1f69b683"
+        );
+    }
+
+    #[test]
+    fn decrypts_with_either_password() {
+        for (revision, data) in FIXTURES {
+            for password in ["user", "owner"] {
+                let document = open(data, Some(password))
+                    .unwrap_or_else(|error| panic!("{revision} with {password}: {error}"));
+                assert!(!is_encrypted(&document), "{revision}");
+                let page = document.get_pages()[&1];
+                let content = document.get_page_content(page).unwrap();
+                assert_eq!(
+                    content, b"BT /F1 12 Tf 72 720 Td (Hello, encrypted world) Tj ET",
+                    "{revision} with {password}"
+                );
+                let title = document
+                    .trailer
+                    .get(b"Info")
+                    .and_then(Object::as_reference)
+                    .and_then(|info| document.get_dictionary(info))
+                    .and_then(|info| info.get(b"Title"))
+                    .and_then(Object::as_str)
+                    .unwrap();
+                assert_eq!(title, b"Encrypted fixture", "{revision} with {password}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_documents_decrypt() {
+        let (_, data) = FIXTURES[0];
+        let mut document = open(data, Some("user")).unwrap();
+        let expected = document.objects.clone();
+        encrypt(&mut document, "secret", &[Permission::Print]).unwrap();
+        let mut saved = Vec::new();
+        document.save_to(&mut saved).unwrap();
+        assert!(!saved
+            .windows(b"Hello".len())
+            .any(|window| window == b"Hello"));
+
+        for password in [None, Some("secret")] {
+            let decrypted = open(&saved, password).unwrap();
+            assert!(!is_encrypted(&decrypted));
+            for (id, object) in &expected {
+                // Lengths change with the padding, and the catalog names
+                // the extension level revision 6 came with.
+                let comparable = |object: &Object| {
+                    let mut object = object.clone();
+                    match &mut object {
+                        Object::Stream(stream) => drop(stream.dict.remove(b"Length")),
+                        Object::Dictionary(dictionary) => drop(dictionary.remove(b"Extensions")),
+                        _ => {}
+                    }
+                    format!("{object:?}")
+                };
+                assert_eq!(
+                    comparable(&decrypted.objects[id]),
+                    comparable(object),
+                    "{id:?}"
+                );
+            }
+        }
+        let encrypt = document
+            .trailer
+            .get(b"Encrypt")
+            .and_then(Object::as_reference)
+            .and_then(|id| document.get_dictionary(id))
+            .unwrap();
+        let granted = encrypt.get(b"P").and_then(Object::as_i64).unwrap() as u32;
+        assert_eq!(granted & Permission::Print.bits(), Permission::Print.bits());
+        assert_eq!(granted & Permission::Copy.bits(), 0);
+        assert_eq!(granted & Permission::Modify.bits(), 0);
+    }
+
+    #[test]
+    fn refuses_wrong_or_missing_passwords() {
+        for (revision, data) in FIXTURES {
+            assert!(
+                matches!(
+                    open(data, Some("wrong")),
+                    Err(PdfError::Encryption(EncryptionError::WrongPassword))
+                ),
+                "{revision}"
+            );
+            assert!(
+                matches!(
+                    open(data, None),
+                    Err(PdfError::Encryption(EncryptionError::PasswordRequired))
+                ),
+                "{revision}"
+            );
+        }
+    }
+}
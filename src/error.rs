@@ -0,0 +1,272 @@
+use crate::config::ConfigError;
+use crate::file_names::FileNameError;
+use crate::ghostscript::GhostscriptError;
+use crate::revision::RevisionError;
+use crate::sources::SourceError;
+use crate::template::TemplateError;
+use crate::RowError;
+use miette::Diagnostic;
+use npch_slicer::encryption::EncryptionError;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::pdf::PdfError;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// What stops a run, with the file, row or slice at fault. Failures of single
+/// slices don't; they are logged and counted instead.
+#[derive(Debug, Error, Diagnostic)]
+pub enum Error {
+    #[error("project file {}", .path.display())]
+    #[diagnostic(code(npch_slicer::config))]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: Box<ConfigError>,
+    },
+    #[error(transparent)]
+    #[diagnostic(code(npch_slicer::requests))]
+    Requests(#[from] SourceError),
+    #[error("row {line} of {source_name}")]
+    #[diagnostic(
+        code(npch_slicer::row),
+        help("`npch_slicer check` lists every problem with the slice requests at once")
+    )]
+    Row {
+        source_name: String,
+        line: u64,
+        #[source]
+        source: RowError,
+    },
+    #[error("{count} rows of {source_name} are invalid")]
+    #[diagnostic(
+        code(npch_slicer::rows),
+        help("every invalid row is listed, grouped by what is wrong with it")
+    )]
+    Rows {
+        source_name: String,
+        count: usize,
+        #[related]
+        groups: Vec<RowGroup>,
+    },
+    #[error("page anchor {anchor:?} is the bookmark {bookmark:?}, which {} doesn't have", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::anchor),
+        help("bookmark anchors name a bookmark's title exactly; give the anchor a page number instead if it has none")
+    )]
+    Bookmark {
+        anchor: String,
+        bookmark: String,
+        path: PathBuf,
+    },
+    #[error(transparent)]
+    #[diagnostic(
+        code(npch_slicer::file_name),
+        help(
+            "file_name and --file-name templates take the slice requests' columns, index, \
+             first_page and last_page, and must tell every slice apart, as \
+             {{index:02}}_{{description}} does"
+        )
+    )]
+    FileName(#[from] FileNameError),
+    #[error("{description:?} requests pages {pages} outside the guide's {page_count} pages")]
+    #[diagnostic(
+        code(npch_slicer::outside),
+        help(
+            "if the slice requests count pages differently, see --end-page and --zero-based; \
+             `npch_slicer check` lists every such slice at once"
+        )
+    )]
+    OutsideDocument {
+        description: String,
+        pages: PageRanges,
+        page_count: u32,
+    },
+    #[error(
+        "{warnings} warning(s) about how the slice requests cover the guide, refused with --strict"
+    )]
+    #[diagnostic(
+        code(npch_slicer::strict),
+        help("they are listed above; without --strict a run only warns about them")
+    )]
+    Strict { warnings: usize },
+    #[error("cannot load {}", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::pdf),
+        help("if the guide comes from Git LFS, `git lfs checkout` fetches it")
+    )]
+    Load {
+        path: PathBuf,
+        #[source]
+        source: PdfError,
+    },
+    #[error("cannot decrypt {}", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::password),
+        help("give its password with --password or NPCH_SLICER_PASSWORD")
+    )]
+    Encrypted {
+        path: PathBuf,
+        #[source]
+        source: EncryptionError,
+    },
+    #[error("{name} is a single command line")]
+    #[diagnostic(
+        code(npch_slicer::shell),
+        help("give it as a list of arguments, or pass --use-shell to run it through the shell")
+    )]
+    Shell {
+        name: &'static str,
+        #[source]
+        source: TemplateError,
+    },
+    #[error(transparent)]
+    #[diagnostic(
+        code(npch_slicer::ghostscript),
+        help("install Ghostscript, or point --gs-path or NPCH_GS at its executable")
+    )]
+    Ghostscript(#[from] GhostscriptError),
+    #[error("cannot read the page fingerprints {}", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::revision),
+        help("delete the file to slice everything and record the fingerprints afresh")
+    )]
+    Revision {
+        path: PathBuf,
+        #[source]
+        source: RevisionError,
+    },
+    #[error("cannot read the slice set version {}", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::slice_set),
+        help("delete the file to start the changelog afresh at version 1")
+    )]
+    SliceSet {
+        path: PathBuf,
+        #[source]
+        source: RevisionError,
+    },
+    #[error("cannot read the incremental manifest {}", .path.display())]
+    #[diagnostic(
+        code(npch_slicer::manifest),
+        help("delete the file, or pass --force, to cut every slice afresh")
+    )]
+    Manifest {
+        path: PathBuf,
+        #[source]
+        source: RevisionError,
+    },
+    #[error("cannot write {}", .path.display())]
+    #[diagnostic(code(npch_slicer::write))]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("cannot write the index {}", .path.display())]
+    #[diagnostic(code(npch_slicer::index))]
+    Index {
+        path: PathBuf,
+        #[source]
+        source: lopdf::Error,
+    },
+    #[error("{setting} needs the {feature} feature, which this npch_slicer was built without")]
+    #[diagnostic(
+        code(npch_slicer::unsupported),
+        help("rebuild with `--features {feature}`")
+    )]
+    Unsupported {
+        setting: &'static str,
+        feature: &'static str,
+    },
+    #[error(
+        "{setting} writes one file for the whole run, so it can't be used with several guides"
+    )]
+    #[diagnostic(
+        code(npch_slicer::batch),
+        help("slice the guides one at a time to use it")
+    )]
+    Batch { setting: &'static str },
+    #[error("{} already exists", .path.display())]
+    #[diagnostic(code(npch_slicer::exists), help("pass --force to overwrite it"))]
+    Exists { path: PathBuf },
+    #[error("the slice requests have {errors} error(s) and {warnings} warning(s)")]
+    #[diagnostic(
+        code(npch_slicer::check),
+        help("they are listed above; warnings only fail the check with --strict")
+    )]
+    Check { errors: usize, warnings: usize },
+    #[error("cannot start the threads slices are cut in")]
+    #[diagnostic(code(npch_slicer::threads), help("--jobs starts fewer of them"))]
+    Threads(#[from] rayon::ThreadPoolBuildError),
+    #[error("{failures} slice(s) failed")]
+    #[diagnostic(
+        code(npch_slicer::failed),
+        help(
+            "rejected optimized outputs are in {failed_dir}, their unoptimized slices in \
+             {scratch_dir}, and what went wrong in the slices' logs in {logs_dir}"
+        )
+    )]
+    SlicesFailed {
+        failures: usize,
+        failed_dir: String,
+        scratch_dir: String,
+        logs_dir: String,
+    },
+}
+
+/// The invalid rows of the slice requests that share one kind of problem.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{kind}, in {} row(s):\n{}", .rows.len(), .rows.join("\n"))]
+pub struct RowGroup {
+    pub kind: &'static str,
+    /// `row <line>: <problem>` for each of them.
+    pub rows: Vec<String>,
+}
+
+impl Error {
+    /// The exit status of a run stopped by this error, one per kind of
+    /// failure so that scripts can tell them apart; see the README.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Check { .. } => 1,
+            Error::Config { .. }
+            | Error::Exists { .. }
+            | Error::Shell { .. }
+            | Error::Unsupported { .. }
+            | Error::Batch { .. } => 2,
+            Error::Requests(_)
+            | Error::Row { .. }
+            | Error::Rows { .. }
+            | Error::Bookmark { .. }
+            | Error::FileName(_)
+            | Error::OutsideDocument { .. }
+            | Error::Strict { .. } => 3,
+            Error::Load { .. } | Error::Encrypted { .. } => 4,
+            Error::Ghostscript(_) => 5,
+            Error::Revision { .. }
+            | Error::SliceSet { .. }
+            | Error::Manifest { .. }
+            | Error::Write { .. }
+            | Error::Index { .. } => 6,
+            Error::SlicesFailed { .. } => 7,
+            Error::Threads(_) => 8,
+        }
+    }
+
+    /// Wraps a write error with the path written to.
+    pub fn write(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Error {
+        let path = path.into();
+        move |source| Error::Write { path, source }
+    }
+
+    /// Wraps a load error with the path loaded, telling encryption errors
+    /// apart.
+    pub fn load(path: impl Into<PathBuf>) -> impl FnOnce(PdfError) -> Error {
+        let path = path.into();
+        move |source| match source {
+            PdfError::Encryption(source) => Error::Encrypted { path, source },
+            source => Error::Load { path, source },
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! The names of the slices' output files: the `file_name` template filled in
+//! per slice, made safe on every file system, and checked for collisions
+//! before anything is cut.
+
+use crate::template::{self, TemplateError, Variables};
+use npch_slicer::SliceRequest;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Output files are named after the description unless the project file or
+/// `--file-name` says otherwise.
+pub const DEFAULT_TEMPLATE: &str = "{description}";
+
+/// Bytes a name may take, leaving room for the output directory and the
+/// longest extension within the usual 255-byte limit on file names.
+const MAX_LENGTH: usize = 120;
+
+/// Names Windows keeps for devices, with any extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Error)]
+pub enum FileNameError {
+    #[error("file_name template for {description:?}: {source}")]
+    Template {
+        description: String,
+        #[source]
+        source: TemplateError,
+    },
+    #[error("{first:?} and {second:?} would both be written to {file_name:?}.pdf")]
+    Collision {
+        file_name: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// `name` safe as a file name anywhere: path separators, characters Windows
+/// refuses and control characters become `_` (one for each run of them),
+/// trailing dots and spaces go, a reserved device name gets a `_` after it,
+/// and overly long names are cut short.
+pub fn sanitize(name: &str) -> String {
+    let mut sanitized = String::new();
+    for character in name.trim().chars() {
+        let unsafe_character = character.is_control() || r#"/\:*?"<>|"#.contains(character);
+        match unsafe_character {
+            true if sanitized.ends_with('_') => {}
+            true => sanitized.push('_'),
+            false => sanitized.push(character),
+        }
+    }
+    if sanitized.len() > MAX_LENGTH {
+        let mut end = MAX_LENGTH;
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+        sanitized.truncate(end);
+    }
+    let mut sanitized = sanitized
+        .trim_end_matches(['.', ' '])
+        .trim_start_matches('.')
+        .to_string();
+    if sanitized.is_empty() {
+        sanitized.push_str("slice");
+    }
+    // Windows takes `CON.txt` for the device too, so the `_` goes before
+    // any extension.
+    let stem = sanitized.find('.').unwrap_or(sanitized.len());
+    if RESERVED
+        .iter()
+        .any(|reserved| sanitized[..stem].eq_ignore_ascii_case(reserved))
+    {
+        sanitized.insert(stem, '_');
+    }
+    sanitized
+}
+
+/// The name of the outputs of `slice_request`, the `index`th of the run
+/// (from 1): `template` filled in with the request's columns, `index`,
+/// `first_page` and `last_page`, sanitized. A `.pdf` ending is dropped, the
+/// extension being per output.
+pub fn file_name(
+    template: &str,
+    index: usize,
+    slice_request: &SliceRequest,
+) -> Result<String, FileNameError> {
+    let index = index.to_string();
+    let first_page = slice_request.pages.first().unwrap_or(0).to_string();
+    let last_page = slice_request.pages.last().unwrap_or(0).to_string();
+    let mut variables = slice_request
+        .fields
+        .iter()
+        .map(|(column, cell)| (column.as_str(), cell.as_str()))
+        .collect::<Variables>();
+    variables.extend([
+        ("description", slice_request.description.as_str()),
+        ("index", index.as_str()),
+        ("first_page", first_page.as_str()),
+        ("last_page", last_page.as_str()),
+    ]);
+    let rendered =
+        template::render(template, &variables).map_err(|source| FileNameError::Template {
+            description: slice_request.description.clone(),
+            source,
+        })?;
+    let stem = rendered
+        .len()
+        .checked_sub(4)
+        .filter(|&end| {
+            rendered
+                .get(end..)
+                .is_some_and(|extension| extension.eq_ignore_ascii_case(".pdf"))
+        })
+        .map_or(rendered.as_str(), |end| &rendered[..end]);
+    Ok(sanitize(stem))
+}
+
+/// Names the outputs of every one of `slice_requests`, failing on the first
+/// two that would share files. Names are compared ignoring case, since that's
+/// how Windows and macOS resolve them.
+pub fn assign(slice_requests: &mut [SliceRequest], template: &str) -> Result<(), FileNameError> {
+    let mut taken = BTreeMap::<String, String>::new();
+    for (index, slice_request) in slice_requests.iter_mut().enumerate() {
+        let name = file_name(template, index + 1, slice_request)?;
+        if let Some(first) = taken.insert(name.to_lowercase(), slice_request.description.clone()) {
+            return Err(FileNameError::Collision {
+                file_name: name,
+                first,
+                second: slice_request.description.clone(),
+            });
+        }
+        slice_request.file_name = Some(name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use npch_slicer::pages::PageRanges;
+
+    fn request(description: &str) -> SliceRequest {
+        SliceRequest {
+            description: description.to_string(),
+            pages: PageRanges::from(1..=4),
+            title: None,
+            author: None,
+            subject: None,
+            keywords: None,
+            expected_pages: None,
+            must_contain: None,
+            file_name: None,
+            fields: BTreeMap::from([(String::from("description"), description.to_string())]),
+        }
+    }
+
+    #[test]
+    fn names_windows_refuses_are_made_safe() {
+        for (name, sanitized) in [
+            ("Part 1/Chapter 2", "Part 1_Chapter 2"),
+            (r"a\b:c*d?e", "a_b_c_d_e"),
+            ("\"Quoted\" <tags> | pipes", "_Quoted_ _tags_ _ pipes"),
+            ("run::of//them", "run_of_them"),
+            ("two\nlines\u{7}", "two_lines_"),
+            ("  Chapter 1. . ", "Chapter 1"),
+            ("..hidden", "hidden"),
+            ("", "slice"),
+            ("...", "slice"),
+        ] {
+            assert_eq!(sanitize(name), sanitized, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn reserved_device_names_are_made_safe() {
+        for (name, sanitized) in [
+            ("CON", "CON_"),
+            ("con", "con_"),
+            ("Lpt9", "Lpt9_"),
+            ("nul.txt", "nul_.txt"),
+            ("AUX.tar.gz", "AUX_.tar.gz"),
+            ("PRN.", "PRN_"),
+            ("COM10", "COM10"),
+            ("CONSOLE", "CONSOLE"),
+            ("Icon", "Icon"),
+        ] {
+            assert_eq!(sanitize(name), sanitized, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn long_names_are_cut_on_a_character() {
+        let sanitized = sanitize(&"é".repeat(MAX_LENGTH));
+        assert_eq!(sanitized, "é".repeat(MAX_LENGTH / 2));
+        let sanitized = sanitize(&format!("{}. x", "a".repeat(MAX_LENGTH - 2)));
+        assert_eq!(sanitized, "a".repeat(MAX_LENGTH - 2));
+    }
+
+    #[test]
+    fn file_names_drop_a_pdf_ending() {
+        let slice_request = request("Intro");
+        for (template, name) in [
+            ("{description}.pdf", "Intro"),
+            ("{description}.PDF", "Intro"),
+            (
+                "{index} {description} p{first_page}-{last_page}",
+                "2 Intro p1-4",
+            ),
+            ("{description}.pdf.bak", "Intro.pdf.bak"),
+        ] {
+            assert_eq!(file_name(template, 2, &slice_request).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn names_differing_only_in_case_collide() {
+        let mut slice_requests = [request("Chapter 1"), request("Notes"), request("NOTES")];
+        let collision = assign(&mut slice_requests, DEFAULT_TEMPLATE).unwrap_err();
+        assert!(matches!(
+            collision,
+            FileNameError::Collision { file_name, first, second }
+                if file_name == "NOTES" && first == "Notes" && second == "NOTES"
+        ));
+    }
+
+    #[test]
+    fn names_colliding_once_sanitized_collide() {
+        let mut slice_requests = [request("Q&A: Part 1"), request("Q&A? Part 1")];
+        let collision = assign(&mut slice_requests, DEFAULT_TEMPLATE).unwrap_err();
+        assert!(matches!(
+            collision,
+            FileNameError::Collision { file_name, .. } if file_name == "Q&A_ Part 1"
+        ));
+    }
+
+    #[test]
+    fn assigned_names_tell_shared_descriptions_apart_by_template() {
+        let mut slice_requests = [request("Notes"), request("notes")];
+        assign(&mut slice_requests, "{index} {description}").unwrap();
+        let names = slice_requests.each_ref().map(SliceRequest::output_name);
+        assert_eq!(names, ["1 Notes", "2 notes"]);
+    }
+}
@@ -0,0 +1,269 @@
+//! Fingerprints of what each page shows, to tell which pages a new revision
+//! of a document changed however its objects are numbered.
+
+use crate::digest::hex;
+use crate::limits::{self, Limits};
+use crate::page::{inherited, INHERITABLE};
+use crate::pages::PageRanges;
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Bookkeeping that changes with the rest of the document rather than the
+/// page: the page tree, annotations' pages and structure tree keys.
+const IGNORED: [&[u8]; 4] = [b"Parent", b"P", b"StructParent", b"StructParents"];
+
+/// A SHA-256 hex digest per page, in page order, of the page's dictionary
+/// with everything it refers to, decoded content streams, resources and
+/// inherited attributes included. Other pages it refers to, e.g. as link
+/// targets, count only by page number.
+pub fn page_fingerprints(document: &Document, limits: &Limits) -> Vec<String> {
+    let pages = document.get_pages();
+    let numbers = pages
+        .iter()
+        .map(|(&number, &page_id)| (page_id, number))
+        .collect::<BTreeMap<ObjectId, u32>>();
+    let mut fingerprint = Fingerprint {
+        document,
+        limits,
+        pages: &numbers,
+        digests: BTreeMap::new(),
+        open: Vec::new(),
+        back: usize::MAX,
+    };
+    pages
+        .values()
+        .map(|&page_id| {
+            let mut page = document
+                .get_dictionary(page_id)
+                .cloned()
+                .unwrap_or_default();
+            for key in INHERITABLE {
+                if let (false, Some(value)) = (page.has(key), inherited(document, page_id, key)) {
+                    page.set(key, value.clone());
+                }
+            }
+            let mut hash = Sha256::new();
+            fingerprint.dictionary(&mut hash, &page, 0);
+            hex(&hash.finalize())
+        })
+        .collect()
+}
+
+/// The pages of the revision fingerprinted as `new` that differ from the
+/// same page number of `old`. Pages past the end of `old` count as changed.
+pub fn changed_pages(old: &[String], new: &[String]) -> PageRanges {
+    new.iter()
+        .enumerate()
+        .filter(|&(index, fingerprint)| old.get(index) != Some(fingerprint))
+        .map(|(index, _)| index as u32 + 1)
+        .collect()
+}
+
+/// How many pages later (or, negative, earlier) `new` holds the same pages
+/// as `pages` of `old`, the nearest such offset first; `None` if it holds
+/// them nowhere.
+pub fn page_shift(old: &[String], new: &[String], pages: &PageRanges) -> Option<i64> {
+    let (first, last) = (i64::from(pages.first()?), i64::from(pages.last()?));
+    let wanted = pages
+        .iter()
+        .map(|page| Some((i64::from(page), old.get(page as usize - 1)?)))
+        .collect::<Option<Vec<(i64, &String)>>>()?;
+    let mut offsets = (1 - first..=new.len() as i64 - last).collect::<Vec<i64>>();
+    offsets.sort_by_key(|offset| offset.abs());
+    offsets.into_iter().find(|offset| {
+        wanted
+            .iter()
+            .all(|&(page, fingerprint)| new.get((page + offset - 1) as usize) == Some(fingerprint))
+    })
+}
+
+struct Fingerprint<'a> {
+    document: &'a Document,
+    limits: &'a Limits,
+    pages: &'a BTreeMap<ObjectId, u32>,
+    /// The digests of the objects hashed so far, for any page, which stand
+    /// in for them wherever they are reached again.
+    digests: BTreeMap<ObjectId, [u8; 32]>,
+    /// The objects being hashed, outermost first.
+    open: Vec<ObjectId>,
+    /// The position in `open` of the outermost object that what was hashed
+    /// since refers back to. Objects in a cycle hash differently depending on
+    /// where the cycle was entered, so their digests are not kept.
+    back: usize,
+}
+
+fn bytes(hash: &mut Sha256, tag: u8, bytes: &[u8]) {
+    hash.update([tag]);
+    hash.update((bytes.len() as u64).to_le_bytes());
+    hash.update(bytes);
+}
+
+impl Fingerprint<'_> {
+    fn object(&mut self, hash: &mut Sha256, object: &Object, depth: usize) {
+        if depth > MAX_NESTING {
+            return bytes(hash, b'.', b"");
+        }
+        match object {
+            Object::Null => bytes(hash, b'n', b""),
+            Object::Boolean(value) => bytes(hash, b'b', &[u8::from(*value)]),
+            Object::Integer(value) => bytes(hash, b'i', &value.to_le_bytes()),
+            Object::Real(value) => bytes(hash, b'r', &value.to_le_bytes()),
+            Object::Name(name) => bytes(hash, b'/', name),
+            Object::String(text, _) => bytes(hash, b's', text),
+            Object::Array(items) => {
+                bytes(hash, b'[', &(items.len() as u64).to_le_bytes());
+                for item in items {
+                    self.object(hash, item, depth + 1);
+                }
+            }
+            Object::Dictionary(dictionary) => self.dictionary(hash, dictionary, depth),
+            Object::Stream(stream) => {
+                // Images are hashed as stored, decoding them being costly.
+                let decoded = match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"Image") => None,
+                    _ => limits::decompressed(stream, self.limits).ok(),
+                };
+                let mut dictionary = stream.dict.clone();
+                if decoded.is_some() {
+                    for key in [&b"Filter"[..], b"DecodeParms", b"Length"] {
+                        dictionary.remove(key);
+                    }
+                } else {
+                    dictionary.remove(b"Length");
+                }
+                self.dictionary(hash, &dictionary, depth);
+                bytes(hash, b'~', decoded.as_deref().unwrap_or(&stream.content));
+            }
+            Object::Reference(id) => self.reference(hash, *id),
+        }
+    }
+
+    fn dictionary(&mut self, hash: &mut Sha256, dictionary: &Dictionary, depth: usize) {
+        let mut entries = dictionary
+            .iter()
+            .filter(|(key, _)| !IGNORED.contains(&key.as_slice()))
+            .collect::<Vec<(&Vec<u8>, &Object)>>();
+        entries.sort_by_key(|&(key, _)| key);
+        bytes(hash, b'<', &(entries.len() as u64).to_le_bytes());
+        for (key, value) in entries {
+            bytes(hash, b'/', key);
+            self.object(hash, value, depth + 1);
+        }
+    }
+
+    /// Hashes the object `id` refers to by its digest, hashing it first if
+    /// no page reached it before.
+    fn reference(&mut self, hash: &mut Sha256, id: ObjectId) {
+        if let Some(&number) = self.pages.get(&id) {
+            return bytes(hash, b'@', &number.to_le_bytes());
+        }
+        if let Some(digest) = self.digests.get(&id) {
+            return bytes(hash, b'#', digest);
+        }
+        if let Some(at) = self.open.iter().position(|&open| open == id) {
+            self.back = self.back.min(at);
+            return bytes(hash, b'^', &((self.open.len() - at) as u64).to_le_bytes());
+        }
+        if self.open.len() > MAX_NESTING {
+            self.back = 0;
+            return bytes(hash, b'.', b"");
+        }
+        let at = self.open.len();
+        self.open.push(id);
+        let outer = std::mem::replace(&mut self.back, usize::MAX);
+        let mut object_hash = Sha256::new();
+        match self.document.get_object(id) {
+            Ok(object) => self.object(&mut object_hash, object, 0),
+            Err(_) => bytes(&mut object_hash, b'n', b""),
+        }
+        self.open.pop();
+        let digest = object_hash.finalize().into();
+        if self.back > at {
+            self.digests.insert(id, digest);
+            self.back = outer;
+        } else {
+            self.back = outer.min(self.back);
+        }
+        bytes(hash, b'#', &digest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    /// Three pages, the last two alike, with a font whose descriptor refers
+    /// back to it. The first page draws the descriptor itself if
+    /// `descriptor_first`, entering the cycle from the other end.
+    fn document(descriptor_first: bool) -> Document {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let (font_id, descriptor_id) = (document.new_object_id(), document.new_object_id());
+        document.objects.insert(
+            font_id,
+            Object::Dictionary(dictionary! { "Type" => "Font", "FontDescriptor" => descriptor_id }),
+        );
+        document.objects.insert(
+            descriptor_id,
+            Object::Dictionary(dictionary! { "Type" => "FontDescriptor", "Font" => font_id }),
+        );
+        let first = match descriptor_first {
+            true => dictionary! { "Descriptor" => descriptor_id },
+            false => dictionary! {},
+        };
+        let resources = [
+            first,
+            dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        ];
+        let kids = resources
+            .into_iter()
+            .map(|resources| {
+                let contents = document.add_object(Stream::new(
+                    dictionary! {},
+                    b"BT /F1 12 Tf 72 720 Td (Alike) Tj ET".to_vec(),
+                ));
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "Resources" => resources,
+                        "Contents" => contents,
+                    })
+                    .into()
+            })
+            .collect::<Vec<Object>>();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => 3 }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        document
+    }
+
+    #[test]
+    fn pages_alike_fingerprint_alike() {
+        let fingerprints = page_fingerprints(&document(false), &Limits::default());
+        assert_eq!(fingerprints[1], fingerprints[2]);
+        assert_ne!(fingerprints[0], fingerprints[1]);
+    }
+
+    #[test]
+    fn fingerprints_do_not_depend_on_other_pages() {
+        let limits = Limits::default();
+        let (plain, entered) = (document(false), document(true));
+        let (plain, entered) = (
+            page_fingerprints(&plain, &limits),
+            page_fingerprints(&entered, &limits),
+        );
+        assert_ne!(plain[0], entered[0]);
+        assert_eq!(plain[1..], entered[1..]);
+    }
+}
@@ -0,0 +1,306 @@
+//! Finding Ghostscript, whose executable is named differently per platform.
+
+use crate::variant::Variant;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::env::consts::EXE_SUFFIX;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Tried in order when no Ghostscript is given: the Unix name, then the
+/// console executables of 64 and 32 bit Windows installs.
+pub const CANDIDATES: [&str; 3] = ["gs", "gswin64c", "gswin32c"];
+
+#[derive(Debug, Error)]
+pub enum GhostscriptError {
+    #[error("Ghostscript {} was not found", .0.display())]
+    Missing(PathBuf),
+    #[error("Ghostscript was not found on PATH (tried {})", CANDIDATES.join(", "))]
+    NotFound,
+}
+
+/// The Ghostscript to run: `explicit` if given, otherwise the first of the
+/// `CANDIDATES` that `resolve` finds. `resolve` is normally `on_path`.
+pub fn locate(
+    explicit: Option<&Path>,
+    resolve: impl Fn(&Path) -> Option<PathBuf>,
+) -> Result<PathBuf, GhostscriptError> {
+    match explicit {
+        Some(program) => resolve(program).ok_or_else(|| GhostscriptError::Missing(program.into())),
+        None => CANDIDATES
+            .iter()
+            .find_map(|candidate| resolve(Path::new(candidate)))
+            .ok_or(GhostscriptError::NotFound),
+    }
+}
+
+/// Resolves `program` the way a shell would: a path if it has a directory in
+/// it, otherwise the first match in a `PATH` directory, `.exe` appended on
+/// Windows.
+pub fn on_path(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+    let mut file_name = program.as_os_str().to_owned();
+    if program.extension().is_none() {
+        file_name.push(EXE_SUFFIX);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|directory| directory.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// The PDF versions Ghostscript's pdfwrite can be asked to stay within.
+pub const COMPATIBILITY_LEVELS: [&str; 6] = ["1.3", "1.4", "1.5", "1.6", "1.7", "2.0"];
+
+#[derive(Debug, Error)]
+pub enum OptionError {
+    #[error("resolution {0:?} is not a whole number of dots per inch")]
+    Resolution(String),
+    #[error("pdf_settings {0:?} is none of screen, ebook, print and prepress")]
+    PdfSettings(String),
+    #[error("compatibility_level {0:?} is none of {}", COMPATIBILITY_LEVELS.join(", "))]
+    CompatibilityLevel(String),
+    #[error("max_size {0:?} is not a size like 10M, 500K or 2000000")]
+    MaxSize(String),
+}
+
+/// A size in bytes written as a number of bytes, or of kilobytes, megabytes
+/// or gigabytes with a `K`, `M` or `G` suffix (`10M`, `2.5MB`); these are
+/// decimal, as in the sizes logged.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let number = text.trim_end_matches(['B', 'b']);
+    let (number, unit) = match number.char_indices().last() {
+        Some((at, 'K' | 'k')) => (&number[..at], 1e3),
+        Some((at, 'M' | 'm')) => (&number[..at], 1e6),
+        Some((at, 'G' | 'g')) => (&number[..at], 1e9),
+        _ => (number, 1.0),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number > 0.0)
+        .map(|number| (number * unit) as u64)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("{text:?} is not a size like 10M, 500K or 2000000"))
+}
+
+/// The resolutions `--max-size` tries in turn, each lower than the last,
+/// until an output fits.
+const RESOLUTION_STEPS: [u32; 6] = [300, 200, 150, 100, 72, 50];
+
+/// How a slice is optimized: the run's `--resolution`, `--pdf-settings`,
+/// `--compatibility-level` and Ghostscript arguments, each of which the
+/// slice's column of the same name overrides (or, for `gs_args`, adds to).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OptimizeOptions {
+    /// Images are downsampled to this many dots per inch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<u32>,
+    /// The Ghostscript preset to start from, in place of the variant's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf_settings: Option<Variant>,
+    /// 1.7 unless set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compatibility_level: Option<String>,
+    /// Extra arguments, after the others; placeholders like `{description}`
+    /// are filled in per slice.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gs_args: Vec<String>,
+    /// The size in bytes outputs must fit; lower resolutions are tried
+    /// until they do.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+}
+
+impl OptimizeOptions {
+    /// These options as overridden by the `resolution`, `pdf_settings`,
+    /// `compatibility_level`, `gs_args` (split at whitespace) and `max_size`
+    /// cells of a slice request; empty cells keep the run's.
+    pub fn for_slice(
+        &self,
+        fields: &BTreeMap<String, String>,
+    ) -> Result<OptimizeOptions, OptionError> {
+        let cell = |column: &str| {
+            fields
+                .get(column)
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+        };
+        let mut options = self.clone();
+        if let Some(resolution) = cell("resolution") {
+            let parsed = resolution
+                .parse::<u32>()
+                .ok()
+                .filter(|&resolution| resolution > 0);
+            options.resolution =
+                Some(parsed.ok_or_else(|| OptionError::Resolution(resolution.to_string()))?);
+        }
+        if let Some(pdf_settings) = cell("pdf_settings") {
+            let preset = Variant::from_str(pdf_settings.trim_start_matches('/'), true)
+                .map_err(|_| OptionError::PdfSettings(pdf_settings.to_string()))?;
+            options.pdf_settings = Some(preset);
+        }
+        if let Some(level) = cell("compatibility_level") {
+            if !COMPATIBILITY_LEVELS.contains(&level) {
+                return Err(OptionError::CompatibilityLevel(level.to_string()));
+            }
+            options.compatibility_level = Some(level.to_string());
+        }
+        if let Some(gs_args) = cell("gs_args") {
+            options
+                .gs_args
+                .extend(gs_args.split_whitespace().map(String::from));
+        }
+        if let Some(max_size) = cell("max_size") {
+            let parsed =
+                parse_size(max_size).map_err(|_| OptionError::MaxSize(max_size.to_string()))?;
+            options.max_size = Some(parsed);
+        }
+        Ok(options)
+    }
+
+    /// The options to try when `variant`'s output came out over `max_size`:
+    /// the next lower of the `RESOLUTION_STEPS`, with the `ebook` or `screen`
+    /// preset once the resolution is down to theirs. `None` once the lowest
+    /// has been tried.
+    pub fn lower_quality(&self, variant: Option<Variant>) -> Option<OptimizeOptions> {
+        let current = self.max_dpi(variant).unwrap_or(f32::INFINITY);
+        let resolution = RESOLUTION_STEPS
+            .into_iter()
+            .find(|&step| (step as f32) < current)?;
+        let preset = [Variant::Screen, Variant::Ebook]
+            .into_iter()
+            .find(|preset| resolution as f32 <= preset.max_dpi())
+            .filter(|preset| {
+                self.preset(variant)
+                    .map_or(true, |current| preset.max_dpi() < current.max_dpi())
+            })
+            .or(self.pdf_settings);
+        Some(OptimizeOptions {
+            resolution: Some(resolution),
+            pdf_settings: preset,
+            ..self.clone()
+        })
+    }
+
+    /// The preset for `variant`'s output: the one set here, else the
+    /// variant's own.
+    pub fn preset(&self, variant: Option<Variant>) -> Option<Variant> {
+        self.pdf_settings.or(variant)
+    }
+
+    /// The Ghostscript arguments for the preset, compatibility level and
+    /// resolution, before `gs_args`.
+    pub fn ghostscript_args(&self, variant: Option<Variant>) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(preset) = self.preset(variant) {
+            args.push(format!("-dPDFSETTINGS={}", preset.pdf_settings()));
+        }
+        args.push(format!(
+            "-dCompatibilityLevel={}",
+            self.compatibility_level.as_deref().unwrap_or("1.7")
+        ));
+        if let Some(resolution) = self.resolution {
+            for kind in ["Color", "Gray", "Mono"] {
+                args.push(format!("-dDownsample{kind}Images=true"));
+                args.push(format!("-d{kind}ImageResolution={resolution}"));
+            }
+        }
+        args
+    }
+
+    /// The resolution the builtin optimizer downsamples images to: the one set
+    /// here, else the preset's.
+    pub fn max_dpi(&self, variant: Option<Variant>) -> Option<f32> {
+        self.resolution
+            .map(|resolution| resolution as f32)
+            .or_else(|| self.preset(variant).map(Variant::max_dpi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Args;
+    use clap::Parser;
+
+    /// A lookup that finds only `installed`, as `on_path` would on a
+    /// machine with nothing else, and the programs it was asked for.
+    fn lookup<'a>(
+        installed: &'a [&'a str],
+        asked: &'a std::cell::RefCell<Vec<PathBuf>>,
+    ) -> impl Fn(&Path) -> Option<PathBuf> + 'a {
+        move |program| {
+            asked.borrow_mut().push(program.to_path_buf());
+            installed
+                .iter()
+                .any(|name| Path::new(name) == program)
+                .then(|| Path::new("/usr/bin").join(program))
+        }
+    }
+
+    #[test]
+    fn gs_path_takes_precedence_over_npch_gs() {
+        std::env::set_var("NPCH_GS", "/from/env/gs");
+        let given = Args::try_parse_from(["npch_slicer", "--gs-path", "/given/gs"]).unwrap();
+        let from_env = Args::try_parse_from(["npch_slicer"]).unwrap();
+        std::env::remove_var("NPCH_GS");
+        assert_eq!(given.gs_path.as_deref(), Some(Path::new("/given/gs")));
+        assert_eq!(from_env.gs_path.as_deref(), Some(Path::new("/from/env/gs")));
+    }
+
+    #[test]
+    fn a_given_ghostscript_takes_precedence_over_the_path() {
+        let asked = Default::default();
+        let installed = ["gs", "/opt/gs/bin/gs"];
+        let found = locate(
+            Some(Path::new("/opt/gs/bin/gs")),
+            lookup(&installed, &asked),
+        );
+        assert_eq!(found.unwrap(), Path::new("/opt/gs/bin/gs"));
+        assert_eq!(*asked.borrow(), [PathBuf::from("/opt/gs/bin/gs")]);
+    }
+
+    #[test]
+    fn a_missing_given_ghostscript_is_not_looked_for_on_the_path() {
+        let asked = Default::default();
+        let found = locate(Some(Path::new("/opt/gs")), lookup(&["gs"], &asked));
+        assert!(
+            matches!(found, Err(GhostscriptError::Missing(path)) if path == Path::new("/opt/gs"))
+        );
+    }
+
+    #[test]
+    fn candidates_are_tried_in_order() {
+        for (installed, expected) in [
+            (&["gs", "gswin64c", "gswin32c"][..], "gs"),
+            (&["gswin32c", "gswin64c"], "gswin64c"),
+            (&["gswin32c"], "gswin32c"),
+        ] {
+            let asked = Default::default();
+            let found = locate(None, lookup(installed, &asked)).unwrap();
+            assert_eq!(found, Path::new("/usr/bin").join(expected));
+            let tried = CANDIDATES
+                .iter()
+                .take_while(|&&candidate| candidate != expected)
+                .chain([&expected])
+                .map(PathBuf::from)
+                .collect::<Vec<PathBuf>>();
+            assert_eq!(*asked.borrow(), tried);
+        }
+    }
+
+    #[test]
+    fn nothing_found_is_an_error() {
+        let asked = Default::default();
+        assert!(matches!(
+            locate(None, lookup(&[], &asked)),
+            Err(GhostscriptError::NotFound)
+        ));
+        assert_eq!(asked.borrow().len(), CANDIDATES.len());
+    }
+}
@@ -0,0 +1,205 @@
+//! `--incremental`: what each slice was last cut from, kept with the outputs
+//! so that slices whose source, request and settings are all unchanged are
+//! not cut again.
+
+use crate::revision::RevisionError;
+use npch_slicer::digest::hex;
+use npch_slicer::encryption::{password_digest, random};
+use npch_slicer::schema::{self, SCHEMA_VERSION};
+use npch_slicer::SliceRequest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// The key of every slice cut, by output name, as of the last run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    schema_version: u32,
+    /// What owner passwords are digested with for the keys, so that the
+    /// manifest doesn't give them away; random, and kept from run to run.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    slices: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(".npch_manifest.json")
+    }
+
+    /// The manifest recorded at `path`; an empty one if there is none yet.
+    pub fn read(path: &Path) -> Result<Manifest, RevisionError> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Manifest::default()),
+            Err(error) => return Err(RevisionError::Read(error)),
+        };
+        let manifest =
+            serde_json::from_slice::<Manifest>(&contents).map_err(RevisionError::Syntax)?;
+        schema::check_version(&path.display().to_string(), manifest.schema_version)?;
+        Ok(manifest)
+    }
+
+    /// The salt of the manifest's keys, made up the first time.
+    pub fn salt(&mut self) -> io::Result<&str> {
+        if self.salt.is_empty() {
+            self.salt = hex(&random::<16>()?);
+        }
+        Ok(&self.salt)
+    }
+
+    /// Whether the slice named `output_name` was last cut with `key`.
+    pub fn is_current(&self, output_name: &str, key: &str) -> bool {
+        self.slices.get(output_name).is_some_and(|last| last == key)
+    }
+
+    /// Records the slices cut this run, then keeps only those still requested.
+    pub fn update<'a>(
+        &mut self,
+        cut: impl IntoIterator<Item = (&'a str, String)>,
+        requested: &[&str],
+    ) {
+        self.schema_version = SCHEMA_VERSION;
+        self.slices.extend(
+            cut.into_iter()
+                .map(|(output_name, key)| (output_name.to_string(), key)),
+        );
+        self.slices
+            .retain(|output_name, _| requested.contains(&output_name.as_str()));
+    }
+
+    pub fn forget(&mut self, output_name: &str) {
+        self.slices.remove(output_name);
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+}
+
+/// What every slice of a run is cut from: the source, by its SHA-256, and the
+/// settings shaping the outputs.
+pub struct Inputs {
+    state: Sha256,
+    salt: String,
+}
+
+/// Stands for an owner password in the settings keys are made from.
+pub fn password_key(password: &str, salt: &str) -> String {
+    hex(&password_digest(password, salt.as_bytes()))
+}
+
+impl Inputs {
+    /// The inputs of `source` and `settings`, whose passwords must already
+    /// be digested with `salt`, as `password_key` does.
+    pub fn new(source: &Path, settings: &impl Serialize, salt: &str) -> io::Result<Inputs> {
+        let mut file = std::fs::File::open(source)?;
+        let mut source = Sha256::new();
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            match file.read(&mut buffer)? {
+                0 => break,
+                read => source.update(&buffer[..read]),
+            }
+        }
+        let mut state = Sha256::new();
+        state.update(hex(&source.finalize()).as_bytes());
+        state.update(b"\n");
+        state.update(serde_json::to_string(settings).unwrap().as_bytes());
+        Ok(Inputs {
+            state,
+            salt: salt.to_string(),
+        })
+    }
+
+    /// The key of `slice_request` cut from these inputs: it changes with the
+    /// source, the settings, and any of the request's columns, an
+    /// `owner_password` column by its digest.
+    pub fn key(&self, slice_request: &SliceRequest) -> String {
+        let mut state = self.state.clone();
+        let mut fields = slice_request.fields.clone();
+        if let Some(password) = fields
+            .get_mut("owner_password")
+            .filter(|password| !password.is_empty())
+        {
+            *password = password_key(password, &self.salt);
+        }
+        for part in [
+            serde_json::to_string(slice_request).unwrap(),
+            serde_json::to_string(&fields).unwrap(),
+        ] {
+            state.update(b"\n");
+            state.update(part.as_bytes());
+        }
+        hex(&state.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use npch_slicer::pages::PageRanges;
+
+    fn request(owner_password: &str) -> SliceRequest {
+        SliceRequest {
+            description: String::from("Blank"),
+            pages: PageRanges::from(9..10),
+            title: None,
+            author: None,
+            subject: None,
+            keywords: None,
+            expected_pages: None,
+            must_contain: None,
+            file_name: None,
+            fields: BTreeMap::from([
+                (String::from("description"), String::from("Blank")),
+                (String::from("owner_password"), owner_password.to_string()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn owner_password_columns_are_keyed_by_their_salted_digest() {
+        let source =
+            std::env::temp_dir().join(format!("npch_slicer-{}-source", std::process::id()));
+        std::fs::write(&source, b"%PDF-1.7").unwrap();
+        let inputs = |salt: &str| Inputs::new(&source, &"settings", salt).unwrap();
+        let (salted, resalted) = (inputs("salt"), inputs("other salt"));
+        std::fs::remove_file(&source).unwrap();
+
+        let key = salted.key(&request("secret"));
+        assert_eq!(key, salted.key(&request("secret")));
+        assert_ne!(key, salted.key(&request("guess")));
+        assert_ne!(key, resalted.key(&request("secret")));
+        let mut digested = request("secret");
+        digested.fields.insert(
+            String::from("owner_password"),
+            password_key("secret", "salt"),
+        );
+        // The key is made from the digest, never the password.
+        assert_eq!(key, {
+            let mut state = salted.state.clone();
+            for part in [
+                serde_json::to_string(&digested).unwrap(),
+                serde_json::to_string(&digested.fields).unwrap(),
+            ] {
+                state.update(b"\n");
+                state.update(part.as_bytes());
+            }
+            hex(&state.finalize())
+        });
+    }
+
+    #[test]
+    fn the_salt_is_made_once_and_kept() {
+        let mut manifest = Manifest::default();
+        let salt = manifest.salt().unwrap().to_string();
+        assert_eq!(salt.len(), 32);
+        assert_eq!(manifest.salt().unwrap(), salt);
+        let written = serde_json::to_string(&manifest).unwrap();
+        let mut read = serde_json::from_str::<Manifest>(&written).unwrap();
+        assert_eq!(read.salt().unwrap(), salt);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::limits::{self, Limits};
+use crate::text::{decode_text_string, text_string};
+use lopdf::{Dictionary, Document, Object, Stream};
+
+/// The document's information dictionary (title, keywords and the like).
+fn info_dictionary(document: &mut Document) -> &mut Dictionary {
+    let existing = match document.trailer.get(b"Info") {
+        Ok(Object::Reference(info_id)) => Some(*info_id),
+        _ => None,
+    }
+    .filter(|info_id| document.get_dictionary(*info_id).is_ok());
+
+    let info_id = existing.unwrap_or_else(|| {
+        let info = document
+            .trailer
+            .get(b"Info")
+            .and_then(Object::as_dict)
+            .cloned()
+            .unwrap_or_default();
+        let info_id = document.add_object(info);
+        document.trailer.set("Info", info_id);
+        info_id
+    });
+
+    document.get_dictionary_mut(info_id).unwrap()
+}
+
+/// Sets `key` of the document information to `value`, creating the
+/// dictionary if the document has none.
+pub fn set_entry(document: &mut Document, key: &str, value: &str) {
+    info_dictionary(document).set(key, text_string(value));
+}
+
+/// The text of `key` in the document information, if it has one.
+pub fn entry(document: &Document, key: &str) -> Option<String> {
+    document
+        .trailer
+        .get(b"Info")
+        .and_then(|info| document.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .and_then(|info| info.get(key.as_bytes()))
+        .and_then(Object::as_str)
+        .map(decode_text_string)
+        .ok()
+}
+
+/// The entries of the document information that the XMP packet repeats, with
+/// the property holding each and whether it is a language alternative (dc
+/// text), an ordered list (dc:creator) or plain text.
+const XMP_PROPERTIES: [(&str, &str, XmpKind); 6] = [
+    ("Title", "dc:title", XmpKind::LangAlt),
+    ("Author", "dc:creator", XmpKind::Seq),
+    ("Subject", "dc:description", XmpKind::LangAlt),
+    ("Keywords", "pdf:Keywords", XmpKind::Text),
+    ("Creator", "xmp:CreatorTool", XmpKind::Text),
+    ("Producer", "pdf:Producer", XmpKind::Text),
+];
+
+#[derive(Clone, Copy)]
+enum XmpKind {
+    LangAlt,
+    Seq,
+    Text,
+}
+
+/// Replaces the document's XMP metadata with a packet saying what its
+/// document information says, so that readers going by the XMP (as most
+/// do, when there is one) show the same title. A PDF/A identification in the
+/// old packet is carried over; the rest of it is dropped.
+pub fn sync_xmp(document: &mut Document, limits: &Limits) {
+    let Ok(catalog) = document.catalog() else {
+        return;
+    };
+    let existing = catalog.get(b"Metadata").and_then(Object::as_reference).ok();
+    let old = existing
+        .and_then(|id| document.get_object(id).ok())
+        .and_then(|object| object.as_stream().ok())
+        .map(|stream| {
+            let content =
+                limits::decompressed(stream, limits).unwrap_or_else(|_| stream.content.clone());
+            String::from_utf8_lossy(&content).into_owned()
+        })
+        .unwrap_or_default();
+
+    let mut attributes = String::new();
+    for (property, valid) in [
+        ("pdfaid:part", char::is_ascii_digit as fn(&char) -> bool),
+        ("pdfaid:conformance", char::is_ascii_alphabetic),
+    ] {
+        if let Some(value) = xmp_value(&old, property, valid) {
+            attributes.push_str(&format!("\n    {property}=\"{value}\""));
+        }
+    }
+    let mut properties = String::new();
+    for (key, property, kind) in XMP_PROPERTIES {
+        let Some(value) = entry(document, key) else {
+            continue;
+        };
+        let value = escape_xml(&value);
+        properties.push_str(&match kind {
+            XmpKind::LangAlt => format!(
+                "   <{property}><rdf:Alt><rdf:li xml:lang=\"x-default\">{value}</rdf:li></rdf:Alt></{property}>\n"
+            ),
+            XmpKind::Seq => {
+                format!("   <{property}><rdf:Seq><rdf:li>{value}</rdf:li></rdf:Seq></{property}>\n")
+            }
+            XmpKind::Text => format!("   <{property}>{value}</{property}>\n"),
+        });
+    }
+    let packet = format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\"\n    \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n    \
+         xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n    \
+         xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n    \
+         xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"{attributes}>\n\
+         {properties}\
+         </rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>"
+    );
+
+    let mut dictionary = Dictionary::new();
+    dictionary.set("Type", Object::Name(b"Metadata".to_vec()));
+    dictionary.set("Subtype", Object::Name(b"XML".to_vec()));
+    let stream = Object::Stream(Stream::new(dictionary, packet.into_bytes()));
+    let id = match existing {
+        Some(id) => {
+            document.objects.insert(id, stream);
+            id
+        }
+        None => document.add_object(stream),
+    };
+    if let Ok(catalog) = document.catalog_mut() {
+        catalog.set("Metadata", id);
+    }
+}
+
+/// The value of `property` in an XMP packet, written either as an attribute
+/// or as an element, if all its characters are `valid`.
+fn xmp_value(packet: &str, property: &str, valid: fn(&char) -> bool) -> Option<String> {
+    let start = packet.find(property)? + property.len();
+    let value = packet[start..]
+        .trim_start_matches(['=', '"', '\'', '>', ' '])
+        .chars()
+        .take_while(valid)
+        .collect::<String>();
+    (!value.is_empty()).then_some(value)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
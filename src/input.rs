@@ -0,0 +1,22 @@
+use flate2::read::GzDecoder;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Reads an input file, transparently decompressing `.gz` and `.zst` files.
+pub fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    let file = std::fs::File::open(path)?;
+    let mut contents = Vec::new();
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => GzDecoder::new(file).read_to_end(&mut contents)?,
+        #[cfg(feature = "zstd")]
+        Some("zst") => zstd::Decoder::new(file)?.read_to_end(&mut contents)?,
+        #[cfg(not(feature = "zstd"))]
+        Some("zst") => {
+            return Err(io::Error::other(
+                "reading .zst files needs the zstd feature, which this build lacks",
+            ))
+        }
+        _ => io::BufReader::new(file).read_to_end(&mut contents)?,
+    };
+    Ok(contents)
+}
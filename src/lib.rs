@@ -0,0 +1,76 @@
+pub mod acroform;
+pub mod content;
+pub mod digest;
+pub mod encryption;
+pub mod fingerprint;
+pub mod info;
+pub mod limits;
+pub mod links;
+pub mod merge;
+pub mod optimize;
+pub mod outline;
+pub mod page;
+pub mod page_labels;
+pub mod pages;
+pub mod pdf;
+pub mod request;
+pub mod schema;
+pub mod slicer;
+pub mod spill;
+pub mod stamp;
+pub mod structure;
+pub mod subset;
+pub mod text;
+pub mod transform;
+pub mod updates;
+
+pub use request::{SliceRequest, SliceRequests};
+pub use slicer::Slicer;
+
+use lopdf::{Document, ObjectId};
+use outline::OutlineItem;
+use pages::PageRanges;
+use pdf::PdfError;
+use std::collections::BTreeSet;
+
+/// How deep the outline, form field and name trees are followed. Real
+/// documents stay in single digits; hostile ones can nest deep enough to
+/// overflow the stack.
+pub const MAX_NESTING: usize = 64;
+
+/// Builds the slice of `document` holding `pages` (1-based), keeping the form
+/// fields, outline entries, internal links and structure elements that still
+/// lead somewhere. Only the objects the slice needs are copied out of
+/// `document`.
+pub fn extract_pages(
+    document: &Document,
+    source_outline: &[OutlineItem],
+    pages: &PageRanges,
+    outline_depth: Option<usize>,
+) -> Document {
+    let kept_pages = document
+        .get_pages()
+        .into_iter()
+        .filter(|(page, _)| pages.contains(*page))
+        .map(|(_, page_id)| page_id)
+        .collect::<BTreeSet<ObjectId>>();
+    let (mut slice_pdf, dropped) = subset::copy_pages(document, &kept_pages);
+    links::retain_links(&mut slice_pdf, &kept_pages);
+    structure::retain_slice_structure(&mut slice_pdf, &kept_pages);
+    subset::strip(&mut slice_pdf, &dropped);
+    acroform::retain_slice_fields(&mut slice_pdf);
+    let slice_outline = outline::rebase(source_outline, &kept_pages, outline_depth);
+    outline::write_outline(&mut slice_pdf, &slice_outline);
+    slice_pdf.prune_objects();
+    slice_pdf
+}
+
+/// The whole load → slice → save path over in-memory bytes. Malformed input
+/// comes back as an error rather than a panic, which is what the fuzz
+/// harness in `fuzz/` checks.
+pub fn slice_bytes(data: &[u8], pages: &PageRanges) -> Result<Vec<u8>, PdfError> {
+    let document = pdf::load_mem(data, &limits::Limits::default())?;
+    let source_outline = outline::read_outline(&document);
+    let mut slice_pdf = extract_pages(&document, &source_outline, pages, None);
+    pdf::save_mem(&mut slice_pdf)
+}
@@ -0,0 +1,390 @@
+use crate::MAX_NESTING;
+use flate2::read::ZlibDecoder;
+use lopdf::filters::png;
+use lopdf::{Dictionary, Object, Stream};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// Bounds on what a source PDF may make the loader do, checked before and
+/// right after lopdf parses it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Indirect objects in the file, object streams included.
+    pub max_objects: usize,
+    /// Decompressed size of a single stream: object streams, which lopdf
+    /// inflates while loading, and page content, images and metadata
+    /// whenever they are decoded later.
+    pub max_stream_bytes: u64,
+    /// How deeply arrays and dictionaries may nest; the parser recurses on
+    /// each level.
+    pub max_nesting: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_objects: 1_000_000,
+            max_stream_bytes: 256 * 1024 * 1024,
+            max_nesting: MAX_NESTING,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("{count} objects is more than the limit of {max}")]
+    TooManyObjects { count: usize, max: usize },
+    #[error("a stream at byte {offset} inflates past the limit of {max} bytes")]
+    StreamTooLarge { offset: usize, max: u64 },
+    #[error("arrays and dictionaries nest more than {max} deep at byte {offset}")]
+    TooDeep { offset: usize, max: usize },
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("it decodes past the limit of {0} bytes")]
+    TooLarge(u64),
+    #[error("its {0} data is corrupt")]
+    Corrupt(&'static str),
+    #[error("its /Filter is not one lopdf decodes")]
+    Unsupported,
+    #[error(transparent)]
+    Pdf(#[from] lopdf::Error),
+}
+
+/// A writer that takes up to `max` bytes and fails past them.
+struct Capped {
+    written: Vec<u8>,
+    max: u64,
+}
+
+impl Write for Capped {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if (self.written.len() + data.len()) as u64 > self.max {
+            return Err(io::Error::other("past the limit"));
+        }
+        self.written.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn inflate_within(data: &[u8], max: u64) -> Result<Vec<u8>, DecodeError> {
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(data)
+        .take(max + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|_| DecodeError::Corrupt("Flate"))?;
+    match inflated.len() as u64 > max {
+        true => Err(DecodeError::TooLarge(max)),
+        false => Ok(inflated),
+    }
+}
+
+fn lzw_within(data: &[u8], params: Option<&Dictionary>, max: u64) -> Result<Vec<u8>, DecodeError> {
+    use weezl::{decode::Decoder, BitOrder};
+    let early_change = params
+        .and_then(|params| params.get(b"EarlyChange").and_then(Object::as_i64).ok())
+        != Some(0);
+    let mut decoder = match early_change {
+        true => Decoder::with_tiff_size_switch(BitOrder::Msb, 8),
+        false => Decoder::new(BitOrder::Msb, 8),
+    };
+    let mut capped = Capped {
+        written: Vec::new(),
+        max,
+    };
+    let status = decoder.into_stream(&mut capped).decode_all(data).status;
+    match status {
+        Err(error) if error.kind() == io::ErrorKind::Other => Err(DecodeError::TooLarge(max)),
+        // lopdf keeps what decodes of broken LZW data too.
+        _ => Ok(capped.written),
+    }
+}
+
+/// Undoes a PNG predictor as lopdf does; it never grows the data.
+fn unpredict(data: Vec<u8>, params: Option<&Dictionary>) -> Result<Vec<u8>, DecodeError> {
+    let Some(params) = params else {
+        return Ok(data);
+    };
+    let entry =
+        |key: &[u8], default: i64| params.get(key).and_then(Object::as_i64).unwrap_or(default);
+    if !(10..=15).contains(&entry(b"Predictor", 1)) {
+        return Ok(data);
+    }
+    let columns = entry(b"Columns", 1).max(1) as usize;
+    let colors = entry(b"Colors", 1).max(1) as usize;
+    let bits = entry(b"BitsPerComponent", 8).max(8) as usize;
+    png::decode_frame(&data, colors * bits / 8, columns)
+        .map_err(|_| DecodeError::Corrupt("predictor"))
+}
+
+/// The data of `stream` with its filters undone, as lopdf's
+/// `decompressed_content` does for page content and metadata, but stopping
+/// as soon as a filter's output grows past `limits.max_stream_bytes`
+/// rather than once memory runs out. Unlike lopdf's, corrupt Flate data is
+/// an error, and images are decoded too.
+pub fn decompressed(stream: &Stream, limits: &Limits) -> Result<Vec<u8>, DecodeError> {
+    if !stream.dict.has(b"Filter") {
+        return Ok(stream.content.clone());
+    }
+    let max = limits.max_stream_bytes;
+    let params = stream
+        .dict
+        .get(b"DecodeParms")
+        .and_then(Object::as_dict)
+        .ok();
+    let mut data = None;
+    for filter in stream.filters()? {
+        let input = data.as_deref().unwrap_or(stream.content.as_slice());
+        let decoded = match filter.as_str() {
+            "FlateDecode" => inflate_within(input, max)?,
+            "LZWDecode" => lzw_within(input, params, max)?,
+            _ => return Err(DecodeError::Unsupported),
+        };
+        data = Some(unpredict(decoded, params)?);
+    }
+    data.ok_or(DecodeError::Unsupported)
+}
+
+fn is_regular(byte: u8) -> bool {
+    !byte.is_ascii_whitespace() && !b"()<>[]{}/%".contains(&byte)
+}
+
+fn keyword_at(data: &[u8], at: usize, keyword: &[u8]) -> bool {
+    data[at..].starts_with(keyword)
+        && (at == 0 || !is_regular(data[at - 1]))
+        && data
+            .get(at + keyword.len())
+            .map_or(true, |byte| !is_regular(*byte))
+}
+
+fn find(data: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| from + position)
+}
+
+fn skip_literal_string(data: &[u8], mut at: usize) -> usize {
+    let mut open = 0;
+    while let Some(byte) = data.get(at) {
+        match byte {
+            b'\\' => at += 1,
+            b'(' => open += 1,
+            b')' => {
+                open -= 1;
+                if open == 0 {
+                    return at + 1;
+                }
+            }
+            _ => {}
+        }
+        at += 1;
+    }
+    at
+}
+
+fn inflate(data: &[u8], offset: usize, max: u64) -> Result<Vec<u8>, LimitError> {
+    let mut inflated = Vec::new();
+    // Streams lopdf can only partly inflate are loaded as far as they go, so
+    // a decoding error just ends the check.
+    let _ = io::copy(&mut ZlibDecoder::new(data).take(max + 1), &mut inflated);
+    if inflated.len() as u64 > max {
+        return Err(LimitError::StreamTooLarge { offset, max });
+    }
+    Ok(inflated)
+}
+
+/// The integer after `key` in the dictionary `header`, e.g. an object
+/// stream's `/N`.
+fn integer_after(header: &[u8], key: &[u8]) -> Option<usize> {
+    let mut from = 0;
+    while let Some(at) = find(header, from, key) {
+        from = at + key.len();
+        if header.get(from).is_some_and(|&byte| is_regular(byte)) {
+            continue;
+        }
+        let digits = header[from..]
+            .iter()
+            .skip_while(|byte| byte.is_ascii_whitespace())
+            .take_while(|byte| byte.is_ascii_digit())
+            .map(|&byte| byte as char)
+            .collect::<String>();
+        return digits.parse().ok();
+    }
+    None
+}
+
+/// Walks the raw file syntax the way the parser will, without building any
+/// objects: nesting is counted outside strings and comments, stream data is
+/// skipped, and object streams are inflated (up to the limit) and walked too,
+/// as cross-reference streams are inflated. The objects are counted along
+/// the way, those in object streams by their `/N`.
+pub fn scan(data: &[u8], limits: &Limits) -> Result<(), LimitError> {
+    scan_counting(data, limits, &mut 0)
+}
+
+fn scan_counting(data: &[u8], limits: &Limits, count: &mut usize) -> Result<(), LimitError> {
+    let mut depth = 0usize;
+    let mut object_start = 0;
+    let mut at = 0;
+    while at < data.len() {
+        match data[at] {
+            b'%' => {
+                while at < data.len() && !b"\r\n".contains(&data[at]) {
+                    at += 1;
+                }
+            }
+            b'(' => {
+                at = skip_literal_string(data, at);
+                continue;
+            }
+            b'[' => depth += 1,
+            b'<' if data.get(at + 1) == Some(&b'<') => {
+                depth += 1;
+                at += 1;
+            }
+            b'<' => {
+                at = find(data, at, b">").unwrap_or(data.len());
+            }
+            b']' => depth = depth.saturating_sub(1),
+            b'>' if data.get(at + 1) == Some(&b'>') => {
+                depth = depth.saturating_sub(1);
+                at += 1;
+            }
+            b'o' if keyword_at(data, at, b"obj") => {
+                object_start = at;
+                *count += 1;
+                check_objects(*count, limits)?;
+            }
+            b's' if keyword_at(data, at, b"stream") => {
+                let start = match data.get(at + 6..at + 8) {
+                    Some(b"\r\n") => at + 8,
+                    _ => at + 7,
+                };
+                let end = find(data, start, b"endstream").unwrap_or(data.len());
+                let header = &data[object_start..at];
+                if find(header, 0, b"/ObjStm").is_some() {
+                    *count += integer_after(header, b"/N").unwrap_or(0);
+                    check_objects(*count, limits)?;
+                    let content = data.get(start..end).unwrap_or_default();
+                    if find(header, 0, b"/FlateDecode").is_some() {
+                        let inflated = inflate(content, at, limits.max_stream_bytes)?;
+                        scan_counting(&inflated, limits, count)?;
+                    } else {
+                        scan_counting(content, limits, count)?;
+                    }
+                } else if find(header, 0, b"/XRef").is_some()
+                    && find(header, 0, b"/FlateDecode").is_some()
+                {
+                    let content = data.get(start..end).unwrap_or_default();
+                    inflate(content, at, limits.max_stream_bytes)?;
+                }
+                at = end + b"endstream".len();
+                continue;
+            }
+            _ => {}
+        }
+        if depth > limits.max_nesting {
+            return Err(LimitError::TooDeep {
+                offset: at,
+                max: limits.max_nesting,
+            });
+        }
+        at += 1;
+    }
+    Ok(())
+}
+
+pub fn check_objects(count: usize, limits: &Limits) -> Result<(), LimitError> {
+    if count > limits.max_objects {
+        return Err(LimitError::TooManyObjects {
+            count,
+            max: limits.max_objects,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use lopdf::dictionary;
+
+    fn limits(max_objects: usize, max_stream_bytes: u64) -> Limits {
+        Limits {
+            max_objects,
+            max_stream_bytes,
+            ..Limits::default()
+        }
+    }
+
+    fn deflated(content: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn streams_inflating_past_the_limit_are_refused() {
+        let stream = Stream::new(
+            dictionary! { "Filter" => "FlateDecode" },
+            deflated(&[0; 4096]),
+        );
+        assert!(matches!(
+            decompressed(&stream, &limits(10, 1024)),
+            Err(DecodeError::TooLarge(1024))
+        ));
+        assert_eq!(decompressed(&stream, &limits(10, 4096)).unwrap(), [0; 4096]);
+    }
+
+    #[test]
+    fn lzw_streams_decode_within_the_limit() {
+        let content = b"BT /F1 12 Tf (Hello) Tj ET ".repeat(20);
+        let encoded = weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+            .encode(&content)
+            .unwrap();
+        let stream = Stream::new(dictionary! { "Filter" => "LZWDecode" }, encoded);
+        assert_eq!(decompressed(&stream, &limits(10, 1024)).unwrap(), content);
+        assert!(matches!(
+            decompressed(&stream, &limits(10, 100)),
+            Err(DecodeError::TooLarge(100))
+        ));
+    }
+
+    #[test]
+    fn objects_are_counted_while_scanning() {
+        let data = b"1 0 obj 1 endobj 2 0 obj 2 endobj 3 0 obj 3 endobj";
+        assert!(scan(data, &limits(3, 1024)).is_ok());
+        assert!(matches!(
+            scan(data, &limits(2, 1024)),
+            Err(LimitError::TooManyObjects { count: 3, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn objects_in_object_streams_count_too() {
+        let objects = b"1 0 2 2 (a) (b)";
+        let mut data =
+            b"3 0 obj << /Type /ObjStm /N 2 /First 8 /Filter /FlateDecode >> stream\n".to_vec();
+        data.extend(deflated(objects));
+        data.extend(b"\nendstream endobj");
+        assert!(scan(&data, &limits(3, 1024)).is_ok());
+        assert!(matches!(
+            scan(&data, &limits(2, 1024)),
+            Err(LimitError::TooManyObjects { count: 3, max: 2 })
+        ));
+        assert!(matches!(
+            scan(&data, &limits(3, 8)),
+            Err(LimitError::StreamTooLarge { max: 8, .. })
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+//! Trimming a slice's internal links and named destinations down to the ones
+//! that still lead to one of its pages.
+
+use crate::outline::{self, destination_target};
+use crate::text::decode_text_string;
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeSet;
+
+/// Removes the link annotations on `retained` pages that go to a page not in
+/// `retained` (or nowhere), and the named destinations that do. Links to
+/// other documents and the web are kept. Meant to run before the other pages
+/// are deleted, while every destination still names its page: deleting a
+/// page strips references to it, leaving destinations that point nowhere.
+pub fn retain_links(document: &mut Document, retained: &BTreeSet<ObjectId>) {
+    let named = outline::named_destinations(document);
+    let live = named
+        .iter()
+        .filter(|(_, destination)| {
+            destination_target(destination).is_some_and(|page| retained.contains(&page))
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<BTreeSet<String>>();
+
+    for &page_id in retained {
+        let Some(annots) = document
+            .get_dictionary(page_id)
+            .and_then(|page| page.get(b"Annots"))
+            .ok()
+            .cloned()
+        else {
+            continue;
+        };
+        let annots = edit(document, &annots, |document, annots| {
+            if let Object::Array(annots) = annots {
+                annots.retain(|annot| {
+                    let Some(annot) = resolve(document, annot).and_then(|a| a.as_dict().ok())
+                    else {
+                        return true;
+                    };
+                    if !is_internal_link(document, annot) {
+                        return true;
+                    }
+                    outline::item_destination(document, annot, &named)
+                        .as_deref()
+                        .and_then(destination_target)
+                        .is_some_and(|page| retained.contains(&page))
+                });
+            }
+        });
+        if let Ok(page) = document.get_dictionary_mut(page_id) {
+            page.set("Annots", annots);
+        }
+    }
+
+    retain_named_destinations(document, &live);
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    document.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// A link annotation going somewhere within the document, by `/Dest` or a
+/// GoTo action.
+fn is_internal_link(document: &Document, annot: &Dictionary) -> bool {
+    if annot.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link") {
+        return false;
+    }
+    if annot.has(b"Dest") {
+        return true;
+    }
+    annot
+        .get(b"A")
+        .ok()
+        .and_then(|action| resolve(document, action)?.as_dict().ok())
+        .and_then(|action| action.get(b"S").and_then(Object::as_name).ok())
+        == Some(b"GoTo")
+}
+
+/// Applies `edit` to the object `entry` is or refers to, returning what to
+/// store in `entry`'s place: the same reference, or the edited direct object.
+pub(crate) fn edit(
+    document: &mut Document,
+    entry: &Object,
+    edit: impl FnOnce(&mut Document, &mut Object),
+) -> Object {
+    match entry {
+        Object::Reference(id) => {
+            if let Ok(mut object) = document.get_object(*id).cloned() {
+                edit(document, &mut object);
+                document.objects.insert(*id, object);
+            }
+            entry.clone()
+        }
+        _ => {
+            let mut object = entry.clone();
+            edit(document, &mut object);
+            object
+        }
+    }
+}
+
+/// Keeps only the `live` names of the catalog's `/Dests` and of its `/Names`
+/// `/Dests` name tree.
+fn retain_named_destinations(document: &mut Document, live: &BTreeSet<String>) {
+    let Ok(catalog_id) = document.trailer.get(b"Root").and_then(Object::as_reference) else {
+        return;
+    };
+    let Ok(mut catalog) = document.get_dictionary(catalog_id).cloned() else {
+        return;
+    };
+
+    if let Ok(dests) = catalog.get(b"Dests").cloned() {
+        let dests = edit(document, &dests, |_, dests| {
+            if let Object::Dictionary(dests) = dests {
+                let dead = dests
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .filter(|name| !live.contains(&*String::from_utf8_lossy(name)))
+                    .collect::<Vec<Vec<u8>>>();
+                for name in dead {
+                    dests.remove(&name);
+                }
+            }
+        });
+        catalog.set("Dests", dests);
+    }
+
+    if let Ok(names) = catalog.get(b"Names").cloned() {
+        let names = edit(document, &names, |document, names| {
+            let Object::Dictionary(names) = names else {
+                return;
+            };
+            if let Ok(tree) = names.get(b"Dests").cloned() {
+                let tree = edit(document, &tree, |document, tree| {
+                    retain_name_tree(document, tree, live, &mut BTreeSet::new(), 0);
+                });
+                names.set("Dests", tree);
+            }
+        });
+        catalog.set("Names", names);
+    }
+
+    document
+        .objects
+        .insert(catalog_id, Object::Dictionary(catalog));
+}
+
+/// Drops the pairs of the name tree below `node` whose name is not `live`.
+/// The `/Limits` of intermediate nodes are left as they were: they may then
+/// be wider than needed, which readers tolerate.
+fn retain_name_tree(
+    document: &mut Document,
+    node: &mut Object,
+    live: &BTreeSet<String>,
+    visited: &mut BTreeSet<ObjectId>,
+    depth: usize,
+) {
+    let Object::Dictionary(node) = node else {
+        return;
+    };
+    if depth > MAX_NESTING {
+        return;
+    }
+    if let Ok(names) = node.get(b"Names").cloned() {
+        let names = edit(document, &names, |_, names| {
+            if let Object::Array(names) = names {
+                *names = names
+                    .chunks_exact(2)
+                    .filter(|pair| {
+                        pair[0]
+                            .as_str()
+                            .is_ok_and(|name| live.contains(&decode_text_string(name)))
+                    })
+                    .flatten()
+                    .cloned()
+                    .collect();
+            }
+        });
+        node.set("Names", names);
+    }
+
+    let kids = node
+        .get(b"Kids")
+        .ok()
+        .and_then(|kids| resolve(document, kids))
+        .and_then(|kids| kids.as_array().ok())
+        .map(|kids| {
+            kids.iter()
+                .filter_map(|kid| kid.as_reference().ok())
+                .collect::<Vec<ObjectId>>()
+        })
+        .unwrap_or_default();
+    for kid in kids {
+        if visited.insert(kid) {
+            edit(document, &Object::Reference(kid), |document, kid| {
+                retain_name_tree(document, kid, live, visited, depth + 1);
+            });
+        }
+    }
+}
@@ -1,126 +1,874 @@
+mod accessibility;
+mod check;
+mod checksums;
+mod config;
+mod cover;
+mod diff;
+mod effective;
+mod error;
+mod file_names;
+mod ghostscript;
+mod incremental;
+mod input;
+mod master;
+mod package;
+mod page_anchors;
+mod plan;
+mod remap;
+mod report;
+mod resolved;
+mod revision;
+mod sink;
+mod slice_log;
+mod slice_set;
+mod source_version;
+mod sources;
+mod spot_check;
+mod template;
+mod variant;
+
+use checksums::ChecksumFormat;
+use clap::{Parser, Subcommand, ValueEnum};
+use config::{Anchor, Config, SliceEncryption};
+use csv::StringRecord;
+use ghostscript::OptimizeOptions;
+use incremental::{Inputs, Manifest};
 use lopdf::Document;
-use serde::Deserialize;
-use std::cmp::Ordering;
-use std::collections::BTreeSet;
-use std::ops::Sub;
-use std::path::PathBuf;
-use std::process::Command;
-use std::slice::Iter;
+use npch_slicer::content::Unreadable;
+#[cfg(feature = "encryption")]
+use npch_slicer::encryption;
+use npch_slicer::encryption::{Permission, UnknownPermission};
+use npch_slicer::limits::Limits;
+use npch_slicer::optimize::{self, Optimization};
+use npch_slicer::page_labels::PageLabels;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::pdf::{self, PdfError};
+use npch_slicer::request::{CoverageIssue, FromRawError, PageNumbering, RawSliceRequest};
+use npch_slicer::slicer::{Slice, SliceError};
+use npch_slicer::{fingerprint, info, outline, schema, stamp, structure, updates};
+use npch_slicer::{SliceRequest, SliceRequests, Slicer};
+#[cfg(feature = "qr-code")]
+use qrcode::types::QrError;
+use rayon::prelude::*;
+use revision::Revision;
+use serde::Serialize;
+use slice_log::{Console, InOrder, SliceLog};
+use slice_set::{SetSlice, SliceSet};
+use sources::{Records, RequestSource, SourceError};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, ExitStatus, Output, Stdio};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use template::{CommandTemplate, TemplateError};
 use thiserror::Error;
+use variant::{OutputTarget, Variant};
 
-#[derive(Debug, Deserialize)]
-struct RawSliceRequest {
-    description: String,
-    start_page: u32,
-    end_page: u32,
+#[derive(Debug, Parser)]
+#[command(about = "Slice the NPCH adventure guide into chunks")]
+struct Args {
+    /// The guide to slice [default: the project file's pdf, or ./inputs/npch_guide.pdf]
+    #[arg(long, value_name = "PATH")]
+    pdf: Option<PathBuf>,
+    /// CSV, TOML, JSON or YAML file of slice requests, by its extension
+    /// [default: the project file's csv, or ./inputs/npch_slicer.csv]
+    #[arg(long, value_name = "PATH", conflicts_with = "sqlite")]
+    requests: Option<PathBuf>,
+    /// Directory to write slices, logs and the index into
+    /// [default: the project file's output_dir, or ./outputs]
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+    /// Extra Ghostscript argument, passed after the project file's gs_args;
+    /// repeat for more
+    #[arg(long = "gs-arg", value_name = "ARG", allow_hyphen_values = true)]
+    gs_args: Vec<String>,
+    /// Downsample images to this many dots per inch, whatever the preset
+    #[arg(long, value_name = "DPI", value_parser = clap::value_parser!(u32).range(1..), env = "NPCH_SLICER_RESOLUTION")]
+    resolution: Option<u32>,
+    /// Ghostscript preset to optimize every output with, in place of its
+    /// variant's
+    #[arg(long, value_name = "PRESET", env = "NPCH_SLICER_PDF_SETTINGS")]
+    pdf_settings: Option<Variant>,
+    /// PDF version for Ghostscript to keep the outputs within [default: 1.7]
+    #[arg(long, value_name = "LEVEL", value_parser = ghostscript::COMPATIBILITY_LEVELS, env = "NPCH_SLICER_COMPATIBILITY_LEVEL")]
+    compatibility_level: Option<String>,
+    /// Largest an optimized slice may be, like 10M; images are downsampled
+    /// further until it fits
+    #[arg(long, value_name = "SIZE", value_parser = ghostscript::parse_size, env = "NPCH_SLICER_MAX_SIZE")]
+    max_size: Option<u64>,
+    /// Sign each optimized slice with this shell command line, in place of
+    /// the project file's hooks.sign; needs --use-shell
+    #[arg(long, value_name = "COMMAND", env = "NPCH_SLICER_SIGN_CMD")]
+    sign_cmd: Option<String>,
+    /// Whether end_page is the page after each slice's last page, or that
+    /// last page
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = EndPage::Exclusive,
+        env = "NPCH_SLICER_END_PAGE"
+    )]
+    end_page: EndPage,
+    /// Count start_page, end_page and last_page from 0 rather than 1
+    #[arg(long, env = "NPCH_SLICER_ZERO_BASED")]
+    zero_based: bool,
+    /// Read the page columns as the labels pages are printed with (xii, 45),
+    /// from the guide's page labels, rather than as page numbers
+    #[arg(
+        long,
+        env = "NPCH_SLICER_USE_PAGE_LABELS",
+        conflicts_with = "zero_based"
+    )]
+    use_page_labels: bool,
+    /// Refuse slice requests that share pages or descriptions, or leave
+    /// pages of the guide out, rather than only warning about them
+    #[arg(long, env = "NPCH_SLICER_STRICT")]
+    strict: bool,
+    /// What optimizes the slices [default: ghostscript, or builtin if
+    /// Ghostscript isn't found]
+    #[arg(long, value_enum, env = "NPCH_SLICER_OPTIMIZER")]
+    optimizer: Option<Optimizer>,
+    /// Ghostscript executable to run
+    /// [default: the first of gs, gswin64c and gswin32c on the PATH]
+    #[arg(long, value_name = "PATH", env = "NPCH_GS")]
+    gs_path: Option<PathBuf>,
+    /// Keep only the top N levels of each slice's bookmarks
+    #[arg(long, value_name = "N", env = "NPCH_SLICER_OUTLINE_DEPTH")]
+    outline_depth: Option<usize>,
+    /// Also write ./outputs/index.pdf, linking to every optimized slice
+    /// (of the first variant)
+    #[arg(long, env = "NPCH_SLICER_MASTER")]
+    master: bool,
+    /// Name slices after the description_<LANG> column instead of description
+    #[arg(long, value_name = "LANG", env = "NPCH_SLICER_LANG")]
+    lang: Option<String>,
+    /// Optimize every slice once per variant, into ./outputs/<variant>/
+    #[arg(long, value_enum, value_delimiter = ',', env = "NPCH_SLICER_VARIANTS")]
+    variants: Vec<Variant>,
+    /// Project file with per-variant settings [default: ./npch_slicer.toml, if present]
+    #[arg(long, value_name = "PATH", env = "NPCH_SLICER_CONFIG")]
+    config: Option<PathBuf>,
+    /// Slice the guide by its bookmarks instead of the CSV, one slice per
+    /// bookmark DEPTH levels deep (or shallower, without children there),
+    /// named after the bookmark
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["requests", "sqlite"],
+        env = "NPCH_SLICER_FROM_OUTLINE"
+    )]
+    from_outline: Option<u32>,
+    /// Slice every <NAME>.pdf in DIR that has a <NAME>.csv (or .toml, .json
+    /// or .yaml) of slice requests beside it, into <NAME>/ in the output
+    /// directory
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["requests", "pdf", "sqlite", "from_outline", "emit_resolved", "package", "report"],
+        env = "NPCH_SLICER_BATCH"
+    )]
+    batch: Option<PathBuf>,
+    /// Read the slice requests from a SQLite database instead of the CSV
+    #[arg(long, value_name = "PATH", env = "NPCH_SLICER_SQLITE")]
+    sqlite: Option<PathBuf>,
+    /// Table of the --sqlite database holding the slice requests
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "slices",
+        requires = "sqlite",
+        env = "NPCH_SLICER_TABLE"
+    )]
+    table: String,
+    /// Only slice what a new revision of the guide changed: slices with pages
+    /// whose content differs from the last run's, and slices missing an
+    /// output
+    #[arg(long, env = "NPCH_SLICER_ONLY_CHANGED")]
+    only_changed: bool,
+    /// Skip slices cut before from the same guide, request and settings,
+    /// as recorded in .npch_manifest.json in the output directory
+    #[arg(long, env = "NPCH_SLICER_INCREMENTAL")]
+    incremental: bool,
+    /// The version of the guide that min_source_version and applies_to
+    /// columns are matched against [default: the guide's Version entry, or
+    /// the first version number in its file name]
+    #[arg(long, value_name = "VERSION", env = "NPCH_SLICER_SOURCE_VERSION")]
+    source_version: Option<String>,
+    /// The user or owner password of an encrypted guide [default: asked for
+    /// on the terminal when the guide needs one]
+    #[arg(long, env = "NPCH_SLICER_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Encrypt every optimized slice with this owner password, in place of
+    /// the project file's encryption.owner_password, restricting readers to
+    /// the --permissions
+    #[arg(long, env = "NPCH_SLICER_OWNER_PASSWORD", hide_env_values = true)]
+    owner_password: Option<String>,
+    /// What readers of encrypted slices may do without the owner password:
+    /// any of print, copy and modify, in place of the project file's
+    /// encryption.permissions [default: nothing but read]
+    #[arg(
+        long,
+        value_name = "PERMISSION",
+        value_delimiter = ',',
+        env = "NPCH_SLICER_PERMISSIONS"
+    )]
+    permissions: Vec<Permission>,
+    /// Names the output files, e.g. {index:02}_{description}, in place of the
+    /// project file's file_name [default: {description}]
+    #[arg(long, value_name = "TEMPLATE", env = "NPCH_SLICER_FILE_NAME")]
+    file_name: Option<String>,
+    /// Cut every slice with --incremental, recording them all afresh
+    #[arg(long, requires = "incremental", env = "NPCH_SLICER_FORCE")]
+    force: bool,
+    /// Write the resolved slice set (pages, page counts, outputs) to a
+    /// .json or .csv file before slicing
+    #[arg(long, value_name = "PATH")]
+    emit_resolved: Option<PathBuf>,
+    /// Check the slice requests against the guide and print what each slice
+    /// would be cut from and written to, without writing anything
+    #[arg(long, conflicts_with = "emit_resolved", env = "NPCH_SLICER_DRY_RUN")]
+    dry_run: bool,
+    /// Treat optimized slices smaller than this fraction of the unoptimized
+    /// size as failed
+    #[arg(
+        long,
+        value_name = "RATIO",
+        default_value_t = 0.01,
+        env = "NPCH_SLICER_MIN_SIZE_RATIO"
+    )]
+    min_size_ratio: f64,
+    /// List outputs whose size changed by more than this many percent since
+    /// the last slice set version in its changelog
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 10.0,
+        env = "NPCH_SLICER_RESIZE_THRESHOLD"
+    )]
+    resize_threshold: f64,
+    /// Also list the output set, with SHA-256 hashes, in these formats
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        value_delimiter = ',',
+        env = "NPCH_SLICER_CHECKSUMS"
+    )]
+    checksums: Vec<ChecksumFormat>,
+    /// Also assemble the slices, with a README, manifest.json, index.html
+    /// and SHA256SUMS, into this folder, or .tar or .tar.gz archive
+    #[arg(long, value_name = "PATH", env = "NPCH_SLICER_PACKAGE")]
+    package: Option<PathBuf>,
+    /// Split the --package into numbered parts of at most this many MB each,
+    /// with an index of which part has which slice
+    #[arg(
+        long,
+        value_name = "MB",
+        requires = "package",
+        env = "NPCH_SLICER_PACKAGE_MAX_SIZE"
+    )]
+    package_max_size: Option<f64>,
+    /// Directory for the intermediate, unoptimized slices
+    /// [default: npch_slicer-<pid> in the system temp directory]
+    #[arg(long, value_name = "DIR", env = "NPCH_SLICER_SCRATCH_DIR")]
+    scratch_dir: Option<PathBuf>,
+    /// Print the effective configuration, after layering the command line,
+    /// environment and project file, and exit
+    #[arg(long)]
+    print_config: bool,
+    /// Allow hooks given as a single command line, run through the shell
+    #[arg(long, env = "NPCH_SLICER_USE_SHELL")]
+    use_shell: bool,
+    /// Keep unoptimized slices in memory and pipe them to Ghostscript; one
+    /// is only written to the scratch directory if its slice fails
+    #[arg(long, env = "NPCH_SLICER_IN_MEMORY")]
+    in_memory: bool,
+    /// Leave the guide's images of at least KB kilobytes in its file rather
+    /// than in memory, copying them into each slice as it is saved
+    #[arg(long, value_name = "KB", env = "NPCH_SLICER_SPILL_IMAGES")]
+    spill_images: Option<u64>,
+    /// Give up on a slice that has not been cut and saved after SECONDS, and
+    /// count it as failed. Its thread stops at the end of the step it is on,
+    /// and until then holds its memory outside the --jobs count
+    #[arg(long, value_name = "SECONDS", env = "NPCH_SLICER_SLICE_TIMEOUT")]
+    slice_timeout: Option<u64>,
+    /// Stamp every page with "Valid until DATE" and record DATE (YYYY-MM-DD)
+    /// as ValidUntil in the document information
+    #[arg(long, value_name = "DATE", value_parser = parse_date, env = "NPCH_SLICER_VALID_UNTIL")]
+    valid_until: Option<String>,
+    /// Put a generated cover page in front of each slice, with its
+    /// description, its pages and the guide's file name, in place of the
+    /// project file's cover command
+    #[arg(long, env = "NPCH_SLICER_COVER_PAGE")]
+    cover_page: bool,
+    /// Title each slice after its description, in its document information
+    /// and XMP metadata, unless its row gives a title
+    #[arg(long, env = "NPCH_SLICER_TITLE_FROM_DESCRIPTION")]
+    title_from_description: bool,
+    /// Add blank pages at the end of each slice until its page count is a
+    /// multiple of N, for duplex and booklet printing
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        env = "NPCH_SLICER_PAD_TO_MULTIPLE"
+    )]
+    pad_to_multiple: Option<u32>,
+    /// Cut and optimize at most N slices at once [default: one per CPU]
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        env = "NPCH_SLICER_JOBS"
+    )]
+    jobs: Option<u32>,
+    /// Also write a report of every slice, with its outputs' sizes and any
+    /// warnings, into a .json or .csv file
+    #[arg(long, value_name = "PATH", env = "NPCH_SLICER_REPORT")]
+    report: Option<PathBuf>,
+    /// Audit every slice's figures for alternate text, and whether its
+    /// structure tree survived, into a .json or .html report
+    #[arg(long, value_name = "PATH")]
+    accessibility_report: Option<PathBuf>,
+    /// Render this many pages of every optimized slice, picked at random,
+    /// into a contact sheet under spot_check/ for a quick look at the quality;
+    /// needs Ghostscript
+    #[arg(long, value_name = "PAGES", value_parser = clap::value_parser!(u32).range(1..), env = "NPCH_SLICER_SPOT_CHECK")]
+    spot_check: Option<u32>,
+    /// Run without a console, e.g. as a scheduled task: write what would be
+    /// printed, and the error that stops the run, to the --log-file instead,
+    /// with every path absolute
+    #[arg(long, env = "NPCH_SLICER_NON_INTERACTIVE")]
+    non_interactive: bool,
+    /// Log of --non-interactive runs, appended to
+    /// [default: npch_slicer.log in the output directory]
+    #[arg(long, value_name = "PATH", env = "NPCH_SLICER_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<SubCommand>,
 }
 
-#[derive(Error, Debug)]
-enum FromRawError {
-    #[error("Invalid page range for {description:?}: {start_page:?}, {end_page:?}")]
-    InvalidPageRange {
-        description: String,
-        start_page: u32,
-        end_page: u32,
-    },
-    #[error("empty page range for {description:?} (start == end)")]
-    EmptyPageRange { description: String },
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Optimizer {
+    Ghostscript,
+    /// lopdf alone: recompresses streams, prunes unused objects and
+    /// downsamples the images it can decode
+    Builtin,
 }
 
-impl TryFrom<RawSliceRequest> for SliceRequest {
-    type Error = FromRawError;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum EndPage {
+    /// The page after the slice's last page
+    Exclusive,
+    /// The slice's last page
+    Inclusive,
+}
 
-    fn try_from(record: RawSliceRequest) -> Result<Self, Self::Error> {
-        let RawSliceRequest {
-            description,
-            start_page,
-            end_page,
-        } = record;
-        match start_page.cmp(&end_page) {
-            Ordering::Less => Ok(SliceRequest {
-                description,
-                start_page,
-                end_page,
-                pages: BTreeSet::from_iter(start_page..end_page),
-            }),
-            Ordering::Equal => Err(Self::Error::EmptyPageRange { description }),
-            Ordering::Greater => Err(Self::Error::InvalidPageRange {
-                description,
-                start_page,
-                end_page,
-            }),
+impl Args {
+    fn page_numbering(&self) -> PageNumbering {
+        PageNumbering {
+            inclusive_end: self.end_page == EndPage::Inclusive,
+            zero_based: self.zero_based,
+            page_labels: self.use_page_labels,
         }
     }
 }
 
-#[derive(Debug)]
-struct SliceRequest {
-    description: String,
-    start_page: u32,
-    end_page: u32,
-    pages: BTreeSet<u32>,
+fn parse_date(text: &str) -> Result<String, String> {
+    let digits = |range: std::ops::Range<usize>| {
+        text.get(range)
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<u32>().ok())
+    };
+    let fields = (
+        digits(0..4),
+        text.get(4..5),
+        digits(5..7),
+        text.get(7..8),
+        digits(8..10),
+    );
+    match fields {
+        (Some(_), Some("-"), Some(1..=12), Some("-"), Some(1..=31)) if text.len() == 10 => {
+            Ok(text.to_string())
+        }
+        _ => Err(format!("{text:?} is not a YYYY-MM-DD date")),
+    }
 }
 
-struct SliceRequests {
-    individuals: Vec<SliceRequest>,
-    #[allow(unused)]
-    required_pages: BTreeSet<u32>,
+#[derive(Debug, Subcommand)]
+enum SubCommand {
+    /// Validate the slice requests against the guide without writing anything
+    Check {
+        /// [default: the project file's csv, or ./inputs/npch_slicer.csv]
+        #[arg(long, value_name = "PATH")]
+        csv: Option<PathBuf>,
+        /// [default: the project file's pdf, or ./inputs/npch_guide.pdf]
+        #[arg(long, value_name = "PATH")]
+        pdf: Option<PathBuf>,
+        /// Fail on warnings (e.g. uncovered or overlapping pages) too
+        #[arg(long)]
+        strict: bool,
+        /// List the page sizes and orientations of every slice
+        #[arg(long)]
+        page_sizes: bool,
+    },
+    /// Report which slices changed between two revisions of the guide
+    DiffSource {
+        /// The earlier revision
+        old: PathBuf,
+        /// The later revision
+        new: PathBuf,
+        /// [default: the project file's csv, or ./inputs/npch_slicer.csv]
+        #[arg(long, value_name = "PATH")]
+        csv: Option<PathBuf>,
+    },
+    /// Draft slice requests for a new revision of the guide, each range moved to where its pages went
+    RemapSource {
+        /// The revision the slice requests were written for
+        old: PathBuf,
+        /// The revision to remap them to
+        new: PathBuf,
+        /// [default: the project file's csv, or ./inputs/npch_slicer.csv]
+        #[arg(long, value_name = "PATH")]
+        csv: Option<PathBuf>,
+        /// [default: the slice requests' path with a .remapped.csv extension]
+        #[arg(long, short, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Write the guide's newest revision as a file of one revision, without its incremental updates
+    Flatten {
+        /// [default: the project file's pdf, or ./inputs/npch_guide.pdf]
+        #[arg(long, value_name = "PATH")]
+        pdf: Option<PathBuf>,
+        /// Where to write the flattened guide
+        output: PathBuf,
+    },
+    /// Write a current project file from an older one, or from the ./inputs/ layout
+    MigrateConfig {
+        /// Directory holding the legacy inputs/ folder
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        root: PathBuf,
+        /// [default: ./npch_slicer.toml]
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-impl SliceRequests {
-    fn new(individuals: Vec<SliceRequest>) -> SliceRequests {
-        let mut required_pages = BTreeSet::new();
+/// Points the `description` column at `description_{lang}`, falling back to
+/// the plain `description` cell for rows without a translation.
+fn localize(
+    source: &RequestSource,
+    headers: &StringRecord,
+    records: Vec<StringRecord>,
+    lang: &str,
+) -> Result<(StringRecord, Vec<StringRecord>), SourceError> {
+    let localized_header = format!("description_{lang}");
+    let localized = headers
+        .iter()
+        .position(|header| header == localized_header)
+        .ok_or_else(|| SourceError::MissingColumn {
+            source_name: source.describe(),
+            column: localized_header.clone(),
+        })?;
+    let fallback = headers.iter().position(|header| header == "description");
+
+    let headers = headers
+        .iter()
+        .enumerate()
+        .map(|(column, header)| match column {
+            column if column == localized => "description",
+            column if Some(column) == fallback => "description_default",
+            _ => header,
+        })
+        .collect::<StringRecord>();
+    let records = records
+        .into_iter()
+        .map(|record| match (record.get(localized), fallback) {
+            (Some(""), Some(fallback)) => record
+                .iter()
+                .enumerate()
+                .map(|(column, cell)| {
+                    if column == localized {
+                        &record[fallback]
+                    } else {
+                        cell
+                    }
+                })
+                .collect::<StringRecord>(),
+            _ => record,
+        })
+        .collect();
+
+    Ok((headers, records))
+}
 
-        for slice_request in individuals.iter() {
-            for pg in slice_request.start_page..slice_request.end_page {
-                required_pages.insert(pg);
+fn apply_defaults(
+    headers: StringRecord,
+    records: Vec<StringRecord>,
+    defaults: &[(&str, String)],
+) -> (StringRecord, Vec<StringRecord>) {
+    let mut headers = headers;
+    let mut records = records;
+    for (column, value) in defaults {
+        match headers.iter().position(|header| header == *column) {
+            Some(index) => {
+                records = records
+                    .into_iter()
+                    .map(|record| {
+                        record
+                            .iter()
+                            .enumerate()
+                            .map(|(cell_index, cell)| match cell {
+                                "" if cell_index == index => value.as_str(),
+                                cell => cell,
+                            })
+                            .collect()
+                    })
+                    .collect();
+            }
+            None => {
+                headers.push_field(column);
+                for record in records.iter_mut() {
+                    record.push_field(value);
+                }
             }
         }
+    }
+
+    (headers, records)
+}
+
+#[derive(Error, Debug)]
+enum RowError {
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Invalid(#[from] FromRawError),
+    #[error(transparent)]
+    Anchor(#[from] page_anchors::AnchorError),
+    #[error(transparent)]
+    Version(#[from] source_version::UnknownVersion),
+    #[error(transparent)]
+    Optimize(#[from] ghostscript::OptionError),
+    #[error("permissions: {0}")]
+    Permissions(#[from] UnknownPermission),
+}
 
-        SliceRequests {
-            individuals,
-            required_pages,
+impl RowError {
+    /// The kind of problem, which `Error::Rows` groups rows by.
+    fn kind(&self) -> &'static str {
+        match self {
+            RowError::Csv(_) => "unreadable rows or cells",
+            RowError::Invalid(FromRawError::InvalidPageRange { .. }) => {
+                "page ranges ending before they start"
+            }
+            RowError::Invalid(FromRawError::EmptyPageRange { .. }) => "empty page ranges",
+            RowError::Invalid(FromRawError::MissingDescription { .. }) => "missing descriptions",
+            RowError::Invalid(FromRawError::MissingPages { .. }) => "missing pages",
+            RowError::Invalid(FromRawError::AmbiguousPages { .. }) => {
+                "pages given in more than one way"
+            }
+            RowError::Anchor(page_anchors::AnchorError::Label(_)) => "page labels",
+            RowError::Anchor(_) => "page anchors",
+            RowError::Version(_) => "rows for some versions of a guide of unknown version",
+            RowError::Optimize(_) => "optimization columns",
+            RowError::Permissions(_) => "permissions columns",
         }
     }
+}
 
-    #[allow(unused)]
-    fn unnecessary_pages(&self, all_pages: &BTreeSet<u32>) -> BTreeSet<u32> {
-        all_pages.sub(&self.required_pages)
+struct Row {
+    line: u64,
+    request: Result<SliceRequest, RowError>,
+}
+
+/// What the rows of the slice requests are read against besides their cells:
+/// the page numbers of the project file's anchors, the page labels of the
+/// guide at `pdf` when rows give pages by label, and its version for rows
+/// limited to some. The guide is only loaded for bookmark anchors or page
+/// labels, or for its version when no `source_version` is set and a row needs
+/// it.
+struct RequestContext<'a> {
+    config: &'a Config,
+    pdf: PathBuf,
+    anchors: BTreeMap<String, u32>,
+    labels: Option<PageLabels>,
+    document: Option<Document>,
+    source_version: OnceLock<Option<String>>,
+}
+
+impl<'a> RequestContext<'a> {
+    fn new(
+        config: &'a Config,
+        pdf: &Path,
+        numbering: PageNumbering,
+    ) -> Result<RequestContext<'a>, error::Error> {
+        let needs_outline = config
+            .anchors
+            .values()
+            .any(|anchor| matches!(anchor, Anchor::Bookmark { .. }));
+        let document = match needs_outline || numbering.page_labels {
+            true => Some(
+                pdf::load_with_password(pdf, &config.limits(), config.password.as_deref())
+                    .map_err(error::Error::load(pdf))?,
+            ),
+            false => None,
+        };
+        Ok(RequestContext {
+            config,
+            pdf: pdf.to_path_buf(),
+            anchors: bind_anchors(config, pdf, document.as_ref())?,
+            labels: document
+                .as_ref()
+                .filter(|_| numbering.page_labels)
+                .map(PageLabels::new),
+            document,
+            source_version: OnceLock::new(),
+        })
     }
 
-    fn iter(&self) -> Iter<'_, SliceRequest> {
-        self.individuals.iter()
+    /// `source_version` from the project file or command line, the guide's
+    /// `Version` entry, or the first version number in its file name.
+    fn source_version(&self) -> Option<String> {
+        self.source_version
+            .get_or_init(|| {
+                if let Some(source_version) = &self.config.source_version {
+                    return Some(source_version.clone());
+                }
+                let loaded;
+                let document = match &self.document {
+                    Some(document) => Some(document),
+                    None => {
+                        loaded = pdf::load_with_password(
+                            &self.pdf,
+                            &self.config.limits(),
+                            self.config.password.as_deref(),
+                        )
+                        .ok();
+                        loaded.as_ref()
+                    }
+                };
+                document
+                    .and_then(|document| info::entry(document, "Version"))
+                    .or_else(|| source_version::from_file_name(&self.pdf))
+            })
+            .clone()
     }
 }
 
-fn slice() -> SliceRequests {
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_path("./inputs/npch_slicer.csv")
-        .unwrap();
+/// The page numbers of the project file's anchors, bookmark ones looked up in
+/// `document`, the guide at `pdf`.
+fn bind_anchors(
+    config: &Config,
+    pdf: &Path,
+    document: Option<&Document>,
+) -> Result<BTreeMap<String, u32>, error::Error> {
+    let bookmarks = document
+        .map(|document| outline::Anchors::new(document).bookmarks)
+        .unwrap_or_default();
+    config
+        .anchors
+        .iter()
+        .map(|(name, anchor)| {
+            let page = match anchor {
+                Anchor::Page(page) => *page,
+                Anchor::Bookmark { bookmark } => {
+                    *bookmarks
+                        .get(bookmark)
+                        .ok_or_else(|| error::Error::Bookmark {
+                            anchor: name.clone(),
+                            bookmark: bookmark.clone(),
+                            path: pdf.to_path_buf(),
+                        })?
+                }
+            };
+            Ok((name.clone(), page))
+        })
+        .collect()
+}
 
-    let raw_slice_requests = reader
-        .deserialize()
-        .collect::<Result<Vec<RawSliceRequest>, _>>()
-        .unwrap();
+fn read_rows(
+    source: &RequestSource,
+    lang: Option<&str>,
+    numbering: PageNumbering,
+    context: &RequestContext,
+) -> Result<Vec<Row>, SourceError> {
+    let config = context.config;
+    let Records {
+        headers,
+        records,
+        errors,
+    } = source.read()?;
 
-    let individual_slice_requests = raw_slice_requests
+    let mut rows = errors
         .into_iter()
-        .map(SliceRequest::try_from)
-        .collect::<Result<Vec<SliceRequest>, _>>()
-        .unwrap();
+        .map(|(line, error)| Row {
+            line,
+            request: Err(error.into()),
+        })
+        .collect::<Vec<Row>>();
+    let (lines, records): (Vec<u64>, Vec<StringRecord>) = records.into_iter().unzip();
+    let (headers, records) = apply_defaults(headers, records, &config.slice_default_cells());
+    let (headers, records) = match lang {
+        Some(lang) => localize(source, &headers, records, lang)?,
+        None => (headers, records),
+    };
 
-    SliceRequests::new(individual_slice_requests)
+    // Rows are independent of each other, so thousands of them are read on
+    // every core; the sort below puts them back in order.
+    let read = lines
+        .into_par_iter()
+        .zip(records)
+        .filter_map(|(line, record)| {
+            match source_version::applies(&headers, &record, || context.source_version()) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(error) => {
+                    return Some(Row {
+                        line,
+                        request: Err(error.into()),
+                    })
+                }
+            }
+            let request =
+                page_anchors::resolve(&headers, &record, &context.anchors, context.labels.as_ref())
+                    .map_err(RowError::from)
+                    .and_then(|resolved| {
+                        Ok(resolved.deserialize::<RawSliceRequest>(Some(&headers))?)
+                    })
+                    .and_then(|raw| Ok(SliceRequest::from_raw(raw, numbering)?))
+                    .map(|request| SliceRequest {
+                        fields: headers
+                            .iter()
+                            .zip(&record)
+                            .map(|(column, cell)| (column.to_string(), cell.to_string()))
+                            .collect(),
+                        ..request
+                    })
+                    .and_then(|request| {
+                        OptimizeOptions::default().for_slice(&request.fields)?;
+                        config.encryption.for_slice(&request.fields)?;
+                        Ok(request)
+                    });
+            Some(Row { line, request })
+        });
+    rows.par_extend(read);
+    rows.sort_by_key(|row| row.line);
+    Ok(rows)
 }
 
-fn slice_guide(slice_requests: SliceRequests) {
-    let document = Document::load("./inputs/npch_guide.pdf").unwrap();
+fn slice(
+    source: &RequestSource,
+    lang: Option<&str>,
+    numbering: PageNumbering,
+    config: &Config,
+) -> Result<SliceRequests, error::Error> {
+    let context = RequestContext::new(config, &config.pdf_path(), numbering)?;
+    let mut individual_slice_requests = Vec::new();
+    let mut invalid = Vec::new();
+    for row in read_rows(source, lang, numbering, &context)? {
+        match row.request {
+            Ok(request) => individual_slice_requests.push(request),
+            Err(error) => invalid.push((row.line, error)),
+        }
+    }
+    if invalid.len() == 1 {
+        let (line, error) = invalid.remove(0);
+        return Err(error::Error::Row {
+            source_name: source.describe(),
+            line,
+            source: error,
+        });
+    }
+    if !invalid.is_empty() {
+        let mut groups = Vec::<error::RowGroup>::new();
+        for (line, error) in &invalid {
+            let row = format!("row {line}: {error}");
+            match groups.iter_mut().find(|group| group.kind == error.kind()) {
+                Some(group) => group.rows.push(row),
+                None => groups.push(error::RowGroup {
+                    kind: error.kind(),
+                    rows: vec![row],
+                }),
+            }
+        }
+        return Err(error::Error::Rows {
+            source_name: source.describe(),
+            count: invalid.len(),
+            groups,
+        });
+    }
+    file_names::assign(&mut individual_slice_requests, config.file_name_template())?;
 
-    let all_pages = document
-        .get_pages()
-        .keys()
-        .copied()
-        .collect::<BTreeSet<u32>>();
+    Ok(SliceRequests::new(individual_slice_requests))
+}
+
+fn slice_guide(
+    slice_requests: SliceRequests,
+    args: &Args,
+    config: &Config,
+    console: &mut Console,
+) -> Result<(), error::Error> {
+    let commands = [
+        ("signing command", config.hooks.sign.as_ref()),
+        ("post_slice hook", config.hooks.post_slice.as_ref()),
+        (
+            "cover command",
+            config.cover.as_ref().map(|cover| &cover.command),
+        ),
+    ];
+    for (name, command) in commands {
+        if let (Some(CommandTemplate::Shell(line)), false) = (command, args.use_shell) {
+            return Err(error::Error::Shell {
+                name,
+                source: TemplateError::ShellNotAllowed(line.clone()),
+            });
+        }
+    }
+    let located = match (
+        args.spot_check,
+        ghostscript::locate(args.gs_path.as_deref(), ghostscript::on_path),
+    ) {
+        // Spot checks are rendered with Ghostscript whichever optimizer runs.
+        (Some(_), Err(error)) => return Err(error.into()),
+        (_, located) => located,
+    };
+    let renderer = located
+        .as_ref()
+        .ok()
+        .filter(|_| args.spot_check.is_some())
+        .cloned();
+    let gs = match (args.optimizer, located) {
+        (Some(Optimizer::Builtin), _) => None,
+        (_, Ok(gs)) => Some(gs),
+        (None, Err(error)) if args.gs_path.is_none() => {
+            console.line(format_args!(
+                "{error}; optimizing with the builtin optimizer instead, which shrinks far less"
+            ));
+            None
+        }
+        (_, Err(error)) => return Err(error.into()),
+    };
+    let pdf_path = config.pdf_path();
+    let limits = config.limits();
+    let slicer = pdf::load_with_password(&pdf_path, &limits, config.password.as_deref())
+        .map(|document| Slicer::new(document).with_limits(limits))
+        .map_err(error::Error::load(&pdf_path))?;
+    let issues = slice_requests.coverage_issues(Some(slicer.page_count()));
+    for issue in issues.iter().filter(|issue| !issue.is_error()) {
+        console.line(format_args!("warning: {issue}"));
+    }
+    check_coverage(issues, args.strict)?;
 
     // let unnecessary_pages = slice_requests
     //     .unnecessary_pages(&all_pages)
@@ -134,60 +882,2090 @@ fn slice_guide(slice_requests: SliceRequests) {
     //     .copied()
     //     .collect::<BTreeSet<u32>>();
 
-    std::fs::create_dir_all("./outputs/unoptimized/").unwrap();
-    std::fs::create_dir_all("./outputs/optimized/").unwrap();
+    let targets = OutputTarget::all(&args.variants, config);
+    let output_dir = config.output_dir();
+
+    let scratch_dir = scratch_dir(args);
+    let logs_dir = output_dir.join("logs");
+    let directories = targets
+        .iter()
+        .map(|target| output_dir.join(target.directory()))
+        .chain([
+            scratch_dir.clone(),
+            output_dir.join("destinations"),
+            logs_dir.clone(),
+        ]);
+    for directory in directories {
+        std::fs::create_dir_all(&directory).map_err(error::Error::write(directory))?;
+    }
+
+    // Fingerprinting reads every page, so it waits until the revision is
+    // recorded unless --only-changed needs it first.
+    let revision = args
+        .only_changed
+        .then(|| Revision::new(slicer.document(), &limits));
+    let revision_path = Revision::path(&output_dir);
+    let todo = match &revision {
+        Some(revision) => changed_slices(
+            &slice_requests,
+            revision,
+            &revision_path,
+            &targets,
+            &output_dir,
+            console,
+        )?,
+        None => slice_requests.iter().collect(),
+    };
+    let manifest_path = Manifest::path(&output_dir);
+    let incremental = match args.incremental {
+        true => {
+            let mut manifest = match args.force {
+                true => Manifest::default(),
+                false => {
+                    Manifest::read(&manifest_path).map_err(|source| error::Error::Manifest {
+                        path: manifest_path.clone(),
+                        source,
+                    })?
+                }
+            };
+            let salt = manifest
+                .salt()
+                .map_err(error::Error::write(&manifest_path))?
+                .to_string();
+            let settings = OutputSettings::new(args, config, &targets, gs.as_deref(), &salt);
+            let inputs =
+                Inputs::new(&pdf_path, &settings, &salt).map_err(|source| error::Error::Load {
+                    path: pdf_path.clone(),
+                    source: PdfError::Io(source),
+                })?;
+            Some((inputs, manifest))
+        }
+        false => None,
+    };
+    let todo = match &incremental {
+        Some((inputs, manifest)) if !args.force => {
+            let todo = todo
+                .into_iter()
+                .filter(|slice_request| {
+                    !manifest.is_current(slice_request.output_name(), &inputs.key(slice_request))
+                        || outputs_missing(slice_request, &targets, &output_dir)
+                })
+                .collect::<Vec<&SliceRequest>>();
+            console.line(format_args!(
+                "Skipping {} slice(s) cut before from the same guide, request and settings",
+                slice_requests.len() - todo.len()
+            ));
+            todo
+        }
+        _ => todo,
+    };
+
+    // Spilled after the fingerprints are taken, which hash what images hold.
+    let slicer = match args.spill_images {
+        Some(kilobytes) => {
+            let min_size = usize::try_from(kilobytes.saturating_mul(1000)).unwrap_or(usize::MAX);
+            let slicer =
+                slicer
+                    .with_spill(&pdf_path, min_size)
+                    .map_err(|source| error::Error::Load {
+                        path: pdf_path.clone(),
+                        source: PdfError::Io(source),
+                    })?;
+            if let Some(spill) = slicer.spill() {
+                console.line(format_args!(
+                    "Left {} image(s), {:.2}MB, in {} rather than in memory",
+                    spill.len(),
+                    spill.bytes() as f64 / 1e6,
+                    pdf_path.display()
+                ));
+            }
+            slicer
+        }
+        None => slicer,
+    };
+    let slicer = Arc::new(slicer);
+    let run = SliceRun {
+        slicer: Arc::clone(&slicer),
+        gs: gs.as_deref(),
+        renderer: renderer.as_deref(),
+        optimize: optimize_options(args, config),
+        targets: &targets,
+        scratch_dir: &scratch_dir,
+        logs_dir: &logs_dir,
+        args,
+        config,
+    };
+    // Each slice holds the objects of its pages and runs its own Ghostscript, so
+    // --jobs bounds memory as much as processes, but for slices --slice-timeout
+    // abandoned, until their threads reach the end of a step.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.map_or(0, |jobs| jobs as usize))
+        .build()?;
+    let in_order = Mutex::new(InOrder::new(console));
+    let outcomes = pool.install(|| {
+        todo.par_iter()
+            .enumerate()
+            .map(|(index, slice_request)| {
+                let (outcome, log) = run.slice(slice_request);
+                in_order.lock().unwrap().push(index, log);
+                outcome
+            })
+            .collect::<Vec<SliceOutcome>>()
+    });
+    drop(in_order);
+    if let Some((inputs, mut manifest)) = incremental {
+        let (cut, failed): (Vec<_>, Vec<_>) = todo
+            .iter()
+            .zip(&outcomes)
+            .partition(|(_, outcome)| outcome.failures == 0);
+        for (slice_request, _) in failed {
+            manifest.forget(slice_request.output_name());
+        }
+        let requested = slice_requests
+            .iter()
+            .map(SliceRequest::output_name)
+            .collect::<Vec<&str>>();
+        manifest.update(
+            cut.into_iter()
+                .map(|(slice_request, _)| (slice_request.output_name(), inputs.key(slice_request))),
+            &requested,
+        );
+        manifest
+            .write(&manifest_path)
+            .map_err(error::Error::write(&manifest_path))?;
+    }
+    let failures = outcomes
+        .iter()
+        .map(|outcome| outcome.failures)
+        .sum::<usize>();
+    let largest = outcomes
+        .iter()
+        .filter_map(|outcome| outcome.memory.as_ref())
+        .max_by_key(|memory| memory.serialized);
+    if let Some(largest) = largest {
+        // Spilled images stay in the file rather than in memory.
+        let source = std::fs::metadata(&pdf_path)
+            .map_or(0, |metadata| metadata.len())
+            .saturating_sub(slicer.spill().map_or(0, |spill| spill.bytes()));
+        console.line(format_args!(
+            "Peak memory per slice: about {:.2}MB on top of the {:.2}MB source \
+             ({}: {} object(s) copied from the source; each slice's log has its own)",
+            largest.serialized as f64 / 1e6,
+            source as f64 / 1e6,
+            largest.slice,
+            largest.objects
+        ));
+    }
+    if let Some(path) = &args.accessibility_report {
+        let source = accessibility::SourceFigures::new(slicer.document());
+        let audit = todo
+            .iter()
+            .zip(&outcomes)
+            .map(|(slice_request, outcome)| {
+                accessibility::SliceAccessibility::new(
+                    slice_request,
+                    &source,
+                    outcome.tagged,
+                    &targets,
+                    &output_dir,
+                    &limits,
+                )
+            })
+            .collect::<Vec<accessibility::SliceAccessibility>>();
+        accessibility::emit(path, source.tagged, &audit).map_err(error::Error::write(path))?;
+    }
+    if let Some(path) = &args.report {
+        // Output names tell slices apart where descriptions may not.
+        let outcomes = todo
+            .iter()
+            .zip(&outcomes)
+            .map(|(slice_request, outcome)| (slice_request.output_name(), outcome))
+            .collect::<BTreeMap<&str, &SliceOutcome>>();
+        let reported = slice_requests
+            .iter()
+            .map(|slice_request| {
+                let outcome = match outcomes.get(slice_request.output_name()) {
+                    Some(outcome) => (
+                        match outcome.failures {
+                            0 => report::Status::Written,
+                            _ => report::Status::Failed,
+                        },
+                        outcome.memory.as_ref().map(|memory| memory.serialized),
+                        outcome.warnings.as_slice(),
+                    ),
+                    None => (report::Status::Unchanged, None, &[][..]),
+                };
+                report::ReportedSlice::new(slice_request, outcome, &targets, &output_dir)
+            })
+            .collect::<Vec<report::ReportedSlice>>();
+        report::emit(path, &reported).map_err(error::Error::write(path))?;
+        console.line(format_args!("Reported the slices in {}", path.display()));
+    }
+    if args.scratch_dir.is_none() {
+        // Only empty once every slice went through; failed ones stay behind.
+        let _ = std::fs::remove_dir(&scratch_dir);
+    }
+
+    if args.master {
+        write_master(slicer.document(), &slice_requests, &targets[0], &output_dir)?;
+    }
+
+    if failures > 0 {
+        // The revision stays the last one that went through, so that
+        // --only-changed gets to the failed slices again.
+        return Err(error::Error::SlicesFailed {
+            failures,
+            failed_dir: output_dir.join("failed").display().to_string(),
+            scratch_dir: scratch_dir.display().to_string(),
+            logs_dir: logs_dir.display().to_string(),
+        });
+    }
+    let set = update_slice_set(&slice_requests, &targets, &output_dir, args, console)?;
+    let listings = checksums::write(&set, &output_dir, &args.checksums)
+        .map_err(|(path, source)| error::Error::Write { path, source })?;
+    for listing in listings {
+        console.line(format_args!("Listed the outputs in {}", listing.display()));
+    }
+    if let Some(path) = &args.package {
+        let title =
+            info::entry(slicer.document(), "Title").unwrap_or_else(|| String::from("Slices"));
+        let max_size = args.package_max_size.map(|size| (size * 1e6) as u64);
+        let written = package::assemble(path, &set, &output_dir, &title, max_size)
+            .map_err(error::Error::write(path))?;
+        for path in written {
+            console.line(format_args!("Packaged the slices in {}", path.display()));
+        }
+    }
+    revision
+        .unwrap_or_else(|| Revision::new(slicer.document(), &limits))
+        .write(&revision_path)
+        .map_err(error::Error::write(revision_path))
+}
+
+/// Versions the slices now in `output_dir`: a run changing them from the last
+/// version records the next one and adds what changed to the changelog.
+/// Returns the set as it now is, at the version that describes it.
+fn update_slice_set(
+    slice_requests: &SliceRequests,
+    targets: &[OutputTarget],
+    output_dir: &Path,
+    args: &Args,
+    console: &mut Console,
+) -> Result<SliceSet, error::Error> {
+    let path = SliceSet::path(output_dir);
+    let earlier = SliceSet::read(&path).map_err(|source| error::Error::SliceSet {
+        path: path.clone(),
+        source,
+    })?;
+    let slices = slice_requests
+        .iter()
+        .map(|slice_request| SetSlice {
+            description: slice_request.description.clone(),
+            pages: slice_request.pages.clone(),
+            outputs: targets
+                .iter()
+                .filter_map(|target| {
+                    let output =
+                        format!("{}/{}.pdf", target.directory(), slice_request.output_name());
+                    let size = output_dir.join(&output).metadata().ok()?.len();
+                    Some((output, size))
+                })
+                .collect(),
+        })
+        .collect();
+    let version = earlier.as_ref().map_or(1, |earlier| earlier.version + 1);
+    let mut set = SliceSet::new(version, slices);
+    let changes = set.changes(earlier.as_ref(), args.resize_threshold);
+    if changes.is_empty() {
+        console.line(format_args!(
+            "Slice set unchanged at version {}",
+            version - 1
+        ));
+        set.version -= 1;
+        return Ok(set);
+    }
+    set.write(&path).map_err(error::Error::write(&path))?;
+    let changelog = SliceSet::changelog_path(output_dir);
+    slice_set::prepend_changelog(&changelog, version, &changes)
+        .map_err(error::Error::write(&changelog))?;
+    console.line(format_args!(
+        "Slice set version {version}: {} change(s), listed in {}",
+        changes.len(),
+        changelog.display()
+    ));
+    Ok(set)
+}
+
+/// The slice requests whose pages changed since the revision recorded at
+/// `revision_path`, and those missing an output; all of them when there is
+/// no earlier revision. Which pages and slices changed goes to the console.
+fn changed_slices<'a>(
+    slice_requests: &'a SliceRequests,
+    revision: &Revision,
+    revision_path: &Path,
+    targets: &[OutputTarget],
+    output_dir: &Path,
+    console: &mut Console,
+) -> Result<Vec<&'a SliceRequest>, error::Error> {
+    let earlier = Revision::read(revision_path).map_err(|source| error::Error::Revision {
+        path: revision_path.to_path_buf(),
+        source,
+    })?;
+    let Some(earlier) = earlier else {
+        console.line(format_args!(
+            "No page fingerprints from an earlier run in {}; slicing everything",
+            revision_path.display()
+        ));
+        return Ok(slice_requests.iter().collect());
+    };
+    let changed = revision.changed_since(&earlier);
+    match changed.is_empty() {
+        true => console.line("No pages changed since the last run"),
+        false => console.line(format_args!("Pages changed since the last run: {changed}")),
+    }
 
+    let (mut todo, mut affected, mut missing) = (Vec::new(), Vec::new(), 0);
     for slice_request in slice_requests.iter() {
-        let required_deletions = all_pages
-            .sub(&slice_request.pages)
-            .into_iter()
+        if !slice_request.pages.intersection(&changed).is_empty() {
+            affected.push(slice_request.description.as_str());
+        } else if outputs_missing(slice_request, targets, output_dir) {
+            missing += 1;
+        } else {
+            continue;
+        }
+        todo.push(slice_request);
+    }
+    if !affected.is_empty() {
+        console.line(format_args!("Affected slices: {}", affected.join(", ")));
+    }
+    if missing > 0 {
+        console.line(format_args!(
+            "Also slicing {missing} unaffected slice(s) missing an output"
+        ));
+    }
+    console.line(format_args!(
+        "Skipping {} unaffected slice(s)",
+        slice_requests.len() - todo.len()
+    ));
+    Ok(todo)
+}
+
+/// How the run optimizes slices, before their own columns.
+fn optimize_options(args: &Args, config: &Config) -> OptimizeOptions {
+    OptimizeOptions {
+        resolution: args.resolution,
+        pdf_settings: args.pdf_settings,
+        compatibility_level: args.compatibility_level.clone(),
+        gs_args: config.gs_args.clone(),
+        max_size: args.max_size,
+    }
+}
+
+/// Whether any of the slice's outputs is not in `output_dir`.
+fn outputs_missing(
+    slice_request: &SliceRequest,
+    targets: &[OutputTarget],
+    output_dir: &Path,
+) -> bool {
+    targets.iter().any(|target| {
+        !output_dir
+            .join(target.directory())
+            .join(format!("{}.pdf", slice_request.output_name()))
+            .exists()
+    })
+}
+
+/// The settings that shape a slice's outputs, which `--incremental` cuts a
+/// slice again for when they change.
+#[derive(Serialize)]
+struct OutputSettings<'a> {
+    targets: Vec<(Option<Variant>, Option<&'a sink::Sink>)>,
+    /// `None` for the builtin optimizer.
+    gs: Option<&'a Path>,
+    optimize: OptimizeOptions,
+    min_size_ratio: f64,
+    outline_depth: Option<usize>,
+    cover: Option<&'a cover::Cover>,
+    cover_page: bool,
+    valid_until: Option<&'a str>,
+    qr_code: Option<&'a config::QrCodeConfig>,
+    pad_to_multiple: Option<u32>,
+    title_from_description: bool,
+    spot_check: Option<u32>,
+    hooks: &'a config::Hooks,
+    encryption: EncryptionSettings<'a>,
+}
+
+/// The project file's encryption, with the owner password digested as
+/// `incremental::password_key` does.
+#[derive(Serialize)]
+struct EncryptionSettings<'a> {
+    owner_password: Option<String>,
+    permissions: &'a [Permission],
+}
+
+impl<'a> OutputSettings<'a> {
+    fn new(
+        args: &'a Args,
+        config: &'a Config,
+        targets: &'a [OutputTarget],
+        gs: Option<&'a Path>,
+        salt: &str,
+    ) -> OutputSettings<'a> {
+        OutputSettings {
+            targets: targets
+                .iter()
+                .map(|target| (target.variant, target.sink.as_ref()))
+                .collect(),
+            gs,
+            optimize: optimize_options(args, config),
+            min_size_ratio: args.min_size_ratio,
+            outline_depth: args.outline_depth,
+            cover: config.cover.as_ref().filter(|_| !args.cover_page),
+            cover_page: args.cover_page,
+            valid_until: args.valid_until.as_deref(),
+            qr_code: config.qr_code.as_ref(),
+            pad_to_multiple: args.pad_to_multiple,
+            title_from_description: args.title_from_description,
+            spot_check: args.spot_check,
+            hooks: &config.hooks,
+            encryption: EncryptionSettings {
+                owner_password: config
+                    .encryption
+                    .owner_password
+                    .as_deref()
+                    .map(|password| incremental::password_key(password, salt)),
+                permissions: &config.encryption.permissions,
+            },
+        }
+    }
+}
+
+/// What every slice of a run shares.
+struct SliceRun<'a> {
+    slicer: Arc<Slicer>,
+    /// `None` to optimize with the builtin optimizer.
+    gs: Option<&'a Path>,
+    /// The Ghostscript rendering `--spot-check` pages.
+    renderer: Option<&'a Path>,
+    optimize: OptimizeOptions,
+    targets: &'a [OutputTarget],
+    scratch_dir: &'a Path,
+    logs_dir: &'a Path,
+    args: &'a Args,
+    config: &'a Config,
+}
+
+impl SliceRun<'_> {
+    /// Cuts, optimizes and delivers one slice, returning how that went along
+    /// with its log, which is also written to the logs directory. Nothing is
+    /// printed; the caller prints the log's console lines, so slices finishing
+    /// out of order still read in order.
+    fn slice(&self, slice_request: &SliceRequest) -> (SliceOutcome, SliceLog) {
+        let mut log = SliceLog::new();
+        let mut outcome = self.slice_logged(slice_request, &mut log);
+        let log_path = self
+            .logs_dir
+            .join(format!("{}.log", slice_request.output_name()));
+        if let Err(error) = log.write(&log_path) {
+            log.report(format!("cannot write {}: {error}", log_path.display()));
+        }
+        outcome.warnings = log.warnings().to_vec();
+        (outcome, log)
+    }
+
+    fn slice_logged(&self, slice_request: &SliceRequest, log: &mut SliceLog) -> SliceOutcome {
+        let config = self.config;
+        log.note(format!(
+            "slicing {} (pages {})",
+            slice_request.description, slice_request.pages
+        ));
+        let touched = config.blocked(&slice_request.pages);
+        if !touched.is_empty() {
+            log.report(format!(
+                "Failed {}: pages {} are in blocked_pages",
+                slice_request.description, touched
+            ));
+            return SliceOutcome::failed();
+        }
+        let options = match self.optimize.for_slice(&slice_request.fields) {
+            Ok(options) => options,
+            Err(error) => {
+                log.report(format!("Failed {}: {error}", slice_request.description));
+                return SliceOutcome::failed();
+            }
+        };
+        let encryption = match config.encryption.for_slice(&slice_request.fields) {
+            Ok(encryption) => encryption,
+            Err(error) => {
+                log.report(format!(
+                    "Failed {}: permissions: {error}",
+                    slice_request.description
+                ));
+                return SliceOutcome::failed();
+            }
+        };
+        let slice_anchors = self.slicer.anchors(slice_request);
+        let anchors_path = config
+            .output_dir()
+            .join("destinations")
+            .join(format!("{}.json", slice_request.output_name()));
+        let written = std::fs::File::create(&anchors_path).and_then(|file| {
+            let mut file = std::io::BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut file, &outline::AnchorsFile::new(&slice_anchors))?;
+            file.flush()
+        });
+        if let Err(error) = written {
+            log.report(format!(
+                "Failed {}: cannot write {}: {error}",
+                slice_request.description,
+                anchors_path.display()
+            ));
+            return SliceOutcome::failed();
+        }
+
+        let unoptimized_path = self
+            .scratch_dir
+            .join(format!("{}.pdf", slice_request.output_name()));
+        let Cut {
+            page_count,
+            objects,
+            tagged,
+            unreadable,
+            intermediate,
+        } = match self.cut(slice_request, &unoptimized_path) {
+            Ok(cut) => cut,
+            Err(error) => {
+                log.report(format!("Failed {}: {error}", slice_request.description));
+                return SliceOutcome::failed();
+            }
+        };
+        log.note(format!("cut {page_count} page(s), {objects} object(s)"));
+        let source_pages = slice_request
+            .pages
+            .iter()
+            .filter(|&page| (1..=self.slicer.page_count()).contains(&page))
             .collect::<Vec<u32>>();
-        let mut slice_pdf = document.clone();
-        slice_pdf.delete_pages(&required_deletions);
-        slice_pdf.prune_objects();
-        slice_pdf
-            .save(format!(
-                "./outputs/unoptimized/{}.pdf",
-                slice_request.description
-            ))
-            .unwrap();
-
-        shrink(&slice_request.description);
-    }
-}
-
-fn shrink(pdf_name: &str) {
-    let input_path = PathBuf::from(format!("./outputs/unoptimized/{pdf_name}.pdf"));
-    let pre_shrink_size = input_path.metadata().unwrap().len() as f32;
-
-    let output_path = PathBuf::from(format!("./outputs/optimized/{pdf_name}.pdf"));
-    // let image_resolution = 1200;
-    Command::new("gswin64")
-        .arg("-dBATCH")
-        .arg("-dNOPAUSE")
-        .arg("-dNOPROMPT")
-        .arg("-q")
-        .arg("-dCompatibilityLevel=1.7")
-        // .arg("-sColorConversionStrategy=Gray")
-        // .arg("-d")
-        // .arg(format!("-r{image_resolution}"))
-        .arg("-sDEVICE=pdfwrite")
-        .arg(format!("-sOutputFile={}", output_path.display()))
-        .arg(&input_path)
-        .output()
-        .unwrap();
+        for (page, why) in &unreadable {
+            let source_page = source_pages.get(*page as usize - 1).copied().unwrap_or(0);
+            log.warn(format!(
+                "page {page} (page {source_page} of the source): {why}; must_contain \
+                 and stamps go by what of it can be read"
+            ));
+        }
+        if slice_request.expected_pages.is_some() {
+            log.note("page count matches expected_pages");
+        }
+        if slice_request.must_contain.is_some() {
+            log.note("text contains must_contain");
+        }
+        let serialized = intermediate.size();
+        log.note(format!(
+            "unoptimized slice in {} ({serialized} bytes)",
+            intermediate.describe()
+        ));
+        log.note(format!(
+            "memory: about {:.2}MB on top of the source for {objects} copied object(s)",
+            serialized as f64 / 1e6
+        ));
+
+        let mut failures = 0;
+        for target in self.targets {
+            if shrink(
+                &intermediate,
+                page_count,
+                slice_request,
+                (target, &options, encryption.as_ref()),
+                self,
+                log,
+            )
+            .is_err()
+            {
+                failures += 1;
+            }
+        }
+        let kept = match &intermediate {
+            Intermediate::File(path) if failures == 0 => std::fs::remove_file(path),
+            Intermediate::Memory(bytes) if failures > 0 => std::fs::write(&unoptimized_path, bytes),
+            _ => Ok(()),
+        };
+        if let Err(error) = kept {
+            log.warn(format!(
+                "could not update the unoptimized slice {}: {error}",
+                unoptimized_path.display()
+            ));
+        }
+        log.note("done");
+        let memory = MemoryEstimate {
+            slice: slice_request.description.clone(),
+            objects,
+            serialized,
+        };
+        SliceOutcome {
+            failures,
+            memory: Some(memory),
+            tagged: Some(tagged),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Cuts the slice out of the source and saves it, on a thread of its own
+    /// with `--slice-timeout`. A slice that runs out of time is left to its
+    /// thread, which is not waited for but gives up at the end of its step.
+    fn cut(
+        &self,
+        slice_request: &SliceRequest,
+        unoptimized_path: &Path,
+    ) -> Result<Cut, CutFailure> {
+        let mut options = CutOptions::new(self.args, self.config, self.scratch_dir);
+        let Some(seconds) = self.args.slice_timeout else {
+            return cut(
+                &self.slicer,
+                slice_request,
+                &options,
+                (!self.args.in_memory).then_some(unoptimized_path),
+            );
+        };
+        options.deadline = Some((Instant::now() + Duration::from_secs(seconds), seconds));
+        let (sender, receiver) = mpsc::channel();
+        let slicer = Arc::clone(&self.slicer);
+        let slice_request = slice_request.clone();
+        let unoptimized_path = (!self.args.in_memory).then(|| unoptimized_path.to_path_buf());
+        let thread = std::thread::spawn(move || {
+            let cut = cut(
+                &slicer,
+                &slice_request,
+                &options,
+                unoptimized_path.as_deref(),
+            );
+            // The receiver is gone if the slice timed out.
+            let _ = sender.send(cut);
+        });
+        match receiver.recv_timeout(Duration::from_secs(seconds)) {
+            Ok(cut) => cut,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(CutFailure::TimedOut(seconds)),
+            // The thread only drops the sender without sending by panicking.
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                let payload = thread.join().err();
+                let message = payload
+                    .as_deref()
+                    .and_then(|payload| {
+                        payload
+                            .downcast_ref::<&str>()
+                            .map(|message| message.to_string())
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                    })
+                    .unwrap_or_default();
+                Err(CutFailure::Panicked(message))
+            }
+        }
+    }
+}
+
+/// How one slice went.
+#[derive(Debug, Serialize)]
+struct SliceOutcome {
+    /// How many of its steps failed.
+    failures: usize,
+    /// `None` when it wasn't cut.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<MemoryEstimate>,
+    /// Whether the cut slice kept a structure tree; `None` when it wasn't cut.
+    tagged: Option<bool>,
+    /// What its log warned about.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+impl SliceOutcome {
+    fn failed() -> SliceOutcome {
+        SliceOutcome {
+            failures: 1,
+            memory: None,
+            tagged: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// A slice cut out of the source, ready to be optimized.
+struct Cut {
+    page_count: usize,
+    objects: usize,
+    tagged: bool,
+    /// By the slice's own pages, before any cover.
+    unreadable: BTreeMap<u32, Unreadable>,
+    intermediate: Intermediate,
+}
+
+#[derive(Error, Debug)]
+enum CutFailure {
+    #[error(transparent)]
+    Slice(#[from] SliceError),
+    #[error(transparent)]
+    Save(#[from] PdfError),
+    #[error(transparent)]
+    Cover(#[from] cover::CoverError),
+    #[cfg(feature = "qr-code")]
+    #[error("qr_code url: {0}")]
+    QrCodeUrl(#[from] TemplateError),
+    #[cfg(feature = "qr-code")]
+    #[error("QR code: {0}")]
+    QrCode(#[from] QrError),
+    #[error("blank pages could not be added: {0}")]
+    Padding(lopdf::Error),
+    #[error("not cut within {0} s (--slice-timeout), abandoned")]
+    TimedOut(u64),
+    #[error("cutting it panicked: {0}")]
+    Panicked(String),
+}
+
+/// What cutting one slice held in memory beyond the loaded source: the
+/// `objects` copied out of it for the slice, then serialized.
+#[derive(Debug, Serialize)]
+struct MemoryEstimate {
+    slice: String,
+    objects: usize,
+    serialized: u64,
+}
+
+/// The settings that shape every slice's pages, owned so that they can go to
+/// the `--slice-timeout` thread.
+struct CutOptions {
+    outline_depth: Option<usize>,
+    cover: Option<cover::Cover>,
+    /// The guide's file name, for `--cover-page`.
+    cover_page: Option<String>,
+    valid_until: Option<String>,
+    #[cfg(feature = "qr-code")]
+    qr_code: Option<config::QrCodeConfig>,
+    pad_to_multiple: Option<u32>,
+    title_from_description: bool,
+    use_shell: bool,
+    scratch_dir: PathBuf,
+    limits: Limits,
+    /// When `--slice-timeout` gives up on the slice, and its seconds.
+    deadline: Option<(Instant, u64)>,
+}
+
+impl CutOptions {
+    /// Whether the slice has been given up on, checked between steps so that
+    /// an abandoned thread stops early and doesn't write the slice.
+    fn check_deadline(&self) -> Result<(), CutFailure> {
+        match self.deadline {
+            Some((deadline, seconds)) if Instant::now() >= deadline => {
+                Err(CutFailure::TimedOut(seconds))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn new(args: &Args, config: &Config, scratch_dir: &Path) -> CutOptions {
+        CutOptions {
+            outline_depth: args.outline_depth,
+            cover: config.cover.clone().filter(|_| !args.cover_page),
+            cover_page: args.cover_page.then(|| {
+                config
+                    .pdf_path()
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }),
+            valid_until: args.valid_until.clone(),
+            #[cfg(feature = "qr-code")]
+            qr_code: config.qr_code.clone(),
+            pad_to_multiple: args.pad_to_multiple,
+            title_from_description: args.title_from_description,
+            use_shell: args.use_shell,
+            scratch_dir: scratch_dir.to_path_buf(),
+            limits: config.limits(),
+            deadline: None,
+        }
+    }
+}
+
+/// Extracts the slice's pages and saves them to `unoptimized_path`, or to
+/// memory without one.
+fn cut(
+    slicer: &Slicer,
+    slice_request: &SliceRequest,
+    options: &CutOptions,
+    unoptimized_path: Option<&Path>,
+) -> Result<Cut, CutFailure> {
+    let titled;
+    let slice_request = match (options.title_from_description, &slice_request.title) {
+        (true, None) => {
+            titled = SliceRequest {
+                title: Some(slice_request.description.clone()),
+                ..slice_request.clone()
+            };
+            &titled
+        }
+        _ => slice_request,
+    };
+    let Slice {
+        document: mut slice_pdf,
+        unreadable,
+    } = slicer.cut(slice_request, options.outline_depth)?;
+    let extracted = slice_pdf.get_pages().len();
+    options.check_deadline()?;
+    if let Some(cover) = &options.cover {
+        let output = options
+            .scratch_dir
+            .join(format!("{}.cover.pdf", slice_request.output_name()));
+        let (pages, page_count, output_name) = (
+            slice_request.pages.to_string(),
+            extracted.to_string(),
+            output.display().to_string(),
+        );
+        let mut variables = slice_request
+            .fields
+            .iter()
+            .map(|(column, cell)| (column.as_str(), cell.as_str()))
+            .collect::<template::Variables>();
+        variables.extend([
+            ("description", slice_request.description.as_str()),
+            ("pages", pages.as_str()),
+            ("page_count", page_count.as_str()),
+            ("output", output_name.as_str()),
+        ]);
+        let cover = cover.render(&variables, &output, options.use_shell, &options.limits)?;
+        npch_slicer::merge::prepend_pages(&mut slice_pdf, cover)
+            .map_err(cover::CoverError::Merge)?;
+        options.check_deadline()?;
+    }
+    if let Some(source) = &options.cover_page {
+        let cover_page = cover::CoverPage {
+            description: &slice_request.description,
+            pages: &slice_request.pages,
+            source,
+        };
+        // As large as the slice's first page, to print on the same paper.
+        let [left, bottom, right, top] = slice_pdf
+            .page_iter()
+            .next()
+            .and_then(|first| npch_slicer::page::page_box(&slice_pdf, first))
+            .unwrap_or([0.0, 0.0, 595.0, 842.0f32]);
+        let cover = cover_page
+            .generate((right - left).abs(), (top - bottom).abs())
+            .map_err(cover::CoverError::Merge)?;
+        npch_slicer::merge::prepend_pages(&mut slice_pdf, cover)
+            .map_err(cover::CoverError::Merge)?;
+    }
+    if let Some(date) = &options.valid_until {
+        stamp::stamp_text(
+            &mut slice_pdf,
+            &format!("Valid until {date}"),
+            &options.limits,
+        );
+        info::set_entry(&mut slice_pdf, "ValidUntil", date);
+    }
+    #[cfg(feature = "qr-code")]
+    if let Some(qr_code) = &options.qr_code {
+        let variables =
+            template::Variables::from([("description", slice_request.description.as_str())]);
+        let url = template::render_url(&qr_code.url, &variables)?;
+        stamp::stamp_qr_code(
+            &mut slice_pdf,
+            &url,
+            qr_code.corner,
+            qr_code.pages,
+            &options.limits,
+        )?;
+    }
+    if let Some(multiple) = options.pad_to_multiple {
+        npch_slicer::merge::pad_to_multiple(&mut slice_pdf, multiple as usize)
+            .map_err(CutFailure::Padding)?;
+    }
+    let page_count = slice_pdf.get_pages().len();
+    options.check_deadline()?;
+    let intermediate = match unoptimized_path {
+        Some(path) => {
+            slicer.save(&mut slice_pdf, path)?;
+            Intermediate::File(path.to_path_buf())
+        }
+        None => Intermediate::Memory(slicer.save_mem(&mut slice_pdf)?),
+    };
+    Ok(Cut {
+        page_count,
+        objects: slice_pdf.objects.len(),
+        tagged: structure::is_tagged(&slice_pdf),
+        unreadable,
+        intermediate,
+    })
+}
+
+fn page_span(pages: &PageRanges) -> String {
+    match (pages.first(), pages.last()) {
+        (Some(first), Some(last)) if first == last => format!("p. {first}"),
+        (Some(first), Some(last)) => format!("pp. {first}-{last}"),
+        _ => String::new(),
+    }
+}
+
+fn write_master(
+    document: &Document,
+    slice_requests: &SliceRequests,
+    target: &OutputTarget,
+    output_dir: &Path,
+) -> Result<(), error::Error> {
+    let title = info::entry(document, "Title").unwrap_or_else(|| String::from("Contents"));
+    let entries = slice_requests
+        .iter()
+        .map(|slice_request| master::MasterEntry {
+            title: slice_request.description.clone(),
+            pages: page_span(&slice_request.pages),
+            target: format!("{}/{}.pdf", target.directory(), slice_request.output_name()),
+        })
+        .collect::<Vec<master::MasterEntry>>();
+
+    let path = output_dir.join("index.pdf");
+    master::write_master(&title, &entries, &path)
+        .map_err(|source| error::Error::Index { path, source })
+}
 
-    let post_shrink_size = output_path.metadata().unwrap().len() as f32;
+#[derive(Error, Debug)]
+enum ShrinkFailure {
+    #[error("{backend} could not be started: {error}")]
+    Spawn {
+        backend: String,
+        error: std::io::Error,
+    },
+    #[error("builtin optimizer failed: {0}")]
+    Builtin(PdfError),
+    #[error("{backend} failed ({status})")]
+    Exit { backend: String, status: ExitStatus },
+    #[error("cannot remove the previous output: {0}")]
+    Stale(std::io::Error),
+    #[error("Ghostscript produced no output: {0}")]
+    Missing(std::io::Error),
+    #[error("optimized output is not a readable PDF: {0}")]
+    Unreadable(PdfError),
+    #[error("optimized output has {optimized} page(s) instead of {expected}")]
+    PageCount { expected: usize, optimized: usize },
+    #[error("optimized output is {size} bytes even at {resolution} dpi, over the max_size of {max_size}")]
+    OverBudget {
+        size: u64,
+        max_size: u64,
+        resolution: u32,
+    },
+    #[error("optimized output is implausibly small ({optimized} bytes from {unoptimized})")]
+    TooSmall { unoptimized: u64, optimized: u64 },
+    #[error("not delivered to {sink}: {error}")]
+    Delivery { sink: String, error: std::io::Error },
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("{hook} could not be started: {error}")]
+    HookSpawn {
+        hook: &'static str,
+        error: std::io::Error,
+    },
+    #[error("{hook} failed ({status})")]
+    Hook {
+        hook: &'static str,
+        status: ExitStatus,
+    },
+    #[cfg(feature = "encryption")]
+    #[error("cannot encrypt the output: {0}")]
+    Encryption(PdfError),
+    #[cfg(not(feature = "encryption"))]
+    #[error(
+        "owner_password needs the encryption feature, which this npch_slicer was built without"
+    )]
+    NoEncryption,
+    #[cfg(feature = "encryption")]
+    #[error("encrypted output has {encrypted} page(s) instead of {expected}")]
+    EncryptedPageCount { expected: usize, encrypted: usize },
+    #[error("cannot replace the output with its signed copy: {0}")]
+    SignedMove(std::io::Error),
+    #[error("signed output is not a readable PDF: {0}")]
+    SignedUnreadable(PdfError),
+    #[error("signed output has {signed} page(s) instead of {expected}")]
+    SignedPageCount { expected: usize, signed: usize },
+    #[error("{step} output is {size} bytes, over the max_size of {max_size}")]
+    OverBudgetAfter {
+        step: &'static str,
+        size: u64,
+        max_size: u64,
+    },
+}
+
+/// Encrypting and signing grow the output after it was fitted to
+/// `max_size`, so it is measured again after each.
+fn recheck_size(
+    output_path: &Path,
+    max_size: Option<u64>,
+    step: &'static str,
+) -> Result<(), ShrinkFailure> {
+    let Some(max_size) = max_size else {
+        return Ok(());
+    };
+    let size = output_path
+        .metadata()
+        .map_err(ShrinkFailure::Missing)?
+        .len();
+    match size <= max_size {
+        true => Ok(()),
+        false => Err(ShrinkFailure::OverBudgetAfter {
+            step,
+            size,
+            max_size,
+        }),
+    }
+}
+
+/// Ghostscript sometimes fails by writing a valid but (nearly) empty PDF, so
+/// the optimized output must keep every page and a plausible share of the size.
+fn verify_shrunk(
+    unoptimized: u64,
+    expected: usize,
+    output_path: &Path,
+    min_size_ratio: f64,
+    limits: &Limits,
+) -> Result<(), ShrinkFailure> {
+    let optimized = output_path
+        .metadata()
+        .map_err(ShrinkFailure::Missing)?
+        .len();
 
-    println!(
+    let optimized_pages = pdf::load(output_path, limits)
+        .map_err(ShrinkFailure::Unreadable)?
+        .get_pages()
+        .len();
+    if optimized_pages != expected {
+        return Err(ShrinkFailure::PageCount {
+            expected,
+            optimized: optimized_pages,
+        });
+    }
+    if (optimized as f64) < unoptimized as f64 * min_size_ratio {
+        return Err(ShrinkFailure::TooSmall {
+            unoptimized,
+            optimized,
+        });
+    }
+
+    Ok(())
+}
+
+/// An unoptimized slice on its way to Ghostscript: a file in the scratch
+/// directory, or with `--in-memory` the bytes themselves.
+enum Intermediate {
+    File(PathBuf),
+    Memory(Vec<u8>),
+}
+
+impl Intermediate {
+    fn size(&self) -> u64 {
+        match self {
+            Intermediate::File(path) => path.metadata().map_or(0, |metadata| metadata.len()),
+            Intermediate::Memory(bytes) => bytes.len() as u64,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Intermediate::File(path) => path.display().to_string(),
+            Intermediate::Memory(_) => String::from("memory"),
+        }
+    }
+
+    /// The input argument for Ghostscript, `-` being its standard input.
+    fn gs_input(&self) -> String {
+        match self {
+            Intermediate::File(path) => path.display().to_string(),
+            Intermediate::Memory(_) => String::from("-"),
+        }
+    }
+
+    /// Runs `gs` on the slice, feeding it through standard input when the
+    /// slice is in memory.
+    fn run(&self, gs: &mut Command) -> std::io::Result<Output> {
+        let Intermediate::Memory(bytes) = self else {
+            return gs.output();
+        };
+        let mut child = gs
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        std::thread::scope(|scope| {
+            // A write error means gs exited early, which its status reports.
+            scope.spawn(move || stdin.write_all(bytes));
+            child.wait_with_output()
+        })
+    }
+}
+
+fn shrink(
+    intermediate: &Intermediate,
+    page_count: usize,
+    slice_request: &SliceRequest,
+    (target, options, encryption): (&OutputTarget, &OptimizeOptions, Option<&SliceEncryption>),
+    run: &SliceRun,
+    log: &mut SliceLog,
+) -> Result<(), ShrinkFailure> {
+    let (args, config) = (run.args, run.config);
+    let unoptimized_size = intermediate.size();
+    let pre_shrink_size = unoptimized_size as f32;
+
+    let (description, pdf_name) = (&slice_request.description, slice_request.output_name());
+    let output_dir = config.output_dir();
+    let output_path = output_dir
+        .join(target.directory())
+        .join(format!("{pdf_name}.pdf"));
+    let label = match target.variant {
+        Some(variant) => format!("{description} ({})", variant.name()),
+        None => description.to_string(),
+    };
+    let input = intermediate.gs_input();
+    let output = output_path.display().to_string();
+    let variables = template::Variables::from([
+        ("description", description.as_str()),
+        ("file_name", pdf_name),
+        ("input", input.as_str()),
+        ("output", output.as_str()),
+        ("variant", target.variant.map(Variant::name).unwrap_or("")),
+        ("directory", target.directory()),
+    ]);
+    let optimize_once = |options: &OptimizeOptions, log: &mut SliceLog| {
+        match run.gs {
+            Some(gs) => {
+                let gs_args =
+                    template::render_args(&options.gs_args, &variables).inspect_err(|error| {
+                        log.report(format!("Failed {label}: gs_args: {error}"));
+                    })?;
+                let mut gs = Command::new(gs);
+                gs.args(options.ghostscript_args(target.variant))
+                    .arg("-dBATCH")
+                    .arg("-dNOPAUSE")
+                    .arg("-dNOPROMPT")
+                    .arg("-q")
+                    // .arg("-sColorConversionStrategy=Gray")
+                    .arg("-sDEVICE=pdfwrite")
+                    .args(&gs_args)
+                    .arg(format!("-sOutputFile={}", output_path.display()))
+                    .arg(&input);
+
+                log.note(format!("optimizing {label}: {gs:?}"));
+                // Only what this run writes may be verified and shipped.
+                match std::fs::remove_file(&output_path) {
+                    Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                        let failure = ShrinkFailure::Stale(error);
+                        log.report(format!("Failed {label}: {failure}"));
+                        return Err(failure);
+                    }
+                    _ => {}
+                }
+                let started = Instant::now();
+                let output = intermediate.run(&mut gs).map_err(|error| {
+                    let failure = ShrinkFailure::Spawn {
+                        backend: gs.get_program().to_string_lossy().into_owned(),
+                        error,
+                    };
+                    log.report(format!("Failed {label}: {failure}"));
+                    failure
+                })?;
+                log.note(format!(
+                    "gs exited with {} after {:.3}s",
+                    output.status,
+                    started.elapsed().as_secs_f64()
+                ));
+                for (stream, text) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+                    if !text.is_empty() {
+                        log.note(format!(
+                            "gs {stream}:\n{}",
+                            String::from_utf8_lossy(text).trim_end()
+                        ));
+                    }
+                }
+                if !output.status.success() {
+                    let failure = ShrinkFailure::Exit {
+                        backend: gs.get_program().to_string_lossy().into_owned(),
+                        status: output.status,
+                    };
+                    log.report(format!("Failed {label}: {failure}"));
+                    return Err(failure);
+                }
+            }
+            None => {
+                let max_dpi = options.max_dpi(target.variant);
+                optimize_builtin(intermediate, max_dpi, &output_path, &config.limits(), log)
+                    .map_err(|error| {
+                        let failure = ShrinkFailure::Builtin(error);
+                        log.report(format!("Failed {label}: {failure}"));
+                        failure
+                    })?;
+            }
+        }
+        Ok::<(), ShrinkFailure>(())
+    };
+    let failed_path = output_dir
+        .join("failed")
+        .join(target.directory())
+        .join(format!("{pdf_name}.pdf"));
+    let mut options = options.clone();
+    let mut lowered = false;
+    loop {
+        optimize_once(&options, log)?;
+        let Some(max_size) = options.max_size else {
+            break;
+        };
+        let size = output_path
+            .metadata()
+            .map_err(ShrinkFailure::Missing)?
+            .len();
+        if size <= max_size {
+            match options.resolution.filter(|_| lowered) {
+                Some(resolution) => log.warn(format!(
+                    "{label} fits the max_size of {max_size} bytes only at {resolution} dpi"
+                )),
+                None => log.note(format!("{label} fits the max_size of {max_size} bytes")),
+            }
+            break;
+        }
+        match options.lower_quality(target.variant) {
+            Some(lower) => {
+                log.note(format!(
+                    "{label} is {size} bytes, over the max_size of {max_size}; \
+                     trying {} dpi",
+                    lower.resolution.unwrap_or_default()
+                ));
+                options = lower;
+                lowered = true;
+            }
+            None => {
+                let failure = ShrinkFailure::OverBudget {
+                    size,
+                    max_size,
+                    resolution: options.max_dpi(target.variant).unwrap_or_default() as u32,
+                };
+                quarantine(&output_path, &failed_path, log);
+                log.report(format!("Failed {label}: {failure}"));
+                return Err(failure);
+            }
+        }
+    }
+
+    if let Err(failure) = verify_shrunk(
+        unoptimized_size,
+        page_count,
+        &output_path,
+        args.min_size_ratio,
+        &config.limits(),
+    ) {
+        quarantine(&output_path, &failed_path, log);
+        log.report(format!("Failed {label}: {failure}"));
+        return Err(failure);
+    }
+
+    log.note(format!("{label} kept every page and a plausible size"));
+    if let Some(encryption) = encryption {
+        if let Err(failure) = encrypt_output(&output_path, encryption, page_count, &config.limits())
+            .and_then(|()| recheck_size(&output_path, options.max_size, "encrypted"))
+        {
+            quarantine(&output_path, &failed_path, log);
+            log.report(format!("Failed {label}: {failure}"));
+            return Err(failure);
+        }
+        log.note(format!(
+            "encrypted {label}, allowing {}",
+            match encryption.permissions.is_empty() {
+                true => String::from("nothing but reading"),
+                false => encryption
+                    .permissions
+                    .iter()
+                    .map(|permission| permission.name())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            }
+        ));
+    }
+    if let Some(sign) = &config.hooks.sign {
+        let signed = output_path.with_extension("signed.pdf");
+        let signed_name = signed.display().to_string();
+        let mut variables = variables.clone();
+        variables.insert("signed", signed_name.as_str());
+        let signing = SigningJob {
+            command: sign,
+            variables: &variables,
+            signed: &signed,
+            output_path: &output_path,
+            page_count,
+        };
+        if let Err(failure) = signing
+            .run(args.use_shell, &config.limits(), log)
+            .and_then(|()| recheck_size(&output_path, options.max_size, "signed"))
+        {
+            quarantine(&output_path, &failed_path, log);
+            log.report(format!("Failed {label}: {failure}"));
+            return Err(failure);
+        }
+        log.note(format!("signed {label}"));
+    }
+    let post_shrink_size = output_path
+        .metadata()
+        .map_err(ShrinkFailure::Missing)?
+        .len() as f32;
+    log.report(format!(
         "Shrunk {}: {:.2}MB -> {:.2}MB",
-        pdf_name,
+        label,
         pre_shrink_size / 1e6,
         post_shrink_size / 1e6,
+    ));
+    if let (Some(count), Some(renderer)) = (args.spot_check, run.renderer) {
+        let pages = spot_check::pick_pages(pdf_name, count as usize, page_count as u32);
+        let sheet = output_dir
+            .join("spot_check")
+            .join(target.directory())
+            .join(format!("{pdf_name}.png"));
+        let scratch = run
+            .scratch_dir
+            .join(format!("{pdf_name}.{}.ppm", target.directory()));
+        match spot_check::write(renderer, &output_path, &pages, &scratch, &sheet) {
+            Ok(()) => log.note(format!(
+                "spot check of page(s) {} in {}",
+                pages
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                sheet.display()
+            )),
+            Err(error) => log.warn(format!("no spot check of {label}: {error}")),
+        }
+    }
+
+    if let Some(sink) = &target.sink {
+        sink.deliver(&output_path).map_err(|error| {
+            let failure = ShrinkFailure::Delivery {
+                sink: sink.describe(),
+                error,
+            };
+            log.report(format!("Failed {label}: {failure}"));
+            failure
+        })?;
+        log.report(format!("Delivered {label} to {}", sink.describe()));
+    }
+
+    if let Some(hook) = &config.hooks.post_slice {
+        run_hook("post_slice hook", hook, &variables, args.use_shell, log).inspect_err(
+            |failure| {
+                log.report(format!("Failed {label}: {failure}"));
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Encrypts the verified output in place, then checks that it still opens
+/// with every page.
+#[cfg(feature = "encryption")]
+fn encrypt_output(
+    output_path: &Path,
+    encryption: &SliceEncryption,
+    page_count: usize,
+    limits: &Limits,
+) -> Result<(), ShrinkFailure> {
+    let mut document = pdf::load(output_path, limits).map_err(ShrinkFailure::Encryption)?;
+    encryption::encrypt(
+        &mut document,
+        &encryption.owner_password,
+        &encryption.permissions,
+    )
+    .map_err(|error| ShrinkFailure::Encryption(error.into()))?;
+    pdf::save(&mut document, output_path).map_err(ShrinkFailure::Encryption)?;
+    let encrypted = pdf::load(output_path, limits)
+        .map_err(ShrinkFailure::Encryption)?
+        .get_pages()
+        .len();
+    if encrypted != page_count {
+        return Err(ShrinkFailure::EncryptedPageCount {
+            expected: page_count,
+            encrypted,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn encrypt_output(
+    _: &Path,
+    _: &SliceEncryption,
+    _: usize,
+    _: &Limits,
+) -> Result<(), ShrinkFailure> {
+    Err(ShrinkFailure::NoEncryption)
+}
+
+/// Optimizes the slice with the builtin optimizer rather than Ghostscript,
+/// downsampling images to the resolution of the variant's preset.
+fn optimize_builtin(
+    intermediate: &Intermediate,
+    max_dpi: Option<f32>,
+    output_path: &Path,
+    limits: &Limits,
+    log: &mut SliceLog,
+) -> Result<(), PdfError> {
+    let mut document = match intermediate {
+        Intermediate::File(path) => pdf::load(path, limits)?,
+        Intermediate::Memory(bytes) => pdf::load_mem(bytes, limits)?,
+    };
+    let optimization = Optimization { max_dpi };
+    log.note(format!(
+        "optimizing with the builtin optimizer: {optimization:?}"
+    ));
+    let started = Instant::now();
+    let optimized = optimize::optimize(&mut document, &optimization, limits);
+    log.note(format!(
+        "downsampled {} image(s), recompressed {} stream(s) and pruned {} object(s) in {:.3}s",
+        optimized.images_downsampled,
+        optimized.streams_recompressed,
+        optimized.objects_pruned,
+        started.elapsed().as_secs_f64()
+    ));
+    pdf::save(&mut document, output_path)
+}
+
+/// Moves an output that failed to `failed_path`, out of the way of sinks and
+/// of anyone picking up the output directory.
+fn quarantine(output_path: &Path, failed_path: &Path, log: &mut SliceLog) {
+    if !output_path.exists() {
+        return;
+    }
+    let moved = std::fs::create_dir_all(failed_path.parent().unwrap())
+        .and_then(|()| std::fs::rename(output_path, failed_path));
+    if let Err(error) = moved {
+        log.warn(format!(
+            "could not move {} to {}: {error}",
+            output_path.display(),
+            failed_path.display()
+        ));
+    }
+}
+
+/// Signing one verified output with `hooks.sign`.
+struct SigningJob<'a> {
+    command: &'a CommandTemplate,
+    variables: &'a template::Variables<'a>,
+    /// Where the command may write the signed copy instead of signing in
+    /// place; it then replaces the output.
+    signed: &'a Path,
+    output_path: &'a Path,
+    page_count: usize,
+}
+
+impl SigningJob<'_> {
+    /// Runs the signing command and checks that the signed output still has
+    /// every page.
+    fn run(
+        &self,
+        use_shell: bool,
+        limits: &Limits,
+        log: &mut SliceLog,
+    ) -> Result<(), ShrinkFailure> {
+        let _ = std::fs::remove_file(self.signed);
+        run_hook(
+            "signing command",
+            self.command,
+            self.variables,
+            use_shell,
+            log,
+        )?;
+        if self.signed.exists() {
+            std::fs::rename(self.signed, self.output_path).map_err(ShrinkFailure::SignedMove)?;
+        }
+        let signed = pdf::load(self.output_path, limits)
+            .map_err(ShrinkFailure::SignedUnreadable)?
+            .get_pages()
+            .len();
+        if signed != self.page_count {
+            return Err(ShrinkFailure::SignedPageCount {
+                expected: self.page_count,
+                signed,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn run_hook(
+    name: &'static str,
+    hook: &CommandTemplate,
+    variables: &template::Variables,
+    use_shell: bool,
+    log: &mut SliceLog,
+) -> Result<(), ShrinkFailure> {
+    let mut command = hook.command(variables, use_shell)?;
+    log.note(format!("running {name}: {command:?}"));
+    let output = command
+        .output()
+        .map_err(|error| ShrinkFailure::HookSpawn { hook: name, error })?;
+    for (stream, text) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        if !text.is_empty() {
+            log.note(format!(
+                "{name} {stream}:\n{}",
+                String::from_utf8_lossy(text).trim_end()
+            ));
+        }
+    }
+    if !output.status.success() {
+        return Err(ShrinkFailure::Hook {
+            hook: name,
+            status: output.status,
+        });
+    }
+    Ok(())
+}
+
+/// Refuses the first slice request for pages the guide doesn't have, and with
+/// `strict` slice requests with any of the other coverage issues.
+fn check_coverage(issues: Vec<CoverageIssue>, strict: bool) -> Result<(), error::Error> {
+    let warnings = issues.iter().filter(|issue| !issue.is_error()).count();
+    for issue in issues {
+        if let CoverageIssue::Outside {
+            description,
+            pages,
+            page_count,
+        } = issue
+        {
+            return Err(error::Error::OutsideDocument {
+                description,
+                pages,
+                page_count,
+            });
+        }
+    }
+    match strict && warnings > 0 {
+        true => Err(error::Error::Strict { warnings }),
+        false => Ok(()),
+    }
+}
+
+/// Prints the plan of a `--dry-run`, for the slice requests a run would cut.
+fn dry_run(
+    slice_requests: &SliceRequests,
+    args: &Args,
+    config: &Config,
+    console: &mut Console,
+) -> Result<(), error::Error> {
+    let pdf_path = config.pdf_path();
+    let document = pdf::load_with_password(&pdf_path, &config.limits(), config.password.as_deref())
+        .map_err(error::Error::load(&pdf_path))?;
+    let page_count = document.get_pages().len() as u32;
+    let issues = slice_requests.coverage_issues(Some(page_count));
+    let cover = match (args.cover_page, &config.cover) {
+        (true, _) => plan::Cover::Generated,
+        (false, Some(_)) => plan::Cover::Rendered,
+        (false, None) => plan::Cover::None,
+    };
+    plan::print(
+        &resolve(slice_requests.iter(), args, config),
+        page_count,
+        &issues,
+        (args.pad_to_multiple, cover),
+        console,
     );
+    check_coverage(issues, args.strict)
+}
+
+fn emit_resolved<'a>(
+    path: &Path,
+    slice_requests: impl Iterator<Item = &'a SliceRequest>,
+    args: &Args,
+    config: &Config,
+) -> Result<(), error::Error> {
+    let resolved = resolve(slice_requests, args, config);
+    resolved::emit(path, &resolved).map_err(error::Error::write(path))
+}
+
+fn resolve<'a>(
+    slice_requests: impl Iterator<Item = &'a SliceRequest>,
+    args: &Args,
+    config: &Config,
+) -> Vec<resolved::ResolvedSlice> {
+    let targets = OutputTarget::all(&args.variants, config);
+    slice_requests
+        .map(|slice_request| {
+            resolved::ResolvedSlice::new(slice_request, &targets, &config.output_dir())
+        })
+        .collect()
+}
+
+fn scratch_dir(args: &Args) -> PathBuf {
+    match &args.scratch_dir {
+        Some(dir) if args.non_interactive => std::path::absolute(dir).unwrap(),
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join(format!("npch_slicer-{}", std::process::id())),
+    }
+}
+
+fn request_source(args: &Args, config: &Config) -> RequestSource {
+    if let Some(depth) = args.from_outline {
+        return RequestSource::Outline {
+            pdf: config.pdf_path(),
+            depth: depth as usize,
+            limits: config.limits(),
+            password: config.password.clone(),
+        };
+    }
+    match &args.sqlite {
+        Some(path) => RequestSource::Sqlite {
+            path: path.clone(),
+            table: args.table.clone(),
+        },
+        None => RequestSource::file(config.csv_path()),
+    }
+}
+
+/// Asks for the password of `pdf` on the terminal, turning echo off while it
+/// is typed where `stty` can.
+fn prompt_password(pdf: &Path) -> std::io::Result<String> {
+    eprint!("Password for {}: ", pdf.display());
+    let hidden = Command::new("stty")
+        .arg("-echo")
+        .status()
+        .is_ok_and(|status| status.success());
+    let mut password = String::new();
+    let read = std::io::stdin().read_line(&mut password);
+    if hidden {
+        let _ = Command::new("stty").arg("echo").status();
+        eprintln!();
+    }
+    read?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Refuses settings that need a cargo feature this build was made without.
+fn check_features(args: &Args, config: &Config) -> Result<(), error::Error> {
+    let needed = [
+        ("--sqlite", "sqlite", args.sqlite.is_some()),
+        ("qr_code", "qr-code", config.qr_code.is_some()),
+        (
+            "encryption.owner_password",
+            "encryption",
+            config.encryption.owner_password.is_some(),
+        ),
+    ];
+    let enabled = [
+        cfg!(feature = "sqlite"),
+        cfg!(feature = "qr-code"),
+        cfg!(feature = "encryption"),
+    ];
+    for ((setting, feature, used), enabled) in needed.into_iter().zip(enabled) {
+        if used && !enabled {
+            return Err(error::Error::Unsupported { setting, feature });
+        }
+    }
+    Ok(())
+}
+
+/// One of the guides of a run over several, with its own paths and, unless
+/// they are still to be read from its requests file, slice requests.
+struct Guide {
+    name: String,
+    config: Config,
+    slice_requests: Option<SliceRequests>,
+}
+
+/// The guides in a `--batch` directory: each `<name>.pdf` with a requests
+/// file of the same name, sliced into `<name>/` in the output directory.
+fn batch_guides(
+    dir: &Path,
+    config: &Config,
+    console: &mut Console,
+) -> Result<Vec<Guide>, error::Error> {
+    let entries = std::fs::read_dir(dir).map_err(|error| SourceError::Read {
+        source_name: dir.display().to_string(),
+        error,
+    })?;
+    let mut pdfs = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "pdf"))
+        .collect::<Vec<PathBuf>>();
+    pdfs.sort();
+    let mut guides = Vec::new();
+    for pdf in pdfs {
+        let name = pdf.file_stem().unwrap().to_string_lossy().into_owned();
+        let requests = ["csv", "toml", "json", "yaml", "yml"]
+            .into_iter()
+            .map(|extension| pdf.with_extension(extension))
+            .find(|requests| requests.is_file());
+        let Some(requests) = requests else {
+            console.line(format_args!(
+                "No slice requests for {} (a .csv, .toml, .json or .yaml named after it); skipped",
+                pdf.display()
+            ));
+            continue;
+        };
+        let mut guide_config = config.clone();
+        guide_config.apply_paths(
+            Some(pdf),
+            Some(requests),
+            Some(config.output_dir().join(&name)),
+        );
+        guides.push(Guide {
+            name,
+            config: guide_config,
+            slice_requests: None,
+        });
+    }
+    Ok(guides)
+}
+
+/// The slice requests grouped by their `source` column: the guide to cut
+/// each from, relative to the requests file, sliced into a subdirectory of
+/// the output directory named after it. Requests without one are cut from
+/// the usual guide. `None` when no request has a source.
+fn source_guides(
+    slice_requests: &SliceRequests,
+    source: &RequestSource,
+    config: &Config,
+) -> Option<Vec<Guide>> {
+    let source_of = |slice_request: &SliceRequest| {
+        slice_request
+            .fields
+            .get("source")
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty())
+            .map(PathBuf::from)
+    };
+    slice_requests.iter().find_map(source_of)?;
+    let base = source
+        .path()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""));
+    let mut groups = Vec::<(PathBuf, Vec<SliceRequest>)>::new();
+    for slice_request in slice_requests.iter() {
+        let pdf = match source_of(slice_request) {
+            Some(pdf) => base.join(pdf),
+            None => config.pdf_path(),
+        };
+        match groups.iter_mut().find(|(other, _)| *other == pdf) {
+            Some((_, requests)) => requests.push(slice_request.clone()),
+            None => groups.push((pdf, vec![slice_request.clone()])),
+        }
+    }
+    let guides = groups
+        .into_iter()
+        .map(|(pdf, requests)| {
+            let name = pdf
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut guide_config = config.clone();
+            guide_config.apply_paths(Some(pdf), None, Some(config.output_dir().join(&name)));
+            Guide {
+                name,
+                config: guide_config,
+                slice_requests: Some(SliceRequests::new(requests)),
+            }
+        })
+        .collect();
+    Some(guides)
 }
 
-fn main() {
-    let slice_requests = slice();
-    slice_guide(slice_requests);
+/// Slices each of `guides`, going on to the next when one fails; the first
+/// error is the run's.
+fn run_guides(guides: Vec<Guide>, args: &Args, console: &mut Console) -> Result<(), error::Error> {
+    let mut first_error = None;
+    for guide in guides {
+        console.line(format_args!(
+            "Guide {}: {}",
+            guide.name,
+            guide.config.pdf_path().display()
+        ));
+        let config = &guide.config;
+        let slice_requests = match guide.slice_requests {
+            Some(slice_requests) => Ok(slice_requests),
+            None => slice(
+                &request_source(args, config),
+                args.lang.as_deref(),
+                args.page_numbering(),
+                config,
+            ),
+        };
+        let result = slice_requests
+            .and_then(|slice_requests| run_guide(slice_requests, args, config, console));
+        if let Err(error) = result {
+            console.line(format_args!("Guide {} stopped: {error}", guide.name));
+            first_error.get_or_insert(error);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Reads the slice requests and slices the guide, or each of the guides in
+/// the `--batch` directory or the requests' `source` column.
+fn run(args: &Args, config: &Config, console: &mut Console) -> Result<(), error::Error> {
+    if let Some(dir) = &args.batch {
+        let guides = batch_guides(dir, config, console)?;
+        return run_guides(guides, args, console);
+    }
+    let source = request_source(args, config);
+    let slice_requests = slice(&source, args.lang.as_deref(), args.page_numbering(), config)?;
+    if let Some(guides) = source_guides(&slice_requests, &source, config) {
+        let settings = [
+            ("--emit-resolved", args.emit_resolved.is_some()),
+            ("--package", args.package.is_some()),
+            ("--report", args.report.is_some()),
+        ];
+        if let Some((setting, _)) = settings.into_iter().find(|(_, used)| *used) {
+            return Err(error::Error::Batch { setting });
+        }
+        return run_guides(guides, args, console);
+    }
+    run_guide(slice_requests, args, config, console)
+}
+
+/// Slices one guide, or prints the plan for it.
+fn run_guide(
+    slice_requests: SliceRequests,
+    args: &Args,
+    config: &Config,
+    console: &mut Console,
+) -> Result<(), error::Error> {
+    if args.dry_run {
+        return dry_run(&slice_requests, args, config, console);
+    }
+    if let Some(path) = &args.emit_resolved {
+        emit_resolved(path, slice_requests.iter(), args, config)?;
+    }
+    slice_guide(slice_requests, args, config, console)
+}
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            let code = error.exit_code();
+            eprintln!("Error: {:?}", miette::Report::new(error));
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn try_main() -> Result<(), error::Error> {
+    let args = Args::parse();
+    let config_path = args.config.clone().or_else(|| {
+        let path = PathBuf::from(config::DEFAULT_CONFIG_PATH);
+        path.exists().then_some(path)
+    });
+    let load = match args.command {
+        Some(SubCommand::MigrateConfig { .. }) => Config::load_verbatim,
+        _ => Config::load,
+    };
+    let mut config = config_path
+        .as_deref()
+        .map(|path| {
+            load(path).map_err(|source| error::Error::Config {
+                path: path.to_path_buf(),
+                source: Box::new(source),
+            })
+        })
+        .transpose()?
+        .unwrap_or_default();
+    if !matches!(args.command, Some(SubCommand::MigrateConfig { .. })) {
+        config.apply_env();
+        config.apply_paths(
+            args.pdf.clone(),
+            args.requests.clone(),
+            args.out_dir.clone(),
+        );
+        config.gs_args.extend(args.gs_args.iter().cloned());
+        if let Some(sign_cmd) = &args.sign_cmd {
+            config.hooks.sign = Some(CommandTemplate::Shell(sign_cmd.clone()));
+        }
+        if let Some(source_version) = &args.source_version {
+            config.source_version = Some(source_version.clone());
+        }
+        if let Some(file_name) = &args.file_name {
+            config.file_name = Some(file_name.clone());
+        }
+        config.password.clone_from(&args.password);
+        if let Some(owner_password) = &args.owner_password {
+            config.encryption.owner_password = Some(owner_password.clone());
+        }
+        if !args.permissions.is_empty() {
+            config.encryption.permissions.clone_from(&args.permissions);
+        }
+        if args.non_interactive {
+            config.make_absolute();
+        }
+        check_features(&args, &config)?;
+    }
+
+    if args.print_config {
+        let effective = effective::EffectiveConfig::new(&args, &config, config_path.as_deref());
+        print!("{}", toml::to_string_pretty(&effective).unwrap());
+        return Ok(());
+    }
+
+    if config.password.is_none()
+        && !matches!(args.command, Some(SubCommand::MigrateConfig { .. }))
+        && !args.non_interactive
+        && std::io::stdin().is_terminal()
+    {
+        let pdf_path = config.pdf_path();
+        // Errors loading the guide are left for the run to report.
+        if pdf::needs_password(&pdf_path, &config.limits()).unwrap_or(false) {
+            config.password = prompt_password(&pdf_path).ok();
+        }
+    }
+
+    match &args.command {
+        Some(SubCommand::Check {
+            csv,
+            pdf,
+            strict,
+            page_sizes,
+        }) => {
+            let source = match csv {
+                Some(csv) => RequestSource::file(csv.clone()),
+                None => request_source(&args, &config),
+            };
+            let pdf = pdf.clone().unwrap_or_else(|| config.pdf_path());
+            let context = RequestContext::new(&config, &pdf, args.page_numbering())?;
+            let report = match read_rows(
+                &source,
+                args.lang.as_deref(),
+                args.page_numbering(),
+                &context,
+            ) {
+                Ok(rows) => {
+                    if let Some(path) = &args.emit_resolved {
+                        let requests = rows.iter().filter_map(|row| row.request.as_ref().ok());
+                        emit_resolved(path, requests, &args, &config)?;
+                    }
+                    check::check(&rows, &pdf, &config, *page_sizes)
+                }
+                Err(error) => check::Report {
+                    errors: vec![error.to_string()],
+                    ..check::Report::default()
+                },
+            };
+            report.print();
+            if report.failed(*strict) {
+                return Err(error::Error::Check {
+                    errors: report.errors.len(),
+                    warnings: report.warnings.len(),
+                });
+            }
+        }
+        Some(SubCommand::DiffSource { old, new, csv }) => {
+            let source = match csv {
+                Some(csv) => RequestSource::file(csv.clone()),
+                None => request_source(&args, &config),
+            };
+            let slice_requests = slice(
+                &source,
+                args.lang.as_deref(),
+                args.page_numbering(),
+                &config,
+            )?;
+            let fingerprints = |path: &Path| {
+                pdf::load_with_password(path, &config.limits(), config.password.as_deref())
+                    .map(|document| fingerprint::page_fingerprints(&document, &config.limits()))
+                    .map_err(error::Error::load(path))
+            };
+            let (old_pages, new_pages) = (fingerprints(old)?, fingerprints(new)?);
+            diff::report(&slice_requests, (old, &old_pages), (new, &new_pages));
+        }
+        Some(SubCommand::RemapSource {
+            old,
+            new,
+            csv,
+            output,
+        }) => {
+            let source = match csv {
+                Some(csv) => RequestSource::file(csv.clone()),
+                None => request_source(&args, &config),
+            };
+            let output = output.clone().unwrap_or_else(|| match &source {
+                RequestSource::Csv(path) | RequestSource::Structured { path, .. } => {
+                    path.with_extension("remapped.csv")
+                }
+                _ => PathBuf::from("remapped.csv"),
+            });
+            let Records {
+                headers,
+                records,
+                errors,
+            } = source.read().map_err(error::Error::Requests)?;
+            for (line, error) in errors {
+                eprintln!("row {line} of {}: {error}; left out", source.describe());
+            }
+            let revision = |path: &Path| {
+                pdf::load_with_password(path, &config.limits(), config.password.as_deref())
+                    .map(|document| remap::Revision::new(&document, &config.limits()))
+                    .map_err(error::Error::load(path))
+            };
+            let (old, new) = (revision(old)?, revision(new)?);
+            let records = records
+                .into_iter()
+                .map(|(_, record)| record)
+                .collect::<Vec<StringRecord>>();
+            let counts = remap::write_draft(
+                &output,
+                &headers,
+                &records,
+                args.page_numbering(),
+                (&old, &new),
+            )
+            .map_err(error::Error::write(&output))?;
+            let counts = counts
+                .iter()
+                .map(|(confidence, count)| format!("{count} {confidence}"))
+                .collect::<Vec<String>>();
+            println!(
+                "Wrote {} ({} confidence)",
+                output.display(),
+                counts.join(", ")
+            );
+        }
+        Some(SubCommand::Flatten { pdf, output }) => {
+            let pdf = pdf.clone().unwrap_or_else(|| config.pdf_path());
+            let mut document =
+                pdf::load_with_password(&pdf, &config.limits(), config.password.as_deref())
+                    .map_err(error::Error::load(&pdf))?;
+            updates::flatten(&mut document);
+            document.save(output).map_err(error::Error::write(output))?;
+            println!("Wrote {}", output.display());
+        }
+        Some(SubCommand::MigrateConfig {
+            root,
+            output,
+            force,
+        }) => {
+            let output = output
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG_PATH));
+            if output.exists() && !force {
+                return Err(error::Error::Exists { path: output });
+            }
+            let migrated = Config::migrate(config_path.is_some().then_some(config), root);
+            std::fs::write(&output, migrated.to_toml()).map_err(error::Error::write(&output))?;
+            println!("Wrote {}", output.display());
+        }
+        None => {
+            let mut console = match args.non_interactive {
+                true => {
+                    let path = args
+                        .log_file
+                        .clone()
+                        .unwrap_or_else(|| config.output_dir().join("npch_slicer.log"));
+                    if let Some(directory) = path.parent() {
+                        std::fs::create_dir_all(directory)
+                            .map_err(error::Error::write(directory))?;
+                    }
+                    Console::open(&path).map_err(error::Error::write(path))?
+                }
+                false => Console::Terminal,
+            };
+            run(&args, &config, &mut console).inspect_err(|error| console.error(error))?;
+        }
+    }
+    Ok(())
 }
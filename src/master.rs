@@ -0,0 +1,134 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+use std::path::Path;
+
+const PAGE_WIDTH: i64 = 595;
+const PAGE_HEIGHT: i64 = 842;
+const MARGIN: i64 = 56;
+const TITLE_SIZE: i64 = 20;
+const ENTRY_SIZE: i64 = 12;
+const LINE_HEIGHT: i64 = 18;
+const FIRST_ENTRY_Y: i64 = PAGE_HEIGHT - MARGIN - 2 * TITLE_SIZE;
+const ENTRIES_PER_PAGE: usize = ((FIRST_ENTRY_Y - MARGIN) / LINE_HEIGHT) as usize;
+
+pub struct MasterEntry {
+    pub title: String,
+    pub pages: String,
+    /// Path of the slice relative to the master PDF, with `/` separators.
+    pub target: String,
+}
+
+fn win_ansi(text: &str) -> Object {
+    Object::string_literal(Document::encode_text(Some("WinAnsiEncoding"), text))
+}
+
+fn text_at(operations: &mut Vec<Operation>, size: i64, x: i64, y: i64, text: &str) {
+    operations.extend([
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec!["F1".into(), size.into()]),
+        Operation::new("Td", vec![x.into(), y.into()]),
+        Operation::new("Tj", vec![win_ansi(text)]),
+        Operation::new("ET", vec![]),
+    ]);
+}
+
+fn link(document: &mut Document, y: i64, target: &str) -> ObjectId {
+    document.add_object(dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Link",
+        "Rect" => vec![
+            MARGIN.into(),
+            (y - 4).into(),
+            (PAGE_WIDTH - MARGIN).into(),
+            (y + ENTRY_SIZE).into(),
+        ],
+        "Border" => vec![0.into(), 0.into(), 0.into()],
+        "A" => dictionary! {
+            "S" => "GoToR",
+            "F" => Object::string_literal(target),
+            "D" => vec![0.into(), "Fit".into()],
+            "NewWindow" => true,
+        },
+    })
+}
+
+/// Writes a table-of-contents style PDF whose entries open the slices
+/// through remote (`GoToR`) links.
+pub fn write_master(title: &str, entries: &[MasterEntry], path: &Path) -> lopdf::Result<()> {
+    let mut document = Document::with_version("1.5");
+    let pages_id = document.new_object_id();
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources_id = document.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+    });
+
+    let chunks = entries.chunks(ENTRIES_PER_PAGE.max(1)).collect::<Vec<_>>();
+    let chunks = if chunks.is_empty() {
+        vec![&[][..]]
+    } else {
+        chunks
+    };
+    let mut kids = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let mut operations = Vec::new();
+        if index == 0 {
+            text_at(
+                &mut operations,
+                TITLE_SIZE,
+                MARGIN,
+                PAGE_HEIGHT - MARGIN - TITLE_SIZE,
+                title,
+            );
+        }
+
+        let mut annotations = Vec::new();
+        for (row, entry) in chunk.iter().enumerate() {
+            let y = FIRST_ENTRY_Y - row as i64 * LINE_HEIGHT;
+            text_at(&mut operations, ENTRY_SIZE, MARGIN, y, &entry.title);
+            text_at(
+                &mut operations,
+                ENTRY_SIZE,
+                PAGE_WIDTH - MARGIN - 90,
+                y,
+                &entry.pages,
+            );
+            annotations.push(Object::Reference(link(&mut document, y, &entry.target)));
+        }
+
+        let content = Content { operations };
+        let content_id = document.add_object(Stream::new(dictionary! {}, content.encode()?));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Annots" => annotations,
+        });
+        kids.push(Object::Reference(page_id));
+    }
+
+    let count = kids.len() as i64;
+    document.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), PAGE_WIDTH.into(), PAGE_HEIGHT.into()],
+        }),
+    );
+    let catalog_id = document.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    document.trailer.set("Root", catalog_id);
+    document.compress();
+    document.save(path)?;
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use crate::page::{inherited, INHERITABLE};
+use crate::MAX_NESTING;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+/// Copies the attributes the page inherits down onto the page itself, so
+/// that it keeps them under another parent.
+fn settle_inherited(document: &mut Document, page_id: ObjectId) {
+    let mut inherited = Dictionary::new();
+    let mut node = document.get_dictionary(page_id).ok();
+    for _ in 0..MAX_NESTING {
+        let Some(current) = node else {
+            break;
+        };
+        for key in INHERITABLE {
+            if let (false, Ok(value)) = (inherited.has(key), current.get(key)) {
+                inherited.set(key, value.clone());
+            }
+        }
+        node = current
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|parent| document.get_dictionary(parent))
+            .ok();
+    }
+    if let Ok(page) = document.get_dictionary_mut(page_id) {
+        for (key, value) in inherited.iter() {
+            page.set(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Puts the pages of `cover` in front of the pages of `document`. Only the
+/// pages and what they use are taken over; the cover's outline, forms and
+/// metadata are not.
+pub fn prepend_pages(document: &mut Document, mut cover: Document) -> lopdf::Result<()> {
+    let root_pages = document
+        .catalog()?
+        .get(b"Pages")
+        .and_then(Object::as_reference)?;
+    cover.renumber_objects_with(document.max_id + 1);
+    let cover_pages = cover.page_iter().collect::<Vec<ObjectId>>();
+    for &page_id in &cover_pages {
+        settle_inherited(&mut cover, page_id);
+        cover.get_dictionary_mut(page_id)?.set("Parent", root_pages);
+    }
+    document.max_id = cover.max_id.max(document.max_id);
+    document.objects.extend(cover.objects);
+
+    let pages = document.get_dictionary_mut(root_pages)?;
+    let mut kids = pages.get(b"Kids").and_then(Object::as_array)?.clone();
+    kids.splice(0..0, cover_pages.iter().map(|&id| Object::Reference(id)));
+    let count =
+        pages.get(b"Count").and_then(Object::as_i64).unwrap_or(0) + cover_pages.len() as i64;
+    pages.set("Kids", kids);
+    pages.set("Count", count);
+    // Drops the cover's catalog, page tree nodes and whatever only they used.
+    document.prune_objects();
+    Ok(())
+}
+
+/// Appends blank pages, the size of the last page, until the page count is a
+/// multiple of `multiple`, returning how many were added.
+pub fn pad_to_multiple(document: &mut Document, multiple: usize) -> lopdf::Result<usize> {
+    let page_ids = document.page_iter().collect::<Vec<ObjectId>>();
+    let padding = (multiple - page_ids.len() % multiple) % multiple;
+    let Some(&last) = page_ids.last().filter(|_| padding > 0) else {
+        return Ok(0);
+    };
+    let media_box = match inherited(document, last, b"MediaBox") {
+        Some(Object::Reference(id)) => document.get_object(*id)?.clone(),
+        Some(media_box) => media_box.clone(),
+        None => vec![0.into(), 0.into(), 595.into(), 842.into()].into(),
+    };
+    let root_pages = document
+        .catalog()?
+        .get(b"Pages")
+        .and_then(Object::as_reference)?;
+
+    let blanks = (0..padding)
+        .map(|_| {
+            Object::Reference(document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => root_pages,
+                "MediaBox" => media_box.clone(),
+                "Resources" => Dictionary::new(),
+            }))
+        })
+        .collect::<Vec<Object>>();
+    let pages = document.get_dictionary_mut(root_pages)?;
+    let mut kids = pages.get(b"Kids").and_then(Object::as_array)?.clone();
+    kids.extend(blanks);
+    let count = pages.get(b"Count").and_then(Object::as_i64).unwrap_or(0) + padding as i64;
+    pages.set("Kids", kids);
+    pages.set("Count", count);
+    Ok(padding)
+}
@@ -0,0 +1,232 @@
+//! Shrinking documents with lopdf alone, for machines without Ghostscript. It
+//! does far less than Ghostscript: JPEG and other images it cannot decode, as
+//! well as fonts, are left as they are.
+
+use crate::limits::{self, Limits};
+use crate::page::{self, inherited};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// What `optimize` may do beyond recompressing streams and pruning objects.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Optimization {
+    /// Downsample images holding more detail than their pages can show at
+    /// this many pixels per inch.
+    pub max_dpi: Option<f32>,
+}
+
+/// What `optimize` did.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Optimized {
+    pub images_downsampled: usize,
+    pub streams_recompressed: usize,
+    pub objects_pruned: usize,
+}
+
+/// Shrinks `document` in place: downsamples images with `max_dpi`, drops
+/// objects nothing refers to and recompresses every stream that gets smaller
+/// for it. Streams that inflate past `limits` are left as they are.
+pub fn optimize(
+    document: &mut Document,
+    optimization: &Optimization,
+    limits: &Limits,
+) -> Optimized {
+    let mut optimized = Optimized::default();
+    if let Some(max_dpi) = optimization.max_dpi {
+        for (id, (components, factor)) in downsampling(document, max_dpi) {
+            if let Ok(Object::Stream(image)) = document.get_object_mut(id) {
+                if downsample(image, components, factor, limits) {
+                    optimized.images_downsampled += 1;
+                }
+            }
+        }
+    }
+    optimized.objects_pruned = document.prune_objects().len();
+    for object in document.objects.values_mut() {
+        if let Object::Stream(stream) = object {
+            if recompress(stream, limits) {
+                optimized.streams_recompressed += 1;
+            }
+        }
+    }
+    document.renumber_objects();
+    optimized
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    document.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// The image XObjects drawn directly by the page.
+fn page_images(document: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+    let xobjects = inherited(document, page_id, b"Resources")
+        .and_then(|resources| resolve(document, resources)?.as_dict().ok())
+        .and_then(|resources| resources.get(b"XObject").ok())
+        .and_then(|xobjects| resolve(document, xobjects)?.as_dict().ok());
+    let Some(xobjects) = xobjects else {
+        return Vec::new();
+    };
+    xobjects
+        .iter()
+        .filter_map(|(_, xobject)| xobject.as_reference().ok())
+        .filter(|&id| {
+            document
+                .get_object(id)
+                .and_then(Object::as_stream)
+                .is_ok_and(|stream| is_name(&stream.dict, b"Subtype", b"Image"))
+        })
+        .collect()
+}
+
+fn is_name(dict: &Dictionary, key: &[u8], name: &[u8]) -> bool {
+    dict.get(key)
+        .and_then(Object::as_name)
+        .is_ok_and(|value| value == name)
+}
+
+/// The images worth downsampling, with their number of colour components
+/// and by how much. No image is drawn larger than the longest side of its
+/// page, so that bounds the detail a page can show; an image on several
+/// pages is bounded by the largest.
+fn downsampling(document: &Document, max_dpi: f32) -> BTreeMap<ObjectId, (usize, usize)> {
+    let mut bounds = BTreeMap::<ObjectId, u32>::new();
+    for page_id in document.get_pages().into_values() {
+        let Some((width, height)) = page::displayed_size(document, page_id) else {
+            continue;
+        };
+        let bound = (width.max(height) / 72.0 * max_dpi).ceil().max(1.0) as u32;
+        for id in page_images(document, page_id) {
+            let entry = bounds.entry(id).or_default();
+            *entry = (*entry).max(bound);
+        }
+    }
+    bounds
+        .into_iter()
+        .filter_map(|(id, bound)| {
+            let image = &document.get_object(id).ok()?.as_stream().ok()?.dict;
+            let dimension = |key: &[u8]| image.get(key).and_then(Object::as_i64).ok();
+            let (width, height) = (dimension(b"Width")?, dimension(b"Height")?);
+            let factor = (width.min(height) / i64::from(bound)) as usize;
+            let components = components(document, image)?;
+            (factor >= 2).then_some((id, (components, factor)))
+        })
+        .collect()
+}
+
+/// The colour components per pixel of an 8-bit image in a colour space that
+/// averaging pixels works in.
+fn components(document: &Document, image: &Dictionary) -> Option<usize> {
+    let bits = image.get(b"BitsPerComponent").and_then(Object::as_i64);
+    let mask = image.get(b"ImageMask").and_then(Object::as_bool);
+    if bits.ok()? != 8 || mask.unwrap_or(false) {
+        return None;
+    }
+    let space = resolve(document, image.get(b"ColorSpace").ok()?)?;
+    if let Ok(name) = space.as_name() {
+        return match name {
+            b"DeviceGray" => Some(1),
+            b"DeviceRGB" => Some(3),
+            b"DeviceCMYK" => Some(4),
+            _ => None,
+        };
+    }
+    match space.as_array().ok()?.as_slice() {
+        [family, profile] if family.as_name().ok()? == b"ICCBased" => {
+            let profile = resolve(document, profile)?.as_stream().ok()?;
+            let components = profile.dict.get(b"N").and_then(Object::as_i64).ok()?;
+            usize::try_from(components).ok()
+        }
+        _ => None,
+    }
+}
+
+/// The decoded content of a stream that is uncompressed or only
+/// Flate-compressed, without a predictor; `None` for any other, or one that
+/// inflates past `limits`.
+fn flate_content(stream: &Stream, limits: &Limits) -> Option<Vec<u8>> {
+    if stream.dict.has(b"DecodeParms") {
+        return None;
+    }
+    let filter = match stream.dict.get(b"Filter") {
+        Err(_) => return Some(stream.content.clone()),
+        Ok(Object::Array(filters)) => match filters.as_slice() {
+            [filter] => filter,
+            _ => return None,
+        },
+        Ok(filter) => filter,
+    };
+    if filter.as_name().ok()? != b"FlateDecode" {
+        return None;
+    }
+    limits::decompressed(stream, limits).ok()
+}
+
+fn deflate(content: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Averages blocks of `factor` by `factor` pixels of the image into one.
+fn downsample(image: &mut Stream, components: usize, factor: usize, limits: &Limits) -> bool {
+    let dimension = |key: &[u8]| {
+        let value = image.dict.get(key).and_then(Object::as_i64).ok()?;
+        usize::try_from(value).ok()
+    };
+    let (Some(width), Some(height)) = (dimension(b"Width"), dimension(b"Height")) else {
+        return false;
+    };
+    let row_length = width * components;
+    let Some(samples) =
+        flate_content(image, limits).filter(|samples| samples.len() >= row_length * height)
+    else {
+        return false;
+    };
+    let (new_width, new_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let mut downsampled = Vec::with_capacity(new_width * new_height * components);
+    let mut sums = vec![0u32; new_width * components];
+    for block_row in 0..new_height {
+        sums.fill(0);
+        let rows = block_row * factor..((block_row + 1) * factor).min(height);
+        for row in rows.clone() {
+            let samples = &samples[row * row_length..][..row_length];
+            for (column, pixel) in samples.chunks_exact(components).enumerate() {
+                let sums = &mut sums[column / factor * components..][..components];
+                for (sum, &sample) in sums.iter_mut().zip(pixel) {
+                    *sum += u32::from(sample);
+                }
+            }
+        }
+        for (block_column, sums) in sums.chunks_exact(components).enumerate() {
+            let columns = factor.min(width - block_column * factor);
+            let count = (rows.len() * columns) as u32;
+            downsampled.extend(sums.iter().map(|sum| ((sum + count / 2) / count) as u8));
+        }
+    }
+    image.dict.set("Width", new_width as i64);
+    image.dict.set("Height", new_height as i64);
+    image.dict.set("Filter", "FlateDecode");
+    image.set_content(deflate(&downsampled));
+    true
+}
+
+/// Flate-compresses the stream at the highest level if that makes it
+/// smaller. XML metadata stays uncompressed, so other tools can find it.
+fn recompress(stream: &mut Stream, limits: &Limits) -> bool {
+    if !stream.allows_compression || is_name(&stream.dict, b"Type", b"Metadata") {
+        return false;
+    }
+    let Some(content) = flate_content(stream, limits) else {
+        return false;
+    };
+    let compressed = deflate(&content);
+    if compressed.len() >= stream.content.len() {
+        return false;
+    }
+    stream.dict.set("Filter", "FlateDecode");
+    stream.set_content(compressed);
+    true
+}
@@ -0,0 +1,444 @@
+use crate::pages::PageRanges;
+use crate::schema::SCHEMA_VERSION;
+use crate::text::{decode_text_string, text_string};
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone)]
+pub struct OutlineItem {
+    pub title: String,
+    pub page: Option<ObjectId>,
+    pub destination: Option<Vec<Object>>,
+    pub open: bool,
+    pub children: Vec<OutlineItem>,
+}
+
+pub fn page_numbers(document: &Document) -> BTreeMap<ObjectId, u32> {
+    document
+        .get_pages()
+        .into_iter()
+        .map(|(number, page_id)| (page_id, number))
+        .collect()
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    document.dereference(object).ok().map(|(_, object)| object)
+}
+
+fn catalog_entry<'a>(document: &'a Document, key: &[u8]) -> Option<&'a Object> {
+    let entry = document.catalog().ok()?.get(key).ok()?;
+    resolve(document, entry)
+}
+
+fn collect_name_tree(
+    document: &Document,
+    node: &Dictionary,
+    visited: &mut BTreeSet<ObjectId>,
+    entries: &mut Vec<(String, Object)>,
+    depth: usize,
+) {
+    if depth > MAX_NESTING {
+        return;
+    }
+    if let Some(names) = node
+        .get(b"Names")
+        .ok()
+        .and_then(|names| resolve(document, names))
+        .and_then(|names| names.as_array().ok())
+    {
+        for pair in names.chunks_exact(2) {
+            if let Ok(name) = pair[0].as_str() {
+                entries.push((decode_text_string(name), pair[1].clone()));
+            }
+        }
+    }
+
+    let kids = node
+        .get(b"Kids")
+        .ok()
+        .and_then(|kids| resolve(document, kids))
+        .and_then(|kids| kids.as_array().ok());
+    for kid in kids.into_iter().flatten() {
+        let Ok(kid_id) = kid.as_reference() else {
+            continue;
+        };
+        if !visited.insert(kid_id) {
+            continue;
+        }
+        if let Ok(kid) = document.get_dictionary(kid_id) {
+            collect_name_tree(document, kid, visited, entries, depth + 1);
+        }
+    }
+}
+
+fn raw_named_destinations(document: &Document) -> Vec<(String, Object)> {
+    let mut entries = Vec::new();
+
+    if let Some(dests) = catalog_entry(document, b"Dests").and_then(|dests| dests.as_dict().ok()) {
+        for (name, dest) in dests.iter() {
+            entries.push((String::from_utf8_lossy(name).into_owned(), dest.clone()));
+        }
+    }
+
+    if let Some(tree) = catalog_entry(document, b"Names")
+        .and_then(|names| names.as_dict().ok())
+        .and_then(|names| names.get(b"Dests").ok())
+        .and_then(|tree| resolve(document, tree))
+        .and_then(|tree| tree.as_dict().ok())
+    {
+        collect_name_tree(document, tree, &mut BTreeSet::new(), &mut entries, 0);
+    }
+
+    entries
+}
+
+fn destination_array(document: &Document, dest: &Object) -> Option<Vec<Object>> {
+    match resolve(document, dest)? {
+        Object::Array(dest) if dest.first()?.as_reference().is_ok() => Some(dest.clone()),
+        _ => None,
+    }
+}
+
+fn explicit_destination(document: &Document, dest: &Object) -> Option<Vec<Object>> {
+    match resolve(document, dest)? {
+        Object::Dictionary(dest) => destination_array(document, dest.get(b"D").ok()?),
+        _ => destination_array(document, dest),
+    }
+}
+
+/// Maps every named destination of the document to its explicit destination
+/// array, whose first element is the target page.
+pub fn named_destinations(document: &Document) -> BTreeMap<String, Vec<Object>> {
+    raw_named_destinations(document)
+        .into_iter()
+        .filter_map(|(name, dest)| Some((name, explicit_destination(document, &dest)?)))
+        .collect()
+}
+
+/// Resolves an explicit or named destination (as found in `/Dest` or a GoTo
+/// action's `/D`) to an explicit destination array.
+pub fn resolve_destination(
+    document: &Document,
+    dest: &Object,
+    named: &BTreeMap<String, Vec<Object>>,
+) -> Option<Vec<Object>> {
+    match resolve(document, dest)? {
+        Object::Name(name) => named.get(&*String::from_utf8_lossy(name)).cloned(),
+        Object::String(name, _) => named.get(&decode_text_string(name)).cloned(),
+        _ => explicit_destination(document, dest),
+    }
+}
+
+pub fn destination_target(destination: &[Object]) -> Option<ObjectId> {
+    destination.first()?.as_reference().ok()
+}
+
+/// The destination an outline item or link annotation goes to, by `/Dest`
+/// or a GoTo action.
+pub fn item_destination(
+    document: &Document,
+    item: &Dictionary,
+    named: &BTreeMap<String, Vec<Object>>,
+) -> Option<Vec<Object>> {
+    if let Ok(dest) = item.get(b"Dest") {
+        return resolve_destination(document, dest, named);
+    }
+    let action = resolve(document, item.get(b"A").ok()?)?.as_dict().ok()?;
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    resolve_destination(document, action.get(b"D").ok()?, named)
+}
+
+fn read_siblings(
+    document: &Document,
+    first: Option<ObjectId>,
+    named: &BTreeMap<String, Vec<Object>>,
+    visited: &mut BTreeSet<ObjectId>,
+    depth: usize,
+) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    if depth > MAX_NESTING {
+        return items;
+    }
+    let mut next = first;
+    while let Some(item_id) = next {
+        if !visited.insert(item_id) {
+            break;
+        }
+        let Ok(item) = document.get_dictionary(item_id) else {
+            break;
+        };
+        let title = item
+            .get(b"Title")
+            .ok()
+            .and_then(|title| resolve(document, title))
+            .and_then(|title| title.as_str().ok())
+            .map(decode_text_string)
+            .unwrap_or_default();
+        let destination = item_destination(document, item, named);
+        let first_child = item.get(b"First").and_then(Object::as_reference).ok();
+        items.push(OutlineItem {
+            title,
+            page: destination.as_deref().and_then(destination_target),
+            destination,
+            open: item.get(b"Count").and_then(Object::as_i64).unwrap_or(0) > 0,
+            children: read_siblings(document, first_child, named, visited, depth + 1),
+        });
+        next = item.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    items
+}
+
+pub fn read_outline(document: &Document) -> Vec<OutlineItem> {
+    let first = catalog_entry(document, b"Outlines")
+        .and_then(|outlines| outlines.as_dict().ok())
+        .and_then(|outlines| outlines.get(b"First").ok())
+        .and_then(|first| first.as_reference().ok());
+    let named = named_destinations(document);
+    read_siblings(document, first, &named, &mut BTreeSet::new(), 0)
+}
+
+/// The pages a bookmark opens, up to where the next one begins.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub title: String,
+    pub first: u32,
+    pub last: u32,
+}
+
+/// The document split up by its bookmarks down to `depth` levels, 1 being
+/// the top level: every bookmark at that depth, and every shallower one
+/// without children, is a section running from its page to the page before
+/// the next bookmark's (of any of those levels), or to the end of the
+/// document. Bookmarks that don't lead to a page are left out.
+pub fn sections(document: &Document, outline: &[OutlineItem], depth: usize) -> Vec<Section> {
+    fn flatten(
+        items: &[OutlineItem],
+        page_numbers: &BTreeMap<ObjectId, u32>,
+        levels_left: usize,
+        flattened: &mut Vec<(u32, Option<String>)>,
+    ) {
+        for item in items {
+            let Some(&number) = item.page.and_then(|page| page_numbers.get(&page)) else {
+                continue;
+            };
+            let section = levels_left == 1 || item.children.is_empty();
+            flattened.push((number, section.then(|| item.title.trim().to_string())));
+            if !section {
+                flatten(&item.children, page_numbers, levels_left - 1, flattened);
+            }
+        }
+    }
+
+    let page_numbers = page_numbers(document);
+    let mut bookmarks = Vec::new();
+    flatten(outline, &page_numbers, depth.max(1), &mut bookmarks);
+    let starts = bookmarks
+        .iter()
+        .map(|&(number, _)| number)
+        .collect::<BTreeSet<u32>>();
+    let page_count = document.get_pages().len() as u32;
+    bookmarks
+        .into_iter()
+        .filter_map(|(first, title)| {
+            let last = starts
+                .range(first + 1..)
+                .next()
+                .map_or(page_count, |next| next - 1);
+            Some(Section {
+                title: title?,
+                first,
+                last,
+            })
+        })
+        .collect()
+}
+
+fn flatten_titles(
+    items: &[OutlineItem],
+    page_numbers: &BTreeMap<ObjectId, u32>,
+    titles: &mut BTreeMap<String, u32>,
+) {
+    for item in items {
+        if let Some(number) = item.page.and_then(|page| page_numbers.get(&page)) {
+            titles.entry(item.title.clone()).or_insert(*number);
+        }
+        flatten_titles(&item.children, page_numbers, titles);
+    }
+}
+
+/// The on-disk form of [`Anchors`], tagged with the schema version.
+#[derive(Debug, Serialize)]
+pub struct AnchorsFile<'a> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub anchors: &'a Anchors,
+}
+
+impl<'a> AnchorsFile<'a> {
+    pub fn new(anchors: &'a Anchors) -> AnchorsFile<'a> {
+        AnchorsFile {
+            schema_version: SCHEMA_VERSION,
+            anchors,
+        }
+    }
+}
+
+/// Named destinations and bookmark titles, keyed to page numbers.
+#[derive(Debug, Default, Serialize)]
+pub struct Anchors {
+    pub destinations: BTreeMap<String, u32>,
+    pub bookmarks: BTreeMap<String, u32>,
+}
+
+impl Anchors {
+    pub fn new(document: &Document) -> Anchors {
+        let page_numbers = page_numbers(document);
+        let destinations = named_destinations(document)
+            .into_iter()
+            .filter_map(|(name, dest)| {
+                let page = destination_target(&dest)?;
+                Some((name, *page_numbers.get(&page)?))
+            })
+            .collect();
+        let mut bookmarks = BTreeMap::new();
+        flatten_titles(&read_outline(document), &page_numbers, &mut bookmarks);
+
+        Anchors {
+            destinations,
+            bookmarks,
+        }
+    }
+
+    /// Keeps the anchors landing on `pages`, renumbered to their position
+    /// within that page set.
+    pub fn within(&self, pages: &PageRanges) -> Anchors {
+        let rebase = |anchors: &BTreeMap<String, u32>| {
+            anchors
+                .iter()
+                .filter_map(|(name, page)| Some((name.clone(), pages.position(*page)? as u32 + 1)))
+                .collect()
+        };
+
+        Anchors {
+            destinations: rebase(&self.destinations),
+            bookmarks: rebase(&self.bookmarks),
+        }
+    }
+}
+
+/// Drops outline entries that no longer lead anywhere in the slice and cuts
+/// the tree below `max_depth` levels. An entry whose own page is gone still
+/// survives if one of its kept descendants does, pointing at that descendant.
+pub fn rebase(
+    items: &[OutlineItem],
+    retained: &BTreeSet<ObjectId>,
+    max_depth: Option<usize>,
+) -> Vec<OutlineItem> {
+    if max_depth == Some(0) {
+        return Vec::new();
+    }
+    let child_depth = max_depth.map(|depth| depth - 1);
+
+    items
+        .iter()
+        .filter_map(|item| {
+            let children = if child_depth == Some(0) {
+                Vec::new()
+            } else {
+                rebase(&item.children, retained, child_depth)
+            };
+            let (page, destination) = match item.page {
+                Some(page) if retained.contains(&page) => (item.page, item.destination.clone()),
+                _ => {
+                    let fallback = first_retained(&item.children, retained)?;
+                    (fallback.page, fallback.destination.clone())
+                }
+            };
+            Some(OutlineItem {
+                title: item.title.clone(),
+                page,
+                destination,
+                open: item.open,
+                children,
+            })
+        })
+        .collect()
+}
+
+fn first_retained<'a>(
+    items: &'a [OutlineItem],
+    retained: &BTreeSet<ObjectId>,
+) -> Option<&'a OutlineItem> {
+    items.iter().find_map(|item| match item.page {
+        Some(page) if retained.contains(&page) => Some(item),
+        _ => first_retained(&item.children, retained),
+    })
+}
+
+fn write_siblings(
+    document: &mut Document,
+    parent: ObjectId,
+    items: &[OutlineItem],
+) -> Vec<ObjectId> {
+    let ids = items
+        .iter()
+        .map(|_| document.new_object_id())
+        .collect::<Vec<ObjectId>>();
+
+    for (index, item) in items.iter().enumerate() {
+        let mut entry = Dictionary::new();
+        entry.set("Title", text_string(&item.title));
+        entry.set("Parent", parent);
+        if let Some(destination) = &item.destination {
+            entry.set("Dest", destination.clone());
+        }
+        if index > 0 {
+            entry.set("Prev", ids[index - 1]);
+        }
+        if let Some(next) = ids.get(index + 1) {
+            entry.set("Next", *next);
+        }
+
+        let children = write_siblings(document, ids[index], &item.children);
+        if let (Some(first), Some(last)) = (children.first(), children.last()) {
+            entry.set("First", *first);
+            entry.set("Last", *last);
+            let count = children.len() as i64;
+            entry.set("Count", if item.open { count } else { -count });
+        }
+        document
+            .objects
+            .insert(ids[index], Object::Dictionary(entry));
+    }
+
+    ids
+}
+
+/// Replaces the document outline with `items`, removing it when empty.
+pub fn write_outline(document: &mut Document, items: &[OutlineItem]) {
+    if items.is_empty() {
+        if let Ok(catalog) = document.catalog_mut() {
+            catalog.remove(b"Outlines");
+        }
+        return;
+    }
+
+    let outlines_id = document.new_object_id();
+    let top_level = write_siblings(document, outlines_id, items);
+    let mut outlines = Dictionary::new();
+    outlines.set("Type", "Outlines");
+    outlines.set("First", top_level[0]);
+    outlines.set("Last", top_level[top_level.len() - 1]);
+    outlines.set("Count", top_level.len() as i64);
+    document
+        .objects
+        .insert(outlines_id, Object::Dictionary(outlines));
+
+    if let Ok(catalog) = document.catalog_mut() {
+        catalog.set("Outlines", outlines_id);
+    }
+}
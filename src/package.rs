@@ -0,0 +1,509 @@
+//! `--package`: the output set assembled for distribution, as a folder or a
+//! tar archive with the same layout every time.
+//!
+//! The slices keep their paths within the output directory, next to a
+//! `README.md` saying what the package is, a `manifest.json` listing every
+//! slice with its pages, files and their hashes, an `index.html` linking to
+//! them, `SHA256SUMS`, and the changelog and `index.pdf` when there are any.
+//!
+//! With a size cap the slices are spread over numbered parts instead, each
+//! with the README, which tells which part has which slice, the manifest and
+//! the `SHA256SUMS` of its own files; the rest goes in the first part.
+
+use crate::checksums::{self, Artifact};
+use crate::slice_log::utc_now;
+use crate::slice_set::SliceSet;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::schema::SCHEMA_VERSION;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What else of the output directory goes in the package when it is there,
+/// and what the package's README says of it.
+const EXTRAS: [(&str, &str); 2] = [
+    ("CHANGELOG.md", "what each version of the slice set changed"),
+    ("index.pdf", "a table of contents linking to every slice"),
+];
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    schema_version: u32,
+    version: u32,
+    created: &'a str,
+    slices: Vec<ManifestSlice<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestSlice<'a> {
+    description: &'a str,
+    pages: &'a PageRanges,
+    files: Vec<ManifestFile<'a>>,
+}
+
+#[derive(Serialize)]
+struct ManifestFile<'a> {
+    path: &'a str,
+    size: u64,
+    sha256: &'a str,
+    /// The part holding the file, in a package split into parts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    part: Option<&'a str>,
+}
+
+/// A file of the package, by its path within it.
+struct Entry<'a> {
+    path: &'a str,
+    contents: Contents,
+}
+
+enum Contents {
+    Generated(Vec<u8>),
+    Copied(PathBuf),
+}
+
+impl Contents {
+    fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Contents::Generated(bytes) => Ok(Cow::Borrowed(bytes)),
+            Contents::Copied(path) => std::fs::read(path).map(Cow::Owned),
+        }
+    }
+}
+
+/// How `--package` writes its path: a `.tar.gz` (or `.tgz`) or `.tar`
+/// archive, or else a folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    Folder,
+    Tar,
+    TarGz,
+}
+
+impl Layout {
+    /// The layout of `path`, and its file name split into a stem and the
+    /// extension naming the layout.
+    fn of(path: &Path) -> (Layout, String, &'static str) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let lowercase = name.to_lowercase();
+        let (layout, extension) = [
+            (Layout::TarGz, ".tar.gz"),
+            (Layout::TarGz, ".tgz"),
+            (Layout::Tar, ".tar"),
+        ]
+        .into_iter()
+        .find(|(_, extension)| lowercase.ends_with(extension))
+        .unwrap_or((Layout::Folder, ""));
+        let stem = name[..name.len() - extension.len()].to_string();
+        (layout, stem, extension)
+    }
+
+    fn write(self, path: &Path, entries: &[Entry]) -> io::Result<()> {
+        match self {
+            Layout::Folder => write_folder(path, entries),
+            Layout::Tar => write_tar(std::fs::File::create(path)?, entries)?.flush(),
+            Layout::TarGz => {
+                let file = std::fs::File::create(path)?;
+                write_tar(GzEncoder::new(file, Compression::default()), entries)?
+                    .finish()?
+                    .flush()
+            }
+        }
+    }
+}
+
+/// The size of a file of `size` bytes in a tar archive, header included.
+fn tar_size(size: u64) -> u64 {
+    512 + size.next_multiple_of(512)
+}
+
+/// Which artifacts go in which part, by index, and the parts' file names.
+struct Parts {
+    names: Vec<String>,
+    files: Vec<Vec<usize>>,
+}
+
+impl Parts {
+    fn part_of(&self, artifact: usize) -> Option<&str> {
+        self.files
+            .iter()
+            .position(|files| files.contains(&artifact))
+            .map(|part| self.names[part].as_str())
+    }
+}
+
+/// The package of a slice set, before it is written.
+struct Package<'a> {
+    title: &'a str,
+    set: &'a SliceSet,
+    output_dir: &'a Path,
+    artifacts: Vec<Artifact<'a>>,
+    created: String,
+    extras: Vec<(&'static str, &'static str)>,
+}
+
+impl<'a> Package<'a> {
+    fn manifest(&self, parts: Option<&Parts>) -> String {
+        let manifest = Manifest {
+            schema_version: SCHEMA_VERSION,
+            version: self.set.version,
+            created: &self.created,
+            slices: self
+                .set
+                .slices()
+                .iter()
+                .map(|slice| ManifestSlice {
+                    description: &slice.description,
+                    pages: &slice.pages,
+                    files: self
+                        .artifacts
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, artifact)| std::ptr::eq(artifact.slice, slice))
+                        .map(|(index, artifact)| ManifestFile {
+                            path: artifact.path,
+                            size: artifact.size,
+                            sha256: &artifact.sha256,
+                            part: parts.and_then(|parts| parts.part_of(index)),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&manifest).unwrap()
+    }
+
+    fn readme(&self, parts: Option<&Parts>) -> String {
+        let mut readme = format!(
+            "# {}\n\n\
+             Version {} of the slice set, packaged on {}.\n\n\
+             - `manifest.json`: every slice, with its pages and files, their sizes and SHA-256 hashes\n\
+             - `index.html`: links to every file\n\
+             - `SHA256SUMS`: the hashes again, for `sha256sum -c SHA256SUMS` in this folder\n",
+            self.title,
+            self.set.version,
+            &self.created[..10]
+        );
+        for (name, description) in &self.extras {
+            readme.push_str(&format!("- `{name}`: {description}\n"));
+        }
+        if let Some(parts) = parts {
+            readme.push_str(&format!(
+                "\n## Parts\n\nThe package comes in {} parts. The first also has `index.html`{}; \
+                 each has the hashes of its own files.\n\n",
+                parts.names.len(),
+                match self.extras.is_empty() {
+                    true => "",
+                    false => " and the other files above",
+                }
+            ));
+            for (name, files) in parts.names.iter().zip(&parts.files) {
+                let mut slices = Vec::<&str>::new();
+                for &file in files {
+                    let description = self.artifacts[file].slice.description.as_str();
+                    if !slices.contains(&description) {
+                        slices.push(description);
+                    }
+                }
+                readme.push_str(&format!("- `{name}`: {}\n", slices.join(", ")));
+            }
+        }
+        readme
+    }
+
+    /// The entries of a part with `files`, `readme` and `manifest`, and
+    /// with `index.html` and the extras when `first`.
+    fn entries(
+        &'a self,
+        files: &[usize],
+        (readme, manifest): (&str, &str),
+        first: bool,
+    ) -> Vec<Entry<'a>> {
+        let artifacts = files
+            .iter()
+            .map(|&file| &self.artifacts[file])
+            .collect::<Vec<&Artifact>>();
+        let mut generated = vec![
+            ("README.md", readme.to_string()),
+            ("manifest.json", manifest.to_string()),
+        ];
+        if first {
+            generated.push(("index.html", self.index()));
+        }
+        generated.push((
+            "SHA256SUMS",
+            checksums::sha256sums(artifacts.iter().copied()),
+        ));
+        let mut entries = generated
+            .into_iter()
+            .map(|(name, contents)| Entry {
+                path: name,
+                contents: Contents::Generated(contents.into_bytes()),
+            })
+            .collect::<Vec<Entry>>();
+        if first {
+            entries.extend(self.extras.iter().map(|&(name, _)| Entry {
+                path: name,
+                contents: Contents::Copied(self.output_dir.join(name)),
+            }));
+        }
+        entries.extend(artifacts.iter().map(|artifact| Entry {
+            path: artifact.path,
+            contents: Contents::Copied(self.output_dir.join(artifact.path)),
+        }));
+        entries
+    }
+
+    fn index(&self) -> String {
+        let title = escape_html(self.title);
+        let mut index = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n\
+             <p>Version {}</p>\n<ul>\n",
+            self.set.version
+        );
+        for artifact in &self.artifacts {
+            index.push_str(&format!(
+                "<li><a href=\"{}\">{}</a> (pages {}, {:.2}MB)</li>\n",
+                escape_html(&href(artifact.path)),
+                escape_html(artifact.path),
+                artifact.slice.pages,
+                artifact.size as f64 / 1e6
+            ));
+        }
+        index.push_str("</ul>\n</body>\n</html>\n");
+        index
+    }
+
+    /// Spreads the artifacts, in order, over parts of at most `max_size`
+    /// bytes as a tar archive before any compression, starting a part when
+    /// the next slice's files don't fit in the current one.
+    fn split(&self, max_size: u64, part_name: impl Fn(usize) -> String) -> io::Result<Parts> {
+        // The README and the manifest come with every part, and are longest
+        // with a part per file; a part's SHA256SUMS is at most that of all
+        // files.
+        let worst = Parts {
+            names: (1..=self.artifacts.len()).map(&part_name).collect(),
+            files: (0..self.artifacts.len()).map(|file| vec![file]).collect(),
+        };
+        let overhead = tar_size(self.readme(Some(&worst)).len() as u64)
+            + tar_size(self.manifest(Some(&worst)).len() as u64)
+            + tar_size(checksums::sha256sums(&self.artifacts).len() as u64)
+            + 1024;
+        let first_only = tar_size(self.index().len() as u64)
+            + self
+                .extras
+                .iter()
+                .map(|(name, _)| Ok(tar_size(self.output_dir.join(name).metadata()?.len())))
+                .sum::<io::Result<u64>>()?;
+
+        let mut files = vec![Vec::new()];
+        let mut used = overhead + first_only;
+        let mut next = 0;
+        for group in self
+            .artifacts
+            .chunk_by(|one, other| std::ptr::eq(one.slice, other.slice))
+        {
+            let indices = next..next + group.len();
+            next += group.len();
+            // A slice's files stay together, unless they take more than a
+            // part on their own.
+            let size = group
+                .iter()
+                .map(|artifact| tar_size(artifact.size))
+                .sum::<u64>();
+            if used + size > max_size && !files.last().unwrap().is_empty() {
+                files.push(Vec::new());
+                used = overhead;
+            }
+            if used + size <= max_size {
+                files.last_mut().unwrap().extend(indices);
+                used += size;
+                continue;
+            }
+            for index in indices {
+                let artifact = &self.artifacts[index];
+                let size = tar_size(artifact.size);
+                if used + size > max_size && !files.last().unwrap().is_empty() {
+                    files.push(Vec::new());
+                    used = overhead;
+                }
+                if used + size > max_size {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} ({:.2}MB) doesn't fit in a part of {:.2}MB along with its README and manifest",
+                            artifact.path,
+                            artifact.size as f64 / 1e6,
+                            max_size as f64 / 1e6
+                        ),
+                    ));
+                }
+                files.last_mut().unwrap().push(index);
+                used += size;
+            }
+        }
+        Ok(Parts {
+            names: (1..=files.len()).map(part_name).collect(),
+            files,
+        })
+    }
+}
+
+/// Assembles the package of `set`, whose outputs are in `output_dir`, at
+/// `path`, or in parts of at most `max_size` bytes next to it, named after
+/// it, with a copy of their README as an index. `title` heads the README and
+/// index. Returns the paths written.
+pub fn assemble(
+    path: &Path,
+    set: &SliceSet,
+    output_dir: &Path,
+    title: &str,
+    max_size: Option<u64>,
+) -> io::Result<Vec<PathBuf>> {
+    let package = Package {
+        title,
+        set,
+        output_dir,
+        artifacts: checksums::artifacts(set, output_dir)?,
+        created: utc_now(),
+        extras: EXTRAS
+            .into_iter()
+            .filter(|(name, _)| output_dir.join(name).is_file())
+            .collect(),
+    };
+    let (layout, stem, extension) = Layout::of(path);
+    let Some(max_size) = max_size else {
+        let every_file = (0..package.artifacts.len()).collect::<Vec<usize>>();
+        let (readme, manifest) = (package.readme(None), package.manifest(None));
+        layout.write(
+            path,
+            &package.entries(&every_file, (&readme, &manifest), true),
+        )?;
+        return Ok(vec![path.to_path_buf()]);
+    };
+
+    let parts = package.split(max_size, |part| format!("{stem}.part{part}{extension}"))?;
+    let (readme, manifest) = (package.readme(Some(&parts)), package.manifest(Some(&parts)));
+    let mut written = Vec::new();
+    for (part, (name, files)) in parts.names.iter().zip(&parts.files).enumerate() {
+        let part_path = path.with_file_name(name);
+        layout.write(
+            &part_path,
+            &package.entries(files, (&readme, &manifest), part == 0),
+        )?;
+        written.push(part_path);
+    }
+    let index = path.with_file_name(format!("{stem}.index.md"));
+    std::fs::write(&index, readme)?;
+    written.push(index);
+    Ok(written)
+}
+
+/// `path` as a relative URL, with everything but unreserved characters and
+/// `/` percent-encoded.
+fn href(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the package as a folder, replacing one from an earlier run (one
+/// with a `manifest.json`) but nothing else.
+fn write_folder(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    if path.exists() {
+        if !path.join("manifest.json").is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "exists and is not a package from an earlier run",
+            ));
+        }
+        std::fs::remove_dir_all(path)?;
+    }
+    for entry in entries {
+        let target = path.join(entry.path);
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        match &entry.contents {
+            Contents::Generated(bytes) => std::fs::write(target, bytes)?,
+            Contents::Copied(source) => std::fs::copy(source, target).map(|_| ())?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes `entries` to `writer` as a POSIX (ustar) tar archive.
+fn write_tar<W: Write>(mut writer: W, entries: &[Entry]) -> io::Result<W> {
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    for entry in entries {
+        let contents = entry.contents.read()?;
+        writer.write_all(&tar_header(entry.path, contents.len() as u64, mtime)?)?;
+        writer.write_all(&contents)?;
+        let padding = contents.len().next_multiple_of(512) - contents.len();
+        writer.write_all(&vec![0; padding])?;
+    }
+    // The end of the archive is two empty blocks.
+    writer.write_all(&[0; 1024])?;
+    Ok(writer)
+}
+
+fn tar_header(path: &str, size: u64, mtime: u64) -> io::Result<[u8; 512]> {
+    // Names longer than 100 bytes are split at a `/` into a prefix of up to
+    // 155 bytes and the name proper.
+    let (prefix, name) = match path.len() {
+        0..=100 => ("", path),
+        _ => path
+            .char_indices()
+            .filter(|&(index, character)| {
+                character == '/' && index <= 155 && path.len() - index - 1 <= 100
+            })
+            .map(|(index, _)| (&path[..index], &path[index + 1..]))
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{path:?} is too long a name for a tar archive"),
+                )
+            })?,
+    };
+    let mut header = [0u8; 512];
+    let mut field = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{size:011o}\0").as_bytes());
+    field(136, format!("{mtime:011o}\0").as_bytes());
+    // The checksum is computed with its own field taken as spaces.
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+    field(345, prefix.as_bytes());
+    let checksum = header.iter().map(|&byte| u32::from(byte)).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    Ok(header)
+}
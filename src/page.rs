@@ -0,0 +1,53 @@
+use crate::MAX_NESTING;
+use lopdf::{Document, Object, ObjectId};
+
+/// Page attributes a page may inherit from the page tree above it.
+pub const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// `key` of the page or, for the inheritable page attributes, of the
+/// nearest page tree node above it that has one.
+pub fn inherited<'a>(document: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = document.get_dictionary(page_id).ok()?;
+    for _ in 0..MAX_NESTING {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// The visible area of the page, `[left, bottom, right, top]` in points: its
+/// crop box, or its media box.
+pub fn page_box(document: &Document, page_id: ObjectId) -> Option<[f32; 4]> {
+    let area = inherited(document, page_id, b"CropBox")
+        .or_else(|| inherited(document, page_id, b"MediaBox"))?;
+    let area = match area {
+        Object::Reference(id) => document.get_object(*id).ok()?,
+        area => area,
+    };
+    match area.as_array().ok()?.as_slice() {
+        [left, bottom, right, top] => Some([
+            left.as_float().ok()?,
+            bottom.as_float().ok()?,
+            right.as_float().ok()?,
+            top.as_float().ok()?,
+        ]),
+        _ => None,
+    }
+}
+
+/// Width and height of the page in points, as it is shown: turned by its
+/// `Rotate`.
+pub fn displayed_size(document: &Document, page_id: ObjectId) -> Option<(f32, f32)> {
+    let [left, bottom, right, top] = page_box(document, page_id)?;
+    let (width, height) = ((right - left).abs(), (top - bottom).abs());
+    let rotate = inherited(document, page_id, b"Rotate")
+        .and_then(|rotate| rotate.as_i64().ok())
+        .unwrap_or(0);
+    match rotate.rem_euclid(180) {
+        90 => Some((height, width)),
+        _ => Some((width, height)),
+    }
+}
@@ -0,0 +1,169 @@
+//! Page anchors: page numbers named once in the project file, which the page
+//! cells of slice requests can count from (`appendix_a + 3`). With
+//! `--use-page-labels`, cells start from the guide's printed page labels
+//! instead of page numbers (`xii`, `45 + 2`).
+
+use csv::StringRecord;
+use npch_slicer::page_labels::{LabelError, PageLabels};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AnchorError {
+    #[error(
+        "unknown page anchor {name:?} in {cell:?}; anchors are set in the project file's [anchors]"
+    )]
+    Unknown { name: String, cell: String },
+    #[error("{cell:?} is not a page: give a number or anchor, plus or minus numbers and anchors")]
+    Syntax { cell: String },
+    #[error("{cell:?} comes to {value}, before the first page")]
+    Negative { cell: String, value: i64 },
+    #[error(transparent)]
+    Label(#[from] LabelError),
+}
+
+/// The value of `cell`, a sum of page numbers and anchors such as
+/// `appendix_a + 3` or `glossary - 1`. With `labels`, a cell that is a page
+/// label is that page, and so is its first term when it is a number or no
+/// anchor; the terms after it are still counts of pages.
+fn evaluate(
+    cell: &str,
+    anchors: &BTreeMap<String, u32>,
+    labels: Option<&PageLabels>,
+) -> Result<u32, AnchorError> {
+    let syntax = || AnchorError::Syntax {
+        cell: cell.to_string(),
+    };
+    if let Some(labels) = labels {
+        match labels.page(cell.trim()) {
+            Err(LabelError::Unknown(_)) => {}
+            page => return Ok(page?),
+        }
+    }
+    let mut value = 0i64;
+    let mut sign = Some(1);
+    let mut rest = cell.trim();
+    while !rest.is_empty() {
+        let operator = match rest.as_bytes()[0] {
+            b'+' => Some(1),
+            b'-' => Some(-1),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            // An operator only ever follows a term.
+            if sign.is_some() {
+                return Err(syntax());
+            }
+            sign = Some(operator);
+            rest = rest[1..].trim_start();
+            continue;
+        }
+        let Some(term_sign) = sign.take() else {
+            return Err(syntax());
+        };
+        let length = rest
+            .find(|character: char| !(character.is_alphanumeric() || character == '_'))
+            .unwrap_or(rest.len());
+        let (term, after) = rest.split_at(length);
+        let first = value == 0 && after.len() + term.len() == cell.trim().len();
+        let term = match (term.parse::<u32>(), labels) {
+            (_, Some(labels)) if first && !anchors.contains_key(term) => labels.page(term)?,
+            (Ok(number), _) => number,
+            (Err(_), _)
+                if term.starts_with(|character: char| {
+                    character.is_alphabetic() || character == '_'
+                }) =>
+            {
+                *anchors.get(term).ok_or_else(|| AnchorError::Unknown {
+                    name: term.to_string(),
+                    cell: cell.to_string(),
+                })?
+            }
+            (Err(_), _) => return Err(syntax()),
+        };
+        value += term_sign * i64::from(term);
+        rest = after.trim_start();
+    }
+    if sign.is_some() {
+        return Err(syntax());
+    }
+    u32::try_from(value).map_err(|_| AnchorError::Negative {
+        cell: cell.to_string(),
+        value,
+    })
+}
+
+/// Whether `cell` is written with anchors or arithmetic rather than as plain
+/// page numbers, which are left for the usual parsing and its errors.
+fn is_expression(cell: &str) -> bool {
+    cell.contains(|character: char| {
+        character.is_alphabetic() || character == '_' || character == '+'
+    })
+}
+
+/// A `pages` list with its anchored items evaluated: single pages like
+/// `glossary + 1`, and ranges like `appendix_a .. appendix_a + 7`, which
+/// take `..` since `-` subtracts. With `labels`, every item is evaluated,
+/// and ranges of labels like `iii-v` may take `-`.
+fn evaluate_pages(
+    cell: &str,
+    anchors: &BTreeMap<String, u32>,
+    labels: Option<&PageLabels>,
+) -> Result<String, AnchorError> {
+    let items = cell
+        .split(',')
+        .map(|item| match (item.split_once(".."), labels) {
+            (Some((first, last)), _) => Ok(format!(
+                "{}-{}",
+                evaluate(first, anchors, labels)?,
+                evaluate(last, anchors, labels)?
+            )),
+            (None, Some(labels)) => match labels.page(item.trim()) {
+                Err(LabelError::Unknown(_)) => match label_range(item, labels) {
+                    Some((first, last)) => Ok(format!("{first}-{last}")),
+                    None => Ok(evaluate(item, anchors, Some(labels))?.to_string()),
+                },
+                page => Ok(page?.to_string()),
+            },
+            (None, None) if is_expression(item) => Ok(evaluate(item, anchors, None)?.to_string()),
+            (None, None) => Ok(item.trim().to_string()),
+        })
+        .collect::<Result<Vec<String>, AnchorError>>()?;
+    Ok(items.join(","))
+}
+
+/// The pages of `item` when it is two labels joined by a `-`, which a label
+/// may contain too.
+fn label_range(item: &str, labels: &PageLabels) -> Option<(u32, u32)> {
+    let item = item.trim();
+    item.match_indices('-').find_map(|(at, _)| {
+        let first = labels.page(item[..at].trim()).ok()?;
+        let last = labels.page(item[at + 1..].trim()).ok()?;
+        Some((first, last))
+    })
+}
+
+/// `record` with every anchored cell of its `start_page`, `end_page`,
+/// `last_page` and `pages` columns replaced by the page numbers it comes to,
+/// and with `labels`, every one of them.
+pub fn resolve(
+    headers: &StringRecord,
+    record: &StringRecord,
+    anchors: &BTreeMap<String, u32>,
+    labels: Option<&PageLabels>,
+) -> Result<StringRecord, AnchorError> {
+    headers
+        .iter()
+        .zip(record)
+        .map(|(column, cell)| match column {
+            "start_page" | "end_page" | "last_page"
+                if is_expression(cell) || labels.is_some() && !cell.trim().is_empty() =>
+            {
+                Ok(evaluate(cell, anchors, labels)?.to_string())
+            }
+            "pages" if labels.is_some() && cell.trim().is_empty() => Ok(cell.to_string()),
+            "pages" => evaluate_pages(cell, anchors, labels),
+            _ => Ok(cell.to_string()),
+        })
+        .collect()
+}
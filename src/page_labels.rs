@@ -0,0 +1,234 @@
+//! Page labels: the numbers pages are printed with, like `xii` in the front
+//! matter or `45` in a body that starts again at 1, as the catalog's
+//! `/PageLabels` number tree gives them.
+
+use crate::pages::PageRanges;
+use crate::text::decode_text_string;
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+/// The largest number labels write in roman numerals or letters, which run
+/// longer the larger it is; no document counts that far in them.
+const MAX_NUMERAL: i64 = 99_999;
+
+#[derive(Error, Debug)]
+pub enum LabelError {
+    #[error("no page is labelled {0:?}")]
+    Unknown(String),
+    #[error("pages {} are all labelled {label:?}", PageRanges::from_iter(pages.iter().copied()))]
+    Ambiguous { label: String, pages: Vec<u32> },
+}
+
+/// The label of every page of a document, and the pages of every label.
+#[derive(Debug, Clone)]
+pub struct PageLabels {
+    /// By page, from page 1.
+    labels: Vec<String>,
+    /// The 1-based pages with each label, in order.
+    pages: BTreeMap<String, Vec<u32>>,
+}
+
+impl PageLabels {
+    /// The labels of `document`'s pages. Pages before the first labelled
+    /// range, and every page of a document without labels, are labelled with
+    /// their page numbers.
+    pub fn new(document: &Document) -> PageLabels {
+        let mut ranges = BTreeMap::new();
+        if let Some(Object::Dictionary(root)) = document
+            .catalog()
+            .ok()
+            .and_then(|catalog| catalog.get(b"PageLabels").ok())
+            .and_then(|root| resolve(document, root))
+        {
+            collect_ranges(document, root, &mut BTreeSet::new(), &mut ranges, 0);
+        }
+
+        let page_count = document.get_pages().len() as u32;
+        let mut labels = Vec::with_capacity(page_count as usize);
+        let mut pages = BTreeMap::<String, Vec<u32>>::new();
+        for index in 0..page_count {
+            let label = match ranges.range(..=index).next_back() {
+                Some((&first, range)) => range.label(index - first),
+                None => (index + 1).to_string(),
+            };
+            pages.entry(label.clone()).or_default().push(index + 1);
+            labels.push(label);
+        }
+        PageLabels { labels, pages }
+    }
+
+    /// The label of the 1-based `page`.
+    pub fn label(&self, page: u32) -> Option<&str> {
+        let index = page.checked_sub(1)? as usize;
+        self.labels.get(index).map(String::as_str)
+    }
+
+    /// The 1-based page labelled `label`, which only that page may be.
+    pub fn page(&self, label: &str) -> Result<u32, LabelError> {
+        match self.pages.get(label).map(Vec::as_slice) {
+            Some(&[page]) => Ok(page),
+            Some(pages) => Err(LabelError::Ambiguous {
+                label: label.to_string(),
+                pages: pages.to_vec(),
+            }),
+            None => Err(LabelError::Unknown(label.to_string())),
+        }
+    }
+}
+
+/// How the pages of a range, from the key it is filed under, are labelled.
+struct LabelRange {
+    style: Option<Vec<u8>>,
+    prefix: String,
+    start: i64,
+}
+
+impl LabelRange {
+    fn new(document: &Document, dictionary: &Dictionary) -> LabelRange {
+        let entry = |key: &[u8]| {
+            dictionary
+                .get(key)
+                .ok()
+                .and_then(|entry| resolve(document, entry))
+        };
+        LabelRange {
+            style: entry(b"S")
+                .and_then(|style| style.as_name().ok())
+                .map(<[u8]>::to_vec),
+            prefix: entry(b"P")
+                .and_then(|prefix| prefix.as_str().ok())
+                .map(decode_text_string)
+                .unwrap_or_default(),
+            start: entry(b"St")
+                .and_then(|start| start.as_i64().ok())
+                .filter(|&start| start >= 1)
+                .unwrap_or(1),
+        }
+    }
+
+    /// The label of the range's page `offset` pages after its first.
+    fn label(&self, offset: u32) -> String {
+        // A hostile /St can sit at the very end of the range of numbers.
+        let number = self.start.saturating_add(i64::from(offset));
+        let numeral = match self.style.as_deref() {
+            Some(b"D") => number.to_string(),
+            Some(b"R" | b"r" | b"A" | b"a") if number > MAX_NUMERAL => number.to_string(),
+            Some(b"R") => roman(number).to_uppercase(),
+            Some(b"r") => roman(number),
+            Some(b"A") => letters(number).to_uppercase(),
+            Some(b"a") => letters(number),
+            _ => String::new(),
+        };
+        format!("{}{numeral}", self.prefix)
+    }
+}
+
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    document.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// The label ranges in the number tree `node`, by the 0-based page they
+/// start on.
+fn collect_ranges(
+    document: &Document,
+    node: &Dictionary,
+    visited: &mut BTreeSet<ObjectId>,
+    ranges: &mut BTreeMap<u32, LabelRange>,
+    depth: usize,
+) {
+    if depth > MAX_NESTING {
+        return;
+    }
+    if let Some(nums) = node
+        .get(b"Nums")
+        .ok()
+        .and_then(|nums| resolve(document, nums))
+        .and_then(|nums| nums.as_array().ok())
+    {
+        for pair in nums.chunks_exact(2) {
+            let first = pair[0]
+                .as_i64()
+                .ok()
+                .and_then(|first| u32::try_from(first).ok());
+            let range = resolve(document, &pair[1]).and_then(|range| range.as_dict().ok());
+            if let (Some(first), Some(range)) = (first, range) {
+                ranges.insert(first, LabelRange::new(document, range));
+            }
+        }
+    }
+
+    let kids = node
+        .get(b"Kids")
+        .ok()
+        .and_then(|kids| resolve(document, kids))
+        .and_then(|kids| kids.as_array().ok());
+    for kid in kids.into_iter().flatten() {
+        if let Ok(id) = kid.as_reference() {
+            if !visited.insert(id) {
+                continue;
+            }
+        }
+        if let Some(Object::Dictionary(kid)) = resolve(document, kid) {
+            collect_ranges(document, kid, visited, ranges, depth + 1);
+        }
+    }
+}
+
+/// `number` in lowercase roman numerals, with an `m` for every thousand.
+fn roman(number: i64) -> String {
+    const NUMERALS: [(i64, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut rest = number;
+    let mut numeral = String::new();
+    for (value, letters) in NUMERALS {
+        while rest >= value {
+            numeral.push_str(letters);
+            rest -= value;
+        }
+    }
+    numeral
+}
+
+/// `number` in lowercase letters the way the standard counts with them: `a`
+/// to `z`, then `aa` to `zz`, then `aaa` and so on.
+fn letters(number: i64) -> String {
+    let letter = (b'a' + ((number - 1) % 26) as u8) as char;
+    letter.to_string().repeat(((number - 1) / 26 + 1) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_starts_do_not_overflow() {
+        let range = LabelRange {
+            style: Some(b"D".to_vec()),
+            prefix: String::from("A-"),
+            start: i64::MAX,
+        };
+        assert_eq!(range.label(0), format!("A-{}", i64::MAX));
+        assert_eq!(range.label(u32::MAX), format!("A-{}", i64::MAX));
+        let range = LabelRange {
+            style: Some(b"r".to_vec()),
+            ..range
+        };
+        // Past the largest numeral, numbers are written in digits.
+        assert_eq!(range.label(3), format!("A-{}", i64::MAX));
+    }
+}
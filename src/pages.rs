@@ -0,0 +1,387 @@
+//! Sets of 1-based page numbers, and the operations used to validate slice
+//! requests against a document.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+use thiserror::Error;
+
+/// A set of pages, kept as sorted inclusive runs so that a slice of thousands
+/// of pages costs one entry rather than one per page. Serialized the way it
+/// prints, e.g. `"1-8, 10, 12-40"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PageRanges {
+    /// Disjoint, with at least one page missing between neighbours.
+    runs: Vec<(u32, u32)>,
+}
+
+impl PageRanges {
+    pub fn new() -> PageRanges {
+        PageRanges::default()
+    }
+
+    /// The runs overlapping `first..=last`, and with `bordering` also the
+    /// ones right next to it.
+    fn touching(&self, first: u32, last: u32, bordering: bool) -> Range<usize> {
+        let slack = u32::from(bordering);
+        let start = self
+            .runs
+            .partition_point(|&(_, end)| end.saturating_add(slack) < first);
+        let end = self
+            .runs
+            .partition_point(|&(begin, _)| begin <= last.saturating_add(slack));
+        start..end
+    }
+
+    /// Adds the pages `first` to `last`, inclusive.
+    pub fn insert_range(&mut self, first: u32, last: u32) {
+        if first > last {
+            return;
+        }
+        let touching = self.touching(first, last, true);
+        let merged = self.runs[touching.clone()]
+            .iter()
+            .fold((first, last), |(first, last), &(begin, end)| {
+                (first.min(begin), last.max(end))
+            });
+        self.runs.splice(touching, [merged]);
+    }
+
+    pub fn insert(&mut self, page: u32) {
+        self.insert_range(page, page);
+    }
+
+    pub fn contains(&self, page: u32) -> bool {
+        let index = self.runs.partition_point(|&(_, end)| end < page);
+        self.runs
+            .get(index)
+            .is_some_and(|&(begin, _)| begin <= page)
+    }
+
+    /// How many pages of the set come before `page`, if it is in the set.
+    pub fn position(&self, page: u32) -> Option<usize> {
+        let index = self.runs.partition_point(|&(_, end)| end < page);
+        let &(begin, _) = self.runs.get(index).filter(|&&(begin, _)| begin <= page)?;
+        let before = self.runs[..index]
+            .iter()
+            .map(|&(begin, end)| (end - begin) as usize + 1)
+            .sum::<usize>();
+        Some(before + (page - begin) as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs
+            .iter()
+            .map(|&(begin, end)| (end - begin) as usize + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn first(&self) -> Option<u32> {
+        self.runs.first().map(|&(begin, _)| begin)
+    }
+
+    pub fn last(&self) -> Option<u32> {
+        self.runs.last().map(|&(_, end)| end)
+    }
+
+    /// The inclusive runs of consecutive pages, in order.
+    pub fn runs(&self) -> impl Iterator<Item = RangeInclusive<u32>> + '_ {
+        self.runs.iter().map(|&(begin, end)| begin..=end)
+    }
+
+    /// Every page, in order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.runs.iter().flat_map(|&(begin, end)| begin..=end)
+    }
+
+    pub fn union(&self, other: &PageRanges) -> PageRanges {
+        let mut union = self.clone();
+        for &(begin, end) in &other.runs {
+            union.insert_range(begin, end);
+        }
+        union
+    }
+
+    pub fn intersection(&self, other: &PageRanges) -> PageRanges {
+        let mut intersection = PageRanges::new();
+        for &(first, last) in &self.runs {
+            for &(begin, end) in &other.runs[other.touching(first, last, false)] {
+                intersection.runs.push((first.max(begin), last.min(end)));
+            }
+        }
+        intersection
+    }
+
+    /// The pages of the set that are not in `other`.
+    pub fn difference(&self, other: &PageRanges) -> PageRanges {
+        let mut difference = PageRanges::new();
+        for &(first, last) in &self.runs {
+            // The first page not yet handled, which can be past u32::MAX.
+            let mut next = u64::from(first);
+            for &(begin, end) in &other.runs[other.touching(first, last, false)] {
+                if u64::from(begin) > next {
+                    difference.runs.push((next as u32, begin - 1));
+                }
+                next = u64::from(end) + 1;
+            }
+            if next <= u64::from(last) {
+                difference.runs.push((next as u32, last));
+            }
+        }
+        difference
+    }
+}
+
+impl From<Range<u32>> for PageRanges {
+    fn from(range: Range<u32>) -> PageRanges {
+        let mut pages = PageRanges::new();
+        if range.start < range.end {
+            pages.insert_range(range.start, range.end - 1);
+        }
+        pages
+    }
+}
+
+impl From<RangeInclusive<u32>> for PageRanges {
+    fn from(range: RangeInclusive<u32>) -> PageRanges {
+        let mut pages = PageRanges::new();
+        pages.insert_range(*range.start(), *range.end());
+        pages
+    }
+}
+
+impl From<&BTreeSet<u32>> for PageRanges {
+    fn from(pages: &BTreeSet<u32>) -> PageRanges {
+        pages.iter().copied().collect()
+    }
+}
+
+impl FromIterator<u32> for PageRanges {
+    fn from_iter<I: IntoIterator<Item = u32>>(pages: I) -> PageRanges {
+        let mut ranges = PageRanges::new();
+        for page in pages {
+            ranges.insert(page);
+        }
+        ranges
+    }
+}
+
+/// Comma separated inclusive runs, e.g. `1-8, 10, 12-40`.
+impl fmt::Display for PageRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, &(begin, end)) in self.runs.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            match begin == end {
+                true => write!(f, "{begin}")?,
+                false => write!(f, "{begin}-{end}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{0:?} is not a list of pages and inclusive page ranges like \"1-8, 10\"")]
+pub struct PageRangesError(String);
+
+impl TryFrom<String> for PageRanges {
+    type Error = PageRangesError;
+
+    fn try_from(text: String) -> Result<PageRanges, PageRangesError> {
+        let page = |page: &str| page.trim().parse::<u32>().ok().filter(|page| *page > 0);
+        let mut pages = PageRanges::new();
+        for run in text.split(',').filter(|run| !run.trim().is_empty()) {
+            let run = match run.split_once('-') {
+                Some((first, last)) => page(first).zip(page(last)),
+                None => page(run).map(|page| (page, page)),
+            };
+            match run {
+                Some((first, last)) if first <= last => pages.insert_range(first, last),
+                _ => return Err(PageRangesError(text)),
+            }
+        }
+        Ok(pages)
+    }
+}
+
+impl From<PageRanges> for String {
+    fn from(pages: PageRanges) -> String {
+        pages.to_string()
+    }
+}
+
+/// The pages in any of `sets`.
+pub fn union<'a>(sets: impl IntoIterator<Item = &'a PageRanges>) -> PageRanges {
+    sets.into_iter()
+        .fold(PageRanges::new(), |union, pages| union.union(pages))
+}
+
+/// The pages of `pages` that a document of `page_count` pages does not have.
+pub fn outside(pages: &PageRanges, page_count: u32) -> PageRanges {
+    pages.difference(&PageRanges::from(1..=page_count))
+}
+
+/// The pages of a document of `page_count` pages that none of `sets` has.
+pub fn gaps<'a>(sets: impl IntoIterator<Item = &'a PageRanges>, page_count: u32) -> PageRanges {
+    PageRanges::from(1..=page_count).difference(&union(sets))
+}
+
+/// The pages in any of the keyed `sets`, grouped by exactly which of them
+/// hold the pages; keys are listed in the order given.
+pub fn coverage<'a, K: Clone + Ord>(
+    sets: impl IntoIterator<Item = (K, &'a PageRanges)>,
+) -> BTreeMap<Vec<K>, PageRanges> {
+    let sets = sets.into_iter().collect::<Vec<(K, &PageRanges)>>();
+    // Which sets hold a page only changes where one of their runs starts or
+    // ends.
+    let bounds = sets
+        .iter()
+        .flat_map(|(_, pages)| pages.runs.iter())
+        .flat_map(|&(begin, end)| [u64::from(begin), u64::from(end) + 1])
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .collect::<Vec<u64>>();
+    let mut coverage = BTreeMap::<Vec<K>, PageRanges>::new();
+    for bound in bounds.windows(2) {
+        let (first, last) = (bound[0] as u32, (bound[1] - 1) as u32);
+        let keys = sets
+            .iter()
+            .filter(|(_, pages)| pages.contains(first))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<K>>();
+        if !keys.is_empty() {
+            coverage.entry(keys).or_default().insert_range(first, last);
+        }
+    }
+    coverage
+}
+
+/// The pages held by more than one of the keyed `sets`, grouped by which
+/// sets share them.
+pub fn overlaps<'a, K: Clone + Ord>(
+    sets: impl IntoIterator<Item = (K, &'a PageRanges)>,
+) -> BTreeMap<Vec<K>, PageRanges> {
+    coverage(sets)
+        .into_iter()
+        .filter(|(keys, _)| keys.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pages(text: &str) -> PageRanges {
+        PageRanges::try_from(text.to_string()).unwrap()
+    }
+
+    #[test]
+    fn adjacent_and_overlapping_runs_merge() {
+        let mut ranges = PageRanges::new();
+        ranges.insert_range(1, 3);
+        ranges.insert_range(4, 6);
+        assert_eq!(ranges.runs, [(1, 6)]);
+        ranges.insert_range(10, 12);
+        ranges.insert_range(5, 10);
+        assert_eq!(ranges.runs, [(1, 12)]);
+        ranges.insert_range(14, 14);
+        ranges.insert_range(20, 30);
+        ranges.insert_range(13, 21);
+        assert_eq!(ranges.runs, [(1, 30)]);
+        ranges.insert_range(40, 32);
+        assert_eq!(ranges.runs, [(1, 30)]);
+        ranges.insert(u32::MAX);
+        ranges.insert(u32::MAX - 1);
+        assert_eq!(ranges.runs, [(1, 30), (u32::MAX - 1, u32::MAX)]);
+    }
+
+    #[test]
+    fn runs_one_page_apart_stay_apart() {
+        let ranges = pages("1-3, 5-6, 8");
+        assert_eq!(ranges.runs, [(1, 3), (5, 6), (8, 8)]);
+        assert_eq!(ranges.len(), 6);
+        assert_eq!(ranges.to_string(), "1-3, 5-6, 8");
+        assert_eq!(ranges.position(5), Some(3));
+        assert_eq!(ranges.position(4), None);
+    }
+
+    #[test]
+    fn intersections_keep_only_shared_pages() {
+        let intersection = pages("1-10, 20-30").intersection(&pages("5-25, 28"));
+        assert_eq!(intersection.runs, [(5, 10), (20, 25), (28, 28)]);
+        assert!(pages("1-4").intersection(&pages("5-9")).is_empty());
+    }
+
+    #[test]
+    fn a_difference_can_split_a_run_in_two() {
+        assert_eq!(
+            pages("1-10").difference(&pages("4-6")).runs,
+            [(1, 3), (7, 10)]
+        );
+        assert_eq!(
+            pages("1-20").difference(&pages("1, 5-6, 20")).runs,
+            [(2, 4), (7, 19)]
+        );
+        assert!(pages("3-4").difference(&pages("1-10")).is_empty());
+        let end = PageRanges::from(u32::MAX - 2..=u32::MAX);
+        assert_eq!(
+            end.difference(&PageRanges::from(u32::MAX..=u32::MAX)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn empty_sets() {
+        let empty = PageRanges::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.first(), None);
+        assert_eq!(empty.to_string(), "");
+        assert_eq!(pages(""), empty);
+        assert_eq!(PageRanges::from(5..5), empty);
+        assert!(empty.intersection(&pages("1-5")).is_empty());
+        assert!(pages("1-5").intersection(&empty).is_empty());
+        assert!(empty.difference(&pages("1-5")).is_empty());
+        assert_eq!(pages("1-5").difference(&empty), pages("1-5"));
+        assert!(union([]).is_empty());
+        assert_eq!(gaps([], 3), pages("1-3"));
+        assert!(gaps([&pages("1-5")], 0).is_empty());
+        assert!(overlaps::<u32>([]).is_empty());
+    }
+
+    #[test]
+    fn gaps_are_what_no_set_covers() {
+        let sets = [pages("2-4"), pages("4-7"), pages("10")];
+        assert_eq!(gaps(&sets, 12), pages("1, 8-9, 11-12"));
+        assert_eq!(outside(&pages("5-12"), 10), pages("11-12"));
+    }
+
+    #[test]
+    fn overlaps_group_pages_by_the_sets_sharing_them() {
+        let (a, b, c) = (pages("1-10"), pages("5-15"), pages("8-9, 14-20"));
+        let shared = overlaps([("a", &a), ("b", &b), ("c", &c)]);
+        assert_eq!(
+            shared,
+            BTreeMap::from([
+                (vec!["a", "b"], pages("5-7, 10")),
+                (vec!["a", "b", "c"], pages("8-9")),
+                (vec!["b", "c"], pages("14-15")),
+            ])
+        );
+        assert!(overlaps([("a", &pages("1-4")), ("b", &pages("5-8"))]).is_empty());
+    }
+
+    #[test]
+    fn malformed_lists_are_refused() {
+        for text in ["0", "3-1", "1-", "a", "1-2-3", "1, x"] {
+            assert!(PageRanges::try_from(text.to_string()).is_err(), "{text:?}");
+        }
+    }
+}
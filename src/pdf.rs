@@ -0,0 +1,273 @@
+use crate::encryption::{self, EncryptionError};
+use crate::limits::{self, LimitError, Limits};
+use crate::updates::{UpdateChain, UpdateError};
+use lopdf::Document;
+use std::future::Future;
+use std::io::{self, Read};
+use std::path::Path;
+use thiserror::Error;
+
+/// lopdf reads and writes cross-reference offsets as 32-bit integers, so a
+/// file past this size would load with the wrong objects or save with a
+/// corrupt xref table.
+pub const MAX_PDF_BYTES: u64 = u32::MAX as u64;
+
+#[derive(Debug, Error)]
+pub enum PdfError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Pdf(#[from] lopdf::Error),
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error(transparent)]
+    Updates(#[from] UpdateError),
+    #[error("{size} bytes is more than the {MAX_PDF_BYTES} bytes 32-bit xref offsets can address")]
+    TooLarge { size: u64 },
+}
+
+pub fn check_size(size: u64) -> Result<(), PdfError> {
+    if size > MAX_PDF_BYTES {
+        return Err(PdfError::TooLarge { size });
+    }
+    Ok(())
+}
+
+pub fn load(path: &Path, limits: &Limits) -> Result<Document, PdfError> {
+    load_with_password(path, limits, None)
+}
+
+/// Loads `path`, decrypting it with `password` if it is encrypted; without
+/// one, only files encrypted with an empty user password load.
+pub fn load_with_password(
+    path: &Path,
+    limits: &Limits,
+    password: Option<&str>,
+) -> Result<Document, PdfError> {
+    check_size(path.metadata()?.len())?;
+    load_mem_with_password(&std::fs::read(path)?, limits, password)
+}
+
+pub fn load_mem(data: &[u8], limits: &Limits) -> Result<Document, PdfError> {
+    load_mem_with_password(data, limits, None)
+}
+
+/// Loads `data` as its newest revision, decrypting it with `password` if it
+/// is encrypted.
+pub fn load_mem_with_password(
+    data: &[u8],
+    limits: &Limits,
+    password: Option<&str>,
+) -> Result<Document, PdfError> {
+    check_size(data.len() as u64)?;
+    limits::scan(data, limits)?;
+    let chain = match UpdateChain::read(data, limits) {
+        Ok(chain) if chain.is_incremental() => Some(chain),
+        Err(error @ UpdateError::Loop(_)) => return Err(error.into()),
+        // Anything else lopdf is left to make what it can of, as before.
+        _ => None,
+    };
+    let resolved = chain.as_ref().map(|chain| chain.resolved(data));
+    let data = resolved.as_deref().unwrap_or(data);
+    let mut document = Document::load_mem(data)?;
+    if let Some(chain) = &chain {
+        document.trailer = chain.trailer().clone();
+        chain.settle(&mut document);
+    }
+    if encryption::is_encrypted(&document) {
+        document = encryption::decrypt(data, document, password, limits)?;
+        if let Some(chain) = &chain {
+            chain.settle(&mut document);
+        }
+    }
+    limits::check_objects(document.objects.len(), limits)?;
+    Ok(document)
+}
+
+/// Where a document is read from.
+pub enum Source<'a> {
+    Path(&'a Path),
+    Bytes(&'a [u8]),
+    /// Read to the end, up to `MAX_PDF_BYTES`, before anything is parsed.
+    Reader(&'a mut dyn Read),
+}
+
+impl<'a> From<&'a Path> for Source<'a> {
+    fn from(path: &'a Path) -> Source<'a> {
+        Source::Path(path)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Source<'a> {
+    fn from(data: &'a [u8]) -> Source<'a> {
+        Source::Bytes(data)
+    }
+}
+
+impl<'a, R: Read> From<&'a mut R> for Source<'a> {
+    fn from(reader: &'a mut R) -> Source<'a> {
+        Source::Reader(reader)
+    }
+}
+
+impl<'a> From<&'a mut dyn Read> for Source<'a> {
+    fn from(reader: &'a mut dyn Read) -> Source<'a> {
+        Source::Reader(reader)
+    }
+}
+
+/// Loads a document from `source`, decrypting it with `password` as
+/// `load_with_password` does.
+pub fn load_source(
+    source: Source,
+    limits: &Limits,
+    password: Option<&str>,
+) -> Result<Document, PdfError> {
+    match source {
+        Source::Path(path) => load_with_password(path, limits, password),
+        Source::Bytes(data) => load_mem_with_password(data, limits, password),
+        Source::Reader(reader) => {
+            let mut data = Vec::new();
+            reader.take(MAX_PDF_BYTES + 1).read_to_end(&mut data)?;
+            load_mem_with_password(&data, limits, password)
+        }
+    }
+}
+
+/// Loads a document arriving in chunks, such as an object storage download:
+/// `next_chunk` is awaited until it gives `None`. Any async runtime will do.
+/// Only the reading is async; parsing the collected bytes blocks, so large
+/// documents are better finished on a blocking thread.
+pub async fn load_stream<F, C, B>(
+    mut next_chunk: F,
+    limits: &Limits,
+    password: Option<&str>,
+) -> Result<Document, PdfError>
+where
+    F: FnMut() -> C,
+    C: Future<Output = io::Result<Option<B>>>,
+    B: AsRef<[u8]>,
+{
+    let mut data = Vec::new();
+    while let Some(chunk) = next_chunk().await? {
+        data.extend_from_slice(chunk.as_ref());
+        check_size(data.len() as u64)?;
+    }
+    load_mem_with_password(&data, limits, password)
+}
+
+/// Whether loading `path` takes a password: whether it is encrypted with a
+/// user password other than the empty one.
+pub fn needs_password(path: &Path, limits: &Limits) -> Result<bool, PdfError> {
+    let data = std::fs::read(path)?;
+    // Files that never name an encryption dictionary needn't be loaded.
+    if !data.windows(8).any(|window| window == b"/Encrypt") {
+        return Ok(false);
+    }
+    match load_mem(&data, limits) {
+        Err(PdfError::Encryption(EncryptionError::PasswordRequired)) => Ok(true),
+        Err(error) => Err(error),
+        Ok(_) => Ok(false),
+    }
+}
+
+pub fn save_mem(document: &mut Document) -> Result<Vec<u8>, PdfError> {
+    let mut bytes = Vec::new();
+    document.save_to(&mut bytes)?;
+    check_size(bytes.len() as u64)?;
+    Ok(bytes)
+}
+
+/// Saves `document`, removing the file again if it came out too large to be
+/// read back.
+pub fn save(document: &mut Document, path: &Path) -> Result<(), PdfError> {
+    save_within(document, path, MAX_PDF_BYTES)
+}
+
+/// `save`, with outputs over `max` bytes removed.
+fn save_within(document: &mut Document, path: &Path, max: u64) -> Result<(), PdfError> {
+    document.save(path)?;
+    let size = path.metadata()?.len();
+    if size > max {
+        std::fs::remove_file(path)?;
+        return Err(PdfError::TooLarge { size });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::dictionary;
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    /// A path of its own in the temporary directory for the test `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("npch_slicer-{}-{name}.pdf", std::process::id()))
+    }
+
+    fn one_page() -> Document {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        });
+        document.objects.insert(
+            pages_id,
+            lopdf::Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        document
+    }
+
+    #[test]
+    fn sizes_past_32_bit_offsets_are_refused() {
+        assert!(check_size(0).is_ok());
+        assert!(check_size(MAX_PDF_BYTES).is_ok());
+        assert!(matches!(
+            check_size(MAX_PDF_BYTES + 1),
+            Err(PdfError::TooLarge { size }) if size == MAX_PDF_BYTES + 1
+        ));
+        assert!(check_size(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn files_past_32_bit_offsets_are_refused_unread() {
+        // Sparse, so it takes no room on disk.
+        let path = temp_path("large-source");
+        File::create(&path)
+            .and_then(|file| file.set_len(MAX_PDF_BYTES + 1))
+            .unwrap();
+        let loaded = load(&path, &Limits::default());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            loaded,
+            Err(PdfError::TooLarge { size }) if size == MAX_PDF_BYTES + 1
+        ));
+    }
+
+    #[test]
+    fn outputs_too_large_are_removed() {
+        let path = temp_path("large-output");
+        let saved = save_within(&mut one_page(), &path, 64);
+        assert!(matches!(saved, Err(PdfError::TooLarge { size }) if size > 64));
+        assert!(!path.exists());
+
+        save_within(&mut one_page(), &path, MAX_PDF_BYTES).unwrap();
+        assert!(load(&path, &Limits::default()).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! `--dry-run`: the slices a run would cut, as a table, with the overlaps and
+//! gaps in their coverage of the guide.
+
+use crate::resolved::ResolvedSlice;
+use crate::slice_log::Console;
+use npch_slicer::request::CoverageIssue;
+
+/// What is put in front of each slice's pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cover {
+    None,
+    /// `--cover-page`'s single page.
+    Generated,
+    /// The project file's cover command's, whose length isn't known before
+    /// it is rendered.
+    Rendered,
+}
+
+/// The page count a slice will come out with: its pages and any cover, padded
+/// to `pad_to`.
+fn estimated_pages(slice: &ResolvedSlice, pad_to: Option<u32>, cover: Cover) -> String {
+    let count = slice.page_count + usize::from(cover == Cover::Generated);
+    let count = match pad_to {
+        Some(multiple) => count.next_multiple_of(multiple as usize),
+        None => count,
+    };
+    match cover {
+        Cover::Rendered => format!("{count} + cover"),
+        Cover::None | Cover::Generated => count.to_string(),
+    }
+}
+
+/// Prints a row per slice with its description, pages, estimated page count
+/// and outputs, then warnings for the coverage `issues` other than pages
+/// beyond the guide's, which are refused.
+pub fn print(
+    slices: &[ResolvedSlice],
+    page_count: u32,
+    issues: &[CoverageIssue],
+    (pad_to, cover): (Option<u32>, Cover),
+    console: &mut Console,
+) {
+    let mut rows = vec![[
+        "description".to_string(),
+        "pages".to_string(),
+        "page count".to_string(),
+        "outputs".to_string(),
+    ]];
+    rows.extend(slices.iter().map(|slice| {
+        [
+            slice.request.description.clone(),
+            slice.request.pages.to_string(),
+            estimated_pages(slice, pad_to, cover),
+            slice.outputs.join(", "),
+        ]
+    }));
+    let mut widths = [0; 3];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for [description, pages, count, outputs] in &rows {
+        console.line(format_args!(
+            "{description:<0$}  {pages:<1$}  {count:>2$}  {outputs}",
+            widths[0], widths[1], widths[2]
+        ));
+    }
+
+    for issue in issues.iter().filter(|issue| !issue.is_error()) {
+        console.line(format_args!("warning: {issue}"));
+    }
+    console.line(format_args!(
+        "{} slice(s) of the guide's {page_count} page(s); nothing written",
+        slices.len()
+    ));
+}
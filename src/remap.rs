@@ -0,0 +1,354 @@
+//! `remap-source`: a best-effort draft of the slice requests for a new
+//! revision of the guide, with every row's page range moved to where its
+//! pages went.
+
+use crate::page_anchors;
+use csv::StringRecord;
+use lopdf::Document;
+use npch_slicer::fingerprint;
+use npch_slicer::limits::Limits;
+use npch_slicer::outline::{self, OutlineItem};
+use npch_slicer::page_labels::PageLabels;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::request::PageNumbering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// How much to trust a remapped range, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// The same pages at the same numbers.
+    Exact,
+    /// The same pages, all moved by the same offset.
+    High,
+    /// Most of the pages found again, in order.
+    Medium,
+    /// Few of the pages found again, or only the bookmark the range starts at.
+    Low,
+    /// Nothing found; the range is left as it was.
+    None,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match self {
+            Confidence::Exact => "exact",
+            Confidence::High => "high",
+            Confidence::Medium => "medium",
+            Confidence::Low => "low",
+            Confidence::None => "none",
+        })
+    }
+}
+
+/// A revision of the guide as far as remapping is concerned.
+pub struct Revision {
+    fingerprints: Vec<String>,
+    /// Every bookmark's title and page number, in outline order.
+    bookmarks: Vec<(String, u32)>,
+    labels: PageLabels,
+}
+
+impl Revision {
+    pub fn new(document: &Document, limits: &Limits) -> Revision {
+        let numbers = outline::page_numbers(document);
+        let mut bookmarks = Vec::new();
+        flatten(&outline::read_outline(document), &numbers, &mut bookmarks);
+        Revision {
+            fingerprints: fingerprint::page_fingerprints(document, limits),
+            bookmarks,
+            labels: PageLabels::new(document),
+        }
+    }
+
+    fn page_count(&self) -> u32 {
+        self.fingerprints.len() as u32
+    }
+
+    /// `page` as the draft writes it: its label with page labels, unless it
+    /// has none, like the page after the last.
+    fn cell(&self, page: u32, numbering: PageNumbering) -> String {
+        match self.labels.label(page) {
+            Some(label) if numbering.page_labels => label.to_string(),
+            _ => page.to_string(),
+        }
+    }
+
+    /// `pages` as the draft writes them.
+    fn list(&self, pages: &PageRanges, numbering: PageNumbering) -> String {
+        if !numbering.page_labels {
+            return pages.to_string();
+        }
+        let runs = pages
+            .runs()
+            .map(|run| match run.start() == run.end() {
+                true => self.cell(*run.start(), numbering),
+                false => format!(
+                    "{}-{}",
+                    self.cell(*run.start(), numbering),
+                    self.cell(*run.end(), numbering)
+                ),
+            })
+            .collect::<Vec<String>>();
+        runs.join(", ")
+    }
+}
+
+fn flatten(
+    items: &[OutlineItem],
+    numbers: &BTreeMap<lopdf::ObjectId, u32>,
+    bookmarks: &mut Vec<(String, u32)>,
+) {
+    for item in items {
+        if let Some(&number) = item.page.and_then(|page| numbers.get(&page)) {
+            bookmarks.push((item.title.clone(), number));
+        }
+        flatten(&item.children, numbers, bookmarks);
+    }
+}
+
+/// Where the pages `start..end` of `old` are in `new`.
+pub struct Remapped {
+    pub start: u32,
+    pub end: u32,
+    pub confidence: Confidence,
+    pub note: String,
+}
+
+/// Tries, in order: the same pages in place, the same pages all moved by one
+/// offset, as many of the pages as can be found in order, and the bookmark
+/// on the first page, keeping the range's length.
+pub fn remap(old: &Revision, new: &Revision, start: u32, end: u32) -> Remapped {
+    let pages = PageRanges::from(start..end);
+    let unchanged = |confidence, note: String| Remapped {
+        start,
+        end,
+        confidence,
+        note,
+    };
+    match fingerprint::page_shift(&old.fingerprints, &new.fingerprints, &pages) {
+        Some(0) => return unchanged(Confidence::Exact, String::new()),
+        Some(offset) => {
+            return Remapped {
+                start: (i64::from(start) + offset) as u32,
+                end: (i64::from(end) + offset) as u32,
+                confidence: Confidence::High,
+                note: format!("moved by {offset:+} page(s)"),
+            }
+        }
+        None => {}
+    }
+
+    let matched = matching_pages(old, new, &pages);
+    if let (Some(&(old_first, first)), Some(&(old_last, last))) = (matched.first(), matched.last())
+    {
+        // The pages not found before the first and after the last one found
+        // are taken to have stayed next to them.
+        let start = first.saturating_sub(old_first - start).max(1);
+        let end = (last + end - old_last).min(new.page_count() + 1);
+        let confidence = match matched.len() as u32 * 2 >= end - start {
+            true => Confidence::Medium,
+            false => Confidence::Low,
+        };
+        return Remapped {
+            start,
+            end,
+            confidence,
+            note: format!(
+                "{} of {} page(s) found unchanged, at pages {}",
+                matched.len(),
+                pages.len(),
+                matched
+                    .iter()
+                    .map(|&(_, page)| page)
+                    .collect::<PageRanges>()
+            ),
+        };
+    }
+
+    let bookmark = old
+        .bookmarks
+        .iter()
+        .filter(|(_, page)| *page == start)
+        .find_map(|(title, _)| {
+            let mut found = new.bookmarks.iter().filter(|(other, _)| other == title);
+            match (found.next(), found.next()) {
+                (Some((_, page)), None) => Some((title, *page)),
+                _ => None,
+            }
+        });
+    if let Some((title, page)) = bookmark {
+        let length = end - start;
+        if page + length <= new.page_count() + 1 {
+            return Remapped {
+                start: page,
+                end: page + length,
+                confidence: Confidence::Low,
+                note: format!("starts at bookmark {title:?}, now on page {page}; length kept"),
+            };
+        }
+    }
+
+    unchanged(
+        Confidence::None,
+        "no pages or bookmark found; check by hand".to_string(),
+    )
+}
+
+/// The pages of `pages` of `old` found unchanged in `new`, each with the
+/// page of `new` showing it, in increasing order: each old page goes to its
+/// nearest copy past the last one matched, so that repeated pages (blank
+/// ones, say) don't scramble them.
+fn matching_pages(old: &Revision, new: &Revision, pages: &PageRanges) -> Vec<(u32, u32)> {
+    let mut copies = BTreeMap::<&String, Vec<u32>>::new();
+    for (index, fingerprint) in new.fingerprints.iter().enumerate() {
+        copies
+            .entry(fingerprint)
+            .or_default()
+            .push(index as u32 + 1);
+    }
+    let mut matched = Vec::<(u32, u32)>::new();
+    let mut offset = 0i64;
+    for page in pages.iter() {
+        let Some(candidates) = old
+            .fingerprints
+            .get(page as usize - 1)
+            .and_then(|fingerprint| copies.get(fingerprint))
+        else {
+            continue;
+        };
+        let after = matched.last().map_or(0, |&(_, candidate)| candidate);
+        let expected = i64::from(page) + offset;
+        let nearest = candidates
+            .iter()
+            .filter(|&&candidate| candidate > after)
+            .min_by_key(|&&candidate| (i64::from(candidate) - expected).abs());
+        if let Some(&candidate) = nearest {
+            offset = i64::from(candidate) - i64::from(page);
+            matched.push((page, candidate));
+        }
+    }
+    matched
+}
+
+/// Remaps every run of `pages` on its own: the pages they went to, how much
+/// to trust the least trustworthy and what was found for each.
+fn remap_list(
+    old: &Revision,
+    new: &Revision,
+    pages: &PageRanges,
+) -> (PageRanges, Confidence, String) {
+    let remapped = pages
+        .runs()
+        .map(|run| (run.clone(), remap(old, new, *run.start(), run.end() + 1)))
+        .collect::<Vec<_>>();
+    let confidence = remapped
+        .iter()
+        .map(|(_, remapped)| remapped.confidence)
+        .max()
+        .unwrap_or(Confidence::None);
+    let notes = remapped
+        .iter()
+        .filter(|(_, remapped)| !remapped.note.is_empty())
+        .map(|(run, remapped)| format!("{}: {}", PageRanges::from(run.clone()), remapped.note))
+        .collect::<Vec<String>>();
+    let pages = remapped
+        .iter()
+        .map(|(_, remapped)| PageRanges::from(remapped.start..remapped.end))
+        .fold(PageRanges::new(), |pages, run| pages.union(&run));
+    (pages, confidence, notes.join("; "))
+}
+
+/// Writes `records` to `path` as CSV with their pages remapped from `old` to
+/// `new`, `start_page` and `end_page` (or `last_page`) counted by `numbering`
+/// or each run of `pages`, by their labels in `old` and `new` with page
+/// labels, plus a
+/// `remap_confidence` and a `remap_note` column; returns how many rows got
+/// each confidence. Rows without pages are copied as they are.
+pub fn write_draft(
+    path: &Path,
+    headers: &StringRecord,
+    records: &[StringRecord],
+    numbering: PageNumbering,
+    (old, new): (&Revision, &Revision),
+) -> io::Result<BTreeMap<Confidence, usize>> {
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let start_column = column("start_page");
+    let pages_column = column("pages");
+    // A last_page is an end_page counted inclusively.
+    let (end_column, numbering) = match (column("end_page"), column("last_page")) {
+        (None, Some(last_column)) => (
+            Some(last_column),
+            PageNumbering {
+                inclusive_end: true,
+                ..numbering
+            },
+        ),
+        (end_column, _) => (end_column, numbering),
+    };
+
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut draft_headers = headers.clone();
+    draft_headers.push_field("remap_confidence");
+    draft_headers.push_field("remap_note");
+    writer.write_record(&draft_headers)?;
+
+    let mut counts = BTreeMap::new();
+    for record in records {
+        let pages = match numbering.page_labels {
+            true => {
+                page_anchors::resolve(headers, record, &BTreeMap::new(), Some(&old.labels)).ok()
+            }
+            false => Some(record.clone()),
+        };
+        let page = |column: Option<usize>| {
+            pages
+                .as_ref()?
+                .get(column?)
+                .and_then(|cell| cell.trim().parse::<u32>().ok())
+        };
+        let list = pages_column
+            .and_then(|column| pages.as_ref()?.get(column))
+            .and_then(|cell| PageRanges::try_from(cell.to_string()).ok())
+            .filter(|pages| !pages.is_empty());
+        let mut draft = record.clone();
+        let (confidence, note) = match (list, page(start_column), page(end_column)) {
+            (Some(pages), _, _) => {
+                let (pages, confidence, note) = remap_list(old, new, &pages);
+                draft = replace(&draft, pages_column, &new.list(&pages, numbering));
+                (confidence, note)
+            }
+            (None, Some(start_page), Some(end_page)) => {
+                let (start, end) = numbering.normalize(start_page, end_page);
+                if start == 0 || start >= end {
+                    (Confidence::None, "not a page range".to_string())
+                } else {
+                    let remapped = remap(old, new, start, end);
+                    let (start_page, end_page) =
+                        numbering.denormalize(remapped.start, remapped.end);
+                    draft = replace(&draft, start_column, &new.cell(start_page, numbering));
+                    draft = replace(&draft, end_column, &new.cell(end_page, numbering));
+                    (remapped.confidence, remapped.note)
+                }
+            }
+            _ => (Confidence::None, "no pages to remap".to_string()),
+        };
+        draft.push_field(&confidence.to_string());
+        draft.push_field(&note);
+        writer.write_record(&draft)?;
+        *counts.entry(confidence).or_insert(0) += 1;
+    }
+    writer.flush()?;
+    Ok(counts)
+}
+
+/// `record` with the cell in `column` replaced by `cell`.
+fn replace(record: &StringRecord, column: Option<usize>, cell: &str) -> StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(index, old)| if Some(index) == column { cell } else { old })
+        .collect()
+}
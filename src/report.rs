@@ -0,0 +1,169 @@
+use crate::schema::SCHEMA_VERSION;
+use crate::variant::{OutputTarget, Variant};
+use npch_slicer::pages::PageRanges;
+use npch_slicer::SliceRequest;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+/// What became of a slice in the run.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Written,
+    Failed,
+    /// Not cut this run, its outputs being up to date.
+    Unchanged,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Written => "written",
+            Status::Failed => "failed",
+            Status::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// One optimized output of a slice found in the output directory.
+#[derive(Debug, Serialize)]
+pub struct ReportedOutput {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<Variant>,
+    pub path: String,
+    pub size: u64,
+    /// Its size over the unoptimized slice's; absent if that wasn't cut
+    /// this run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratio: Option<f64>,
+}
+
+/// A slice as the run left it, for build scripts to pick up.
+#[derive(Debug, Serialize)]
+pub struct ReportedSlice {
+    pub description: String,
+    pub pages: PageRanges,
+    pub page_count: usize,
+    pub status: Status,
+    /// Absent if it wasn't cut this run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unoptimized_size: Option<u64>,
+    pub outputs: Vec<ReportedOutput>,
+    pub warnings: Vec<String>,
+    pub log: String,
+}
+
+impl ReportedSlice {
+    pub fn new(
+        slice_request: &SliceRequest,
+        (status, unoptimized_size, warnings): (Status, Option<u64>, &[String]),
+        targets: &[OutputTarget],
+        output_dir: &Path,
+    ) -> ReportedSlice {
+        let outputs = targets
+            .iter()
+            .filter_map(|target| {
+                let path = output_dir
+                    .join(target.directory())
+                    .join(format!("{}.pdf", slice_request.output_name()));
+                let size = path.metadata().ok()?.len();
+                Some(ReportedOutput {
+                    variant: target.variant,
+                    path: path.display().to_string(),
+                    size,
+                    ratio: unoptimized_size
+                        .filter(|&unoptimized| unoptimized > 0)
+                        .map(|unoptimized| size as f64 / unoptimized as f64),
+                })
+            })
+            .collect();
+        ReportedSlice {
+            description: slice_request.description.clone(),
+            pages: slice_request.pages.clone(),
+            page_count: slice_request.pages.len(),
+            status,
+            unoptimized_size,
+            outputs,
+            warnings: warnings.to_vec(),
+            log: output_dir
+                .join("logs")
+                .join(format!("{}.log", slice_request.output_name()))
+                .display()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReportFile<'a> {
+    schema_version: u32,
+    slices: &'a [ReportedSlice],
+}
+
+/// Writes the report as JSON when `path` ends in `.json`, and as CSV
+/// otherwise: a row per output, or one with the output columns empty for a
+/// slice without any, and warnings separated by `;`.
+pub fn emit(path: &Path, slices: &[ReportedSlice]) -> io::Result<()> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let file = ReportFile {
+            schema_version: SCHEMA_VERSION,
+            slices,
+        };
+        return std::fs::write(path, serde_json::to_string_pretty(&file).unwrap());
+    }
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "description",
+        "pages",
+        "page_count",
+        "status",
+        "unoptimized_size",
+        "variant",
+        "output",
+        "size",
+        "ratio",
+        "warnings",
+        "log",
+    ])?;
+    for slice in slices {
+        let rows = slice
+            .outputs
+            .iter()
+            .map(|output| {
+                [
+                    output.variant.map(Variant::name).unwrap_or("").to_string(),
+                    output.path.clone(),
+                    output.size.to_string(),
+                    output
+                        .ratio
+                        .map_or(String::new(), |ratio| format!("{ratio:.4}")),
+                ]
+            })
+            .collect::<Vec<[String; 4]>>();
+        let empty = [[String::new(), String::new(), String::new(), String::new()]];
+        let rows = if rows.is_empty() { &empty[..] } else { &rows };
+        for [variant, output, size, ratio] in rows {
+            writer.write_record([
+                slice.description.as_str(),
+                &slice.pages.to_string(),
+                &slice.page_count.to_string(),
+                slice.status.name(),
+                &slice
+                    .unoptimized_size
+                    .map_or(String::new(), |size| size.to_string()),
+                variant,
+                output,
+                size,
+                ratio,
+                &slice.warnings.join(";"),
+                &slice.log,
+            ])?;
+        }
+    }
+    writer.flush()
+}
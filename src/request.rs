@@ -0,0 +1,535 @@
+use crate::pages::{self, PageRanges};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::slice::Iter;
+use thiserror::Error;
+
+/// A slice request as written in a row of the requests file: its pages either
+/// as `start_page` and the usual exclusive `end_page` (or an inclusive
+/// `last_page`), or as a `pages` list of pages and inclusive ranges like
+/// `3-10, 200-205, 250`.
+#[derive(Debug, Deserialize)]
+pub struct RawSliceRequest {
+    pub description: String,
+    pub start_page: Option<u32>,
+    pub end_page: Option<u32>,
+    pub last_page: Option<u32>,
+    pub pages: Option<PageRanges>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub expected_pages: Option<usize>,
+    pub must_contain: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum FromRawError {
+    #[error("Invalid page range for {description:?}: {start_page:?}, {end_page:?}")]
+    InvalidPageRange {
+        description: String,
+        start_page: u32,
+        end_page: u32,
+    },
+    #[error("empty page range for {description:?} (start == end)")]
+    EmptyPageRange { description: String },
+    #[error("missing description for page range {pages}")]
+    MissingDescription { pages: String },
+    #[error(
+        "no pages for {description:?}: give either start_page with end_page or last_page, \
+         or pages"
+    )]
+    MissingPages { description: String },
+    #[error("{description:?} gives its pages in more than one way; give only one")]
+    AmbiguousPages { description: String },
+}
+
+/// How the `start_page`, `end_page` and `last_page` columns count pages;
+/// `pages` lists always count from 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageNumbering {
+    /// `end_page` is the slice's last page rather than the one after it.
+    pub inclusive_end: bool,
+    /// The document's first page is page 0 rather than page 1.
+    pub zero_based: bool,
+    /// The page columns, `pages` lists too, give the labels pages are
+    /// printed with (see `page_labels`), which are turned into page numbers
+    /// counting from 1 before the rows are read, so not with `zero_based`.
+    pub page_labels: bool,
+}
+
+impl PageNumbering {
+    /// `start_page` and `end_page` as 1-based page numbers with an exclusive
+    /// end.
+    pub fn normalize(&self, start_page: u32, end_page: u32) -> (u32, u32) {
+        let offset = u32::from(self.zero_based);
+        let end_offset = offset + u32::from(self.inclusive_end);
+        (
+            start_page.saturating_add(offset),
+            end_page.saturating_add(end_offset),
+        )
+    }
+
+    /// The `start_page` and `end_page` counting this way for the 1-based
+    /// `start..end`.
+    pub fn denormalize(&self, start: u32, end: u32) -> (u32, u32) {
+        let offset = u32::from(self.zero_based);
+        let end_offset = offset + u32::from(self.inclusive_end);
+        (start - offset, end - end_offset)
+    }
+}
+
+impl TryFrom<RawSliceRequest> for SliceRequest {
+    type Error = FromRawError;
+
+    fn try_from(record: RawSliceRequest) -> Result<Self, Self::Error> {
+        SliceRequest::from_raw(record, PageNumbering::default())
+    }
+}
+
+impl SliceRequest {
+    /// The name of its output files, before the extension: its `file_name`,
+    /// else its description.
+    pub fn output_name(&self) -> &str {
+        self.file_name.as_deref().unwrap_or(&self.description)
+    }
+
+    /// The request in `record`, its page columns counted by `numbering`.
+    pub fn from_raw(
+        record: RawSliceRequest,
+        numbering: PageNumbering,
+    ) -> Result<SliceRequest, FromRawError> {
+        let RawSliceRequest {
+            description,
+            start_page,
+            end_page,
+            last_page,
+            pages,
+            title,
+            author,
+            subject,
+            keywords,
+            expected_pages,
+            must_contain,
+        } = record;
+        let request = |pages| SliceRequest {
+            description: description.clone(),
+            pages,
+            title,
+            author,
+            subject,
+            keywords,
+            expected_pages,
+            must_contain,
+            file_name: None,
+            fields: BTreeMap::new(),
+        };
+        if description.trim().is_empty() {
+            let cell = |page: Option<u32>| page.map_or(String::new(), |page| page.to_string());
+            let pages = match &pages {
+                Some(pages) => pages.to_string(),
+                None => format!("{}, {}", cell(start_page), cell(end_page)),
+            };
+            return Err(FromRawError::MissingDescription { pages });
+        }
+        let inclusive = PageNumbering {
+            inclusive_end: true,
+            ..numbering
+        };
+        let ((start, end), (start_page, end_page)) = match (pages, start_page, end_page, last_page)
+        {
+            (Some(pages), None, None, None) if pages.is_empty() => {
+                return Err(FromRawError::MissingPages { description })
+            }
+            (Some(pages), None, None, None) => return Ok(request(pages)),
+            (Some(_), _, _, _) | (None, _, Some(_), Some(_)) => {
+                return Err(FromRawError::AmbiguousPages { description })
+            }
+            (None, Some(start_page), Some(end_page), None) => (
+                numbering.normalize(start_page, end_page),
+                (start_page, end_page),
+            ),
+            (None, Some(start_page), None, Some(last_page)) => (
+                inclusive.normalize(start_page, last_page),
+                (start_page, last_page),
+            ),
+            (None, _, _, _) => return Err(FromRawError::MissingPages { description }),
+        };
+        match start.cmp(&end) {
+            Ordering::Less => Ok(request(PageRanges::from(start..end))),
+            Ordering::Equal => Err(FromRawError::EmptyPageRange { description }),
+            Ordering::Greater => Err(FromRawError::InvalidPageRange {
+                description,
+                start_page,
+                end_page,
+            }),
+        }
+    }
+}
+
+/// Serialized into the resolved slice list and other JSON outputs, whose
+/// shape is covered by `SCHEMA_VERSION`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SliceRequest {
+    /// Names the slice and its output files.
+    pub description: String,
+    pub pages: PageRanges,
+    /// Written to the slice's document information and XMP metadata, in
+    /// place of the source's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Written to the slice's document information.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<String>,
+    /// The page count the slice must come out with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_pages: Option<usize>,
+    /// A phrase the slice's text must contain, such as its chapter title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub must_contain: Option<String>,
+    /// Names its output files, before the extension, in place of the
+    /// description.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Every cell of the request's row, by column, for templates.
+    #[serde(skip)]
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Something amiss in how the slice requests of a run cover the guide.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CoverageIssue {
+    #[error("{description:?} requests pages {pages} outside the guide's {page_count} pages")]
+    Outside {
+        description: String,
+        pages: PageRanges,
+        page_count: u32,
+    },
+    #[error("pages {pages} are shared by {}", .descriptions.join(", "))]
+    Overlap {
+        descriptions: Vec<String>,
+        pages: PageRanges,
+    },
+    #[error("pages {0} are not covered by any slice")]
+    Uncovered(PageRanges),
+}
+
+impl CoverageIssue {
+    /// Whether no slice can be cut with it, rather than it being worth a
+    /// warning: only pages beyond the guide's.
+    pub fn is_error(&self) -> bool {
+        matches!(self, CoverageIssue::Outside { .. })
+    }
+}
+
+/// The slice requests of a run, in order.
+pub struct SliceRequests {
+    individuals: Vec<SliceRequest>,
+    required_pages: PageRanges,
+}
+
+impl SliceRequests {
+    pub fn new(individuals: Vec<SliceRequest>) -> SliceRequests {
+        let required_pages =
+            pages::union(individuals.iter().map(|slice_request| &slice_request.pages));
+
+        SliceRequests {
+            individuals,
+            required_pages,
+        }
+    }
+
+    /// The pages any of the slices needs.
+    pub fn required_pages(&self) -> &PageRanges {
+        &self.required_pages
+    }
+
+    /// The ones of `all_pages` that no slice needs.
+    pub fn unnecessary_pages(&self, all_pages: &PageRanges) -> PageRanges {
+        all_pages.difference(&self.required_pages)
+    }
+
+    /// What is amiss in how the slices cover a guide of `page_count` pages, or
+    /// of any length when it isn't known: pages beyond the guide's, then
+    /// descriptions shared by several slices, pages shared by several slices,
+    /// and pages no slice covers.
+    pub fn coverage_issues(&self, page_count: Option<u32>) -> Vec<CoverageIssue> {
+        let mut issues = Vec::new();
+        if let Some(page_count) = page_count {
+            for slice_request in &self.individuals {
+                let outside = pages::outside(&slice_request.pages, page_count);
+                if !outside.is_empty() {
+                    issues.push(CoverageIssue::Outside {
+                        description: slice_request.description.clone(),
+                        pages: outside,
+                        page_count,
+                    });
+                }
+            }
+        }
+
+        let mut counts = BTreeMap::<&str, usize>::new();
+        for slice_request in &self.individuals {
+            *counts.entry(&slice_request.description).or_default() += 1;
+        }
+        let shared = |description: &str| counts[description] > 1;
+
+        // Slices sharing a description are told apart by their positions.
+        let keyed = self
+            .individuals
+            .iter()
+            .enumerate()
+            .map(|(index, slice_request)| (index, &slice_request.pages));
+        for (indices, pages) in pages::overlaps(keyed) {
+            let descriptions = indices
+                .into_iter()
+                .map(|index| {
+                    let description = &self.individuals[index].description;
+                    match shared(description) {
+                        true => format!("{description} (slice {})", index + 1),
+                        false => description.clone(),
+                    }
+                })
+                .collect();
+            issues.push(CoverageIssue::Overlap {
+                descriptions,
+                pages,
+            });
+        }
+
+        if let Some(page_count) = page_count {
+            let uncovered = pages::gaps([&self.required_pages], page_count);
+            if !uncovered.is_empty() {
+                issues.push(CoverageIssue::Uncovered(uncovered));
+            }
+        }
+        issues
+    }
+
+    pub fn len(&self) -> usize {
+        self.individuals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.individuals.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, SliceRequest> {
+        self.individuals.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::RangeInclusive;
+
+    fn raw(description: &str) -> RawSliceRequest {
+        RawSliceRequest {
+            description: description.to_string(),
+            start_page: None,
+            end_page: None,
+            last_page: None,
+            pages: None,
+            title: None,
+            author: None,
+            subject: None,
+            keywords: None,
+            expected_pages: None,
+            must_contain: None,
+        }
+    }
+
+    fn between(start_page: u32, end_page: u32) -> RawSliceRequest {
+        RawSliceRequest {
+            start_page: Some(start_page),
+            end_page: Some(end_page),
+            ..raw("Chapter 1")
+        }
+    }
+
+    const INCLUSIVE: PageNumbering = PageNumbering {
+        inclusive_end: true,
+        zero_based: false,
+        page_labels: false,
+    };
+    const ZERO_BASED: PageNumbering = PageNumbering {
+        inclusive_end: false,
+        zero_based: true,
+        page_labels: false,
+    };
+
+    fn pages(raw: RawSliceRequest, numbering: PageNumbering) -> String {
+        SliceRequest::from_raw(raw, numbering)
+            .unwrap()
+            .pages
+            .to_string()
+    }
+
+    #[test]
+    fn numberings_normalize_to_exclusive_ends_from_1() {
+        let both = PageNumbering {
+            inclusive_end: true,
+            zero_based: true,
+            page_labels: false,
+        };
+        for (numbering, columns, normalized) in [
+            (PageNumbering::default(), (3, 8), (3, 8)),
+            (INCLUSIVE, (3, 8), (3, 9)),
+            (ZERO_BASED, (2, 7), (3, 8)),
+            (both, (2, 7), (3, 9)),
+        ] {
+            assert_eq!(numbering.normalize(columns.0, columns.1), normalized);
+            assert_eq!(numbering.denormalize(normalized.0, normalized.1), columns);
+        }
+        assert_eq!(INCLUSIVE.normalize(1, u32::MAX), (1, u32::MAX));
+    }
+
+    #[test]
+    fn page_columns_follow_the_numbering() {
+        assert_eq!(pages(between(3, 8), PageNumbering::default()), "3-7");
+        assert_eq!(pages(between(3, 8), INCLUSIVE), "3-8");
+        assert_eq!(pages(between(0, 5), ZERO_BASED), "1-5");
+        let last = RawSliceRequest {
+            start_page: Some(3),
+            last_page: Some(8),
+            ..raw("Chapter 1")
+        };
+        assert_eq!(pages(last, PageNumbering::default()), "3-8");
+        let listed = RawSliceRequest {
+            pages: Some(PageRanges::from(1..=4)),
+            ..raw("Chapter 1")
+        };
+        assert_eq!(pages(listed, ZERO_BASED), "1-4");
+        // An end on the start page is one page when the end is inclusive.
+        assert_eq!(pages(between(3, 3), INCLUSIVE), "3");
+    }
+
+    #[test]
+    fn bad_page_columns_are_refused() {
+        let from_raw = |raw| SliceRequest::from_raw(raw, PageNumbering::default());
+        assert!(matches!(
+            from_raw(between(8, 3)),
+            Err(FromRawError::InvalidPageRange {
+                start_page: 8,
+                end_page: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            from_raw(between(3, 3)),
+            Err(FromRawError::EmptyPageRange { .. })
+        ));
+        assert!(matches!(
+            from_raw(RawSliceRequest {
+                description: String::from("  "),
+                ..between(3, 8)
+            }),
+            Err(FromRawError::MissingDescription { pages }) if pages == "3, 8"
+        ));
+        assert!(matches!(
+            from_raw(raw("Chapter 1")),
+            Err(FromRawError::MissingPages { .. })
+        ));
+        assert!(matches!(
+            from_raw(RawSliceRequest {
+                start_page: Some(3),
+                ..raw("Chapter 1")
+            }),
+            Err(FromRawError::MissingPages { .. })
+        ));
+        assert!(matches!(
+            from_raw(RawSliceRequest {
+                pages: Some(PageRanges::new()),
+                ..raw("Chapter 1")
+            }),
+            Err(FromRawError::MissingPages { .. })
+        ));
+        assert!(matches!(
+            from_raw(RawSliceRequest {
+                last_page: Some(9),
+                ..between(3, 8)
+            }),
+            Err(FromRawError::AmbiguousPages { .. })
+        ));
+        assert!(matches!(
+            from_raw(RawSliceRequest {
+                pages: Some(PageRanges::from(1..=4)),
+                ..between(3, 8)
+            }),
+            Err(FromRawError::AmbiguousPages { .. })
+        ));
+    }
+
+    fn slices(requests: &[(&str, RangeInclusive<u32>)]) -> SliceRequests {
+        let individuals = requests.iter().map(|(description, pages)| {
+            let raw = RawSliceRequest {
+                pages: Some(PageRanges::from(pages.clone())),
+                ..raw(description)
+            };
+            SliceRequest::from_raw(raw, PageNumbering::default()).unwrap()
+        });
+        SliceRequests::new(individuals.collect())
+    }
+
+    fn issues(slice_requests: &SliceRequests, page_count: Option<u32>) -> Vec<String> {
+        let issues = slice_requests.coverage_issues(page_count);
+        issues.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn covering_every_page_once_raises_no_issues() {
+        let slice_requests = slices(&[("Chapter 1", 1..=4), ("Chapter 2", 5..=9)]);
+        assert!(issues(&slice_requests, Some(9)).is_empty());
+    }
+
+    #[test]
+    fn coverage_issues_are_reported_in_order() {
+        let slice_requests = slices(&[
+            ("Chapter 1", 1..=5),
+            ("Chapter 2", 4..=7),
+            ("Appendix", 12..=14),
+        ]);
+        let page_count = Some(12);
+        assert_eq!(
+            issues(&slice_requests, page_count),
+            [
+                "\"Appendix\" requests pages 13-14 outside the guide's 12 pages",
+                "pages 4-5 are shared by Chapter 1, Chapter 2",
+                "pages 8-11 are not covered by any slice",
+            ]
+        );
+        let is_error = slice_requests
+            .coverage_issues(page_count)
+            .iter()
+            .map(CoverageIssue::is_error)
+            .collect::<Vec<_>>();
+        assert_eq!(is_error, [true, false, false]);
+    }
+
+    #[test]
+    fn overlaps_tell_shared_descriptions_apart_by_position() {
+        let slice_requests = slices(&[("Chapter", 1..=5), ("Chapter", 5..=8), ("Appendix", 8..=9)]);
+        assert_eq!(
+            issues(&slice_requests, Some(9)),
+            [
+                "pages 5 are shared by Chapter (slice 1), Chapter (slice 2)",
+                "pages 8 are shared by Chapter (slice 2), Appendix",
+            ]
+        );
+    }
+
+    #[test]
+    fn without_a_page_count_only_overlaps_are_reported() {
+        let slice_requests = slices(&[("Chapter 1", 3..=5), ("Chapter 2", 5..=40)]);
+        assert_eq!(
+            issues(&slice_requests, None),
+            ["pages 5 are shared by Chapter 1, Chapter 2"]
+        );
+    }
+}
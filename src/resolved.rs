@@ -0,0 +1,71 @@
+use crate::schema::SCHEMA_VERSION;
+use crate::variant::OutputTarget;
+use npch_slicer::SliceRequest;
+use serde::Serialize;
+use std::io;
+use std::path::Path;
+
+/// A slice request as it will actually be cut from the guide.
+#[derive(Debug, Serialize)]
+pub struct ResolvedSlice {
+    #[serde(flatten)]
+    pub request: SliceRequest,
+    pub page_count: usize,
+    pub outputs: Vec<String>,
+}
+
+impl ResolvedSlice {
+    pub fn new(
+        slice_request: &SliceRequest,
+        targets: &[OutputTarget],
+        output_dir: &Path,
+    ) -> ResolvedSlice {
+        ResolvedSlice {
+            request: slice_request.clone(),
+            page_count: slice_request.pages.len(),
+            outputs: targets
+                .iter()
+                .map(|target| {
+                    output_dir
+                        .join(target.directory())
+                        .join(format!("{}.pdf", slice_request.output_name()))
+                        .display()
+                        .to_string()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ResolvedFile<'a> {
+    schema_version: u32,
+    slices: &'a [ResolvedSlice],
+}
+
+/// Writes the resolved slices as JSON when `path` ends in `.json`, and as CSV
+/// (with `;` separated outputs) otherwise.
+pub fn emit(path: &Path, slices: &[ResolvedSlice]) -> io::Result<()> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let file = ResolvedFile {
+            schema_version: SCHEMA_VERSION,
+            slices,
+        };
+        return std::fs::write(path, serde_json::to_string_pretty(&file).unwrap());
+    }
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["description", "pages", "page_count", "outputs"])?;
+    for slice in slices {
+        writer.write_record([
+            slice.request.description.as_str(),
+            &slice.request.pages.to_string(),
+            &slice.page_count.to_string(),
+            &slice.outputs.join(";"),
+        ])?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,62 @@
+use npch_slicer::fingerprint;
+use npch_slicer::limits::Limits;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::schema::{self, SchemaError, SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RevisionError {
+    #[error(transparent)]
+    Read(io::Error),
+    #[error(transparent)]
+    Syntax(serde_json::Error),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+}
+
+/// The page fingerprints of the source as of the last run that sliced it
+/// without failures, kept with the outputs so that `--only-changed` can tell
+/// which slices a new revision of the source affects.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Revision {
+    schema_version: u32,
+    pages: Vec<String>,
+}
+
+impl Revision {
+    pub fn new(document: &lopdf::Document, limits: &Limits) -> Revision {
+        Revision {
+            schema_version: SCHEMA_VERSION,
+            pages: fingerprint::page_fingerprints(document, limits),
+        }
+    }
+
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join("page_fingerprints.json")
+    }
+
+    /// The revision recorded at `path`; `None` if there is none yet.
+    pub fn read(path: &Path) -> Result<Option<Revision>, RevisionError> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(RevisionError::Read(error)),
+        };
+        let revision =
+            serde_json::from_slice::<Revision>(&contents).map_err(RevisionError::Syntax)?;
+        schema::check_version(&path.display().to_string(), revision.schema_version)?;
+        Ok(Some(revision))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+
+    /// The pages of this revision that differ from `earlier`.
+    pub fn changed_since(&self, earlier: &Revision) -> PageRanges {
+        fingerprint::changed_pages(&earlier.pages, &self.pages)
+    }
+}
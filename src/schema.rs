@@ -0,0 +1,60 @@
+use thiserror::Error;
+
+/// Version of the slice request columns, project file keys and JSON outputs
+/// understood by this build. Bump it whenever one of them changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const CSV_MARKER: &str = "#schema=";
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+    #[error("{what} uses schema version {found}, but this npch_slicer only understands up to version {SCHEMA_VERSION}; upgrade npch_slicer")]
+    TooNew { what: String, found: u32 },
+    #[error("{what} uses unknown schema version {found}")]
+    Unknown { what: String, found: u32 },
+    #[error("{what} has a malformed schema marker {marker:?} (expected e.g. \"{CSV_MARKER}{SCHEMA_VERSION}\")")]
+    Malformed { what: String, marker: String },
+}
+
+pub fn check_version(what: &str, found: u32) -> Result<(), SchemaError> {
+    match found {
+        0 => Err(SchemaError::Unknown {
+            what: what.to_string(),
+            found,
+        }),
+        found if found > SCHEMA_VERSION => Err(SchemaError::TooNew {
+            what: what.to_string(),
+            found,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Splits an optional `#schema=N` first line off a CSV file, returning the
+/// declared version (unmarked files are version 1) and the number of lines
+/// taken up by the marker.
+pub fn split_csv_marker<'a>(
+    what: &str,
+    contents: &'a [u8],
+) -> Result<(u32, &'a [u8], u64), SchemaError> {
+    if !contents.starts_with(b"#") {
+        return Ok((1, contents, 0));
+    }
+
+    let end = contents
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map(|newline| newline + 1)
+        .unwrap_or(contents.len());
+    let marker = String::from_utf8_lossy(&contents[..end]).trim().to_string();
+    let found = marker
+        .strip_prefix(CSV_MARKER)
+        .and_then(|version| version.trim().parse().ok())
+        .ok_or_else(|| SchemaError::Malformed {
+            what: what.to_string(),
+            marker: marker.clone(),
+        })?;
+    check_version(what, found)?;
+
+    Ok((found, &contents[end..], 1))
+}
@@ -0,0 +1,141 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A place optimized slices are delivered to once written to the output
+/// directory.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Sink {
+    Directory {
+        path: PathBuf,
+    },
+    /// Uploaded with the `aws` CLI, which picks up credentials the usual way
+    /// unless they are given here.
+    S3 {
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+        #[serde(flatten)]
+        credentials: S3Credentials,
+    },
+}
+
+/// Passed to the `aws` CLI through its environment variables.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct S3Credentials {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_access_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_token: Option<String>,
+}
+
+impl S3Credentials {
+    fn apply(&self, aws: &mut Command) {
+        let variables = [
+            ("AWS_PROFILE", &self.profile),
+            ("AWS_REGION", &self.region),
+            ("AWS_ACCESS_KEY_ID", &self.access_key_id),
+            ("AWS_SECRET_ACCESS_KEY", &self.secret_access_key),
+            ("AWS_SESSION_TOKEN", &self.session_token),
+        ];
+        for (variable, value) in variables {
+            if let Some(value) = value {
+                aws.env(variable, value);
+            }
+        }
+    }
+}
+
+impl Sink {
+    /// Resolves a relative directory against the project file's location.
+    pub fn relative_to(&self, config: &Config) -> Sink {
+        match self {
+            Sink::Directory { path } => Sink::Directory {
+                path: config.resolve(path),
+            },
+            sink => sink.clone(),
+        }
+    }
+
+    /// A copy safe to print, with secrets masked.
+    pub fn redacted(&self) -> Sink {
+        let mask = |secret: &Option<String>| secret.as_ref().map(|_| String::from("<redacted>"));
+        match self {
+            Sink::S3 {
+                bucket,
+                prefix,
+                credentials,
+            } => Sink::S3 {
+                bucket: bucket.clone(),
+                prefix: prefix.clone(),
+                credentials: S3Credentials {
+                    secret_access_key: mask(&credentials.secret_access_key),
+                    session_token: mask(&credentials.session_token),
+                    ..credentials.clone()
+                },
+            },
+            sink => sink.clone(),
+        }
+    }
+
+    pub fn deliver(&self, file: &Path) -> io::Result<()> {
+        let file_name = file
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+
+        match self {
+            Sink::Directory { path } => {
+                std::fs::create_dir_all(path)?;
+                std::fs::copy(file, path.join(file_name))?;
+            }
+            Sink::S3 {
+                bucket,
+                prefix,
+                credentials,
+            } => {
+                let prefix = prefix.trim_matches('/');
+                let key = if prefix.is_empty() {
+                    file_name.to_string_lossy().into_owned()
+                } else {
+                    format!("{prefix}/{}", file_name.to_string_lossy())
+                };
+                let mut aws = Command::new("aws");
+                credentials.apply(&mut aws);
+                let status = aws
+                    .arg("s3")
+                    .arg("cp")
+                    .arg("--only-show-errors")
+                    .arg(file)
+                    .arg(format!("s3://{bucket}/{key}"))
+                    // Never wait on a prompt, which nobody may be there to answer.
+                    .stdin(Stdio::null())
+                    .status()?;
+                if !status.success() {
+                    return Err(io::Error::other(format!(
+                        "aws s3 cp to s3://{bucket}/{key} failed ({status})"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Sink::Directory { path } => path.display().to_string(),
+            Sink::S3 { bucket, prefix, .. } => {
+                format!("s3://{bucket}/{}", prefix.trim_matches('/'))
+            }
+        }
+    }
+}
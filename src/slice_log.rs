@@ -0,0 +1,154 @@
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// What happened to one slice, kept in `logs/<description>.log` under the
+/// output directory so a failed slice can be looked into without re-running
+/// the batch.
+pub struct SliceLog {
+    started: Instant,
+    lines: Vec<String>,
+    console: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl SliceLog {
+    pub fn new() -> SliceLog {
+        SliceLog {
+            started: Instant::now(),
+            lines: Vec::new(),
+            console: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Records `line`, stamped with the time since the slice was started.
+    pub fn note(&mut self, line: impl Display) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        self.lines.push(format!("[{elapsed:>8.3}s] {line}"));
+    }
+
+    /// Records `line` for the console as well.
+    pub fn report(&mut self, line: impl Display) {
+        self.console.push(line.to_string());
+        self.note(line);
+    }
+
+    /// Records `line` as something the slice got through but that may need
+    /// looking into; `--report` lists these.
+    pub fn warn(&mut self, line: impl Display) {
+        self.warnings.push(line.to_string());
+        self.note(format_args!("warning: {line}"));
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn print(&self, console: &mut Console) {
+        for line in &self.console {
+            console.line(line);
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+        std::fs::write(path, contents)
+    }
+}
+
+/// Prints the console lines of finished slices in request order, holding
+/// back any slice that finishes before an earlier one.
+pub struct InOrder<'a> {
+    console: &'a mut Console,
+    next: usize,
+    pending: BTreeMap<usize, SliceLog>,
+}
+
+impl InOrder<'_> {
+    pub fn new(console: &mut Console) -> InOrder<'_> {
+        InOrder {
+            console,
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, index: usize, log: SliceLog) {
+        self.pending.insert(index, log);
+        while let Some(log) = self.pending.remove(&self.next) {
+            log.print(self.console);
+            self.next += 1;
+        }
+    }
+}
+
+/// Where a run's messages go: the terminal, or for `--non-interactive` runs a
+/// log file, appended to with every line stamped with the time.
+pub enum Console {
+    Terminal,
+    File(File),
+}
+
+impl Console {
+    pub fn open(path: &Path) -> io::Result<Console> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Console::File(file))
+    }
+
+    pub fn line(&mut self, line: impl Display) {
+        match self {
+            Console::Terminal => println!("{line}"),
+            Console::File(file) => writeln!(file, "{} {line}", utc_now()).unwrap(),
+        }
+    }
+
+    /// Logs the error that stopped the run. On a terminal `main` reports it.
+    pub fn error(&mut self, error: &dyn Diagnostic) {
+        if let Console::Terminal = self {
+            return;
+        }
+        let mut rendered = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut rendered, error)
+            .unwrap();
+        for line in rendered.lines().filter(|line| !line.trim().is_empty()) {
+            self.line(line);
+        }
+    }
+}
+
+/// The current UTC time, as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn utc_now() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let (days, time) = (seconds / 86_400, seconds % 86_400);
+    // Howard Hinnant's civil_from_days, with years starting in March so that
+    // leap days come last.
+    let shifted = days + 719_468;
+    let (era, day_of_era) = (shifted / 146_097, shifted % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
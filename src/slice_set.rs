@@ -0,0 +1,186 @@
+//! The version of the set of slices in an output directory, bumped by every
+//! run that changes it, and the changelog telling what each version changed.
+
+use crate::revision::RevisionError;
+use crate::slice_log::utc_now;
+use npch_slicer::pages::PageRanges;
+use npch_slicer::schema::{self, SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetSlice {
+    pub description: String,
+    pub pages: PageRanges,
+    /// The size in bytes of each output, by its path within the output
+    /// directory.
+    pub outputs: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SliceSet {
+    schema_version: u32,
+    pub version: u32,
+    slices: Vec<SetSlice>,
+}
+
+/// What a version of the slice set changed.
+#[derive(Debug)]
+pub enum Change<'a> {
+    Added(&'a SetSlice),
+    Removed(&'a SetSlice),
+    Reranged {
+        slice: &'a SetSlice,
+        was: &'a PageRanges,
+    },
+    Resized {
+        slice: &'a SetSlice,
+        output: &'a str,
+        was: u64,
+        size: u64,
+    },
+}
+
+impl fmt::Display for Change<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(slice) => {
+                write!(
+                    formatter,
+                    "Added {:?} (pages {})",
+                    slice.description, slice.pages
+                )
+            }
+            Change::Removed(slice) => write!(formatter, "Removed {:?}", slice.description),
+            Change::Reranged { slice, was } => write!(
+                formatter,
+                "{:?} now has pages {} (was {was})",
+                slice.description, slice.pages
+            ),
+            Change::Resized {
+                slice,
+                output,
+                was,
+                size,
+            } => write!(
+                formatter,
+                "{:?} {output}: {:.2}MB -> {:.2}MB ({:+.0}%)",
+                slice.description,
+                *was as f64 / 1e6,
+                *size as f64 / 1e6,
+                (*size as f64 / *was as f64 - 1.0) * 100.0
+            ),
+        }
+    }
+}
+
+impl SliceSet {
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join("slice_set.json")
+    }
+
+    pub fn changelog_path(output_dir: &Path) -> PathBuf {
+        output_dir.join("CHANGELOG.md")
+    }
+
+    /// The slice set recorded at `path`; `None` if there is none yet.
+    pub fn read(path: &Path) -> Result<Option<SliceSet>, RevisionError> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(RevisionError::Read(error)),
+        };
+        let set = serde_json::from_slice::<SliceSet>(&contents).map_err(RevisionError::Syntax)?;
+        schema::check_version(&path.display().to_string(), set.schema_version)?;
+        Ok(Some(set))
+    }
+
+    pub fn new(version: u32, slices: Vec<SetSlice>) -> SliceSet {
+        SliceSet {
+            schema_version: SCHEMA_VERSION,
+            version,
+            slices,
+        }
+    }
+
+    /// What this set changed from `earlier`: outputs count as resized when
+    /// their size changed by more than `threshold` percent.
+    pub fn changes<'a>(&'a self, earlier: Option<&'a SliceSet>, threshold: f64) -> Vec<Change<'a>> {
+        let earlier = earlier
+            .map(|earlier| {
+                earlier
+                    .slices
+                    .iter()
+                    .map(|slice| (slice.description.as_str(), slice))
+                    .collect::<BTreeMap<&str, &SetSlice>>()
+            })
+            .unwrap_or_default();
+        let mut changes = Vec::new();
+        for slice in &self.slices {
+            let Some(was) = earlier.get(slice.description.as_str()) else {
+                changes.push(Change::Added(slice));
+                continue;
+            };
+            if slice.pages != was.pages {
+                changes.push(Change::Reranged {
+                    slice,
+                    was: &was.pages,
+                });
+            }
+            for (output, &size) in &slice.outputs {
+                let Some(&was) = was.outputs.get(output) else {
+                    continue;
+                };
+                if was > 0 && (size as f64 / was as f64 - 1.0).abs() * 100.0 > threshold {
+                    changes.push(Change::Resized {
+                        slice,
+                        output,
+                        was,
+                        size,
+                    });
+                }
+            }
+        }
+        let current = self
+            .slices
+            .iter()
+            .map(|slice| slice.description.as_str())
+            .collect::<Vec<&str>>();
+        changes.extend(
+            earlier
+                .into_values()
+                .filter(|slice| !current.contains(&slice.description.as_str()))
+                .map(Change::Removed),
+        );
+        changes
+    }
+
+    pub fn slices(&self) -> &[SetSlice] {
+        &self.slices
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+}
+
+/// Puts the section for `version` with `changes` at the top of the
+/// changelog at `path`, under its `# Changelog` heading.
+pub fn prepend_changelog(path: &Path, version: u32, changes: &[Change]) -> io::Result<()> {
+    const HEADING: &str = "# Changelog\n";
+    let earlier = match std::fs::read_to_string(path) {
+        Ok(earlier) => earlier,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(error),
+    };
+    let earlier = earlier.strip_prefix(HEADING).unwrap_or(&earlier);
+    let mut changelog = format!("{HEADING}\n## {version} ({})\n\n", &utc_now()[..10]);
+    for change in changes {
+        changelog.push_str(&format!("- {change}\n"));
+    }
+    changelog.push_str(earlier);
+    std::fs::write(path, changelog)
+}
@@ -0,0 +1,309 @@
+use crate::content::{self, Unreadable};
+use crate::limits::Limits;
+use crate::outline::{self, Anchors, OutlineItem};
+use crate::pages::PageRanges;
+use crate::pdf::{self, PdfError, Source};
+use crate::request::SliceRequest;
+use crate::spill::Spill;
+use crate::transform::{PageTransform, SlicePage, TransformError};
+use crate::{extract_pages, info, text};
+use lopdf::Document;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SliceError {
+    #[error("extracted {page_count} page(s), expected_pages is {expected_pages}")]
+    PageCount {
+        page_count: usize,
+        expected_pages: usize,
+    },
+    #[error(
+        "the slice's text doesn't contain must_contain {phrase:?}{}",
+        unread(unreadable)
+    )]
+    MissingText {
+        phrase: String,
+        /// The slice's pages whose content couldn't all be read.
+        unreadable: PageRanges,
+    },
+    #[error("cannot transform page {page} of the slice: {source}")]
+    Transform {
+        page: u32,
+        #[source]
+        source: TransformError,
+    },
+    #[error(transparent)]
+    Save(#[from] PdfError),
+}
+
+fn unread(unreadable: &PageRanges) -> String {
+    match unreadable.is_empty() {
+        true => String::new(),
+        false => format!(", though the content of pages {unreadable} couldn't all be read"),
+    }
+}
+
+/// A slice cut out of the source, with the 1-based pages whose content
+/// couldn't all be read: their text, and what is stamped over them, go by the
+/// rest of it.
+pub struct Slice {
+    pub document: Document,
+    pub unreadable: BTreeMap<u32, Unreadable>,
+}
+
+/// A source document, read once, that any number of slices are cut from.
+pub struct Slicer {
+    document: Document,
+    outline: Vec<OutlineItem>,
+    anchors: Anchors,
+    transforms: Vec<Box<dyn PageTransform>>,
+    spill: Option<Spill>,
+    limits: Limits,
+}
+
+impl Slicer {
+    pub fn new(document: Document) -> Slicer {
+        Slicer {
+            outline: outline::read_outline(&document),
+            anchors: Anchors::new(&document),
+            document,
+            transforms: Vec::new(),
+            spill: None,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Decodes the slices' streams within `limits`, the ones the source was
+    /// loaded with, rather than the default ones.
+    pub fn with_limits(mut self, limits: Limits) -> Slicer {
+        self.limits = limits;
+        self
+    }
+
+    /// Leaves the source's image streams of at least `min_size` bytes in
+    /// `path`, the file it was loaded from, as `Spill::new` does, so that
+    /// neither the source nor the slices hold them. Slices holding any are
+    /// to be saved with `save` or `save_mem`.
+    pub fn with_spill(mut self, path: &Path, min_size: usize) -> io::Result<Slicer> {
+        self.spill = Some(Spill::new(&mut self.document, path, min_size)?);
+        Ok(self)
+    }
+
+    pub fn spill(&self) -> Option<&Spill> {
+        self.spill.as_ref()
+    }
+
+    /// Has `transform` made to every page of every slice, after the
+    /// transforms added before it.
+    pub fn with_transform(mut self, transform: impl PageTransform + 'static) -> Slicer {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn load(path: &Path, limits: &Limits) -> Result<Slicer, PdfError> {
+        Ok(Slicer::new(pdf::load(path, limits)?).with_limits(*limits))
+    }
+
+    pub fn load_mem(data: &[u8], limits: &Limits) -> Result<Slicer, PdfError> {
+        Ok(Slicer::new(pdf::load_mem(data, limits)?).with_limits(*limits))
+    }
+
+    /// Loads the source from a path, bytes or a reader, with its password if
+    /// it is encrypted.
+    pub fn open<'a>(
+        source: impl Into<Source<'a>>,
+        limits: &Limits,
+        password: Option<&str>,
+    ) -> Result<Slicer, PdfError> {
+        let document = pdf::load_source(source.into(), limits, password)?;
+        Ok(Slicer::new(document).with_limits(*limits))
+    }
+
+    /// Loads the source from chunks as `pdf::load_stream` does.
+    pub async fn load_stream<F, C, B>(
+        next_chunk: F,
+        limits: &Limits,
+        password: Option<&str>,
+    ) -> Result<Slicer, PdfError>
+    where
+        F: FnMut() -> C,
+        C: Future<Output = io::Result<Option<B>>>,
+        B: AsRef<[u8]>,
+    {
+        let document = pdf::load_stream(next_chunk, limits, password).await?;
+        Ok(Slicer::new(document).with_limits(*limits))
+    }
+
+    /// The source, with placeholders for its spilled streams.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    pub fn outline(&self) -> &[OutlineItem] {
+        &self.outline
+    }
+
+    pub fn page_count(&self) -> u32 {
+        self.document.get_pages().len() as u32
+    }
+
+    /// The named destinations and bookmarks landing in the slice, numbered by
+    /// the slice's own pages.
+    pub fn anchors(&self, slice_request: &SliceRequest) -> Anchors {
+        self.anchors.within(&slice_request.pages)
+    }
+
+    /// Cuts the slice out of the source, puts its pages through the
+    /// transforms, and sets its title, author, subject and keywords, failing
+    /// if it doesn't come out with its `expected_pages` or its text lacks its
+    /// `must_contain`. Bookmarks deeper than `outline_depth` levels are
+    /// dropped. Spilled streams are still placeholders in the slice.
+    pub fn slice(
+        &self,
+        slice_request: &SliceRequest,
+        outline_depth: Option<usize>,
+    ) -> Result<Document, SliceError> {
+        Ok(self.cut(slice_request, outline_depth)?.document)
+    }
+
+    /// Cuts the slice as `slice` does, along with which of its pages'
+    /// content couldn't all be read.
+    pub fn cut(
+        &self,
+        slice_request: &SliceRequest,
+        outline_depth: Option<usize>,
+    ) -> Result<Slice, SliceError> {
+        let mut slice_pdf = extract_pages(
+            &self.document,
+            &self.outline,
+            &slice_request.pages,
+            outline_depth,
+        );
+        self.transform(&mut slice_pdf, slice_request)?;
+        let entries = [
+            ("Title", &slice_request.title),
+            ("Author", &slice_request.author),
+            ("Subject", &slice_request.subject),
+            ("Keywords", &slice_request.keywords),
+        ];
+        let mut described = false;
+        for (key, value) in entries {
+            if let Some(value) = value {
+                info::set_entry(&mut slice_pdf, key, value);
+                described = true;
+            }
+        }
+        if described {
+            info::sync_xmp(&mut slice_pdf, &self.limits);
+        }
+        let page_count = slice_pdf.get_pages().len();
+        match slice_request.expected_pages {
+            Some(expected_pages) if page_count != expected_pages => {
+                return Err(SliceError::PageCount {
+                    page_count,
+                    expected_pages,
+                })
+            }
+            _ => {}
+        }
+        let unreadable = match &slice_request.must_contain {
+            Some(phrase) => {
+                let pages = (1..=page_count as u32).collect::<Vec<u32>>();
+                let search = text::contains(&slice_pdf, &pages, phrase, &self.limits);
+                if !search.found {
+                    return Err(SliceError::MissingText {
+                        phrase: phrase.clone(),
+                        unreadable: search.unreadable.into_keys().collect(),
+                    });
+                }
+                search.unreadable
+            }
+            None => content::unreadable_pages(&slice_pdf, &self.limits),
+        };
+        Ok(Slice {
+            document: slice_pdf,
+            unreadable,
+        })
+    }
+
+    fn transform(
+        &self,
+        slice_pdf: &mut Document,
+        slice_request: &SliceRequest,
+    ) -> Result<(), SliceError> {
+        if self.transforms.is_empty() {
+            return Ok(());
+        }
+        let page_ids = slice_pdf.get_pages();
+        let page_count = page_ids.len() as u32;
+        let source_page_count = self.page_count();
+        let source_numbers = slice_request
+            .pages
+            .iter()
+            .filter(|&page| (1..=source_page_count).contains(&page));
+        for ((number, id), source_number) in page_ids.into_iter().zip(source_numbers) {
+            let page = SlicePage {
+                id,
+                number,
+                source_number,
+                page_count,
+                limits: self.limits,
+            };
+            for transform in &self.transforms {
+                transform
+                    .apply(slice_pdf, &page)
+                    .map_err(|source| SliceError::Transform {
+                        page: number,
+                        source,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cuts the slice and saves it to `path`.
+    pub fn slice_to_file(
+        &self,
+        slice_request: &SliceRequest,
+        outline_depth: Option<usize>,
+        path: &Path,
+    ) -> Result<(), SliceError> {
+        let mut slice_pdf = self.slice(slice_request, outline_depth)?;
+        Ok(self.save(&mut slice_pdf, path)?)
+    }
+
+    /// Cuts the slice and serializes it.
+    pub fn slice_to_bytes(
+        &self,
+        slice_request: &SliceRequest,
+        outline_depth: Option<usize>,
+    ) -> Result<Vec<u8>, SliceError> {
+        let mut slice_pdf = self.slice(slice_request, outline_depth)?;
+        Ok(self.save_mem(&mut slice_pdf)?)
+    }
+
+    /// Saves `slice_pdf`, a slice of the source, with its spilled streams
+    /// copied from the source file.
+    pub fn save(&self, slice_pdf: &mut Document, path: &Path) -> Result<(), PdfError> {
+        match &self.spill {
+            Some(spill) => spill.save(slice_pdf, path),
+            None => pdf::save(slice_pdf, path),
+        }
+    }
+
+    /// Serializes `slice_pdf` as `save` saves it.
+    pub fn save_mem(&self, slice_pdf: &mut Document) -> Result<Vec<u8>, PdfError> {
+        match &self.spill {
+            Some(spill) => {
+                let mut bytes = Vec::new();
+                spill.save_to(slice_pdf, &mut bytes)?;
+                Ok(bytes)
+            }
+            None => pdf::save_mem(slice_pdf),
+        }
+    }
+}
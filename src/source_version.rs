@@ -0,0 +1,91 @@
+//! Slice requests limited to some editions of the guide, by a
+//! `min_source_version` or `applies_to` column matched against its version.
+
+use csv::StringRecord;
+use std::cmp::Ordering;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error(
+    "{column} limits the row to some versions of the guide, but its version isn't known; \
+     set source_version in the project file or pass --source-version"
+)]
+pub struct UnknownVersion {
+    column: &'static str,
+}
+
+/// The first version number in the file name of `pdf`: digits with any dots
+/// between them, as in `guide-v2.3.pdf`.
+pub fn from_file_name(pdf: &Path) -> Option<String> {
+    let stem = pdf.file_stem()?.to_str()?;
+    let start = stem.find(|character: char| character.is_ascii_digit())?;
+    let rest = &stem[start..];
+    let length = rest
+        .find(|character: char| !(character.is_ascii_digit() || character == '.'))
+        .unwrap_or(rest.len());
+    Some(rest[..length].trim_end_matches('.').to_string())
+}
+
+/// Compares versions a dot-separated component at a time, numerically where
+/// both are numbers; missing components count as 0, so `2` is `2.0`.
+pub fn compare(version: &str, other: &str) -> Ordering {
+    let mut components = version.split('.');
+    let mut others = other.split('.');
+    loop {
+        let (component, other) = match (components.next(), others.next()) {
+            (None, None) => return Ordering::Equal,
+            (component, other) => (component.unwrap_or("0"), other.unwrap_or("0")),
+        };
+        let ordering = match (component.parse::<u64>(), other.parse::<u64>()) {
+            (Ok(component), Ok(other)) => component.cmp(&other),
+            _ => component.cmp(other),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Whether `version` is one of `listed`, or within one of them: `2` covers
+/// `2.1` and `2.1.4`.
+fn is_listed(version: &str, listed: &str) -> bool {
+    listed.split(',').map(str::trim).any(|listed| {
+        version == listed
+            || version
+                .strip_prefix(listed)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Whether the row `record` is for the guide at `version`: at or after its
+/// `min_source_version`, and one of its `applies_to` versions. Rows leaving
+/// both empty are for every version.
+pub fn applies(
+    headers: &StringRecord,
+    record: &StringRecord,
+    version: impl FnOnce() -> Option<String>,
+) -> Result<bool, UnknownVersion> {
+    let cell = |column: &str| {
+        headers
+            .iter()
+            .position(|header| header == column)
+            .and_then(|index| record.get(index))
+            .map(str::trim)
+            .filter(|cell| !cell.is_empty())
+    };
+    let (minimum, listed) = (cell("min_source_version"), cell("applies_to"));
+    if minimum.is_none() && listed.is_none() {
+        return Ok(true);
+    }
+    let version = version().ok_or(UnknownVersion {
+        column: match minimum {
+            Some(_) => "min_source_version",
+            None => "applies_to",
+        },
+    })?;
+    let recent_enough =
+        minimum.map_or(true, |minimum| compare(&version, minimum) != Ordering::Less);
+    let listed = listed.map_or(true, |listed| is_listed(&version, listed));
+    Ok(recent_enough && listed)
+}
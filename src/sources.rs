@@ -0,0 +1,505 @@
+use crate::input::read_input;
+use crate::schema::{self, SchemaError};
+use csv::{Position, StringRecord};
+use lopdf::Document;
+use npch_slicer::limits::Limits;
+use npch_slicer::outline;
+use npch_slicer::pdf::{self, PdfError};
+#[cfg(feature = "sqlite")]
+use rusqlite::types::ValueRef;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Where slice requests are read from.
+#[derive(Debug, Clone)]
+pub enum RequestSource {
+    Csv(PathBuf),
+    /// A TOML, JSON or YAML file with a `slices` array of tables, one per request,
+    /// keyed like the CSV's columns.
+    Structured {
+        path: PathBuf,
+        format: Format,
+    },
+    Sqlite {
+        path: PathBuf,
+        table: String,
+    },
+    /// One request per bookmark of the guide, down to `depth` levels.
+    Outline {
+        pdf: PathBuf,
+        depth: usize,
+        limits: Limits,
+        password: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    #[error("cannot read slice requests from {source_name}: {error}")]
+    Read {
+        source_name: String,
+        error: std::io::Error,
+    },
+    #[error("cannot read the header of {source_name}: {error}")]
+    Header {
+        source_name: String,
+        error: csv::Error,
+    },
+    #[error("cannot read slice requests from {source_name}: {error}")]
+    Syntax { source_name: String, error: String },
+    #[error("{source_name} has no {column} column")]
+    MissingColumn { source_name: String, column: String },
+    #[error("cannot read slice requests from {source_name}: {error}")]
+    Pdf {
+        source_name: String,
+        error: PdfError,
+    },
+    #[cfg(feature = "sqlite")]
+    #[error("cannot read slice requests from {source_name}: {error}")]
+    Sqlite {
+        source_name: String,
+        error: rusqlite::Error,
+    },
+}
+
+/// Raw rows, keyed by their line (CSV) or row number (SQLite), before they
+/// are interpreted as slice requests.
+pub struct Records {
+    pub headers: StringRecord,
+    pub records: Vec<(u64, StringRecord)>,
+    pub errors: Vec<(u64, csv::Error)>,
+}
+
+impl RequestSource {
+    /// The requests in the file at `path`: TOML, JSON or YAML by its extension
+    /// (that before any `.gz` or `.zst`), CSV otherwise.
+    pub fn file(path: PathBuf) -> RequestSource {
+        let inner = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz" | "zst") => path.with_extension(""),
+            _ => path.clone(),
+        };
+        let format = match inner.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("json") => Format::Json,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => return RequestSource::Csv(path),
+        };
+        RequestSource::Structured { path, format }
+    }
+
+    /// The file the requests are read from, unless they come from the guide.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            RequestSource::Csv(path)
+            | RequestSource::Structured { path, .. }
+            | RequestSource::Sqlite { path, .. } => Some(path),
+            RequestSource::Outline { .. } => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            RequestSource::Csv(path) | RequestSource::Structured { path, .. } => {
+                path.display().to_string()
+            }
+            RequestSource::Sqlite { path, table } => format!("{}:{table}", path.display()),
+            RequestSource::Outline { pdf, depth, .. } => {
+                format!("the outline of {} (depth {depth})", pdf.display())
+            }
+        }
+    }
+
+    pub fn read(&self) -> Result<Records, SourceError> {
+        match self {
+            RequestSource::Csv(path) => self.read_csv(path),
+            RequestSource::Structured { path, format } => self.read_structured(path, *format),
+            RequestSource::Outline {
+                pdf,
+                depth,
+                limits,
+                password,
+            } => {
+                let document =
+                    pdf::load_with_password(pdf, limits, password.as_deref()).map_err(|error| {
+                        SourceError::Pdf {
+                            source_name: self.describe(),
+                            error,
+                        }
+                    })?;
+                Ok(read_outline(&document, *depth))
+            }
+            #[cfg(feature = "sqlite")]
+            RequestSource::Sqlite { path, table } => {
+                self.read_sqlite(path, table)
+                    .map_err(|error| SourceError::Sqlite {
+                        source_name: self.describe(),
+                        error,
+                    })
+            }
+            #[cfg(not(feature = "sqlite"))]
+            RequestSource::Sqlite { .. } => {
+                unreachable!("--sqlite is refused by builds without the sqlite feature")
+            }
+        }
+    }
+
+    fn read_csv(&self, path: &std::path::Path) -> Result<Records, SourceError> {
+        let contents = read_input(path).map_err(|error| SourceError::Read {
+            source_name: self.describe(),
+            error,
+        })?;
+        let (_, contents, marker_lines) = schema::split_csv_marker(&self.describe(), &contents)?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents);
+
+        let headers = reader
+            .headers()
+            .map_err(|error| SourceError::Header {
+                source_name: self.describe(),
+                error,
+            })?
+            .clone();
+        let mut records = Vec::new();
+        let mut errors = Vec::new();
+        for record in reader.records() {
+            match record {
+                Ok(record) => {
+                    let line = record.position().map(Position::line).unwrap_or_default();
+                    records.push((line + marker_lines, record));
+                }
+                Err(error) => {
+                    let line = error.position().map(Position::line).unwrap_or_default();
+                    errors.push((line + marker_lines, error));
+                }
+            }
+        }
+
+        Ok(Records {
+            headers,
+            records,
+            errors,
+        })
+    }
+
+    /// Reads the `slices` of a TOML, JSON or YAML file as rows, groups flattened,
+    /// with a column for every key any of them has, in the order first seen. Numbers and
+    /// booleans are written out; arrays, such as a list of `pages`, are
+    /// joined with commas.
+    fn read_structured(&self, path: &Path, format: Format) -> Result<Records, SourceError> {
+        let syntax = |error: String| SourceError::Syntax {
+            source_name: self.describe(),
+            error,
+        };
+        let contents = read_input(path).map_err(|error| SourceError::Read {
+            source_name: self.describe(),
+            error,
+        })?;
+        let document = match format {
+            Format::Toml => std::str::from_utf8(&contents)
+                .map_err(|error| error.to_string())
+                .and_then(|text| {
+                    toml::from_str::<toml::Value>(text).map_err(|error| error.to_string())
+                })
+                .map(|document| serde_json::to_value(document).unwrap()),
+            Format::Json => {
+                serde_json::from_slice::<Value>(&contents).map_err(|error| error.to_string())
+            }
+            Format::Yaml => {
+                serde_yaml::from_slice::<Value>(&contents).map_err(|error| error.to_string())
+            }
+        }
+        .map_err(syntax)?;
+        if let Some(version) = document.get("schema_version") {
+            let version = version
+                .as_u64()
+                .ok_or_else(|| syntax("schema_version is not a number".to_string()))?;
+            schema::check_version(&self.describe(), version as u32)?;
+        }
+        let slices = document
+            .get("slices")
+            .and_then(Value::as_array)
+            .ok_or_else(|| syntax("no slices array".to_string()))?;
+        let mut flattened = Vec::new();
+        flatten(slices, (&Map::new(), "", ""), &mut flattened).map_err(syntax)?;
+
+        let mut headers = Vec::<&str>::new();
+        for (_, slice) in &flattened {
+            for key in slice.keys() {
+                if !headers.contains(&key.as_str()) {
+                    headers.push(key);
+                }
+            }
+        }
+        let mut records = Vec::new();
+        for (index, (label, slice)) in flattened.iter().enumerate() {
+            let mut record = StringRecord::new();
+            for header in &headers {
+                let cell = match slice.get(*header) {
+                    None => Some(String::new()),
+                    Some(value) => cell(value),
+                };
+                let cell = cell.ok_or_else(|| {
+                    syntax(format!("{header} of slice {label} is a table, not a value"))
+                })?;
+                record.push_field(&cell);
+            }
+            records.push((index as u64 + 1, record));
+        }
+
+        Ok(Records {
+            headers: StringRecord::from(headers),
+            records,
+            errors: Vec::new(),
+        })
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn read_sqlite(&self, path: &std::path::Path, table: &str) -> rusqlite::Result<Records> {
+        let connection = rusqlite::Connection::open_with_flags(
+            path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        let mut statement =
+            connection.prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))?;
+        let headers = statement
+            .column_names()
+            .into_iter()
+            .collect::<StringRecord>();
+        let column_count = headers.len();
+
+        let mut records = Vec::new();
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let mut record = StringRecord::new();
+            for column in 0..column_count {
+                let cell = match row.get_ref(column)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(value) => value.to_string(),
+                    ValueRef::Real(value) => value.to_string(),
+                    ValueRef::Text(text) | ValueRef::Blob(text) => {
+                        String::from_utf8_lossy(text).into_owned()
+                    }
+                };
+                record.push_field(&cell);
+            }
+            records.push((records.len() as u64 + 1, record));
+        }
+
+        Ok(Records {
+            headers,
+            records,
+            errors: Vec::new(),
+        })
+    }
+}
+
+/// `value` as a CSV cell would have it; `None` for tables.
+fn cell(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => Some(String::new()),
+        Value::String(text) => Some(text.clone()),
+        Value::Bool(_) | Value::Number(_) => Some(value.to_string()),
+        Value::Array(values) => values
+            .iter()
+            .map(cell)
+            .collect::<Option<Vec<String>>>()
+            .map(|cells| cells.join(",")),
+        Value::Object(_) => None,
+    }
+}
+
+/// The requests of `slices`, with every group among them replaced by its own
+/// `slices`, each labelled for errors by its place in the tree (`2.3`, the
+/// third of the second). A group's keys other than `slices`, `prefix` and
+/// `description` are inherited by the requests within it that don't set them,
+/// and its `prefix` goes before each of their descriptions.
+fn flatten(
+    slices: &[Value],
+    (inherited, prefix, label): (&Map<String, Value>, &str, &str),
+    flattened: &mut Vec<(String, Map<String, Value>)>,
+) -> Result<(), String> {
+    for (index, slice) in slices.iter().enumerate() {
+        let label = format!("{label}{}", index + 1);
+        let slice = slice
+            .as_object()
+            .ok_or_else(|| format!("slice {label} is not a table"))?;
+        let prefix = match slice.get("prefix") {
+            None => prefix.to_string(),
+            Some(Value::String(own)) => format!("{prefix}{own}"),
+            Some(_) => return Err(format!("prefix of slice {label} is not a string")),
+        };
+        if let Some(children) = slice.get("slices") {
+            let children = children
+                .as_array()
+                .ok_or_else(|| format!("slices of group {label} is not an array"))?;
+            let mut inherited = inherited.clone();
+            inherited.extend(
+                slice
+                    .iter()
+                    .filter(|(key, _)| !["slices", "prefix", "description"].contains(&key.as_str()))
+                    .map(|(key, value)| (key.clone(), value.clone())),
+            );
+            flatten(
+                children,
+                (&inherited, &prefix, &format!("{label}.")),
+                flattened,
+            )?;
+            continue;
+        }
+        let mut request = inherited.clone();
+        request.extend(
+            slice
+                .iter()
+                .filter(|(key, _)| *key != "prefix")
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        if let Some(Value::String(description)) = request.get_mut("description") {
+            description.insert_str(0, &prefix);
+        }
+        flattened.push((label, request));
+    }
+    Ok(())
+}
+
+/// The bookmarks' sections as rows of `description`, `start_page` and
+/// `end_page` (exclusive), numbered in outline order.
+fn read_outline(document: &Document, depth: usize) -> Records {
+    let outline = outline::read_outline(document);
+    let records = outline::sections(document, &outline, depth)
+        .into_iter()
+        .enumerate()
+        .map(|(index, section)| {
+            let end_page = (section.last + 1).to_string();
+            let record =
+                StringRecord::from(vec![section.title, section.first.to_string(), end_page]);
+            (index as u64 + 1, record)
+        })
+        .collect();
+    Records {
+        headers: StringRecord::from(vec!["description", "start_page", "end_page"]),
+        records,
+        errors: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rows read from `contents`, written to a file named `name`.
+    fn read(name: &str, contents: &str) -> Records {
+        let path = std::env::temp_dir().join(format!("npch_slicer-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let records = RequestSource::file(path.clone()).read();
+        std::fs::remove_file(&path).unwrap();
+        records.unwrap()
+    }
+
+    fn rows(records: &Records) -> Vec<Vec<&str>> {
+        records
+            .records
+            .iter()
+            .map(|(_, record)| record.iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn formats_follow_the_extension() {
+        for (name, expected) in [
+            ("slices.toml", Some(Format::Toml)),
+            ("slices.json.gz", Some(Format::Json)),
+            ("slices.yaml", Some(Format::Yaml)),
+            ("slices.yml.zst", Some(Format::Yaml)),
+            ("slices.csv", None),
+        ] {
+            let format = match RequestSource::file(PathBuf::from(name)) {
+                RequestSource::Structured { format, .. } => Some(format),
+                _ => None,
+            };
+            assert_eq!(format, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn yaml_reads_like_toml() {
+        let yaml = read(
+            "slices.yaml",
+            "schema_version: 1\n\
+             slices:\n  \
+               - description: Character creation\n    \
+                 start_page: 12\n    \
+                 end_page: 30\n  \
+               - description: Ancestries\n    \
+                 pages: [\"36-38\", 40]\n    \
+                 keywords: ancestry, lineage\n",
+        );
+        let toml = read(
+            "slices.toml",
+            "schema_version = 1\n\
+             [[slices]]\n\
+             description = \"Character creation\"\n\
+             start_page = 12\n\
+             end_page = 30\n\
+             [[slices]]\n\
+             description = \"Ancestries\"\n\
+             pages = [\"36-38\", 40]\n\
+             keywords = \"ancestry, lineage\"\n",
+        );
+        assert_eq!(
+            yaml.headers.iter().collect::<Vec<&str>>(),
+            ["description", "end_page", "start_page", "keywords", "pages"]
+        );
+        assert_eq!(yaml.headers, toml.headers);
+        assert_eq!(
+            rows(&yaml),
+            [
+                ["Character creation", "30", "12", "", ""],
+                ["Ancestries", "", "", "ancestry, lineage", "36-38,40"],
+            ]
+        );
+        assert_eq!(rows(&yaml), rows(&toml));
+    }
+
+    #[test]
+    fn yaml_groups_nest() {
+        let records = read(
+            "groups.yml",
+            "slices:\n  \
+               - prefix: \"Part I - \"\n    \
+                 author: Rules team\n    \
+                 slices:\n      \
+                   - description: Character creation\n        \
+                     pages: 12-29\n      \
+                   - prefix: \"Ancestries: \"\n        \
+                     slices:\n          \
+                       - description: Humans\n            \
+                         pages: 36\n            \
+                         author: Lore team\n  \
+               - description: Appendix\n    \
+                 pages: 150\n",
+        );
+        assert_eq!(
+            records.headers.iter().collect::<Vec<&str>>(),
+            ["author", "description", "pages"]
+        );
+        assert_eq!(
+            rows(&records),
+            [
+                ["Rules team", "Part I - Character creation", "12-29"],
+                ["Lore team", "Part I - Ancestries: Humans", "36"],
+                ["", "Appendix", "150"],
+            ]
+        );
+    }
+}
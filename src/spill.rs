@@ -0,0 +1,393 @@
+//! Spilled streams: large images left in the source file rather than held in
+//! memory, each standing in the document as a short placeholder until a
+//! document holding it is saved, when its bytes are copied from the file.
+
+use crate::encryption::random;
+use crate::pdf::{check_size, PdfError};
+use lopdf::xref::{XrefEntry, XrefType};
+use lopdf::{Document, Object};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How many bytes a placeholder takes: the marker, then the stream's index.
+const PLACEHOLDER_LENGTH: usize = 24;
+
+/// The streams spilled from the document loaded from `path`.
+pub struct Spill {
+    path: PathBuf,
+    /// The file's length and modification time as it was loaded, to notice a
+    /// different one in its place.
+    stamp: (u64, Option<SystemTime>),
+    /// What every placeholder starts with; random, so no other bytes of a
+    /// document look like one.
+    marker: [u8; 16],
+    /// Where each spilled stream's content is in the file, and how long it
+    /// is.
+    streams: Vec<(u64, u64)>,
+}
+
+impl Spill {
+    /// Spills the content of every image stream of `document`, loaded from
+    /// `path`, that is at least `min_size` bytes long and still exactly as
+    /// the file has it: not decrypted, nor changed since it was loaded.
+    pub fn new(document: &mut Document, path: &Path, min_size: usize) -> io::Result<Spill> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut spill = Spill {
+            path: path.to_path_buf(),
+            stamp: (metadata.len(), metadata.modified().ok()),
+            marker: random()?,
+            streams: Vec::new(),
+        };
+        for (&(number, generation), object) in document.objects.iter_mut() {
+            let Object::Stream(stream) = object else {
+                continue;
+            };
+            let is_image = stream
+                .dict
+                .get(b"Subtype")
+                .and_then(Object::as_name)
+                .is_ok_and(|subtype| subtype == b"Image");
+            let offset = match document.reference_table.get(number) {
+                Some(&XrefEntry::Normal {
+                    offset,
+                    generation: kept,
+                }) if kept == generation => u64::from(offset),
+                _ => continue,
+            };
+            if !is_image || stream.content.len() < min_size.max(PLACEHOLDER_LENGTH) {
+                continue;
+            }
+            let Some(start) = content_start(&mut file, offset, &stream.content)? else {
+                continue;
+            };
+            let length = stream.content.len() as u64;
+            let mut placeholder = spill.marker.to_vec();
+            placeholder.extend_from_slice(&(spill.streams.len() as u64).to_le_bytes());
+            spill.streams.push((start, length));
+            stream.dict.set("Length", length as i64);
+            stream.content = placeholder;
+        }
+        Ok(spill)
+    }
+
+    /// How many streams were spilled.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// How many bytes the spilled streams hold.
+    pub fn bytes(&self) -> u64 {
+        self.streams.iter().map(|&(_, length)| length).sum()
+    }
+
+    /// Writes `document`, with the content of the spilled streams it holds
+    /// copied from the file, to `target`. Only the rest of the document is
+    /// serialized in memory first.
+    pub fn save_to(&self, document: &mut Document, target: &mut dyn Write) -> Result<(), PdfError> {
+        // Only a cross-reference table can be corrected for the placeholders
+        // growing back into the streams.
+        document.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes)?;
+        let placeholders = self.placeholders(&bytes);
+        if placeholders.is_empty() {
+            return Ok(target.write_all(&bytes)?);
+        }
+        let mut file = File::open(&self.path)?;
+        let metadata = file.metadata()?;
+        if (metadata.len(), metadata.modified().ok()) != self.stamp {
+            return Err(io::Error::other(format!(
+                "{} changed after streams were spilled from it",
+                self.path.display()
+            ))
+            .into());
+        }
+        let grown = |offset: u64| {
+            placeholders
+                .iter()
+                .take_while(|&&(position, _)| (position as u64) < offset)
+                .map(|&(_, index)| self.streams[index].1 - PLACEHOLDER_LENGTH as u64)
+                .sum::<u64>()
+        };
+        let size = bytes.len() as u64 + grown(u64::MAX);
+        check_size(size)?;
+
+        let malformed = || io::Error::other("lopdf wrote no cross-reference table");
+        let startxref = find_last(&bytes, b"startxref").ok_or_else(malformed)?;
+        let xref_start = std::str::from_utf8(&bytes[startxref + 9..])
+            .ok()
+            .and_then(|rest| rest.split_whitespace().next()?.parse::<usize>().ok())
+            .filter(|&xref_start| xref_start < startxref)
+            .ok_or_else(malformed)?;
+        let trailer = find_last(&bytes[..startxref], b"trailer").ok_or_else(malformed)?;
+
+        let mut at = 0;
+        for &(position, index) in &placeholders {
+            target.write_all(&bytes[at..position])?;
+            let (start, length) = self.streams[index];
+            file.seek(SeekFrom::Start(start))?;
+            let copied = io::copy(&mut (&mut file).take(length), target)?;
+            if copied != length {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            at = position + PLACEHOLDER_LENGTH;
+        }
+        target.write_all(&bytes[at..xref_start])?;
+        // Every object's offset moves by what the placeholders before it grew.
+        for line in bytes[xref_start..trailer].split_inclusive(|&byte| byte == b'\n') {
+            let fields = std::str::from_utf8(line)
+                .ok()
+                .map(|line| line.split_whitespace().collect::<Vec<&str>>());
+            match fields.as_deref() {
+                Some(&[offset, generation, "n"]) => {
+                    let offset = offset.parse::<u64>().map_err(|_| malformed())?;
+                    writeln!(target, "{:010} {generation} n ", offset + grown(offset))?;
+                }
+                _ => target.write_all(line)?,
+            }
+        }
+        target.write_all(&bytes[trailer..startxref])?;
+        write!(
+            target,
+            "startxref\n{}\n%%EOF",
+            xref_start as u64 + grown(xref_start as u64)
+        )?;
+        Ok(())
+    }
+
+    /// Saves `document` to `path` as `save_to` writes it.
+    pub fn save(&self, document: &mut Document, path: &Path) -> Result<(), PdfError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.save_to(document, &mut file)?;
+        Ok(file.flush()?)
+    }
+
+    /// Where the placeholders are in `bytes`, and which stream each stands
+    /// for.
+    fn placeholders(&self, bytes: &[u8]) -> Vec<(usize, usize)> {
+        let mut placeholders = Vec::new();
+        let mut at = 0;
+        while let Some(found) = bytes[at..]
+            .windows(self.marker.len())
+            .position(|window| window == self.marker)
+        {
+            let position = at + found;
+            let index = bytes
+                .get(position + self.marker.len()..position + PLACEHOLDER_LENGTH)
+                .map(|index| u64::from_le_bytes(index.try_into().unwrap()) as usize)
+                .filter(|&index| index < self.streams.len());
+            match index {
+                Some(index) => {
+                    placeholders.push((position, index));
+                    at = position + PLACEHOLDER_LENGTH;
+                }
+                None => at = position + 1,
+            }
+        }
+        placeholders
+    }
+}
+
+/// Where the object at `offset` of `file` starts its stream's content, if
+/// that content is `content`. Whatever looks like the `stream` keyword in the
+/// object's first block is tried.
+fn content_start(file: &mut File, offset: u64, content: &[u8]) -> io::Result<Option<u64>> {
+    let mut head = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    Read::by_ref(file).take(64 * 1024).read_to_end(&mut head)?;
+    let candidates = head
+        .windows(7)
+        .enumerate()
+        .filter(|(_, window)| window.starts_with(b"stream") && b"\r\n".contains(&window[6]))
+        .map(
+            |(at, window)| match window[6] == b'\r' && head.get(at + 7) == Some(&b'\n') {
+                true => at + 8,
+                false => at + 7,
+            },
+        )
+        .collect::<Vec<usize>>();
+    for start in candidates {
+        let start = offset + start as u64;
+        if matches_file(file, start, content)? {
+            return Ok(Some(start));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `file` holds `content` at `start`, read a block at a time.
+fn matches_file(file: &mut File, start: u64, content: &[u8]) -> io::Result<bool> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut block = [0; 64 * 1024];
+    for expected in content.chunks(block.len()) {
+        let read = &mut block[..expected.len()];
+        match file.read_exact(read) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            result => result?,
+        }
+        if read != expected {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn find_last(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Dictionary, ObjectId, Stream};
+
+    /// A path of its own in the temporary directory for the test `name`.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("npch_slicer-{}-{name}.pdf", std::process::id()))
+    }
+
+    fn image_bytes(seed: u8, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|index| (index as u8).wrapping_mul(7).wrapping_add(seed))
+            .collect()
+    }
+
+    /// A page drawing an image of each of `images`' dictionaries, with
+    /// content of its own, saved to `path` and loaded back. The images' ids
+    /// come back with it.
+    fn saved(path: &Path, images: Vec<Dictionary>) -> (Document, Vec<ObjectId>) {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let image_ids = images
+            .into_iter()
+            .enumerate()
+            .map(|(index, dict)| {
+                document.add_object(Stream::new(dict, image_bytes(index as u8, 1000 + index)))
+            })
+            .collect::<Vec<ObjectId>>();
+        let xobjects = image_ids
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (format!("Im{index}"), Object::Reference(id)))
+            .collect::<Dictionary>();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        document.save(path).unwrap();
+        (Document::load(path).unwrap(), image_ids)
+    }
+
+    fn image() -> Dictionary {
+        dictionary! { "Type" => "XObject", "Subtype" => "Image" }
+    }
+
+    fn content(document: &Document, id: ObjectId) -> Vec<u8> {
+        document
+            .get_object(id)
+            .unwrap()
+            .as_stream()
+            .unwrap()
+            .content
+            .clone()
+    }
+
+    #[test]
+    fn spilled_images_are_saved_as_the_file_has_them() {
+        let path = temp_path("spill-images");
+        let (mut document, ids) = saved(&path, vec![image(), image(), image()]);
+        let originals = ids
+            .iter()
+            .map(|&id| content(&document, id))
+            .collect::<Vec<Vec<u8>>>();
+        let spill = Spill::new(&mut document, &path, 64).unwrap();
+        assert_eq!(spill.len(), 3);
+        assert_eq!(spill.bytes(), (1000 + 1001 + 1002) as u64);
+        for &id in &ids {
+            assert_eq!(content(&document, id).len(), PLACEHOLDER_LENGTH);
+        }
+
+        let mut bytes = Vec::new();
+        spill.save_to(&mut document, &mut bytes).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reloaded = Document::load_mem(&bytes).unwrap();
+        for (&id, original) in ids.iter().zip(&originals) {
+            assert_eq!(&content(&reloaded, id), original, "{id:?}");
+        }
+    }
+
+    #[test]
+    fn only_streams_the_file_still_holds_are_spilled() {
+        let path = temp_path("spill-candidates");
+        // The first holds what looks like its stream keyword in its
+        // dictionary, before the real one; the second changes once loaded.
+        let mut decoy = image();
+        decoy.set("Note", Object::string_literal("stream\nnot the content"));
+        let (mut document, ids) = saved(&path, vec![decoy, image()]);
+        let changed = image_bytes(99, 2000);
+        document
+            .get_object_mut(ids[1])
+            .and_then(Object::as_stream_mut)
+            .unwrap()
+            .set_content(changed.clone());
+        let original = content(&document, ids[0]);
+
+        let spill = Spill::new(&mut document, &path, 64).unwrap();
+        assert_eq!(spill.len(), 1);
+        assert_eq!(content(&document, ids[0]).len(), PLACEHOLDER_LENGTH);
+        assert_eq!(content(&document, ids[1]), changed);
+
+        let mut bytes = Vec::new();
+        spill.save_to(&mut document, &mut bytes).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let reloaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(content(&reloaded, ids[0]), original);
+        assert_eq!(content(&reloaded, ids[1]), changed);
+    }
+
+    #[test]
+    fn a_changed_file_is_not_copied_from() {
+        let path = temp_path("spill-changed");
+        let (mut document, _) = saved(&path, vec![image()]);
+        let spill = Spill::new(&mut document, &path, 64).unwrap();
+        assert_eq!(spill.len(), 1);
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(b"\n% appended"))
+            .unwrap();
+
+        let mut bytes = Vec::new();
+        let saved = spill.save_to(&mut document, &mut bytes);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            saved,
+            Err(PdfError::Io(error)) if error.to_string().contains("changed after streams were spilled")
+        ));
+        assert!(bytes.is_empty());
+    }
+}
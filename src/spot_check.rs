@@ -0,0 +1,200 @@
+//! `--spot-check`: a few pages of every optimized output rendered side by
+//! side into a PNG contact sheet, to judge the optimization by eye.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+/// Dots per inch the pages are rendered at; a letter page comes out 425
+/// pixels wide.
+const RESOLUTION: u32 = 50;
+/// Pixels of background around and between the pages.
+const GAP: usize = 12;
+const BACKGROUND: [u8; 3] = [0xd0, 0xd0, 0xd0];
+
+#[derive(Debug, Error)]
+pub enum SpotCheckError {
+    #[error("Ghostscript could not be started: {0}")]
+    Spawn(io::Error),
+    #[error("Ghostscript could not render page {page} ({status})")]
+    Render {
+        page: u32,
+        status: std::process::ExitStatus,
+    },
+    #[error("Ghostscript's rendering of page {page} is not a binary PPM image")]
+    Image { page: u32 },
+    #[error("cannot write the contact sheet: {0}")]
+    Write(io::Error),
+}
+
+/// An 8-bit RGB image, row by row.
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// `count` of the pages `1..=page_count`, in order, picked at random but the
+/// same every run for the same `seed`, so that a re-run shows the same pages.
+pub fn pick_pages(seed: &str, count: usize, page_count: u32) -> Vec<u32> {
+    let mut state = Sha256::new();
+    state.update(seed.as_bytes());
+    let digest = state.finalize();
+    let mut state = u64::from_le_bytes(digest[..8].try_into().unwrap());
+    // Splitmix64, drawing a page at a time from those left.
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut mixed = state;
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        mixed ^ (mixed >> 31)
+    };
+    let mut left = (1..=page_count).collect::<Vec<u32>>();
+    let mut picked = Vec::new();
+    while picked.len() < count && !left.is_empty() {
+        let index = (next() % left.len() as u64) as usize;
+        picked.push(left.swap_remove(index));
+    }
+    picked.sort_unstable();
+    picked
+}
+
+/// Renders page `page` of `pdf` with Ghostscript's `ppmraw` device.
+fn render(gs: &Path, pdf: &Path, page: u32, scratch: &Path) -> Result<Image, SpotCheckError> {
+    let output = Command::new(gs)
+        .arg("-dBATCH")
+        .arg("-dNOPAUSE")
+        .arg("-dSAFER")
+        .arg("-q")
+        .arg("-sDEVICE=ppmraw")
+        .arg(format!("-r{RESOLUTION}"))
+        .arg(format!("-dFirstPage={page}"))
+        .arg(format!("-dLastPage={page}"))
+        .arg(format!("-sOutputFile={}", scratch.display()))
+        .arg(pdf)
+        .output()
+        .map_err(SpotCheckError::Spawn)?;
+    if !output.status.success() {
+        return Err(SpotCheckError::Render {
+            page,
+            status: output.status,
+        });
+    }
+    let ppm = std::fs::read(scratch).map_err(|_| SpotCheckError::Image { page });
+    let _ = std::fs::remove_file(scratch);
+    parse_ppm(&ppm?).ok_or(SpotCheckError::Image { page })
+}
+
+/// A binary (`P6`) PPM image with 8-bit samples.
+fn parse_ppm(data: &[u8]) -> Option<Image> {
+    let mut at = 0;
+    let mut token = || {
+        // Whitespace and comments run up to each header field.
+        loop {
+            match data.get(at)? {
+                byte if byte.is_ascii_whitespace() => at += 1,
+                b'#' => at += data[at..].iter().position(|&byte| byte == b'\n')?,
+                _ => break,
+            }
+        }
+        let length = data[at..]
+            .iter()
+            .position(|byte| byte.is_ascii_whitespace())?;
+        let token = std::str::from_utf8(&data[at..at + length]).ok();
+        at += length;
+        token
+    };
+    if token()? != "P6" {
+        return None;
+    }
+    let mut number = || token()?.parse::<usize>().ok();
+    let (width, height, max) = (number()?, number()?, number()?);
+    // A single whitespace byte ends the header.
+    let pixels = data.get(at + 1..at + 1 + width * height * 3)?;
+    (max == 255).then(|| Image {
+        width,
+        height,
+        pixels: pixels.to_vec(),
+    })
+}
+
+/// The pages side by side, top-aligned, with a gap around each.
+fn contact_sheet(pages: &[Image]) -> Image {
+    let width = pages.iter().map(|page| page.width + GAP).sum::<usize>() + GAP;
+    let height = pages.iter().map(|page| page.height).max().unwrap_or(0) + 2 * GAP;
+    let mut pixels = BACKGROUND.repeat(width * height);
+    let mut left = GAP;
+    for page in pages {
+        for row in 0..page.height {
+            let from = row * page.width * 3;
+            let to = ((GAP + row) * width + left) * 3;
+            pixels[to..to + page.width * 3]
+                .copy_from_slice(&page.pixels[from..from + page.width * 3]);
+        }
+        left += page.width + GAP;
+    }
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn write_png(path: &Path, image: &Image) -> io::Result<()> {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut chunk = |kind: &[u8], data: &[u8]| {
+        png.extend((data.len() as u32).to_be_bytes());
+        png.extend(kind);
+        png.extend(data);
+        png.extend(crc32(&[kind, data]).to_be_bytes());
+    };
+    let mut header = Vec::new();
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    // 8-bit RGB, deflated, no interlacing.
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(b"IHDR", &header);
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in image.pixels.chunks(image.width * 3) {
+        // Rows without a filter.
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    chunk(b"IDAT", &encoder.finish()?);
+    chunk(b"IEND", &[]);
+    std::fs::write(path, png)
+}
+
+/// Renders `pages` of `pdf` with Ghostscript and writes them as a contact
+/// sheet to `sheet`; the rendered pages pass through `scratch`.
+pub fn write(
+    gs: &Path,
+    pdf: &Path,
+    pages: &[u32],
+    scratch: &Path,
+    sheet: &Path,
+) -> Result<(), SpotCheckError> {
+    let rendered = pages
+        .iter()
+        .map(|&page| render(gs, pdf, page, scratch))
+        .collect::<Result<Vec<Image>, SpotCheckError>>()?;
+    if let Some(directory) = sheet.parent() {
+        std::fs::create_dir_all(directory).map_err(SpotCheckError::Write)?;
+    }
+    write_png(sheet, &contact_sheet(&rendered)).map_err(SpotCheckError::Write)
+}
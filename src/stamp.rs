@@ -0,0 +1,328 @@
+use crate::content;
+use crate::limits::Limits;
+use crate::page::{inherited, page_box};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+#[cfg(feature = "qr-code")]
+use qrcode::types::QrError;
+#[cfg(feature = "qr-code")]
+use qrcode::{Color, QrCode};
+use serde::{Deserialize, Serialize};
+
+/// Resource name of the stamp font, unlikely to clash with a page's own.
+const FONT: &str = "NpchStamp";
+const TEXT_SIZE: f32 = 8.0;
+/// Points between a stamp and the edges of the page.
+pub const MARGIN: f32 = 12.0;
+#[cfg(feature = "qr-code")]
+const QR_CODE_SIZE: f32 = 54.0;
+/// Light modules around the code, the 4 that ISO/IEC 18004 asks for.
+#[cfg(feature = "qr-code")]
+const QUIET_ZONE: usize = 4;
+
+/// A corner of the page, to stamp into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// The pages of a slice that get a stamp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StampPages {
+    #[default]
+    First,
+    Every,
+}
+
+/// The page's resource dictionary, after copying inherited resources down so
+/// that what is added to it doesn't hide them.
+fn resources_mut(document: &mut Document, page_id: ObjectId) -> Option<&mut Dictionary> {
+    let resources = inherited(document, page_id, b"Resources")
+        .cloned()
+        .unwrap_or_else(|| Object::Dictionary(Dictionary::new()));
+    let page = document.get_dictionary_mut(page_id).ok()?;
+    if !page.has(b"Resources") {
+        page.set("Resources", resources);
+    }
+    match page.get(b"Resources").ok()? {
+        Object::Reference(id) => {
+            let id = *id;
+            document.get_dictionary_mut(id).ok()
+        }
+        _ => document
+            .get_dictionary_mut(page_id)
+            .ok()?
+            .get_mut(b"Resources")
+            .ok()?
+            .as_dict_mut()
+            .ok(),
+    }
+}
+
+/// Sets `name` in the `category` (`Font`, `XObject`, ...) of the page's
+/// resources.
+fn add_resource(
+    document: &mut Document,
+    page_id: ObjectId,
+    category: &str,
+    name: &str,
+    id: ObjectId,
+) -> Option<()> {
+    let resources = resources_mut(document, page_id)?;
+    let entries = match resources.get(category.as_bytes()) {
+        Ok(Object::Reference(entries_id)) => Some(*entries_id),
+        Ok(Object::Dictionary(_)) => None,
+        _ => {
+            resources.set(category, Dictionary::new());
+            None
+        }
+    };
+    let entries = match entries {
+        Some(entries_id) => document.get_dictionary_mut(entries_id).ok()?,
+        None => resources_mut(document, page_id)?
+            .get_mut(category.as_bytes())
+            .ok()?
+            .as_dict_mut()
+            .ok()?,
+    };
+    entries.set(name, id);
+    Some(())
+}
+
+/// Draws `operations` on top of the page. Its own content is wrapped in
+/// `q`/`Q` first, after closing whatever text object and graphics states it
+/// leaves open, so that they don't move or hide the overlay.
+fn overlay(
+    document: &mut Document,
+    page_id: ObjectId,
+    operations: Vec<Operation>,
+    limits: &Limits,
+) -> Option<()> {
+    // Content too broken to read is drawn over as it is.
+    let mut closing = content::closing(document, page_id, limits).unwrap_or_default();
+    let mut contents = match document.get_dictionary(page_id).ok()?.get(b"Contents") {
+        Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+        Ok(Object::Array(contents)) => contents.clone(),
+        _ => Vec::new(),
+    };
+    let save = document.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+    closing.push(Operation::new("Q", vec![]));
+    closing.extend(operations);
+    let overlay = Content {
+        operations: closing,
+    };
+    let overlay = document.add_object(Stream::new(dictionary! {}, overlay.encode().ok()?));
+    contents.insert(0, Object::Reference(save));
+    contents.push(Object::Reference(overlay));
+    document
+        .get_dictionary_mut(page_id)
+        .ok()?
+        .set("Contents", contents);
+    Some(())
+}
+
+/// The font stamps are written in: the one the first page was stamped with,
+/// if it was, so that stamping a document page by page adds it only once.
+pub fn text_font(document: &mut Document) -> ObjectId {
+    let stamped = document.page_iter().next().and_then(|first| {
+        let resources = inherited(document, first, b"Resources")?;
+        let (_, resources) = document.dereference(resources).ok()?;
+        let fonts = resources.as_dict().ok()?.get(b"Font").ok()?;
+        let (_, fonts) = document.dereference(fonts).ok()?;
+        fonts
+            .as_dict()
+            .ok()?
+            .get(FONT.as_bytes())
+            .ok()?
+            .as_reference()
+            .ok()
+    });
+    stamped.unwrap_or_else(|| {
+        document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        })
+    })
+}
+
+/// Width of `digits` digits in the stamp font: Helvetica's are all 556/1000
+/// of the text size wide.
+pub fn digits_width(digits: usize) -> f32 {
+    digits as f32 * 0.556 * TEXT_SIZE
+}
+
+/// Writes `text` in small print on the page, starting at `x`, `y` from its
+/// visible area's bottom left corner, in the font from `text_font`. Does
+/// nothing on pages whose dictionaries are too broken to draw on.
+pub fn stamp_page_text(
+    document: &mut Document,
+    page_id: ObjectId,
+    font_id: ObjectId,
+    text: &str,
+    (x, y): (f32, f32),
+    limits: &Limits,
+) -> Option<()> {
+    let [left, bottom, _, _] = page_box(document, page_id)?;
+    add_resource(document, page_id, "Font", FONT, font_id)?;
+    let text = Object::string_literal(Document::encode_text(Some("WinAnsiEncoding"), text));
+    let operations = vec![
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![FONT.into(), TEXT_SIZE.into()]),
+        Operation::new("Td", vec![(left + x).into(), (bottom + y).into()]),
+        Operation::new("Tj", vec![text]),
+        Operation::new("ET", vec![]),
+    ];
+    overlay(document, page_id, operations, limits)
+}
+
+/// Writes `text` in small print in the bottom left corner of every page.
+/// Pages whose dictionaries are too broken to draw on are left alone.
+pub fn stamp_text(document: &mut Document, text: &str, limits: &Limits) {
+    let font_id = text_font(document);
+    for page_id in document.page_iter().collect::<Vec<ObjectId>>() {
+        stamp_page_text(document, page_id, font_id, text, (MARGIN, MARGIN), limits);
+    }
+}
+
+#[cfg(feature = "qr-code")]
+fn rectangle(x: f32, y: f32, width: f32, height: f32) -> Operation {
+    Operation::new("re", vec![x.into(), y.into(), width.into(), height.into()])
+}
+
+/// Draws a QR code encoding `data` into `corner` of the first or every page,
+/// on a white square so that it scans on any background.
+#[cfg(feature = "qr-code")]
+pub fn stamp_qr_code(
+    document: &mut Document,
+    data: &str,
+    corner: Corner,
+    pages: StampPages,
+    limits: &Limits,
+) -> Result<(), QrError> {
+    let code = QrCode::new(data.as_bytes())?;
+    let width = code.width();
+    let colors = code.to_colors();
+    let module = QR_CODE_SIZE / (width + 2 * QUIET_ZONE) as f32;
+
+    let page_ids = document.page_iter().collect::<Vec<ObjectId>>();
+    let page_ids = match pages {
+        StampPages::First => &page_ids[..page_ids.len().min(1)],
+        StampPages::Every => &page_ids[..],
+    };
+    for &page_id in page_ids {
+        let Some([left, bottom, right, top]) = page_box(document, page_id) else {
+            continue;
+        };
+        let x = match corner {
+            Corner::TopLeft | Corner::BottomLeft => left + MARGIN,
+            Corner::TopRight | Corner::BottomRight => right - MARGIN - QR_CODE_SIZE,
+        };
+        let y = match corner {
+            Corner::BottomLeft | Corner::BottomRight => bottom + MARGIN,
+            Corner::TopLeft | Corner::TopRight => top - MARGIN - QR_CODE_SIZE,
+        };
+
+        let mut operations = vec![
+            Operation::new("g", vec![1.into()]),
+            rectangle(x, y, QR_CODE_SIZE, QR_CODE_SIZE),
+            Operation::new("f", vec![]),
+            Operation::new("g", vec![0.into()]),
+        ];
+        for (row, colors) in colors.chunks(width).enumerate() {
+            let row_y = y + QR_CODE_SIZE - (QUIET_ZONE + row + 1) as f32 * module;
+            let mut column = 0;
+            while column < width {
+                let run = colors[column..]
+                    .iter()
+                    .take_while(|color| **color == colors[column])
+                    .count();
+                if colors[column] == Color::Dark {
+                    let run_x = x + (QUIET_ZONE + column) as f32 * module;
+                    operations.push(rectangle(run_x, row_y, run as f32 * module, module));
+                }
+                column += run;
+            }
+        }
+        operations.push(Operation::new("f", vec![]));
+        overlay(document, page_id, operations, limits);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "qr-code"))]
+mod tests {
+    use super::*;
+
+    fn number(object: &Object) -> f32 {
+        object.as_float().unwrap()
+    }
+
+    #[test]
+    fn qr_codes_keep_a_quiet_zone_of_four_modules() {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let data = "https://docs.example.org/npch/Chapter%201";
+        let (corner, pages) = (Corner::BottomLeft, StampPages::First);
+        stamp_qr_code(&mut document, data, corner, pages, &Limits::default()).unwrap();
+
+        let overlay = document
+            .get_page_contents(page_id)
+            .into_iter()
+            .last()
+            .unwrap();
+        let overlay = document.get_object(overlay).unwrap().as_stream().unwrap();
+        let operations = Content::decode(&overlay.content).unwrap().operations;
+        let squares = operations
+            .iter()
+            .filter(|operation| operation.operator == "re")
+            .map(|operation| {
+                let [x, y, width, height] = [0, 1, 2, 3].map(|i| number(&operation.operands[i]));
+                (x, y, x + width, y + height)
+            })
+            .collect::<Vec<(f32, f32, f32, f32)>>();
+        let (&white, dark) = squares.split_first().unwrap();
+        assert_eq!(
+            white,
+            (MARGIN, MARGIN, MARGIN + QR_CODE_SIZE, MARGIN + QR_CODE_SIZE)
+        );
+
+        let width = QrCode::new(data.as_bytes()).unwrap().width();
+        let module = QR_CODE_SIZE / (width + 8) as f32;
+        let quiet = 4.0 * module - 0.01;
+        for &(left, bottom, right, top) in dark {
+            assert!(left >= white.0 + quiet && bottom >= white.1 + quiet);
+            assert!(right <= white.2 - quiet && top <= white.3 - quiet);
+        }
+        // The code's finder patterns reach its corners.
+        let left = dark.iter().map(|square| square.0).fold(f32::MAX, f32::min);
+        let top = dark.iter().map(|square| square.3).fold(f32::MIN, f32::max);
+        assert!((left - (white.0 + 4.0 * module)).abs() < 0.01);
+        assert!((top - (white.3 - 4.0 * module)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,534 @@
+use crate::links::edit;
+use crate::text::decode_text_string;
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeSet;
+
+/// A `Figure` element of the structure tree (logical structure), which screen
+/// readers rely on to describe images.
+#[derive(Debug, Clone)]
+pub struct Figure {
+    /// The page the figure is on, when the tree says.
+    pub page: Option<ObjectId>,
+    /// Its alternate text; `None` when missing or empty.
+    pub alt: Option<String>,
+}
+
+fn structure_tree_root(document: &Document) -> Option<&Dictionary> {
+    let root = document.catalog().ok()?.get(b"StructTreeRoot").ok()?;
+    match root {
+        Object::Reference(id) => document.get_dictionary(*id).ok(),
+        root => root.as_dict().ok(),
+    }
+}
+
+/// Whether the document has a structure tree with anything in it.
+pub fn is_tagged(document: &Document) -> bool {
+    structure_tree_root(document).is_some_and(|root| root.has(b"K"))
+}
+
+/// The figures of the structure tree, in tree order.
+pub fn figures(document: &Document) -> Vec<Figure> {
+    let mut figures = Vec::new();
+    let Some(root) = structure_tree_root(document) else {
+        return figures;
+    };
+    let role_map = root
+        .get(b"RoleMap")
+        .and_then(|map| match map {
+            Object::Reference(id) => document.get_dictionary(*id),
+            map => map.as_dict(),
+        })
+        .ok();
+    if let Ok(kids) = root.get(b"K") {
+        let mut walk = Walk {
+            document,
+            role_map,
+            visited: BTreeSet::new(),
+            figures: &mut figures,
+        };
+        walk.kids(kids, None, 0);
+    }
+    figures
+}
+
+struct Walk<'a> {
+    document: &'a Document,
+    role_map: Option<&'a Dictionary>,
+    visited: BTreeSet<ObjectId>,
+    figures: &'a mut Vec<Figure>,
+}
+
+impl<'a> Walk<'a> {
+    fn resolve(&mut self, object: &'a Object) -> Option<&'a Object> {
+        match object {
+            Object::Reference(id) if !self.visited.insert(*id) => None,
+            Object::Reference(id) => self.document.get_object(*id).ok(),
+            object => Some(object),
+        }
+    }
+
+    fn kids(&mut self, kids: &'a Object, page: Option<ObjectId>, depth: usize) {
+        if depth > MAX_NESTING {
+            return;
+        }
+        match self.resolve(kids) {
+            Some(Object::Array(kids)) => {
+                for kid in kids {
+                    if let Some(Object::Dictionary(element)) = self.resolve(kid) {
+                        self.element(element, page, depth + 1);
+                    }
+                }
+            }
+            Some(Object::Dictionary(element)) => self.element(element, page, depth + 1),
+            _ => {}
+        }
+    }
+
+    /// The structure type of `element`, through the role map for custom ones.
+    fn role(&self, element: &'a Dictionary) -> Option<&'a [u8]> {
+        let role = element.get(b"S").and_then(Object::as_name).ok()?;
+        let mapped = self
+            .role_map
+            .and_then(|map| map.get(role).and_then(Object::as_name).ok());
+        Some(mapped.unwrap_or(role))
+    }
+
+    fn element(&mut self, element: &'a Dictionary, page: Option<ObjectId>, depth: usize) {
+        // Marked-content and object references point into pages rather than
+        // being elements themselves.
+        if matches!(
+            element.get(b"Type").and_then(Object::as_name),
+            Ok(b"MCR" | b"OBJR")
+        ) {
+            return;
+        }
+        let page = element
+            .get(b"Pg")
+            .and_then(Object::as_reference)
+            .ok()
+            .or(page);
+        if self.role(element) == Some(b"Figure") {
+            let page = page.or_else(|| self.content_page(element));
+            let alt = element
+                .get(b"Alt")
+                .and_then(Object::as_str)
+                .map(decode_text_string)
+                .ok()
+                .filter(|alt| !alt.trim().is_empty());
+            self.figures.push(Figure { page, alt });
+        }
+        if let Ok(kids) = element.get(b"K") {
+            self.kids(kids, page, depth);
+        }
+    }
+
+    /// The page of the first marked-content or object reference of `element`.
+    fn content_page(&self, element: &Dictionary) -> Option<ObjectId> {
+        let kids = match element.get(b"K").ok()? {
+            Object::Array(kids) => kids.iter().collect::<Vec<&Object>>(),
+            kid => vec![kid],
+        };
+        kids.into_iter().find_map(|kid| {
+            let kid = match kid {
+                Object::Reference(id) => self.document.get_object(*id).ok()?,
+                kid => kid,
+            };
+            kid.as_dict()
+                .ok()?
+                .get(b"Pg")
+                .and_then(Object::as_reference)
+                .ok()
+        })
+    }
+}
+
+/// Keeps the structure elements with content on the `kept` pages, dropping
+/// the rest along with their `/ParentTree` entries (and the whole
+/// `StructTreeRoot` if nothing survives). Meant to run before the other
+/// pages are stripped, while every element still names its page.
+pub fn retain_slice_structure(document: &mut Document, kept: &BTreeSet<ObjectId>) {
+    let Some(entry) = document
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"StructTreeRoot").ok())
+        .cloned()
+    else {
+        return;
+    };
+    let mut prune = Prune {
+        kept,
+        keys: parent_keys(document, kept),
+        visited: BTreeSet::new(),
+        removed: BTreeSet::new(),
+    };
+    let mut surviving = true;
+    let root = edit(document, &entry, |document, root| {
+        let Object::Dictionary(root) = root else {
+            return;
+        };
+        let Ok(kids) = root.get(b"K").cloned() else {
+            return;
+        };
+        match prune.kids(document, &kids, None, 0) {
+            Some(kids) => root.set("K", kids),
+            None => surviving = false,
+        }
+        if let Ok(parent_tree) = root.get(b"ParentTree").cloned() {
+            let parent_tree = edit(document, &parent_tree, |document, node| {
+                prune.parent_tree(document, node, 0);
+            });
+            root.set("ParentTree", parent_tree);
+        }
+    });
+    if let Ok(catalog) = document.catalog_mut() {
+        match surviving {
+            true => catalog.set("StructTreeRoot", root),
+            false => {
+                catalog.remove(b"StructTreeRoot");
+            }
+        }
+    }
+}
+
+/// The `/StructParents` and `/StructParent` keys of the `kept` pages and of
+/// the annotations and form XObjects on them.
+fn parent_keys(document: &Document, kept: &BTreeSet<ObjectId>) -> BTreeSet<i64> {
+    let mut keys = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut pending = kept.iter().copied().collect::<Vec<ObjectId>>();
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let dictionary = match document.get_object(id) {
+            Ok(Object::Dictionary(dictionary)) => dictionary,
+            Ok(Object::Stream(stream)) => &stream.dict,
+            _ => continue,
+        };
+        for key in [&b"StructParents"[..], b"StructParent"] {
+            if let Ok(key) = dictionary.get(key).and_then(Object::as_i64) {
+                keys.insert(key);
+            }
+        }
+        // Up the page tree and back to the page lead to other pages.
+        for (key, value) in dictionary.iter() {
+            if !matches!(key.as_slice(), b"Parent" | b"P" | b"Pg") {
+                collect_references(value, &mut pending);
+            }
+        }
+    }
+    keys
+}
+
+fn collect_references(object: &Object, pending: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => pending.push(*id),
+        Object::Array(items) => items
+            .iter()
+            .for_each(|item| collect_references(item, pending)),
+        Object::Dictionary(dictionary) => dictionary
+            .iter()
+            .for_each(|(_, value)| collect_references(value, pending)),
+        _ => {}
+    }
+}
+
+struct Prune<'a> {
+    kept: &'a BTreeSet<ObjectId>,
+    /// The parent tree entries the kept pages use.
+    keys: BTreeSet<i64>,
+    visited: BTreeSet<ObjectId>,
+    /// The elements dropped, whose references the parent tree gives up.
+    removed: BTreeSet<ObjectId>,
+}
+
+impl Prune<'_> {
+    /// Whether content on `page` stays; content of no known page does.
+    fn is_kept(&self, page: Option<ObjectId>) -> bool {
+        page.map_or(true, |page| self.kept.contains(&page))
+    }
+
+    /// The `/K` of an element cut down to what stays, in the same shape, or
+    /// `None` if there was content and none of it stays.
+    fn kids(
+        &mut self,
+        document: &mut Document,
+        kids: &Object,
+        page: Option<ObjectId>,
+        depth: usize,
+    ) -> Option<Object> {
+        let Object::Array(kids) = kids else {
+            return self.kid(document, kids, page, depth);
+        };
+        let surviving = kids
+            .iter()
+            .filter_map(|kid| self.kid(document, kid, page, depth))
+            .collect::<Vec<Object>>();
+        (kids.is_empty() || !surviving.is_empty()).then_some(Object::Array(surviving))
+    }
+
+    /// `kid` if any of it stays: a marked-content sequence, a marked-content
+    /// or object reference, or an element, cut down to what stays.
+    fn kid(
+        &mut self,
+        document: &mut Document,
+        kid: &Object,
+        page: Option<ObjectId>,
+        depth: usize,
+    ) -> Option<Object> {
+        if depth > MAX_NESTING {
+            return Some(kid.clone());
+        }
+        match kid {
+            Object::Integer(_) => self.is_kept(page).then(|| kid.clone()),
+            Object::Reference(id) => {
+                if !self.visited.insert(*id) {
+                    return Some(kid.clone());
+                }
+                let Ok(Object::Dictionary(element)) = document.get_object(*id).cloned() else {
+                    return Some(kid.clone());
+                };
+                match self.element(document, element, page, depth) {
+                    Some(element) => {
+                        document.objects.insert(*id, Object::Dictionary(element));
+                        Some(kid.clone())
+                    }
+                    None => {
+                        self.removed.insert(*id);
+                        None
+                    }
+                }
+            }
+            Object::Dictionary(element) => self
+                .element(document, element.clone(), page, depth)
+                .map(Object::Dictionary),
+            kid => Some(kid.clone()),
+        }
+    }
+
+    fn element(
+        &mut self,
+        document: &mut Document,
+        mut element: Dictionary,
+        page: Option<ObjectId>,
+        depth: usize,
+    ) -> Option<Dictionary> {
+        let page = element
+            .get(b"Pg")
+            .and_then(Object::as_reference)
+            .ok()
+            .or(page);
+        let is_reference = matches!(
+            element.get(b"Type").and_then(Object::as_name),
+            Ok(b"MCR" | b"OBJR")
+        );
+        let kids = match element.get(b"K") {
+            Ok(kids) if !is_reference => kids.clone(),
+            _ => return self.is_kept(page).then_some(element),
+        };
+        let kids = self.kids(document, &kids, page, depth + 1)?;
+        element.set("K", kids);
+        Some(element)
+    }
+
+    /// Keeps the entries of a node of the number tree mapping `/StructParents`
+    /// to elements that the kept pages still use, without the dropped
+    /// elements.
+    fn parent_tree(&mut self, document: &mut Document, node: &mut Object, depth: usize) {
+        let Object::Dictionary(node) = node else {
+            return;
+        };
+        if depth > MAX_NESTING {
+            return;
+        }
+        if let Ok(Object::Array(nums)) = node.get(b"Nums").cloned() {
+            let mut surviving = Vec::new();
+            for pair in nums.chunks(2) {
+                let [key, value] = pair else {
+                    continue;
+                };
+                if !key.as_i64().is_ok_and(|key| self.keys.contains(&key)) {
+                    continue;
+                }
+                let value = edit(document, value, |_, value| {
+                    let Object::Array(elements) = value else {
+                        return;
+                    };
+                    for element in elements.iter_mut() {
+                        if matches!(element, Object::Reference(id) if self.removed.contains(id)) {
+                            *element = Object::Null;
+                        }
+                    }
+                });
+                surviving.extend([key.clone(), value]);
+            }
+            node.set("Nums", surviving);
+        }
+        if let Ok(Object::Array(kids)) = node.get(b"Kids").cloned() {
+            let kids = kids
+                .iter()
+                .map(|kid| match kid {
+                    Object::Reference(id) if !self.visited.insert(*id) => kid.clone(),
+                    kid => edit(document, kid, |document, kid| {
+                        self.parent_tree(document, kid, depth + 1)
+                    }),
+                })
+                .collect::<Vec<Object>>();
+            node.set("Kids", kids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pages::PageRanges;
+    use lopdf::dictionary;
+
+    /// Three pages: a paragraph on the first, a figure and a span on the
+    /// second, nothing tagged on the third.
+    fn tagged() -> Document {
+        let mut document = Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let root_id = document.new_object_id();
+        let page_ids = (0..3)
+            .map(|i| {
+                document.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                    "StructParents" => i,
+                })
+            })
+            .collect::<Vec<ObjectId>>();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => page_ids.iter().copied().map(Object::Reference).collect::<Vec<Object>>(),
+                "Count" => 3,
+            }),
+        );
+        let document_id = document.new_object_id();
+        let paragraph_id = document.add_object(dictionary! {
+            "Type" => "StructElem",
+            "S" => "P",
+            "P" => document_id,
+            "Pg" => page_ids[0],
+            "K" => 0,
+        });
+        let figure_id = document.add_object(dictionary! {
+            "Type" => "StructElem",
+            "S" => "Figure",
+            "P" => document_id,
+            "Alt" => Object::string_literal("A figure"),
+            "K" => dictionary! { "Type" => "MCR", "Pg" => page_ids[1], "MCID" => 0 },
+        });
+        let span = dictionary! {
+            "Type" => "StructElem",
+            "S" => "Span",
+            "P" => document_id,
+            "Pg" => page_ids[1],
+            "K" => vec![1.into()],
+        };
+        document.objects.insert(
+            document_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "StructElem",
+                "S" => "Document",
+                "P" => root_id,
+                "K" => vec![paragraph_id.into(), figure_id.into(), span.into()],
+            }),
+        );
+        let second_page = document.add_object(vec![figure_id.into(), document_id.into()]);
+        document.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "StructTreeRoot",
+                "K" => document_id,
+                "ParentTree" => dictionary! {
+                    "Nums" => vec![
+                        0.into(),
+                        vec![paragraph_id.into()].into(),
+                        1.into(),
+                        second_page.into(),
+                    ],
+                },
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "StructTreeRoot" => root_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        document
+    }
+
+    fn slice(document: &Document, pages: &[u32]) -> Document {
+        let mut ranges = PageRanges::new();
+        pages.iter().for_each(|&page| ranges.insert(page));
+        crate::extract_pages(document, &[], &ranges, None)
+    }
+
+    /// The structure types under the `Document` element, and the keys of
+    /// the parent tree.
+    fn shape(document: &Document) -> (Vec<String>, Vec<i64>) {
+        let root = structure_tree_root(document).unwrap();
+        let top = document
+            .get_dictionary(root.get(b"K").unwrap().as_reference().unwrap())
+            .unwrap();
+        let types = top
+            .get(b"K")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|kid| {
+                let element = match kid {
+                    Object::Reference(id) => document.get_dictionary(*id).unwrap(),
+                    kid => kid.as_dict().unwrap(),
+                };
+                String::from_utf8_lossy(element.get(b"S").unwrap().as_name().unwrap()).into_owned()
+            })
+            .collect();
+        let keys = root
+            .get(b"ParentTree")
+            .and_then(Object::as_dict)
+            .and_then(|tree| tree.get(b"Nums"))
+            .and_then(Object::as_array)
+            .unwrap()
+            .chunks(2)
+            .map(|pair| pair[0].as_i64().unwrap())
+            .collect();
+        (types, keys)
+    }
+
+    #[test]
+    fn slices_keep_the_elements_of_their_pages() {
+        let source = tagged();
+        assert_eq!(figures(&source).len(), 1);
+
+        let first = slice(&source, &[1]);
+        assert_eq!(shape(&first), (vec!["P".to_string()], vec![0]));
+        assert!(figures(&first).is_empty());
+
+        let second = slice(&source, &[2]);
+        assert_eq!(
+            shape(&second),
+            (vec!["Figure".to_string(), "Span".to_string()], vec![1])
+        );
+        assert_eq!(figures(&second).len(), 1);
+    }
+
+    #[test]
+    fn slices_without_tagged_pages_lose_the_tree() {
+        let third = slice(&tagged(), &[3]);
+        assert!(!is_tagged(&third));
+        assert!(third
+            .objects
+            .values()
+            .all(|object| object.as_dict().map_or(true, |object| !object.has(b"S"))));
+    }
+}
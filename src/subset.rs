@@ -0,0 +1,163 @@
+//! Building a slice as a fresh document holding only the objects its pages
+//! (and the catalog) lead to, rather than a copy of the whole source with the
+//! other pages deleted.
+
+use crate::MAX_NESTING;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Every node of the page tree under `node`, pages and intermediate nodes
+/// alike.
+fn page_tree(document: &Document, node: ObjectId, depth: usize, nodes: &mut BTreeSet<ObjectId>) {
+    if depth > MAX_NESTING || !nodes.insert(node) {
+        return;
+    }
+    let kids = document
+        .get_dictionary(node)
+        .and_then(|node| node.get(b"Kids"))
+        .and_then(Object::as_array)
+        .map(|kids| {
+            kids.iter()
+                .filter_map(|kid| kid.as_reference().ok())
+                .collect()
+        })
+        .unwrap_or_else(|_| Vec::new());
+    for kid in kids {
+        page_tree(document, kid, depth + 1, nodes);
+    }
+}
+
+fn parent(document: &Document, node: ObjectId) -> Option<ObjectId> {
+    document
+        .get_dictionary(node)
+        .and_then(|node| node.get(b"Parent"))
+        .and_then(Object::as_reference)
+        .ok()
+}
+
+/// How many of `kept` are under each node of the page tree holding any.
+fn kept_counts(document: &Document, kept: &BTreeSet<ObjectId>) -> BTreeMap<ObjectId, i64> {
+    let mut counts = BTreeMap::new();
+    for &page in kept {
+        counts.insert(page, 1);
+        let mut node = parent(document, page);
+        let mut depth = 0;
+        while let Some(ancestor) = node.filter(|_| depth <= MAX_NESTING) {
+            *counts.entry(ancestor).or_insert(0) += 1;
+            node = parent(document, ancestor);
+            depth += 1;
+        }
+    }
+    counts
+}
+
+fn push_references(object: &Object, pending: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => pending.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| push_references(item, pending)),
+        Object::Dictionary(dictionary) => push_dictionary_references(dictionary, pending),
+        Object::Stream(stream) => push_dictionary_references(&stream.dict, pending),
+        _ => {}
+    }
+}
+
+fn push_dictionary_references(dictionary: &Dictionary, pending: &mut Vec<ObjectId>) {
+    dictionary
+        .iter()
+        .for_each(|(_, value)| push_references(value, pending));
+}
+
+/// Removes the references to `dropped` from `object`: array items and
+/// dictionary entries alike, as deleting those objects would.
+fn strip_references(object: &mut Object, dropped: &BTreeSet<ObjectId>) {
+    let is_dropped =
+        |object: &Object| matches!(object, Object::Reference(id) if dropped.contains(id));
+    match object {
+        Object::Array(items) => {
+            items.retain(|item| !is_dropped(item));
+            items
+                .iter_mut()
+                .for_each(|item| strip_references(item, dropped));
+        }
+        Object::Dictionary(dictionary) => strip_dictionary_references(dictionary, dropped),
+        Object::Stream(stream) => strip_dictionary_references(&mut stream.dict, dropped),
+        _ => {}
+    }
+}
+
+fn strip_dictionary_references(dictionary: &mut Dictionary, dropped: &BTreeSet<ObjectId>) {
+    let keys = dictionary
+        .iter()
+        .filter(|(_, value)| matches!(value, Object::Reference(id) if dropped.contains(id)))
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<Vec<u8>>>();
+    for key in keys {
+        dictionary.remove(&key);
+    }
+    dictionary
+        .iter_mut()
+        .for_each(|(_, value)| strip_references(value, dropped));
+}
+
+/// A document with the trailer, version, object numbers and kind of
+/// cross-reference of `document` but only the objects reachable without
+/// passing through a page outside `kept` (or a branch of the page tree
+/// holding none of them). The rest of the page tree keeps its shape, with its
+/// counts corrected.
+///
+/// References to the pages and branches left out are still in place, so
+/// that links and destinations can be checked against them; `strip` removes
+/// them once that is done.
+pub fn copy_pages(
+    document: &Document,
+    kept: &BTreeSet<ObjectId>,
+) -> (Document, BTreeSet<ObjectId>) {
+    let mut nodes = BTreeSet::new();
+    if let Ok(root) = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Pages"))
+        .and_then(Object::as_reference)
+    {
+        page_tree(document, root, 0, &mut nodes);
+    }
+    let counts = kept_counts(document, kept);
+    let dropped = nodes
+        .into_iter()
+        .filter(|node| !counts.contains_key(node))
+        .collect::<BTreeSet<ObjectId>>();
+
+    let mut subset = Document::new();
+    subset.version = document.version.clone();
+    subset.trailer = document.trailer.clone();
+    subset.max_id = document.max_id;
+    subset.reference_table.cross_reference_type = document.reference_table.cross_reference_type;
+    let mut pending = Vec::new();
+    push_dictionary_references(&document.trailer, &mut pending);
+    while let Some(id) = pending.pop() {
+        if dropped.contains(&id) || subset.objects.contains_key(&id) {
+            continue;
+        }
+        let Ok(object) = document.get_object(id) else {
+            continue;
+        };
+        push_references(object, &mut pending);
+        subset.objects.insert(id, object.clone());
+    }
+    for (node, count) in counts {
+        let is_branch = document
+            .get_dictionary(node)
+            .is_ok_and(|node| node.has(b"Kids"));
+        if let (true, Ok(node)) = (is_branch, subset.get_dictionary_mut(node)) {
+            node.set("Count", count);
+        }
+    }
+    (subset, dropped)
+}
+
+/// Removes every reference to the objects `copy_pages` left out.
+pub fn strip(document: &mut Document, dropped: &BTreeSet<ObjectId>) {
+    strip_dictionary_references(&mut document.trailer, dropped);
+    for object in document.objects.values_mut() {
+        strip_references(object, dropped);
+    }
+}
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::process::Command;
+use thiserror::Error;
+
+/// A command from the project file, with `{name}` placeholders filled in per
+/// slice. Given as an array, it runs without a shell and every placeholder
+/// stays inside its own argument, whatever the description contains. Given as
+/// a string, it is a shell command line and only runs with `--use-shell`;
+/// the substituted values are quoted for the shell.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CommandTemplate {
+    Argv(Vec<String>),
+    Shell(String),
+}
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("empty command")]
+    Empty,
+    #[error("{0:?} is a shell command line; pass --use-shell to run it through the shell")]
+    ShellNotAllowed(String),
+    #[error("unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("{{{0}}} has a format other than :0 and a width, as in {{index:02}}")]
+    UnknownFormat(String),
+    #[cfg(windows)]
+    #[error(
+        "{0:?} cannot be quoted for cmd, which expands % and ! and ends quotes \
+         at \" even within them; give the command as an array instead"
+    )]
+    Unquotable(String),
+}
+
+pub type Variables<'a> = BTreeMap<&'a str, &'a str>;
+
+/// Replaces the `{name}` placeholders of `text`, passing each value through
+/// `quote`; `{{` and `}}` stand for literal braces. `{name:03}` pads the value
+/// with zeros to 3 characters.
+fn substitute(
+    text: &str,
+    variables: &Variables,
+    quote: impl Fn(&str) -> Result<String, TemplateError>,
+) -> Result<String, TemplateError> {
+    let mut substituted = String::new();
+    let mut rest = text;
+    while let Some(brace) = rest.find(['{', '}']) {
+        substituted.push_str(&rest[..brace]);
+        let (open, after) = rest[brace..].split_at(1);
+        if let Some(after) = after.strip_prefix(open) {
+            substituted.push_str(open);
+            rest = after;
+            continue;
+        }
+        if open == "}" {
+            substituted.push('}');
+            rest = after;
+            continue;
+        }
+        let Some(end) = after.find('}') else {
+            substituted.push('{');
+            rest = after;
+            continue;
+        };
+        let placeholder = &after[..end];
+        let (name, width) = match placeholder.split_once(':') {
+            Some((name, format)) => {
+                let width = format
+                    .strip_prefix('0')
+                    .and_then(|width| width.parse::<usize>().ok())
+                    .ok_or_else(|| TemplateError::UnknownFormat(placeholder.to_string()))?;
+                (name, width)
+            }
+            None => (placeholder, 0),
+        };
+        let value = variables
+            .get(name)
+            .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_string()))?;
+        substituted.push_str(&quote(&format!("{value:0>width$}"))?);
+        rest = &after[end + 1..];
+    }
+    substituted.push_str(rest);
+    Ok(substituted)
+}
+
+/// Quoted, nothing within single quotes is special to `sh`.
+#[cfg(not(windows))]
+fn shell_quote(value: &str) -> Result<String, TemplateError> {
+    Ok(format!("'{}'", value.replace('\'', r"'\''")))
+}
+
+/// `cmd` has no quoting that keeps every value literal: it expands `%NAME%`
+/// (and `!NAME!`) within double quotes, and a `"` in the value would end
+/// them, so values with those, its `^` escape or a line break are refused
+/// rather than run.
+#[cfg(windows)]
+fn shell_quote(value: &str) -> Result<String, TemplateError> {
+    if value.contains(['%', '"', '^', '!', '\r', '\n']) {
+        return Err(TemplateError::Unquotable(value.to_string()));
+    }
+    Ok(format!("\"{value}\""))
+}
+
+fn as_is(value: &str) -> Result<String, TemplateError> {
+    Ok(value.to_string())
+}
+
+/// Fills in the placeholders of `template` as they are.
+pub fn render(template: &str, variables: &Variables) -> Result<String, TemplateError> {
+    substitute(template, variables, as_is)
+}
+
+/// Fills in the placeholders of `args`, one argument at a time.
+pub fn render_args(args: &[String], variables: &Variables) -> Result<Vec<String>, TemplateError> {
+    args.iter()
+        .map(|arg| substitute(arg, variables, as_is))
+        .collect()
+}
+
+/// Fills in the placeholders of `url`, percent-encoding the values so that
+/// descriptions with spaces or `#` stay within their path segment.
+#[cfg(feature = "qr-code")]
+pub fn render_url(url: &str, variables: &Variables) -> Result<String, TemplateError> {
+    substitute(url, variables, |value| {
+        Ok(value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (byte as char).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect())
+    })
+}
+
+impl CommandTemplate {
+    pub fn command(
+        &self,
+        variables: &Variables,
+        use_shell: bool,
+    ) -> Result<Command, TemplateError> {
+        match self {
+            CommandTemplate::Argv(args) => {
+                let args = render_args(args, variables)?;
+                let (program, args) = args.split_first().ok_or(TemplateError::Empty)?;
+                let mut command = Command::new(program);
+                command.args(args);
+                Ok(command)
+            }
+            CommandTemplate::Shell(line) if !use_shell => {
+                Err(TemplateError::ShellNotAllowed(line.clone()))
+            }
+            CommandTemplate::Shell(line) => {
+                let line = substitute(line, variables, shell_quote)?;
+                let mut command = if cfg!(windows) {
+                    let mut command = Command::new("cmd");
+                    command.arg("/C");
+                    command
+                } else {
+                    let mut command = Command::new("sh");
+                    command.arg("-c");
+                    command
+                };
+                command.arg(line);
+                Ok(command)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTILE: [&str; 6] = [
+        "%PATH%",
+        "a\"&calc&\"",
+        "it's $(touch pwned) `id`",
+        "!PATH! ^& more",
+        "two\nlines",
+        "; rm -rf ~",
+    ];
+
+    #[cfg(not(windows))]
+    #[test]
+    fn shell_lines_keep_values_literal() {
+        let template = CommandTemplate::Shell(String::from("printf %s {description}"));
+        for value in HOSTILE {
+            let variables = Variables::from([("description", value)]);
+            let output = template
+                .command(&variables, true)
+                .unwrap()
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{value:?}");
+            assert_eq!(String::from_utf8(output.stdout).unwrap(), value);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn shell_lines_refuse_what_cmd_would_expand() {
+        let template = CommandTemplate::Shell(String::from("echo {description}"));
+        for value in HOSTILE {
+            let variables = Variables::from([("description", value)]);
+            let refused = matches!(
+                template.command(&variables, true),
+                Err(TemplateError::Unquotable(_))
+            );
+            assert_eq!(
+                refused,
+                value.contains(['%', '"', '^', '!', '\n']),
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn shell_lines_need_use_shell() {
+        let template = CommandTemplate::Shell(String::from("echo {description}"));
+        let variables = Variables::from([("description", "x")]);
+        assert!(matches!(
+            template.command(&variables, false),
+            Err(TemplateError::ShellNotAllowed(_))
+        ));
+    }
+}
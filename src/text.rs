@@ -0,0 +1,108 @@
+use crate::content::{PageContent, Unreadable};
+use crate::limits::Limits;
+use lopdf::{Document, Object, ObjectId, StringFormat};
+use std::collections::BTreeMap;
+
+pub fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<u16>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// `text` in lower case with every run of whitespace made a single space, so
+/// that phrases match wherever extraction breaks lines.
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// What `contains` found: whether the phrase is in the text, and the pages
+/// whose content couldn't all be read, with why, whose text it was looked for
+/// without.
+#[derive(Debug)]
+pub struct Search {
+    pub found: bool,
+    pub unreadable: BTreeMap<u32, Unreadable>,
+}
+
+/// Whether the text of the 1-based `pages` of `document` contains `phrase`,
+/// ignoring case and line breaks.
+pub fn contains(document: &Document, pages: &[u32], phrase: &str, limits: &Limits) -> Search {
+    let page_ids = document.get_pages();
+    let mut text = String::new();
+    let mut unreadable = BTreeMap::new();
+    for &page in pages {
+        let read = match page_ids.get(&page) {
+            Some(&page_id) => page_text(document, page_id, &mut text, limits),
+            None => Err(Unreadable::NoPage),
+        };
+        if let Err(why) = read {
+            unreadable.insert(page, why);
+        }
+    }
+    Search {
+        found: normalize(&text).contains(&normalize(phrase)),
+        unreadable,
+    }
+}
+
+/// Adds the text of the page to `text`, as `Document::extract_text` reads
+/// it, from what of its content can be read, or why not all of it could.
+fn page_text(
+    document: &Document,
+    page_id: ObjectId,
+    text: &mut String,
+    limits: &Limits,
+) -> Result<(), Unreadable> {
+    fn collect(text: &mut String, encoding: Option<&str>, operands: &[Object]) {
+        for operand in operands {
+            match operand {
+                Object::String(bytes, _) => text.push_str(&Document::decode_text(encoding, bytes)),
+                Object::Array(operands) => collect(text, encoding, operands),
+                _ => {}
+            }
+        }
+    }
+
+    let content = PageContent::new(document, page_id, limits)?;
+    let encodings = document
+        .get_page_fonts(page_id)
+        .into_iter()
+        .map(|(name, font)| (name, font.get_font_encoding()))
+        .collect::<BTreeMap<Vec<u8>, &str>>();
+    let mut encoding = None;
+    for operation in &content.operations {
+        match operation.operator.as_str() {
+            "Tf" => {
+                encoding = operation
+                    .operands
+                    .first()
+                    .and_then(|font| font.as_name().ok())
+                    .and_then(|font| encodings.get(font).copied());
+            }
+            "Tj" | "TJ" | "'" | "\"" => collect(text, encoding, &operation.operands),
+            "ET" if !text.ends_with('\n') => text.push('\n'),
+            _ => {}
+        }
+    }
+    content.readable()
+}
@@ -0,0 +1,139 @@
+//! Changes made to every page a slice keeps, as it is cut: the built-in
+//! rotation, stamps, cropping and page numbers, and whatever embedders
+//! implement `PageTransform` for.
+
+use crate::limits::Limits;
+use crate::page::{inherited, page_box};
+use crate::stamp::{self, MARGIN};
+use lopdf::{Document, Object, ObjectId};
+use thiserror::Error;
+
+/// What a transform fails with; any error will do.
+pub type TransformError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A page of the slice being cut.
+#[derive(Debug, Clone, Copy)]
+pub struct SlicePage {
+    /// The page's dictionary in the slice.
+    pub id: ObjectId,
+    /// Its number in the slice, from 1.
+    pub number: u32,
+    /// Its number in the source.
+    pub source_number: u32,
+    /// How many pages the slice has.
+    pub page_count: u32,
+    /// What decoding the page's streams may take.
+    pub limits: Limits,
+}
+
+/// A change made to each page of a slice, one page after the other, once
+/// the slice is cut and before its metadata is set and it is checked.
+pub trait PageTransform: Send + Sync {
+    fn apply(&self, slice_pdf: &mut Document, page: &SlicePage) -> Result<(), TransformError>;
+}
+
+#[derive(Debug, Error)]
+pub enum BuiltinError {
+    #[error("pages turn by multiples of 90 degrees, not {0}")]
+    Rotation(i64),
+    #[error("cropping leaves nothing of the page")]
+    Crop,
+}
+
+/// Turns pages clockwise by a multiple of 90 degrees, on top of however
+/// they're turned already.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotate(pub i64);
+
+impl PageTransform for Rotate {
+    fn apply(&self, slice_pdf: &mut Document, page: &SlicePage) -> Result<(), TransformError> {
+        if self.0 % 90 != 0 {
+            return Err(BuiltinError::Rotation(self.0).into());
+        }
+        let rotate = inherited(slice_pdf, page.id, b"Rotate")
+            .and_then(|rotate| rotate.as_i64().ok())
+            .unwrap_or(0);
+        slice_pdf
+            .get_dictionary_mut(page.id)?
+            .set("Rotate", (rotate + self.0).rem_euclid(360));
+        Ok(())
+    }
+}
+
+/// Writes text in small print in the bottom left corner of pages, with
+/// `{page}`, `{source_page}` and `{page_count}` filled in.
+#[derive(Debug, Clone)]
+pub struct Stamp(pub String);
+
+impl PageTransform for Stamp {
+    fn apply(&self, slice_pdf: &mut Document, page: &SlicePage) -> Result<(), TransformError> {
+        let text = self
+            .0
+            .replace("{page}", &page.number.to_string())
+            .replace("{source_page}", &page.source_number.to_string())
+            .replace("{page_count}", &page.page_count.to_string());
+        let font_id = stamp::text_font(slice_pdf);
+        let position = (MARGIN, MARGIN);
+        stamp::stamp_page_text(slice_pdf, page.id, font_id, &text, position, &page.limits);
+        Ok(())
+    }
+}
+
+/// Trims margins, in points, off what pages show, by setting their crop
+/// box.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crop {
+    pub left: f32,
+    pub bottom: f32,
+    pub right: f32,
+    pub top: f32,
+}
+
+impl PageTransform for Crop {
+    fn apply(&self, slice_pdf: &mut Document, page: &SlicePage) -> Result<(), TransformError> {
+        let Some([left, bottom, right, top]) = page_box(slice_pdf, page.id) else {
+            return Ok(());
+        };
+        let cropped = [
+            left.min(right) + self.left,
+            bottom.min(top) + self.bottom,
+            left.max(right) - self.right,
+            bottom.max(top) - self.top,
+        ];
+        if cropped[0] >= cropped[2] || cropped[1] >= cropped[3] {
+            return Err(BuiltinError::Crop.into());
+        }
+        slice_pdf
+            .get_dictionary_mut(page.id)?
+            .set("CropBox", cropped.map(Object::Real).to_vec());
+        Ok(())
+    }
+}
+
+/// Prints pages' numbers within the slice at the bottom centre, counting
+/// from `first`, for slices read apart from the guide its printed page
+/// numbers belong to.
+#[derive(Debug, Clone, Copy)]
+pub struct Renumber {
+    pub first: u32,
+}
+
+impl Default for Renumber {
+    fn default() -> Renumber {
+        Renumber { first: 1 }
+    }
+}
+
+impl PageTransform for Renumber {
+    fn apply(&self, slice_pdf: &mut Document, page: &SlicePage) -> Result<(), TransformError> {
+        let Some([left, _, right, _]) = page_box(slice_pdf, page.id) else {
+            return Ok(());
+        };
+        let number = (self.first + page.number - 1).to_string();
+        let x = (right - left).abs() / 2.0 - stamp::digits_width(number.len()) / 2.0;
+        let font_id = stamp::text_font(slice_pdf);
+        let position = (x, MARGIN);
+        stamp::stamp_page_text(slice_pdf, page.id, font_id, &number, position, &page.limits);
+        Ok(())
+    }
+}
@@ -0,0 +1,737 @@
+//! Incremental updates: the revisions a document saved by appending, each
+//! with a cross-reference section leading to the one before it, resolved the
+//! way readers resolve them.
+//!
+//! lopdf follows the chain itself, but loops forever on a chain that leads
+//! back into itself, skips the cross-reference streams of most hybrid
+//! sections, lets objects an update deleted come back, and picks a copy at
+//! random of an object more than one object stream holds. So a document with
+//! more than one section is handed to lopdf with one more section appended,
+//! listing where the newest revision keeps each object, and the objects in
+//! object streams are settled afterwards.
+
+use crate::limits::{self, DecodeError, Limits};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use thiserror::Error;
+
+/// Keys of a trailer, or a cross-reference stream's dictionary, that
+/// describe the section rather than the document.
+const SECTION_KEYS: [&[u8]; 8] = [
+    b"Type",
+    b"W",
+    b"Index",
+    b"Length",
+    b"Filter",
+    b"DecodeParms",
+    b"Prev",
+    b"XRefStm",
+];
+
+/// Room left past a stream's content, as its dictionary gives its length,
+/// for the white space and the `endstream` and `endobj` after it.
+const STREAM_END_ROOM: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("no startxref leads to a cross-reference section")]
+    Start,
+    #[error("the cross-reference sections lead back to the one at byte {0}")]
+    Loop(usize),
+    #[error("unreadable cross-reference section at byte {0}")]
+    Section(usize),
+}
+
+/// Where the newest revision keeps an object.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Entry {
+    /// Deleted, or never there.
+    Free,
+    Normal {
+        offset: usize,
+        generation: u16,
+    },
+    /// The `index`th object of the object stream numbered `container`.
+    Compressed {
+        container: u32,
+        index: usize,
+    },
+}
+
+/// One cross-reference section: its entries, and the trailer, or stream
+/// dictionary, that goes with it.
+struct Section {
+    entries: Vec<(u32, Entry)>,
+    dictionary: Dictionary,
+}
+
+/// A document's cross-reference sections, newest first, merged.
+pub struct UpdateChain {
+    /// Byte offsets of the sections, newest first; a hybrid section's
+    /// cross-reference stream comes right after its table.
+    sections: Vec<usize>,
+    entries: BTreeMap<u32, Entry>,
+    trailer: Dictionary,
+    limits: Limits,
+}
+
+impl UpdateChain {
+    /// Follows the chain from the last `startxref` of `data`.
+    pub fn read(data: &[u8], limits: &Limits) -> Result<UpdateChain, UpdateError> {
+        let mut chain = UpdateChain {
+            sections: Vec::new(),
+            entries: BTreeMap::new(),
+            trailer: Dictionary::new(),
+            limits: *limits,
+        };
+        let mut next = Some(start(data).ok_or(UpdateError::Start)?);
+        while let Some(offset) = next {
+            let section = chain.section(data, offset)?;
+            let mut entries = section
+                .entries
+                .iter()
+                .copied()
+                .collect::<BTreeMap<u32, Entry>>();
+            // A hybrid section's stream lists what its table leaves out or
+            // marks free, mostly the objects in object streams, for readers
+            // that know them.
+            if let Ok(stream) = section.dictionary.get(b"XRefStm").and_then(Object::as_i64) {
+                let stream = chain.section(data, usize::try_from(stream).unwrap_or(usize::MAX))?;
+                for (number, entry) in stream.entries {
+                    let kept = entries.entry(number).or_insert(entry);
+                    if *kept == Entry::Free {
+                        *kept = entry;
+                    }
+                }
+            }
+            for (number, entry) in entries {
+                chain.entries.entry(number).or_insert(entry);
+            }
+            if chain.trailer.is_empty() {
+                chain.trailer = section.dictionary.clone();
+                for key in SECTION_KEYS {
+                    chain.trailer.remove(key);
+                }
+            }
+            next = section
+                .dictionary
+                .get(b"Prev")
+                .and_then(Object::as_i64)
+                .ok()
+                .map(|prev| usize::try_from(prev).unwrap_or(usize::MAX));
+        }
+        Ok(chain)
+    }
+
+    /// Reads the section at `offset`, unless the chain has been there.
+    fn section(&mut self, data: &[u8], offset: usize) -> Result<Section, UpdateError> {
+        if self.sections.contains(&offset) {
+            return Err(UpdateError::Loop(offset));
+        }
+        self.sections.push(offset);
+        read_section(data, offset, &self.limits).ok_or(UpdateError::Section(offset))
+    }
+
+    /// Whether lopdf would resolve the document differently: whether it has
+    /// more than one section.
+    pub fn is_incremental(&self) -> bool {
+        self.sections.len() > 1
+    }
+
+    /// `data` with one more cross-reference table, listing every object
+    /// outside object streams where the newest revision keeps it, for lopdf
+    /// to load.
+    pub fn resolved(&self, data: &[u8]) -> Vec<u8> {
+        let normal = self
+            .entries
+            .iter()
+            .filter_map(|(&number, entry)| match *entry {
+                Entry::Normal { offset, generation } => Some((number, offset, generation)),
+                _ => None,
+            })
+            .collect::<Vec<(u32, usize, u16)>>();
+        append_table(data, &normal)
+    }
+
+    /// The newest revision's trailer.
+    pub fn trailer(&self) -> &Dictionary {
+        &self.trailer
+    }
+
+    /// Brings the objects of `document`, as lopdf loaded it from `resolved`,
+    /// to the newest revision: without the objects it deleted or replaced,
+    /// and with each object in an object stream taken from the stream this
+    /// revision names. Encrypted object streams can only be read once
+    /// decrypted, so this is done again after decrypting.
+    pub fn settle(&self, document: &mut Document) {
+        document
+            .objects
+            .retain(|&(number, generation), _| match self.entries.get(&number) {
+                Some(Entry::Normal {
+                    generation: kept, ..
+                }) => *kept == generation,
+                Some(Entry::Compressed { .. }) => generation == 0,
+                Some(Entry::Free) | None => false,
+            });
+
+        let mut containers = BTreeMap::<u32, BTreeSet<u32>>::new();
+        for (&number, entry) in &self.entries {
+            if let Entry::Compressed { container, .. } = *entry {
+                containers.entry(container).or_default().insert(number);
+            }
+        }
+        for (container, numbers) in containers {
+            let Some(Object::Stream(stream)) = document.objects.get(&(container, 0)) else {
+                continue;
+            };
+            let Ok(mut objects) = read_object_stream(&mut stream.clone(), &self.limits) else {
+                continue;
+            };
+            for number in numbers {
+                if let Some(object) = objects.remove(&(number, 0)) {
+                    document.objects.insert((number, 0), object);
+                }
+            }
+        }
+        let highest = document.objects.keys().map(|&(number, _)| number).max();
+        document.max_id = document.max_id.max(highest.unwrap_or(0));
+    }
+}
+
+/// The objects packed in the object stream `stream`, decrypted if need be.
+/// lopdf only reads them as it loads a file, so they are laid out as a file
+/// of their own for it to load.
+pub fn read_object_stream(
+    stream: &mut Stream,
+    limits: &Limits,
+) -> Result<BTreeMap<ObjectId, Object>, lopdf::Error> {
+    decompress(stream, limits).map_err(|_| lopdf::Error::ContentDecode)?;
+    let first = stream.dict.get(b"First")?.as_i64()?.max(0) as usize;
+    let header = stream
+        .content
+        .get(..first)
+        .ok_or(lopdf::Error::Offset(first))?;
+    let numbers = std::str::from_utf8(header)?
+        .split_whitespace()
+        .map(str::parse::<usize>)
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| lopdf::Error::Syntax("object stream header".to_string()))?;
+    let mut entries = numbers
+        .chunks_exact(2)
+        .map(|pair| (pair[0], first + pair[1]))
+        .collect::<Vec<(usize, usize)>>();
+    entries.sort_by_key(|&(_, offset)| offset);
+
+    let mut file = b"%PDF-1.7\n".to_vec();
+    let mut offsets = Vec::new();
+    for (index, &(id, start)) in entries.iter().enumerate() {
+        let end = entries
+            .get(index + 1)
+            .map_or(stream.content.len(), |&(_, next)| next);
+        let object = stream
+            .content
+            .get(start..end)
+            .ok_or(lopdf::Error::Offset(start))?;
+        offsets.push((id as u32, file.len(), 0));
+        writeln!(file, "{id} 0 obj").unwrap();
+        file.extend_from_slice(object);
+        file.extend_from_slice(b"\nendobj\n");
+    }
+    Ok(Document::load_mem(&append_table(&file, &offsets))?.objects)
+}
+
+/// `data` with a cross-reference table of `objects` (number, offset,
+/// generation) and a trailer after it. lopdf looks for the last `%%EOF` only
+/// in a file's last 512 bytes, so they are kept clear of any already there.
+fn append_table(data: &[u8], objects: &[(u32, usize, u16)]) -> Vec<u8> {
+    let mut file = data.to_vec();
+    file.push(b'\n');
+    file.resize(file.len() + 512, b' ');
+    file.push(b'\n');
+    let start = file.len();
+    file.extend_from_slice(b"xref\n0 1\n0000000000 65535 f \n");
+    for &(number, offset, generation) in objects {
+        write!(file, "{number} 1\n{offset:010} {generation:05} n \n").unwrap();
+    }
+    let size = objects
+        .iter()
+        .map(|&(number, ..)| number + 1)
+        .max()
+        .unwrap_or(1);
+    write!(
+        file,
+        "trailer\n<< /Size {size} >>\nstartxref\n{start}\n%%EOF\n"
+    )
+    .unwrap();
+    file
+}
+
+fn is_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace() || b"()<>[]{}/%".contains(&byte)
+}
+
+fn skip_whitespace(data: &[u8], mut at: usize) -> usize {
+    while data.get(at).is_some_and(u8::is_ascii_whitespace) {
+        at += 1;
+    }
+    at
+}
+
+/// The token at `at`, after any whitespace, and where it ends.
+fn token(data: &[u8], at: usize) -> Option<(&[u8], usize)> {
+    let start = skip_whitespace(data, at);
+    let length = data
+        .get(start..)?
+        .iter()
+        .take_while(|&&byte| !is_delimiter(byte))
+        .count();
+    (length > 0).then(|| (&data[start..start + length], start + length))
+}
+
+fn number<T: std::str::FromStr>(token: &[u8]) -> Option<T> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// The offset the last `startxref` of `data` gives.
+fn start(data: &[u8]) -> Option<usize> {
+    let keyword = data.windows(9).rposition(|window| window == b"startxref")?;
+    number(token(data, keyword + 9)?.0)
+}
+
+fn read_section(data: &[u8], offset: usize, limits: &Limits) -> Option<Section> {
+    let (keyword, after) = token(data, offset)?;
+    match keyword {
+        b"xref" => read_table(data, after),
+        _ => read_stream(data, offset, limits),
+    }
+}
+
+/// A cross-reference table and its trailer, from just after `xref`.
+fn read_table(data: &[u8], mut at: usize) -> Option<Section> {
+    let mut entries = Vec::new();
+    loop {
+        let (first, after) = token(data, at)?;
+        if first == b"trailer" {
+            at = after;
+            break;
+        }
+        let first = number::<u32>(first)?;
+        let (count, after) = token(data, after)?;
+        at = after;
+        for number_ in first..first.checked_add(number::<u32>(count)?)? {
+            let (offset, after) = token(data, at)?;
+            let (generation, after) = token(data, after)?;
+            let (kind, after) = token(data, after)?;
+            at = after;
+            let entry = match kind {
+                b"n" => Entry::Normal {
+                    offset: number(offset)?,
+                    generation: number(generation)?,
+                },
+                b"f" => Entry::Free,
+                _ => return None,
+            };
+            entries.push((number_, entry));
+        }
+    }
+    let start = skip_whitespace(data, at);
+    let length = dictionary_length(data.get(start..)?)?;
+    let dictionary = parse_dictionary(&data[start..start + length])?;
+    Some(Section {
+        entries,
+        dictionary,
+    })
+}
+
+/// How long the dictionary `data` starts with is, strings and all.
+fn dictionary_length(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(b"<<") {
+        return None;
+    }
+    let (mut depth, mut at) = (0usize, 0);
+    while at < data.len() {
+        match data[at] {
+            b'<' if data.get(at + 1) == Some(&b'<') => {
+                depth += 1;
+                at += 2;
+            }
+            b'>' if data.get(at + 1) == Some(&b'>') => {
+                depth -= 1;
+                at += 2;
+                if depth == 0 {
+                    return Some(at);
+                }
+            }
+            b'<' => at += data[at..].iter().position(|&byte| byte == b'>')? + 1,
+            b'(' => {
+                let mut parentheses = 0;
+                while at < data.len() {
+                    match data[at] {
+                        b'\\' => at += 1,
+                        b'(' => parentheses += 1,
+                        b')' => parentheses -= 1,
+                        _ => {}
+                    }
+                    at += 1;
+                    if parentheses == 0 {
+                        break;
+                    }
+                }
+            }
+            _ => at += 1,
+        }
+    }
+    None
+}
+
+/// Replaces the data of `stream` with its decoded data, as lopdf's
+/// `Stream::decompress` does, within the limits.
+fn decompress(stream: &mut Stream, limits: &Limits) -> Result<(), DecodeError> {
+    let content = limits::decompressed(stream, limits)?;
+    stream.dict.remove(b"DecodeParms");
+    stream.dict.remove(b"Filter");
+    stream.set_content(content);
+    Ok(())
+}
+
+/// A cross-reference stream, whose entries are packed as `W` says.
+fn read_stream(data: &[u8], offset: usize, limits: &Limits) -> Option<Section> {
+    let Object::Stream(mut stream) = object_at(data, offset)? else {
+        return None;
+    };
+    if !stream.dict.type_is(b"XRef") {
+        return None;
+    }
+    decompress(&mut stream, limits).ok()?;
+    let widths = stream
+        .dict
+        .get(b"W")
+        .and_then(Object::as_array)
+        .ok()?
+        .iter()
+        .map(|width| {
+            width
+                .as_i64()
+                .ok()
+                .and_then(|width| usize::try_from(width).ok())
+        })
+        .collect::<Option<Vec<usize>>>()?;
+    let [type_width, second_width, third_width] = widths[..] else {
+        return None;
+    };
+    let size = stream.dict.get(b"Size").and_then(Object::as_i64).ok()?;
+    let index = match stream.dict.get(b"Index").and_then(Object::as_array) {
+        Ok(index) => index
+            .iter()
+            .map(|value| {
+                value
+                    .as_i64()
+                    .ok()
+                    .and_then(|value| u32::try_from(value).ok())
+            })
+            .collect::<Option<Vec<u32>>>()?,
+        Err(_) => vec![0, u32::try_from(size).ok()?],
+    };
+    let field = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .fold(0usize, |value, &byte| value << 8 | usize::from(byte))
+    };
+    let width = type_width + second_width + third_width;
+    let mut rows = stream.content.chunks_exact(width.max(1));
+    let mut entries = Vec::new();
+    for range in index.chunks_exact(2) {
+        for number_ in range[0]..range[0].checked_add(range[1])? {
+            let row = rows.next()?;
+            let kind = match type_width {
+                0 => 1,
+                _ => field(&row[..type_width]),
+            };
+            let second = field(&row[type_width..type_width + second_width]);
+            let third = field(&row[type_width + second_width..]);
+            let entry = match kind {
+                0 => Entry::Free,
+                1 => Entry::Normal {
+                    offset: second,
+                    generation: u16::try_from(third).ok()?,
+                },
+                2 => Entry::Compressed {
+                    container: u32::try_from(second).ok()?,
+                    index: third,
+                },
+                // Types a later standard may add are to be read as null
+                // objects.
+                _ => Entry::Free,
+            };
+            entries.push((number_, entry));
+        }
+    }
+    Some(Section {
+        entries,
+        dictionary: stream.dict,
+    })
+}
+
+/// The dictionary `data` holds, and nothing else.
+fn parse_dictionary(data: &[u8]) -> Option<Dictionary> {
+    let mut file = b"%PDF-1.7\n1 0 obj\n".to_vec();
+    file.extend_from_slice(data);
+    file.extend_from_slice(b"\nendobj\n");
+    let mut document = Document::load_mem(&append_table(&file, &[(1, 9, 0)])).ok()?;
+    match document.objects.remove(&(1, 0))? {
+        Object::Dictionary(dictionary) => Some(dictionary),
+        _ => None,
+    }
+}
+
+/// The stream object at `offset` of `data`. A cross-reference stream's
+/// length is always direct, so only as much of `data` as its dictionary
+/// says the stream takes is handed to lopdf, not the rest of the file.
+fn object_at(data: &[u8], offset: usize) -> Option<Object> {
+    let (number_, after) = token(data, offset)?;
+    let (generation, after) = token(data, after)?;
+    let (keyword, after) = token(data, after)?;
+    if keyword != b"obj" {
+        return None;
+    }
+    let id = (number::<u32>(number_)?, number::<u16>(generation)?);
+    let start = skip_whitespace(data, after);
+    let length = dictionary_length(data.get(start..)?)?;
+    let content_length = parse_dictionary(&data[start..start + length])?
+        .get(b"Length")
+        .and_then(Object::as_i64)
+        .ok()
+        .and_then(|length| usize::try_from(length).ok())?;
+    let end = (start + length)
+        .saturating_add(content_length)
+        .saturating_add(STREAM_END_ROOM)
+        .min(data.len());
+    let mut file = b"%PDF-1.7\n".to_vec();
+    file.extend_from_slice(&data[offset..end]);
+    let mut document = Document::load_mem(&append_table(&file, &[(id.0, 9, id.1)])).ok()?;
+    document.objects.remove(&id)
+}
+
+/// Makes `document`, loaded as its newest revision, a document of one
+/// revision: without the cross-reference streams and object streams of the
+/// revisions, the objects no revision still uses, or what in its trailer
+/// led to the older ones. Signatures over the revisions no longer hold
+/// once it is saved.
+pub fn flatten(document: &mut Document) {
+    document.objects.retain(|_, object| match object {
+        Object::Stream(stream) => !stream.dict.type_is(b"XRef") && !stream.dict.type_is(b"ObjStm"),
+        _ => true,
+    });
+    for key in SECTION_KEYS {
+        document.trailer.remove(key);
+    }
+    document.prune_objects();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::pdf::{self, PdfError};
+
+    /// Appends the indirect object `id` and returns where it starts.
+    fn push_object(data: &mut Vec<u8>, (number, generation): ObjectId, body: &[u8]) -> usize {
+        let offset = data.len();
+        writeln!(data, "{number} {generation} obj").unwrap();
+        data.extend_from_slice(body);
+        data.extend_from_slice(b"\nendobj\n");
+        offset
+    }
+
+    /// How a cross-reference table lists an object.
+    #[derive(Clone, Copy)]
+    enum Listed {
+        /// In use, at its offset with its generation.
+        At(usize, u16),
+        /// Freed, with the generation it would be used again with.
+        Freed(u16),
+    }
+
+    /// Appends a cross-reference table of `entries`, then `trailer`, and
+    /// returns where the table starts.
+    fn push_table(data: &mut Vec<u8>, entries: &[(u32, Listed)], trailer: &str) -> usize {
+        let offset = data.len();
+        data.extend_from_slice(b"xref\n");
+        for &(number, entry) in entries {
+            match entry {
+                Listed::At(at, generation) => {
+                    write!(data, "{number} 1\n{at:010} {generation:05} n \n")
+                }
+                Listed::Freed(generation) => {
+                    write!(data, "{number} 1\n0000000000 {generation:05} f \n")
+                }
+            }
+            .unwrap();
+        }
+        write!(data, "trailer\n{trailer}\nstartxref\n{offset}\n%%EOF\n").unwrap();
+        offset
+    }
+
+    fn stream(dictionary: &str, content: &[u8]) -> Vec<u8> {
+        let mut body =
+            format!("<< {dictionary} /Length {} >>\nstream\n", content.len()).into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\nendstream");
+        body
+    }
+
+    /// A one-page document whose page shows "Old" and whose object 5 is a
+    /// string, saved with one section; and where that section starts.
+    fn original() -> (Vec<u8>, usize) {
+        let mut data = b"%PDF-1.7\n".to_vec();
+        let offsets = [
+            push_object(&mut data, (1, 0), b"<< /Type /Catalog /Pages 2 0 R >>"),
+            push_object(
+                &mut data,
+                (2, 0),
+                b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+            ),
+            push_object(
+                &mut data,
+                (3, 0),
+                b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R >>",
+            ),
+            push_object(&mut data, (4, 0), &stream("", b"(Old) Tj")),
+            push_object(&mut data, (5, 0), b"(doomed)"),
+        ];
+        let mut entries = vec![(0, Listed::Freed(65535))];
+        entries.extend((1..).zip(offsets.map(|offset| Listed::At(offset, 0))));
+        let section = push_table(&mut data, &entries, "<< /Size 6 /Root 1 0 R >>");
+        (data, section)
+    }
+
+    fn load(data: &[u8]) -> Result<Document, PdfError> {
+        pdf::load_mem(data, &Limits::default())
+    }
+
+    fn page_content(document: &Document) -> Vec<u8> {
+        document.get_page_content(document.get_pages()[&1]).unwrap()
+    }
+
+    #[test]
+    fn newest_revision_of_a_replaced_object_wins() {
+        let (mut data, first) = original();
+        let replaced = push_object(&mut data, (4, 0), &stream("", b"(New) Tj"));
+        push_table(
+            &mut data,
+            &[(4, Listed::At(replaced, 0))],
+            &format!("<< /Size 6 /Root 1 0 R /Prev {first} >>"),
+        );
+
+        let chain = UpdateChain::read(&data, &Limits::default()).unwrap();
+        assert!(chain.is_incremental());
+        let document = load(&data).unwrap();
+        assert_eq!(page_content(&document), b"(New) Tj");
+    }
+
+    #[test]
+    fn freed_objects_stay_deleted() {
+        let (mut data, first) = original();
+        push_table(
+            &mut data,
+            &[(5, Listed::Freed(1))],
+            &format!("<< /Size 6 /Root 1 0 R /Prev {first} >>"),
+        );
+
+        let document = load(&data).unwrap();
+        assert!(!document.objects.keys().any(|&(number, _)| number == 5));
+        assert_eq!(page_content(&document), b"(Old) Tj");
+    }
+
+    #[test]
+    fn hybrid_sections_read_their_cross_reference_streams() {
+        let (mut data, first) = original();
+        // Object 7 is packed in the object stream 6, which only the
+        // cross-reference stream 8 lists it in.
+        let container = push_object(
+            &mut data,
+            (6, 0),
+            &stream("/Type /ObjStm /N 1 /First 4", b"7 0 (packed)"),
+        );
+        let row = [&[2][..], &6u32.to_be_bytes(), &0u16.to_be_bytes()].concat();
+        let xref_stream = push_object(
+            &mut data,
+            (8, 0),
+            &stream("/Type /XRef /Size 9 /W [1 4 2] /Index [7 1]", &row),
+        );
+        push_table(
+            &mut data,
+            &[
+                (6, Listed::At(container, 0)),
+                (8, Listed::At(xref_stream, 0)),
+            ],
+            &format!("<< /Size 9 /Root 1 0 R /Prev {first} /XRefStm {xref_stream} >>"),
+        );
+
+        let chain = UpdateChain::read(&data, &Limits::default()).unwrap();
+        assert_eq!(chain.sections.len(), 3);
+        assert_eq!(
+            chain.entries.get(&7),
+            Some(&Entry::Compressed {
+                container: 6,
+                index: 0
+            })
+        );
+        assert!(!chain.trailer().has(b"XRefStm"));
+        let document = load(&data).unwrap();
+        assert_eq!(
+            document
+                .get_object((7, 0))
+                .and_then(Object::as_str)
+                .unwrap(),
+            b"packed"
+        );
+    }
+
+    #[test]
+    fn a_chain_leading_back_into_itself_is_refused() {
+        let (mut data, _) = original();
+        let replaced = push_object(&mut data, (4, 0), &stream("", b"(New) Tj"));
+        let section = data.len();
+        push_table(
+            &mut data,
+            &[(4, Listed::At(replaced, 0))],
+            &format!("<< /Size 6 /Root 1 0 R /Prev {section} >>"),
+        );
+
+        assert!(matches!(
+            UpdateChain::read(&data, &Limits::default()),
+            Err(UpdateError::Loop(offset)) if offset == section
+        ));
+        assert!(matches!(
+            load(&data),
+            Err(PdfError::Updates(UpdateError::Loop(_)))
+        ));
+    }
+
+    #[test]
+    fn flattened_documents_reload_as_one_revision() {
+        let (mut data, first) = original();
+        let replaced = push_object(&mut data, (4, 0), &stream("", b"(New) Tj"));
+        push_table(
+            &mut data,
+            &[(4, Listed::At(replaced, 0)), (5, Listed::Freed(1))],
+            &format!("<< /Size 6 /Root 1 0 R /Prev {first} >>"),
+        );
+
+        let mut document = load(&data).unwrap();
+        flatten(&mut document);
+        assert!(!document.trailer.has(b"Prev"));
+        let mut saved = Vec::new();
+        document.save_to(&mut saved).unwrap();
+
+        assert!(!UpdateChain::read(&saved, &Limits::default())
+            .unwrap()
+            .is_incremental());
+        let reloaded = load(&saved).unwrap();
+        assert_eq!(page_content(&reloaded), b"(New) Tj");
+        assert!(!reloaded.objects.keys().any(|&(number, _)| number == 5));
+        assert_eq!(reloaded.objects.len(), 4);
+    }
+}
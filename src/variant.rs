@@ -0,0 +1,86 @@
+use crate::config::Config;
+use crate::sink::Sink;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Screen,
+    Ebook,
+    Print,
+    Prepress,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Screen => "screen",
+            Variant::Ebook => "ebook",
+            Variant::Print => "print",
+            Variant::Prepress => "prepress",
+        }
+    }
+
+    pub fn pdf_settings(self) -> &'static str {
+        match self {
+            Variant::Screen => "/screen",
+            Variant::Ebook => "/ebook",
+            Variant::Print => "/printer",
+            Variant::Prepress => "/prepress",
+        }
+    }
+
+    /// The image resolution of the variant's Ghostscript preset, which the
+    /// builtin optimizer downsamples to.
+    pub fn max_dpi(self) -> f32 {
+        match self {
+            Variant::Screen => 72.0,
+            Variant::Ebook => 150.0,
+            Variant::Print | Variant::Prepress => 300.0,
+        }
+    }
+}
+
+/// Where an optimized slice is written, with which Ghostscript preset, and
+/// where it is delivered afterwards; without variants everything goes to
+/// `optimized/` in the output directory.
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    pub variant: Option<Variant>,
+    pub sink: Option<Sink>,
+}
+
+impl OutputTarget {
+    /// Variants named on the command line win over those listed in the
+    /// project file.
+    pub fn all(variants: &[Variant], config: &Config) -> Vec<OutputTarget> {
+        let variants = if variants.is_empty() {
+            config.variants.keys().copied().collect()
+        } else {
+            variants.to_vec()
+        };
+        if variants.is_empty() {
+            return vec![OutputTarget {
+                variant: None,
+                sink: None,
+            }];
+        }
+
+        variants
+            .into_iter()
+            .map(|variant| OutputTarget {
+                variant: Some(variant),
+                sink: config
+                    .variants
+                    .get(&variant)
+                    .and_then(|variant| variant.sink.as_ref())
+                    .map(|sink| sink.relative_to(config)),
+            })
+            .collect()
+    }
+
+    pub fn directory(&self) -> &'static str {
+        self.variant.map(Variant::name).unwrap_or("optimized")
+    }
+}
@@ -0,0 +1,183 @@
+"""Writes the encrypted fixtures the encryption tests open: one page saying
+"Hello, encrypted world" and a document title, encrypted with the user
+password "user" and the owner password "owner" by revisions 2, 3 and 4 (RC4)
+and 6 (256-bit AES) of the standard security handler.
+
+Written from ISO 32000-1 section 7.6 and ISO 32000-2 section 7.6.4.3 with
+Python's hashlib and the cryptography package's ciphers, so the tests don't
+check the crate against itself. Salts and initialization vectors are fixed,
+so the files come out the same every time.
+
+    python3 tests/fixtures/make_encrypted.py
+"""
+
+import hashlib
+import os
+import struct
+
+from cryptography.hazmat.decrepit.ciphers.algorithms import ARC4
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+
+PADDING = bytes.fromhex(
+    "28bf4e5e4e758a4164004e56fffa01082e2e00b6d0683e802f0ca9fe6453697a"
+)
+USER = b"user"
+OWNER = b"owner"
+FILE_ID = bytes.fromhex("0123456789abcdef0123456789abcdef")
+CONTENT = b"BT /F1 12 Tf 72 720 Td (Hello, encrypted world) Tj ET"
+TITLE = b"Encrypted fixture"
+PERMISSIONS = -3904  # Everything but printing, copying and modifying.
+
+
+def rc4(key, data):
+    return Cipher(ARC4(key), mode=None).encryptor().update(data)
+
+
+def aes_cbc(key, iv, data):
+    return Cipher(algorithms.AES(key), modes.CBC(iv)).encryptor().update(data)
+
+
+def aes_ecb(key, data):
+    return Cipher(algorithms.AES(key), modes.ECB()).encryptor().update(data)
+
+
+def md5(data):
+    return hashlib.md5(data).digest()
+
+
+def padded(password):
+    return (password + PADDING)[:32]
+
+
+def legacy(revision, length):
+    """The O and U entries and the file key of revisions 2 to 4."""
+    # Algorithm 3.
+    key = md5(padded(OWNER))
+    if revision >= 3:
+        for _ in range(50):
+            key = md5(key[:length])
+    key = key[:length]
+    owner = rc4(key, padded(USER))
+    if revision >= 3:
+        for round in range(1, 20):
+            owner = rc4(bytes(byte ^ round for byte in key), owner)
+    # Algorithm 2.
+    file_key = md5(padded(USER) + owner + struct.pack("<i", PERMISSIONS) + FILE_ID)
+    if revision >= 3:
+        for _ in range(50):
+            file_key = md5(file_key[:length])
+    file_key = file_key[:length]
+    # Algorithms 4 and 5.
+    if revision == 2:
+        user = rc4(file_key, PADDING)
+    else:
+        user = rc4(file_key, md5(PADDING + FILE_ID))
+        for round in range(1, 20):
+            user = rc4(bytes(byte ^ round for byte in file_key), user)
+        user += bytes(16)
+    return owner, user, file_key
+
+
+def hash_2b(password, salt, user):
+    """Algorithm 2.B of ISO 32000-2."""
+    hash = hashlib.sha256(password + salt + user).digest()
+    round = 0
+    while True:
+        block = (password + hash + user) * 64
+        encrypted = aes_cbc(hash[:16], hash[16:32], block)
+        hash = [hashlib.sha256, hashlib.sha384, hashlib.sha512][
+            int.from_bytes(encrypted[:16], "big") % 3
+        ](encrypted).digest()
+        round += 1
+        if round >= 64 and encrypted[-1] <= round - 32:
+            return hash[:32]
+
+
+def encrypt_string(revision, file_key, number, data, iv=None):
+    if revision == 6:
+        padding = 16 - len(data) % 16
+        return iv + aes_cbc(file_key, iv, data + bytes([padding]) * padding)
+    # Algorithm 1.
+    key = md5(file_key + struct.pack("<i", number)[:3] + b"\0\0")
+    return rc4(key[: min(len(file_key) + 5, 16)], data)
+
+
+def hex(data):
+    return b"<" + data.hex().encode() + b">"
+
+
+def encryption_dictionary(revision):
+    if revision in (2, 3, 4):
+        length = 5 if revision == 2 else 16
+        owner, user, file_key = legacy(revision, length)
+        fields = {
+            2: b"/V 1 /R 2",
+            3: b"/V 2 /R 3 /Length 128",
+            4: b"/V 4 /R 4 /Length 128 /CF << /StdCF << /CFM /V2 /Length 16 >> >>"
+            b" /StmF /StdCF /StrF /StdCF",
+        }[revision]
+        return file_key, b"<< /Filter /Standard %s /O %s /U %s /P %d >>" % (
+            fields,
+            hex(owner),
+            hex(user),
+            PERMISSIONS,
+        )
+    file_key = bytes(range(32))
+    user_salts = bytes.fromhex("a1a2a3a4a5a6a7a8b1b2b3b4b5b6b7b8")
+    owner_salts = bytes.fromhex("c1c2c3c4c5c6c7c8d1d2d3d4d5d6d7d8")
+    user = hash_2b(USER, user_salts[:8], b"") + user_salts
+    user_key = aes_cbc(hash_2b(USER, user_salts[8:], b""), bytes(16), file_key)
+    owner = hash_2b(OWNER, owner_salts[:8], user) + owner_salts
+    owner_key = aes_cbc(hash_2b(OWNER, owner_salts[8:], user), bytes(16), file_key)
+    perms = struct.pack("<i", PERMISSIONS) + b"\xff\xff\xff\xffTadb" + b"\0" * 4
+    return file_key, (
+        b"<< /Filter /Standard /V 5 /R 6 /Length 256"
+        b" /CF << /StdCF << /CFM /AESV3 /AuthEvent /DocOpen /Length 32 >> >>"
+        b" /StmF /StdCF /StrF /StdCF /O %s /U %s /OE %s /UE %s /P %d /Perms %s >>"
+        % (
+            hex(owner),
+            hex(user),
+            hex(owner_key),
+            hex(user_key),
+            PERMISSIONS,
+            hex(aes_ecb(file_key, perms)),
+        )
+    )
+
+
+def write(revision, path):
+    file_key, encrypt = encryption_dictionary(revision)
+    iv = bytes.fromhex("00112233445566778899aabbccddeeff")
+    content = encrypt_string(revision, file_key, 4, CONTENT, iv)
+    title = encrypt_string(revision, file_key, 6, TITLE, iv)
+    objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>",
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>",
+        b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792]"
+        b" /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>",
+        b"<< /Length %d >>\nstream\n%s\nendstream" % (len(content), content),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+        b"<< /Title %s >>" % hex(title),
+        encrypt,
+    ]
+    out = bytearray(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n")
+    offsets = []
+    for number, body in enumerate(objects, 1):
+        offsets.append(len(out))
+        out += b"%d 0 obj\n%s\nendobj\n" % (number, body)
+    xref = len(out)
+    out += b"xref\n0 %d\n0000000000 65535 f \n" % (len(objects) + 1)
+    for offset in offsets:
+        out += b"%010d 00000 n \n" % offset
+    out += (
+        b"trailer\n<< /Size %d /Root 1 0 R /Info 6 0 R /Encrypt 7 0 R /ID [%s %s] >>\n"
+        b"startxref\n%d\n%%%%EOF\n" % (len(objects) + 1, hex(FILE_ID), hex(FILE_ID), xref)
+    )
+    with open(path, "wb") as file:
+        file.write(out)
+
+
+if __name__ == "__main__":
+    directory = os.path.dirname(os.path.abspath(__file__))
+    for revision in (2, 3, 4, 6):
+        write(revision, os.path.join(directory, "encrypted_r%d.pdf" % revision))